@@ -0,0 +1,328 @@
+//! Zero-shot auto-tagging: score every image against a user-defined
+//! label vocabulary in a text encoder's shared text+image space and
+//! store the hits as suggestions for the user to accept or reject.
+//!
+//! Per label:
+//!
+//! 1. **Encode once.** Each prompt template (`"a photo of a {}."`, …) is
+//!    filled with the label name and encoded; the unit-normalised
+//!    embeddings are averaged and re-normalised (prompt ensembling).
+//!    The result is cached in `tag_label_embeddings` per text encoder
+//!    and only recomputed when the label's templates change.
+//! 2. **Score.** One matrix–vector pass of that embedding over every
+//!    visible image's embedding for the matching image encoder.
+//! 3. **Calibrate.** Raw cosine scores aren't comparable across labels
+//!    ("dog" might top out at 0.31 where "receipt" tops out at 0.24),
+//!    so each label gets its own threshold — see `calibrate_threshold`.
+//!    A user-set threshold on the label wins over calibration.
+//! 4. **Store.** Images above threshold replace the label's pending
+//!    suggestions (`db/tag_suggestions.rs`); decided pairs are left
+//!    alone.
+//!
+//! Storage and the accept / reject flow live in the DB layer; this
+//! module is the scoring run and the pure maths behind it.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use tracing::info;
+
+use crate::db::{ImageDatabase, ID};
+use crate::error::ApiError;
+use crate::search::semantic::{encode_text, resolve_text_encoder};
+use crate::similarity_and_semantic_search::cosine::EmbeddingMatrix;
+use crate::TextEncoderState;
+
+/// Templates used for labels that don't set their own. A small subset
+/// of the CLIP paper's ImageNet ensemble — enough to smooth out the
+/// phrasing sensitivity of a bare label without multiplying encode
+/// time.
+pub const DEFAULT_PROMPT_TEMPLATES: &[&str] = &[
+    "a photo of a {}.",
+    "a close-up photo of the {}.",
+    "a bright photo of a {}.",
+];
+
+/// Spread multiplier of the statistical threshold: suggest images more
+/// than this many robust standard deviations above the label's median
+/// score. Most images in a library are *not* a given label, so the
+/// bulk of the distribution is background and matches sit in the
+/// upper tail.
+const OUTLIER_SIGMAS: f32 = 3.0;
+
+/// Per-label outcome of a run.
+#[derive(Debug, Clone, Serialize)]
+pub struct LabelRun {
+    pub label_id: ID,
+    pub name: String,
+    pub threshold: f32,
+    /// Whether `threshold` came from calibration (false: user override).
+    pub calibrated: bool,
+    /// Suggestions pending for this label after the run.
+    pub suggested: usize,
+}
+
+/// Outcome of `run`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AutoTagReport {
+    /// Text encoder the labels were encoded with; images were scored
+    /// in the matching image encoder's space.
+    pub encoder_id: String,
+    pub images_scored: usize,
+    pub labels: Vec<LabelRun>,
+}
+
+/// Score every visible image against every label and refresh the
+/// pending suggestions. `text_encoder_id` picks the space the same way
+/// `semantic_search` does (CLIP unless it names SigLIP-2).
+pub fn run(
+    db: &ImageDatabase,
+    text_encoders: &TextEncoderState,
+    text_encoder_id: Option<&str>,
+) -> Result<AutoTagReport, ApiError> {
+    let encoder_id = resolve_text_encoder(text_encoder_id);
+    let labels = db.list_tag_labels()?;
+
+    let mut label_embeddings = Vec::with_capacity(labels.len());
+    for label in &labels {
+        let embedding = match db.get_label_embedding(label.id, encoder_id)? {
+            Some(e) => e,
+            None => {
+                let e = encode_name(text_encoders, &label.name, &label.templates, encoder_id)?;
+                db.set_label_embedding(label.id, encoder_id, &e)?;
+                e
+            }
+        };
+        label_embeddings.push(embedding);
+    }
+
+    let mut matrix = EmbeddingMatrix::new();
+    let mut image_ids = Vec::new();
+    if !labels.is_empty() {
+        // Both text encoders share their id with the image encoder
+        // whose space they embed into.
+        let rows = db.get_all_embeddings_for(encoder_id)?;
+        matrix.reserve(rows.len());
+        image_ids.reserve(rows.len());
+        for (id, path, embedding) in rows {
+            if matrix.push(PathBuf::from(path), &embedding) {
+                image_ids.push(id);
+            }
+        }
+    }
+    let row_of: HashMap<ID, usize> = image_ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+
+    let mut report = AutoTagReport {
+        encoder_id: encoder_id.to_string(),
+        images_scored: image_ids.len(),
+        labels: Vec::with_capacity(labels.len()),
+    };
+    if image_ids.is_empty() {
+        return Ok(report);
+    }
+
+    let mut scores = Vec::new();
+    for (label, embedding) in labels.iter().zip(&label_embeddings) {
+        matrix.scores_into(embedding, &mut scores);
+
+        let (threshold, calibrated) = match label.threshold {
+            Some(t) => (t, false),
+            None => {
+                let (accepted, rejected) = db.label_feedback(label.id)?;
+                let score_of = |ids: Vec<ID>| -> Vec<f32> {
+                    ids.iter().filter_map(|id| row_of.get(id)).map(|&i| scores[i]).collect()
+                };
+                let t = calibrate_threshold(&scores, &score_of(accepted), &score_of(rejected));
+                (t, true)
+            }
+        };
+
+        let above: Vec<(ID, f32)> = image_ids
+            .iter()
+            .zip(&scores)
+            .filter(|(_, &s)| s > threshold)
+            .map(|(&id, &s)| (id, s))
+            .collect();
+        let suggested = db.replace_pending_suggestions(label.id, encoder_id, threshold, &above)?;
+        report.labels.push(LabelRun {
+            label_id: label.id,
+            name: label.name.clone(),
+            threshold,
+            calibrated,
+            suggested,
+        });
+    }
+
+    info!(
+        "auto-tagging ({encoder_id}): {} images x {} labels, {} suggestions pending",
+        report.images_scored,
+        report.labels.len(),
+        report.labels.iter().map(|l| l.suggested).sum::<usize>()
+    );
+    Ok(report)
+}
+
+/// The prompts a label is encoded from: each template with `{}`
+/// replaced by the label name.
+pub fn expand_prompts(name: &str, templates: &[String]) -> Vec<String> {
+    if templates.is_empty() {
+        DEFAULT_PROMPT_TEMPLATES.iter().map(|t| t.replace("{}", name)).collect()
+    } else {
+        templates.iter().map(|t| t.replace("{}", name)).collect()
+    }
+}
+
+/// Prompt-ensembled embedding of a label `name` — also how
+/// `clusters.rs` encodes its board vocabulary.
+pub(crate) fn encode_name(
+    text_encoders: &TextEncoderState,
+    name: &str,
+    templates: &[String],
+    encoder_id: &str,
+) -> Result<Vec<f32>, ApiError> {
+    let mut embeddings = Vec::new();
+    for prompt in expand_prompts(name, templates) {
+        let (embedding, _, _) = encode_text(text_encoders, &prompt, encoder_id)?;
+        embeddings.push(embedding);
+    }
+    Ok(ensemble(&embeddings))
+}
+
+/// Mean of the unit-normalised `embeddings`, re-normalised. Zero-norm
+/// inputs are skipped; an empty result is the zero vector.
+pub fn ensemble(embeddings: &[Vec<f32>]) -> Vec<f32> {
+    let dim = embeddings.first().map_or(0, Vec::len);
+    let mut sum = vec![0.0f32; dim];
+    for e in embeddings.iter().filter(|e| e.len() == dim) {
+        let norm = e.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            for (s, x) in sum.iter_mut().zip(e) {
+                *s += x / norm;
+            }
+        }
+    }
+    let norm = sum.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        sum.iter_mut().for_each(|x| *x /= norm);
+    }
+    sum
+}
+
+/// Score threshold for one label; images scoring strictly above it
+/// are suggested.
+///
+/// - **With feedback on both sides** (at least one accepted and one
+///   rejected suggestion): the cut that best separates the two sets,
+///   by balanced accuracy (accepted above + rejected at-or-below, each
+///   as a fraction of its set). Ties go to the higher cut, favouring
+///   precision — the user reviews every suggestion. The cut sits
+///   midway between the neighbouring feedback scores.
+/// - **Otherwise:** `median + OUTLIER_SIGMAS × 1.4826 × MAD` of the
+///   label's scores over the whole library. The median/MAD pair is
+///   used instead of mean/σ so a label that genuinely matches a large
+///   share of the library doesn't drag its own threshold up.
+pub fn calibrate_threshold(scores: &[f32], accepted: &[f32], rejected: &[f32]) -> f32 {
+    if !accepted.is_empty() && !rejected.is_empty() {
+        let mut cuts: Vec<f32> = accepted.iter().chain(rejected).copied().collect();
+        cuts.sort_by(f32::total_cmp);
+        cuts.dedup();
+        let balanced_accuracy = |t: f32| {
+            let tp = accepted.iter().filter(|&&s| s > t).count() as f32 / accepted.len() as f32;
+            let tn = rejected.iter().filter(|&&s| s <= t).count() as f32 / rejected.len() as f32;
+            tp + tn
+        };
+        // Candidate cuts: below everything, then each feedback score
+        // (anything in the gap above it classifies identically).
+        let mut best = (balanced_accuracy(cuts[0] - f32::EPSILON), 0usize, true);
+        for (i, &t) in cuts.iter().enumerate() {
+            let acc = balanced_accuracy(t);
+            if acc >= best.0 {
+                best = (acc, i, false);
+            }
+        }
+        let (_, i, below_all) = best;
+        return if below_all {
+            cuts[0] - f32::EPSILON
+        } else if i + 1 < cuts.len() {
+            (cuts[i] + cuts[i + 1]) / 2.0
+        } else {
+            cuts[i]
+        };
+    }
+
+    if scores.is_empty() {
+        return f32::INFINITY;
+    }
+    let center = median(scores.to_vec());
+    let mad = median(scores.iter().map(|s| (s - center).abs()).collect());
+    center + OUTLIER_SIGMAS * 1.4826 * mad
+}
+
+fn median(mut values: Vec<f32>) -> f32 {
+    values.sort_by(f32::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompts_fill_templates_or_fall_back_to_defaults() {
+        assert_eq!(
+            expand_prompts("red car", &["{} at night".into(), "a {} parked".into()]),
+            vec!["red car at night", "a red car parked"]
+        );
+        assert_eq!(expand_prompts("cat", &[]).len(), DEFAULT_PROMPT_TEMPLATES.len());
+        assert_eq!(expand_prompts("cat", &[])[0], "a photo of a cat.");
+    }
+
+    #[test]
+    fn ensemble_is_the_normalised_mean_direction() {
+        let e = ensemble(&[vec![2.0, 0.0], vec![0.0, 5.0]]);
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        assert!((e[0] - h).abs() < 1e-6 && (e[1] - h).abs() < 1e-6);
+        // A zero vector contributes nothing rather than NaN.
+        assert_eq!(ensemble(&[vec![0.0, 0.0], vec![0.0, 3.0]]), vec![0.0, 1.0]);
+        assert!(ensemble(&[]).is_empty());
+    }
+
+    #[test]
+    fn statistical_threshold_sits_above_the_background() {
+        // 95 background images around 0.20, five clear matches.
+        let mut scores: Vec<f32> = (0..95).map(|i| 0.19 + (i % 5) as f32 * 0.005).collect();
+        scores.extend([0.31, 0.32, 0.30, 0.33, 0.29]);
+        let t = calibrate_threshold(&scores, &[], &[]);
+        assert!(t > 0.21 && t < 0.29, "threshold {t}");
+        assert_eq!(scores.iter().filter(|&&s| s > t).count(), 5);
+        assert_eq!(calibrate_threshold(&[], &[], &[]), f32::INFINITY);
+    }
+
+    #[test]
+    fn feedback_threshold_separates_accepted_from_rejected() {
+        let t = calibrate_threshold(&[], &[0.28, 0.30], &[0.22, 0.25]);
+        assert!((t - 0.265).abs() < 1e-6, "threshold {t}");
+
+        // Overlap: the cut keeps the higher of the equally good options.
+        let t = calibrate_threshold(&[], &[0.24, 0.30], &[0.26]);
+        assert!(t > 0.26 && t < 0.30, "threshold {t}");
+
+        // Every rejected score above every accepted one: the cut lands
+        // on the rejected score rather than below the accepted one.
+        let t = calibrate_threshold(&[], &[0.20], &[0.30]);
+        assert!(t >= 0.30, "threshold {t}");
+
+        // Feedback on one side only falls back to the statistics.
+        let scores = [0.1, 0.2, 0.3];
+        assert_eq!(
+            calibrate_threshold(&scores, &[0.3], &[]),
+            calibrate_threshold(&scores, &[], &[])
+        );
+    }
+}
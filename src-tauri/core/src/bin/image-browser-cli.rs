@@ -0,0 +1,763 @@
+//! Headless front-end to the same library the app manages.
+//!
+//! Indexes roots and answers searches without opening a window, so a
+//! library can be built on a server and queried from scripts or CI.
+//! Every command works against the app's `images.db` (and its
+//! thumbnails, models and settings) under `paths::app_data_dir()`;
+//! point it elsewhere with `--data-dir` or `IMAGE_BROWSER_DATA_DIR`.
+//!
+//! Argument parsing is hand-rolled over `std::env::args`, the same way
+//! `main.rs` reads `--profiling` — the surface is small enough that a
+//! parser dependency would cost more than it saves.
+
+use std::error::Error;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use image_browser_core::db::{Cluster, ImageDatabase};
+use image_browser_core::duplicates::DuplicateKind;
+use image_browser_core::http_api;
+use image_browser_core::indexing::{CancelToken, IndexingProgress, Phase, ProgressSink};
+use image_browser_core::search::{ImageSearchResult, QueryTerm};
+use image_browser_core::tag_struct::DEFAULT_TAG_COLOR;
+use image_browser_core::{paths, Library};
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_TOP_N: usize = 20;
+
+const USAGE: &str = "\
+usage: image-browser-cli [--json] [--data-dir <dir>] <command>
+
+commands:
+  roots list                       list configured folders
+  roots add <dir>                  add a folder (run `index` afterwards)
+  roots remove <id>                remove a folder (adding it again restores it)
+  index                            scan, thumbnail and encode every enabled folder
+  search <text> [--top <n>]        text search fused across enabled encoders
+  similar <image> [--top <n>]      images similar to <image>, fused across encoders
+                                   both take --like <image> / --unlike <image>
+                                   (repeatable) to refine by relevance feedback
+  compose [<text>] [--image <image>] [--not <text>] [--top <n>]
+                                   blend a phrase, example images and negative phrases
+                                   (--image / --not repeatable)
+  tags list                        list tags
+  tags create <name> [--color <c>] create a tag
+  tags delete <name>               delete a tag
+  tag <image> <name>               tag an image (creates the tag if missing)
+  untag <image> <name>             remove a tag from an image
+  suggest <image>                  tags carried by <image>'s nearest tagged look-alikes
+  propagate <name> [--top <n>]     tag the <n> closest look-alikes of images tagged <name>
+  labels list                      list auto-tagging labels and pending suggestions
+  labels add <name>                add an auto-tagging label (default prompt templates)
+  labels remove <name>             remove an auto-tagging label
+  autotag [--encoder <id>]         score every image against the labels
+  duplicates                       list exact and near-duplicate groups, keeper first
+  boards list                      list the visual boards of the last clustering run
+  boards run [--encoder <id>] [--k <n>]
+                                   re-cluster the library into <n> boards (default: by size)
+  boards show <id> [--top <n>]     a board's most representative images
+  boards tag <id> [<name>]         tag a board's images (default: its proposed label)
+  serve [--port <n>]               serve the HTTP API on 127.0.0.1 until killed
+
+options:
+  --json            print results as JSON instead of text
+  --data-dir <dir>  use the library under <dir> instead of the app's
+";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    RootsList,
+    RootsAdd(String),
+    RootsRemove(i64),
+    Index,
+    Search { query: String, top_n: usize, feedback: Feedback },
+    Similar { image: String, top_n: usize, feedback: Feedback },
+    Compose { text: Option<String>, images: Vec<String>, not: Vec<String>, top_n: usize },
+    TagsList,
+    TagsCreate { name: String, color: String },
+    TagsDelete(String),
+    Tag { image: String, tag: String },
+    Untag { image: String, tag: String },
+    Suggest(String),
+    Propagate { tag: String, top_n: usize },
+    LabelsList,
+    LabelsAdd(String),
+    LabelsRemove(String),
+    Autotag { encoder: Option<String> },
+    Duplicates,
+    BoardsList,
+    BoardsRun { encoder: Option<String>, k: Option<usize> },
+    BoardsShow { id: i64, top_n: usize },
+    BoardsTag { id: i64, name: Option<String> },
+    Serve { port: u16 },
+}
+
+/// Images marked with `--like` / `--unlike`.
+#[derive(Debug, Default, PartialEq)]
+struct Feedback {
+    like: Vec<String>,
+    unlike: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Cli {
+    json: bool,
+    data_dir: Option<String>,
+    command: Command,
+}
+
+/// Parse everything after the program name. Global flags may appear
+/// anywhere; `--top` / `--color` / `--port` / `--encoder` / `--k` /
+/// `--like` / `--unlike` / `--image` / `--not` only where their
+/// subcommand takes them.
+fn parse_args(args: &[String]) -> Result<Cli, String> {
+    let mut json = false;
+    let mut data_dir = None;
+    let mut top_n = None;
+    let mut color = None;
+    let mut port = None;
+    let mut encoder = None;
+    let mut k = None;
+    let mut feedback = Feedback::default();
+    let mut images: Vec<String> = Vec::new();
+    let mut not: Vec<String> = Vec::new();
+    let mut positional: Vec<&str> = Vec::new();
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |flag: &str| {
+            it.next()
+                .cloned()
+                .ok_or_else(|| format!("{flag} needs a value"))
+        };
+        match arg.as_str() {
+            "--json" => json = true,
+            "--data-dir" => data_dir = Some(value("--data-dir")?),
+            "--top" => {
+                let raw = value("--top")?;
+                let n = raw
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| format!("--top expects a positive number, got '{raw}'"))?;
+                top_n = Some(n);
+            }
+            "--color" => color = Some(value("--color")?),
+            "--encoder" => encoder = Some(value("--encoder")?),
+            "--k" => {
+                let raw = value("--k")?;
+                let n = raw
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| format!("--k expects a positive number, got '{raw}'"))?;
+                k = Some(n);
+            }
+            "--like" => feedback.like.push(value("--like")?),
+            "--unlike" => feedback.unlike.push(value("--unlike")?),
+            "--image" => images.push(value("--image")?),
+            "--not" => not.push(value("--not")?),
+            "--port" => {
+                let raw = value("--port")?;
+                port = Some(
+                    raw.parse::<u16>()
+                        .map_err(|_| format!("--port expects a port number, got '{raw}'"))?,
+                );
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option {flag}")),
+            other => positional.push(other),
+        }
+    }
+
+    let command = match positional.as_slice() {
+        ["roots", "list"] => Command::RootsList,
+        ["roots", "add", dir] => Command::RootsAdd(dir.to_string()),
+        ["roots", "remove", id] => Command::RootsRemove(
+            id.parse()
+                .map_err(|_| format!("root id must be a number, got '{id}'"))?,
+        ),
+        ["index"] => Command::Index,
+        ["search", words @ ..] if !words.is_empty() => Command::Search {
+            query: words.join(" "),
+            top_n: top_n.take().unwrap_or(DEFAULT_TOP_N),
+            feedback: std::mem::take(&mut feedback),
+        },
+        ["similar", image] => Command::Similar {
+            image: image.to_string(),
+            top_n: top_n.take().unwrap_or(DEFAULT_TOP_N),
+            feedback: std::mem::take(&mut feedback),
+        },
+        ["compose", words @ ..] => Command::Compose {
+            text: (!words.is_empty()).then(|| words.join(" ")),
+            images: std::mem::take(&mut images),
+            not: std::mem::take(&mut not),
+            top_n: top_n.take().unwrap_or(DEFAULT_TOP_N),
+        },
+        ["tags", "list"] => Command::TagsList,
+        ["tags", "create", name] => Command::TagsCreate {
+            name: name.to_string(),
+            color: color.take().unwrap_or_else(|| DEFAULT_TAG_COLOR.to_string()),
+        },
+        ["tags", "delete", name] => Command::TagsDelete(name.to_string()),
+        ["tag", image, tag] => Command::Tag {
+            image: image.to_string(),
+            tag: tag.to_string(),
+        },
+        ["untag", image, tag] => Command::Untag {
+            image: image.to_string(),
+            tag: tag.to_string(),
+        },
+        ["suggest", image] => Command::Suggest(image.to_string()),
+        ["propagate", tag] => Command::Propagate {
+            tag: tag.to_string(),
+            top_n: top_n.take().unwrap_or(DEFAULT_TOP_N),
+        },
+        ["labels", "list"] => Command::LabelsList,
+        ["labels", "add", name] => Command::LabelsAdd(name.to_string()),
+        ["labels", "remove", name] => Command::LabelsRemove(name.to_string()),
+        ["autotag"] => Command::Autotag {
+            encoder: encoder.take(),
+        },
+        ["duplicates"] => Command::Duplicates,
+        ["boards", "list"] => Command::BoardsList,
+        ["boards", "run"] => Command::BoardsRun {
+            encoder: encoder.take(),
+            k: k.take(),
+        },
+        ["boards", "show", id] => Command::BoardsShow {
+            id: board_id(id)?,
+            top_n: top_n.take().unwrap_or(DEFAULT_TOP_N),
+        },
+        ["boards", "tag", id, name @ ..] if name.len() <= 1 => Command::BoardsTag {
+            id: board_id(id)?,
+            name: name.first().map(|n| n.to_string()),
+        },
+        ["serve"] => Command::Serve {
+            port: port.take().unwrap_or(http_api::DEFAULT_PORT),
+        },
+        [] => return Err("missing command".into()),
+        other => return Err(format!("unrecognised command: {}", other.join(" "))),
+    };
+    if top_n.is_some() {
+        return Err(
+            "--top only applies to search, similar, compose, propagate and boards show".into(),
+        );
+    }
+    if color.is_some() {
+        return Err("--color only applies to tags create".into());
+    }
+    if port.is_some() {
+        return Err("--port only applies to serve".into());
+    }
+    if encoder.is_some() {
+        return Err("--encoder only applies to autotag and boards run".into());
+    }
+    if k.is_some() {
+        return Err("--k only applies to boards run".into());
+    }
+    if feedback != Feedback::default() {
+        return Err("--like and --unlike only apply to search and similar".into());
+    }
+    if !images.is_empty() || !not.is_empty() {
+        return Err("--image and --not only apply to compose".into());
+    }
+
+    Ok(Cli {
+        json,
+        data_dir,
+        command,
+    })
+}
+
+fn board_id(raw: &str) -> Result<i64, String> {
+    raw.parse()
+        .map_err(|_| format!("board id must be a number, got '{raw}'"))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        print!("{USAGE}");
+        return;
+    }
+    let cli = match parse_args(&args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    // Quieter than the app's default: progress goes to stderr through
+    // the sink below, and info-level pipeline logs would bury it.
+    let _ = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")),
+        )
+        .try_init();
+
+    if let Some(dir) = &cli.data_dir {
+        // Single-threaded at this point; everything below resolves its
+        // paths through `paths::app_data_dir`, which reads this.
+        std::env::set_var("IMAGE_BROWSER_DATA_DIR", dir);
+    }
+
+    if let Err(e) = run(&cli) {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: &Cli) -> Result<(), Box<dyn Error>> {
+    let library = Arc::new(Library::open_default()?);
+    let db = library.db();
+
+    match &cli.command {
+        Command::RootsList => {
+            let roots = db.list_roots()?;
+            if cli.json {
+                return print_json(&roots);
+            }
+            for r in roots {
+                let state = if r.enabled { "enabled" } else { "disabled" };
+                println!("{}\t{}\t{}", r.id, state, r.path);
+            }
+        }
+        Command::RootsAdd(dir) => {
+            if !Path::new(dir).is_dir() {
+                return Err(format!("Not a directory: {dir}").into());
+            }
+            let root = db.add_root(canonical_path(dir)?)?;
+            if cli.json {
+                return print_json(&root);
+            }
+            println!("added root {} ({})", root.id, root.path);
+        }
+        Command::RootsRemove(id) => {
+            library.remove_root(*id, TerminalProgress::new())?;
+            if !cli.json {
+                println!("removed root {id}");
+            }
+        }
+        Command::Index => {
+            library.run_indexing(&TerminalProgress::new(), &CancelToken::new())?;
+        }
+        Command::Search {
+            query,
+            top_n,
+            feedback,
+        } => {
+            let results = if *feedback == Feedback::default() {
+                library.search(query, *top_n, None, None)?
+            } else {
+                let session = library.start_search_session(query, *top_n, None)?;
+                refine(&library, session.session_id, feedback, *top_n)?
+            };
+            print_results(cli.json, &results)?;
+        }
+        Command::Similar {
+            image,
+            top_n,
+            feedback,
+        } => {
+            let image_id = image_id_for(db, image)?;
+            let results = if *feedback == Feedback::default() {
+                library.similar(image_id, *top_n, None, None)?
+            } else {
+                let session = library.start_similar_session(image_id, *top_n, None)?;
+                refine(&library, session.session_id, feedback, *top_n)?
+            };
+            print_results(cli.json, &results)?;
+        }
+        Command::Compose {
+            text,
+            images,
+            not,
+            top_n,
+        } => {
+            let mut terms: Vec<QueryTerm> = text
+                .iter()
+                .map(|text| QueryTerm::Text {
+                    text: text.clone(),
+                    weight: 1.0,
+                })
+                .collect();
+            for image in images {
+                terms.push(QueryTerm::Image {
+                    image_id: image_id_for(db, image)?,
+                    weight: 1.0,
+                });
+            }
+            terms.extend(not.iter().map(|text| QueryTerm::Text {
+                text: text.clone(),
+                weight: -1.0,
+            }));
+            let results = library.composite_search(&terms, *top_n, None)?;
+            print_results(cli.json, &results)?;
+        }
+        Command::TagsList => {
+            let tags = db.get_tags()?;
+            if cli.json {
+                return print_json(&tags);
+            }
+            for t in tags {
+                println!("{}\t{}\t{}", t.id, t.color, t.name);
+            }
+        }
+        Command::TagsCreate { name, color } => {
+            let tag = library.create_tag(name, color, None)?;
+            if cli.json {
+                return print_json(&tag);
+            }
+            println!("created tag {} ({})", tag.id, tag.name);
+        }
+        Command::TagsDelete(name) => {
+            let tag_id = tag_id_for(db, name)?.ok_or_else(|| format!("No tag named '{name}'"))?;
+            library.delete_tag(tag_id)?;
+            if !cli.json {
+                println!("deleted tag {name}");
+            }
+        }
+        Command::Tag { image, tag } => {
+            let image_id = image_id_for(db, image)?;
+            let tag_id = match tag_id_for(db, tag)? {
+                Some(id) => id,
+                None => library.create_tag(tag, DEFAULT_TAG_COLOR, None)?.id,
+            };
+            library.add_tag_to_image(image_id, tag_id)?;
+        }
+        Command::Untag { image, tag } => {
+            let image_id = image_id_for(db, image)?;
+            let tag_id = tag_id_for(db, tag)?.ok_or_else(|| format!("No tag named '{tag}'"))?;
+            library.remove_tag_from_image(image_id, tag_id)?;
+        }
+        Command::Suggest(image) => {
+            let image_id = image_id_for(db, image)?;
+            let votes = library.suggest_tags_for_image(image_id, None)?;
+            if cli.json {
+                return print_json(&votes);
+            }
+            for v in votes {
+                println!("{:.4}\t{} votes\t{}", v.score, v.votes, v.tag.name);
+            }
+        }
+        Command::Propagate { tag, top_n } => {
+            let tag_id = tag_id_for(db, tag)?.ok_or_else(|| format!("No tag named '{tag}'"))?;
+            let results = library.propagate_tag(tag_id, *top_n)?;
+            print_results(cli.json, &results)?;
+        }
+        Command::LabelsList => {
+            let labels = library.tag_labels()?;
+            if cli.json {
+                return print_json(&labels);
+            }
+            for l in labels {
+                let threshold = l.threshold.map_or("auto".to_string(), |t| format!("{t:.4}"));
+                println!("{}\t{}\t{} pending\t{}", l.id, threshold, l.pending, l.name);
+            }
+        }
+        Command::LabelsAdd(name) => {
+            let label = library.add_tag_label(name, Vec::new())?;
+            if cli.json {
+                return print_json(&label);
+            }
+            println!("added label {} ({})", label.id, label.name);
+        }
+        Command::LabelsRemove(name) => {
+            let label = library
+                .tag_labels()?
+                .into_iter()
+                .find(|l| l.name == *name)
+                .ok_or_else(|| format!("No label named '{name}'"))?;
+            library.delete_tag_label(label.id)?;
+            if !cli.json {
+                println!("removed label {name}");
+            }
+        }
+        Command::Autotag { encoder } => {
+            let report = library.run_auto_tagging(encoder.as_deref())?;
+            if cli.json {
+                return print_json(&report);
+            }
+            for l in &report.labels {
+                let how = if l.calibrated { "calibrated" } else { "fixed" };
+                println!("{}\t{} suggested\t{how} threshold {:.4}", l.name, l.suggested, l.threshold);
+            }
+            eprintln!(
+                "scored {} images with {}; review suggestions in the app",
+                report.images_scored, report.encoder_id
+            );
+        }
+        Command::Duplicates => {
+            let clusters = library.find_duplicates(None)?;
+            if cli.json {
+                return print_json(&clusters);
+            }
+            for c in &clusters {
+                let kind = match c.kind {
+                    DuplicateKind::Exact => "exact",
+                    DuplicateKind::Near => "near",
+                };
+                for img in &c.images {
+                    let mark = if img.id == c.keeper_id { "keep" } else { kind };
+                    println!("{mark}\t{:.4}\t{}", img.similarity, img.path);
+                }
+                println!();
+            }
+            eprintln!("{} duplicate groups; trash the extras from the app", clusters.len());
+        }
+        Command::BoardsList => print_boards(cli.json, &library.clusters()?)?,
+        Command::BoardsRun { encoder, k } => {
+            print_boards(cli.json, &library.run_clustering(encoder.as_deref(), *k)?)?;
+        }
+        Command::BoardsShow { id, top_n } => {
+            let results = library.cluster_images(*id, *top_n, 0)?;
+            print_results(cli.json, &results)?;
+        }
+        Command::BoardsTag { id, name } => {
+            let tag = library.cluster_to_tag(*id, name.as_deref())?;
+            if cli.json {
+                return print_json(&tag);
+            }
+            println!("tagged board {id} '{}'", tag.name);
+        }
+        Command::Serve { port } => {
+            let token = http_api::load_or_create_token()?;
+            let server = http_api::spawn(library.clone(), *port, token)?;
+            eprintln!(
+                "serving on http://127.0.0.1:{} (token in {})",
+                server.port(),
+                paths::http_api_token_path().display()
+            );
+            server.wait();
+        }
+    }
+    Ok(())
+}
+
+/// Absolute form of a user-supplied path, matching how the app stores
+/// paths picked through the folder dialog.
+fn canonical_path(path: &str) -> Result<String, Box<dyn Error>> {
+    let canonical = std::fs::canonicalize(path)?;
+    Ok(paths::strip_windows_extended_prefix(&canonical.to_string_lossy()).into_owned())
+}
+
+fn image_id_for(db: &ImageDatabase, path: &str) -> Result<i64, Box<dyn Error>> {
+    let path = canonical_path(path)?;
+    db.get_image_id_by_path(&path)
+        .map_err(|_| format!("Not in the library: {path}").into())
+}
+
+/// One relevance-feedback round over a fresh session.
+fn refine(
+    library: &Library,
+    session_id: u64,
+    feedback: &Feedback,
+    top_n: usize,
+) -> Result<Vec<ImageSearchResult>, Box<dyn Error>> {
+    let ids = |images: &[String]| -> Result<Vec<i64>, Box<dyn Error>> {
+        images
+            .iter()
+            .map(|i| image_id_for(library.db(), i))
+            .collect()
+    };
+    let refined = library.refine_search(
+        session_id,
+        &ids(&feedback.like)?,
+        &ids(&feedback.unlike)?,
+        &[],
+        top_n,
+    )?;
+    Ok(refined.results)
+}
+
+/// The tag called `name`, or one of its aliases.
+fn tag_id_for(db: &ImageDatabase, name: &str) -> Result<Option<i64>, Box<dyn Error>> {
+    Ok(db.resolve_tag_name(name)?.map(|t| t.id))
+}
+
+fn print_json<T: serde::Serialize>(value: &T) -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn print_results(json: bool, results: &[ImageSearchResult]) -> Result<(), Box<dyn Error>> {
+    if json {
+        return print_json(&results);
+    }
+    for r in results {
+        println!("{:.4}\t{}", r.score, r.path);
+    }
+    Ok(())
+}
+
+fn print_boards(json: bool, boards: &[Cluster]) -> Result<(), Box<dyn Error>> {
+    if json {
+        return print_json(&boards);
+    }
+    for b in boards {
+        let label = b.label.as_deref().unwrap_or("-");
+        println!("{}\t{} images\t{label}\t{}", b.id, b.size, b.cover_path);
+    }
+    Ok(())
+}
+
+/// Writes pipeline progress to stderr: one rewritten status line on a
+/// terminal, one line per report otherwise (CI logs).
+struct TerminalProgress {
+    interactive: bool,
+}
+
+impl TerminalProgress {
+    fn new() -> Self {
+        Self {
+            interactive: std::io::stderr().is_terminal(),
+        }
+    }
+}
+
+impl ProgressSink for TerminalProgress {
+    fn report(&self, p: &IndexingProgress) {
+        let phase = match p.phase {
+            Phase::Scan => "scan",
+            Phase::ModelDownload => "models",
+            Phase::Thumbnail => "thumbnails",
+            Phase::Encode => "encode",
+            Phase::Ready => "ready",
+            Phase::Error => "error",
+            Phase::Cancelled => "cancelled",
+        };
+        let mut line = format!("[{phase}]");
+        if p.total > 0 {
+            line.push_str(&format!(" {}/{}", p.processed, p.total));
+        }
+        if let Some(msg) = &p.message {
+            line.push(' ');
+            line.push_str(msg);
+        }
+        let done = matches!(p.phase, Phase::Ready | Phase::Error | Phase::Cancelled);
+        let mut err = std::io::stderr().lock();
+        let _ = if self.interactive && !done {
+            write!(err, "\r\x1b[2K{line}")
+        } else if self.interactive {
+            writeln!(err, "\r\x1b[2K{line}")
+        } else {
+            writeln!(err, "{line}")
+        };
+        let _ = err.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, String> {
+        parse_args(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parses_subcommands_with_global_flags_anywhere() {
+        let cli = parse(&["search", "red", "--json", "car", "--top", "5"]).unwrap();
+        assert!(cli.json);
+        assert_eq!(
+            cli.command,
+            Command::Search {
+                query: "red car".into(),
+                top_n: 5,
+                feedback: Feedback::default(),
+            }
+        );
+
+        let cli = parse(&[
+            "similar", "a.jpg", "--like", "b.jpg", "--unlike", "c.jpg", "--like", "d.jpg",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Similar {
+                image: "a.jpg".into(),
+                top_n: DEFAULT_TOP_N,
+                feedback: Feedback {
+                    like: vec!["b.jpg".into(), "d.jpg".into()],
+                    unlike: vec!["c.jpg".into()],
+                },
+            }
+        );
+
+        let cli = parse(&[
+            "compose", "--image", "a.jpg", "at", "night", "--not", "people",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Compose {
+                text: Some("at night".into()),
+                images: vec!["a.jpg".into()],
+                not: vec!["people".into()],
+                top_n: DEFAULT_TOP_N,
+            }
+        );
+
+        let cli = parse(&["propagate", "cat", "--top", "40"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Propagate {
+                tag: "cat".into(),
+                top_n: 40
+            }
+        );
+
+        let cli = parse(&["autotag", "--encoder", "siglip2_base"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Autotag {
+                encoder: Some("siglip2_base".into())
+            }
+        );
+
+        let cli = parse(&["boards", "run", "--k", "12", "--encoder", "clip_vit_b_32"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::BoardsRun {
+                encoder: Some("clip_vit_b_32".into()),
+                k: Some(12)
+            }
+        );
+        let cli = parse(&["boards", "tag", "3"]).unwrap();
+        assert_eq!(cli.command, Command::BoardsTag { id: 3, name: None });
+
+        let cli = parse(&["--data-dir", "/lib", "tags", "create", "trip"]).unwrap();
+        assert_eq!(cli.data_dir.as_deref(), Some("/lib"));
+        assert_eq!(
+            cli.command,
+            Command::TagsCreate {
+                name: "trip".into(),
+                color: DEFAULT_TAG_COLOR.into()
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["search"]).is_err());
+        assert!(parse(&["roots", "remove", "x"]).is_err());
+        assert!(parse(&["similar", "a.jpg", "--top", "0"]).is_err());
+        assert!(parse(&["index", "--top", "3"]).is_err());
+        assert!(parse(&["index", "--verbose"]).is_err());
+        assert!(parse(&["search", "cat", "--port", "80"]).is_err());
+        assert!(parse(&["serve", "--port", "70000"]).is_err());
+        assert!(parse(&["labels", "list", "--encoder", "siglip2_base"]).is_err());
+        assert!(parse(&["suggest", "a.jpg", "--top", "3"]).is_err());
+        assert!(parse(&["duplicates", "--top", "3"]).is_err());
+        assert!(parse(&["boards", "list", "--k", "5"]).is_err());
+        assert!(parse(&["boards", "show", "x"]).is_err());
+        assert!(parse(&["boards", "tag", "1", "a", "b"]).is_err());
+        assert!(parse(&["propagate", "cat", "--like", "a.jpg"]).is_err());
+        assert!(parse(&["search", "--unlike"]).is_err());
+        assert!(parse(&["search", "cat", "--not", "dog"]).is_err());
+    }
+}
@@ -0,0 +1,638 @@
+//! Visual boards: unsupervised clusters of the library in one image
+//! encoder's space, each with a proposed name.
+//!
+//! A run:
+//!
+//! 1. **Cluster.** Spherical k-means over every visible image's
+//!    embedding for the chosen encoder (DINOv2 unless told otherwise —
+//!    it groups by look rather than by caption). k-means++ seeding
+//!    from a fixed seed, so re-running on an unchanged library gives
+//!    the same boards. `k` defaults to √(n/2), capped at
+//!    `MAX_CLUSTERS`.
+//! 2. **Represent.** Each board keeps its centroid — the mean of its
+//!    members' unit embeddings — and its medoid, the member with the
+//!    highest cosine to that mean. Since Σⱼ cos(x, mⱼ) = x · Σⱼ mⱼ for
+//!    unit vectors, that member is exactly the one closest on average
+//!    to all the others.
+//! 3. **Name.** The board's centroid in a text encoder's space (the
+//!    clustered encoder's own if it has a text branch, CLIP's
+//!    otherwise — the members' CLIP embeddings are averaged) is scored
+//!    against a vocabulary: the auto-tagging labels plus
+//!    `DEFAULT_VOCABULARY`. The pick is the word that scores highest
+//!    *relative to its mean over all boards*, so a word that is close
+//!    to every photo ("a photo") doesn't name them all.
+//! 4. **Store.** The boards replace the previous run's
+//!    (`db/clusters.rs`).
+//!
+//! Between runs the indexing pipeline calls `assign_new_images`:
+//! images newly embedded in the boards' encoder join the nearest board
+//! and its centroid moves as a running mean. Names and the medoid are
+//! only revisited by the next full run.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use tracing::{info, warn};
+
+use crate::autotag::encode_name;
+use crate::db::{Cluster, ImageDatabase, NewCluster, ID};
+use crate::error::ApiError;
+use crate::search::semantic::resolve_text_encoder;
+use crate::similarity_and_semantic_search::cosine::math::dot;
+use crate::similarity_and_semantic_search::cosine::EmbeddingMatrix;
+use crate::similarity_and_semantic_search::encoder_dinov2::DINOV2_ENCODER_ID;
+use crate::TextEncoderState;
+
+/// Encoder clustered when the caller doesn't pick one.
+pub const DEFAULT_ENCODER: &str = DINOV2_ENCODER_ID;
+
+/// Upper bound on `k`, defaulted or requested. Past a few dozen,
+/// boards stop being browsable.
+pub const MAX_CLUSTERS: usize = 64;
+
+/// Lloyd iterations before giving up on convergence. Spherical
+/// k-means on encoder embeddings settles in well under this.
+const MAX_ITERATIONS: usize = 30;
+
+/// Fixed k-means++ seed: same library, same boards.
+const SEED: u64 = 0x626f_6172_6473;
+
+/// Names a board can get besides the auto-tagging labels. Broad photo
+/// subjects, so most boards find a fitting one.
+pub const DEFAULT_VOCABULARY: &[&str] = &[
+    "beach",
+    "ocean",
+    "lake",
+    "river",
+    "waterfall",
+    "mountains",
+    "forest",
+    "desert",
+    "snow",
+    "field",
+    "garden",
+    "flowers",
+    "park",
+    "sunset",
+    "night sky",
+    "clouds",
+    "city street",
+    "skyline",
+    "architecture",
+    "building interior",
+    "kitchen",
+    "living room",
+    "office",
+    "food",
+    "drinks",
+    "dessert",
+    "dog",
+    "cat",
+    "bird",
+    "horse",
+    "wildlife",
+    "insect",
+    "fish",
+    "portrait",
+    "selfie",
+    "group of people",
+    "children",
+    "baby",
+    "wedding",
+    "party",
+    "concert",
+    "sports",
+    "car",
+    "bicycle",
+    "boat",
+    "airplane",
+    "train",
+    "document",
+    "screenshot",
+    "receipt",
+    "artwork",
+    "drawing",
+    "sculpture",
+    "fashion",
+    "shoes",
+    "furniture",
+    "toys",
+    "books",
+    "computer",
+    "macro close-up",
+    "abstract pattern",
+    "holiday decorations",
+];
+
+/// Result of `kmeans`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KMeans {
+    /// Board index of each row.
+    pub assignments: Vec<usize>,
+    /// Unit-length centroid directions, `k` of them.
+    pub centroids: Vec<Vec<f32>>,
+    pub iterations: usize,
+}
+
+/// Default `k` for `n` images: √(n/2), within [2, `MAX_CLUSTERS`] and
+/// never more than `n`.
+pub fn default_k(n: usize) -> usize {
+    (((n as f64) / 2.0).sqrt().round() as usize)
+        .clamp(2, MAX_CLUSTERS)
+        .min(n)
+}
+
+/// Cluster the library in `encoder_id`'s space into `k` boards (default
+/// `default_k`), name them, and replace the stored boards. Returns the
+/// new boards, largest first.
+pub fn run(
+    db: &ImageDatabase,
+    text_encoders: &TextEncoderState,
+    encoder_id: &str,
+    k: Option<usize>,
+) -> Result<Vec<Cluster>, ApiError> {
+    let mut rows = EmbeddingMatrix::new();
+    let mut ids = Vec::new();
+    for (id, path, embedding) in db.get_all_embeddings_for(encoder_id)? {
+        if rows.push(PathBuf::from(path), &embedding) {
+            ids.push(id);
+        }
+    }
+    if ids.is_empty() {
+        return Err(ApiError::BadInput(format!(
+            "no {encoder_id} embeddings to cluster yet"
+        )));
+    }
+    let k = k.unwrap_or_else(|| default_k(ids.len())).min(ids.len());
+    let result = kmeans(&rows, k, SEED);
+
+    let mut boards: Vec<Board> = (0..k)
+        .map(|c| Board::collect(&rows, &result.assignments, c))
+        .filter(|b| !b.rows.is_empty())
+        .collect();
+    boards.sort_by_key(|b| std::cmp::Reverse(b.rows.len()));
+
+    let labels = propose_labels(db, text_encoders, encoder_id, &ids, &boards);
+    let clusters: Vec<NewCluster> = boards
+        .iter()
+        .zip(labels)
+        .map(|(b, label)| NewCluster {
+            centroid: b.mean.clone(),
+            medoid_image_id: ids[b.medoid()],
+            label_score: label.as_ref().map(|(_, s)| *s),
+            label: label.map(|(l, _)| l),
+            members: b
+                .rows
+                .iter()
+                .zip(&b.similarities)
+                .map(|(&r, &s)| (ids[r], s))
+                .collect(),
+        })
+        .collect();
+    db.replace_clusters(encoder_id, &clusters)?;
+    info!(
+        "clustered {} images ({encoder_id}) into {} boards in {} iterations",
+        ids.len(),
+        clusters.len(),
+        result.iterations
+    );
+    Ok(db.list_clusters()?)
+}
+
+/// Put every visible image that has an embedding in the boards'
+/// encoder but no board yet into its nearest board, updating that
+/// board's running centroid. No-op while there are no boards. Returns
+/// how many images were assigned.
+pub fn assign_new_images(db: &ImageDatabase) -> Result<usize, ApiError> {
+    let mut boards = db.get_cluster_centroids()?;
+    let Some(encoder_id) = boards.first().map(|b| b.encoder_id.clone()) else {
+        return Ok(0);
+    };
+    let pending = db.get_unclustered_embeddings(&encoder_id)?;
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    // Assign against the centroids as they stood, then fold the batch
+    // in — order-independent, and one batch is small next to a board.
+    let directions: Vec<Vec<f32>> = boards.iter().map(|b| unit(&b.centroid)).collect();
+    let dim = directions[0].len();
+    let mut sums = vec![vec![0.0f32; dim]; boards.len()];
+    let mut added = vec![0i64; boards.len()];
+    let mut assignments = Vec::with_capacity(pending.len());
+    for (image_id, embedding) in pending {
+        if embedding.len() != dim {
+            continue;
+        }
+        let x = unit(&embedding);
+        let (c, similarity) = nearest(&x, &directions);
+        for (s, v) in sums[c].iter_mut().zip(&x) {
+            *s += v;
+        }
+        added[c] += 1;
+        assignments.push((boards[c].id, image_id, similarity));
+        if boards[c].medoid_similarity.is_none_or(|m| similarity > m) {
+            boards[c].medoid_image_id = Some(image_id);
+            boards[c].medoid_similarity = Some(similarity);
+        }
+    }
+
+    let mut updated = Vec::new();
+    for (c, board) in boards.iter_mut().enumerate() {
+        if added[c] > 0 {
+            fold_into_mean(&mut board.centroid, board.member_count, &sums[c], added[c]);
+            board.member_count += added[c];
+            updated.push(board.clone());
+        }
+    }
+    db.add_cluster_members(&assignments, &updated)?;
+    Ok(assignments.len())
+}
+
+/// Spherical k-means: k-means++ seeding on cosine distance, then
+/// Lloyd iterations that assign each row to the centroid it has the
+/// highest cosine with and re-point each centroid along its members'
+/// mean direction. A board left empty is reseeded with the row that
+/// fits its own board worst. `k` must be in 1..=rows.len().
+pub fn kmeans(rows: &EmbeddingMatrix, k: usize, seed: u64) -> KMeans {
+    let n = rows.len();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let unit_row =
+        |i: usize| -> Vec<f32> { rows.row(i).iter().map(|v| v * rows.inv_norm(i)).collect() };
+
+    // k-means++: each next seed is drawn with probability ∝ D², D the
+    // cosine distance to the closest seed so far.
+    let mut centroids = vec![unit_row(rng.random_range(0..n))];
+    let mut closest: Vec<f32> = (0..n).map(|i| cos_to(rows, i, &centroids[0])).collect();
+    while centroids.len() < k {
+        let weights: Vec<f64> = closest
+            .iter()
+            .map(|&c| f64::from(1.0 - c).powi(2))
+            .collect();
+        let total: f64 = weights.iter().sum();
+        let next = if total > 0.0 {
+            let mut target = rng.random::<f64>() * total;
+            weights
+                .iter()
+                .position(|&w| {
+                    target -= w;
+                    target <= 0.0
+                })
+                .unwrap_or(n - 1)
+        } else {
+            // Every row coincides with a seed: duplicates, any will do.
+            rng.random_range(0..n)
+        };
+        let centroid = unit_row(next);
+        for (i, c) in closest.iter_mut().enumerate() {
+            *c = c.max(cos_to(rows, i, &centroid));
+        }
+        centroids.push(centroid);
+    }
+
+    let mut assignments = vec![usize::MAX; n];
+    let mut iterations = 0;
+    while iterations < MAX_ITERATIONS {
+        iterations += 1;
+        let next: Vec<(usize, f32)> = (0..n)
+            .into_par_iter()
+            .map(|i| {
+                let (c, d) = nearest(rows.row(i), &centroids);
+                (c, d * rows.inv_norm(i))
+            })
+            .collect();
+        let changed = next.iter().zip(&assignments).any(|((c, _), a)| c != a);
+        assignments = next.iter().map(|(c, _)| *c).collect();
+        if !changed {
+            break;
+        }
+
+        let mut sums = vec![vec![0.0f32; rows.dim()]; k];
+        let mut counts = vec![0usize; k];
+        for (i, &c) in assignments.iter().enumerate() {
+            let scale = rows.inv_norm(i);
+            for (s, v) in sums[c].iter_mut().zip(rows.row(i)) {
+                *s += v * scale;
+            }
+            counts[c] += 1;
+        }
+        for c in 0..k {
+            if counts[c] > 0 {
+                centroids[c] = unit(&sums[c]);
+                continue;
+            }
+            let worst = (0..n)
+                .filter(|&i| counts[assignments[i]] > 1)
+                .min_by(|&a, &b| next[a].1.total_cmp(&next[b].1));
+            if let Some(i) = worst {
+                counts[assignments[i]] -= 1;
+                assignments[i] = c;
+                counts[c] = 1;
+                centroids[c] = unit_row(i);
+            }
+        }
+    }
+    KMeans {
+        assignments,
+        centroids,
+        iterations,
+    }
+}
+
+/// Pick a name for each board: index into `words` and the raw cosine,
+/// or `None` for a board without a centroid. With two or more boards
+/// the pick is the word scoring highest above its own mean over the
+/// boards; with one it is simply the highest-scoring word.
+pub fn pick_labels(
+    centroids: &[Option<Vec<f32>>],
+    words: &[Vec<f32>],
+) -> Vec<Option<(usize, f32)>> {
+    let scores: Vec<Option<Vec<f32>>> = centroids
+        .iter()
+        .map(|c| {
+            c.as_ref()
+                .map(|c| words.iter().map(|w| dot(c, w)).collect())
+        })
+        .collect();
+    let scored: Vec<&Vec<f32>> = scores.iter().flatten().collect();
+    let baseline: Vec<f32> = if scored.len() >= 2 {
+        (0..words.len())
+            .map(|w| scored.iter().map(|s| s[w]).sum::<f32>() / scored.len() as f32)
+            .collect()
+    } else {
+        vec![0.0; words.len()]
+    };
+    scores
+        .iter()
+        .map(|s| {
+            let s = s.as_ref()?;
+            (0..words.len())
+                .max_by(|&a, &b| (s[a] - baseline[a]).total_cmp(&(s[b] - baseline[b])))
+                .map(|w| (w, s[w]))
+        })
+        .collect()
+}
+
+/// One non-empty board of a k-means result.
+struct Board {
+    /// Row indices of the members.
+    rows: Vec<usize>,
+    /// Mean of the members' unit embeddings.
+    mean: Vec<f32>,
+    /// Each member's cosine to `mean`.
+    similarities: Vec<f32>,
+}
+
+impl Board {
+    fn collect(matrix: &EmbeddingMatrix, assignments: &[usize], c: usize) -> Self {
+        let rows: Vec<usize> = (0..assignments.len())
+            .filter(|&i| assignments[i] == c)
+            .collect();
+        let mut mean = vec![0.0f32; matrix.dim()];
+        for &i in &rows {
+            let scale = matrix.inv_norm(i) / rows.len() as f32;
+            for (m, v) in mean.iter_mut().zip(matrix.row(i)) {
+                *m += v * scale;
+            }
+        }
+        let direction = unit(&mean);
+        let similarities = rows
+            .iter()
+            .map(|&i| cos_to(matrix, i, &direction))
+            .collect();
+        Self {
+            rows,
+            mean,
+            similarities,
+        }
+    }
+
+    /// Row of the member closest to the centroid.
+    fn medoid(&self) -> usize {
+        let best = (0..self.rows.len())
+            .max_by(|&a, &b| self.similarities[a].total_cmp(&self.similarities[b]))
+            .unwrap_or(0);
+        self.rows[best]
+    }
+}
+
+/// Proposed (name, score) per board; all `None` if the text encoder
+/// can't be loaded or there is nothing to score against.
+fn propose_labels(
+    db: &ImageDatabase,
+    text_encoders: &TextEncoderState,
+    encoder_id: &str,
+    ids: &[ID],
+    boards: &[Board],
+) -> Vec<Option<(String, f32)>> {
+    let none = || vec![None; boards.len()];
+    // CLIP and SigLIP-2 share their id with their text branch; any
+    // other encoder is named through CLIP.
+    let text_id = resolve_text_encoder(Some(encoder_id));
+
+    let centroids: Vec<Option<Vec<f32>>> = if text_id == encoder_id {
+        boards.iter().map(|b| Some(unit(&b.mean))).collect()
+    } else {
+        let in_text_space: HashMap<ID, Vec<f32>> = match db.get_all_embeddings_for(text_id) {
+            Ok(rows) => rows.into_iter().map(|(id, _, e)| (id, e)).collect(),
+            Err(e) => {
+                warn!("board labels skipped: {e}");
+                return none();
+            }
+        };
+        boards
+            .iter()
+            .map(|b| {
+                let members: Vec<Vec<f32>> = b
+                    .rows
+                    .iter()
+                    .filter_map(|&r| in_text_space.get(&ids[r]))
+                    .map(|e| unit(e))
+                    .collect();
+                let dim = members.first()?.len();
+                let mut sum = vec![0.0f32; dim];
+                for m in members.iter().filter(|m| m.len() == dim) {
+                    sum.iter_mut().zip(m).for_each(|(s, v)| *s += v);
+                }
+                Some(unit(&sum))
+            })
+            .collect()
+    };
+    if centroids.iter().all(Option::is_none) {
+        return none();
+    }
+
+    let (names, words) = match vocabulary(db, text_encoders, text_id) {
+        Ok(v) if !v.0.is_empty() => v,
+        Ok(_) => return none(),
+        Err(e) => {
+            warn!("board labels skipped: {e}");
+            return none();
+        }
+    };
+    pick_labels(&centroids, &words)
+        .into_iter()
+        .map(|pick| pick.map(|(w, score)| (names[w].clone(), score)))
+        .collect()
+}
+
+/// The naming vocabulary and its embeddings in `text_id`'s space: the
+/// auto-tagging labels (cached embeddings reused) followed by the
+/// `DEFAULT_VOCABULARY` words they don't already cover.
+fn vocabulary(
+    db: &ImageDatabase,
+    text_encoders: &TextEncoderState,
+    text_id: &str,
+) -> Result<(Vec<String>, Vec<Vec<f32>>), ApiError> {
+    let mut names = Vec::new();
+    let mut words = Vec::new();
+    for label in db.list_tag_labels()? {
+        let embedding = match db.get_label_embedding(label.id, text_id)? {
+            Some(e) => e,
+            None => {
+                let e = encode_name(text_encoders, &label.name, &label.templates, text_id)?;
+                db.set_label_embedding(label.id, text_id, &e)?;
+                e
+            }
+        };
+        names.push(label.name);
+        words.push(embedding);
+    }
+    for word in DEFAULT_VOCABULARY {
+        if !names.iter().any(|n| n.eq_ignore_ascii_case(word)) {
+            words.push(encode_name(text_encoders, word, &[], text_id)?);
+            names.push((*word).to_string());
+        }
+    }
+    Ok((names, words))
+}
+
+/// Index and dot product of the centroid closest to `x`.
+fn nearest(x: &[f32], centroids: &[Vec<f32>]) -> (usize, f32) {
+    centroids
+        .iter()
+        .map(|c| dot(x, c))
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((0, 0.0))
+}
+
+/// Cosine of row `i` to the unit vector `direction`.
+fn cos_to(rows: &EmbeddingMatrix, i: usize, direction: &[f32]) -> f32 {
+    dot(rows.row(i), direction) * rows.inv_norm(i)
+}
+
+/// `v` scaled to unit length; the zero vector stays zero.
+fn unit(v: &[f32]) -> Vec<f32> {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter().map(|x| x / norm).collect()
+    } else {
+        v.to_vec()
+    }
+}
+
+/// Running mean: `mean` of `n` items absorbs `m` more whose sum is
+/// `sum`.
+fn fold_into_mean(mean: &mut [f32], n: i64, sum: &[f32], m: i64) {
+    let total = (n + m) as f32;
+    for (x, s) in mean.iter_mut().zip(sum) {
+        *x = (*x * n as f32 + s) / total;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three tight groups of 3-d points around the axes.
+    fn blobs() -> EmbeddingMatrix {
+        let mut rows = EmbeddingMatrix::new();
+        let jitter = [0.0, 0.05, -0.05, 0.1, -0.1];
+        for axis in 0..3 {
+            for (j, &d) in jitter.iter().enumerate() {
+                let mut v = [d, -d, d * 0.5];
+                v[axis] = 1.0 + d;
+                rows.push(PathBuf::from(format!("{axis}-{j}")), &v);
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn kmeans_recovers_separated_groups() {
+        let rows = blobs();
+        let result = kmeans(&rows, 3, SEED);
+        for group in result.assignments.chunks(5) {
+            assert!(
+                group.iter().all(|&c| c == group[0]),
+                "{:?}",
+                result.assignments
+            );
+        }
+        let mut boards: Vec<usize> = result.assignments.iter().step_by(5).copied().collect();
+        boards.sort();
+        boards.dedup();
+        assert_eq!(boards.len(), 3);
+        assert!(result.iterations < MAX_ITERATIONS);
+
+        // Deterministic for a fixed seed.
+        assert_eq!(kmeans(&rows, 3, SEED), result);
+
+        // More boards than groups: none left empty.
+        let result = kmeans(&rows, 5, SEED);
+        for c in 0..5 {
+            assert!(result.assignments.contains(&c), "board {c} empty");
+        }
+    }
+
+    #[test]
+    fn board_medoid_is_closest_to_the_mean() {
+        let rows = blobs();
+        let board = Board::collect(&rows, &[0; 15], 0);
+        assert_eq!(board.rows.len(), 15);
+        let mut lone = EmbeddingMatrix::new();
+        lone.push(PathBuf::from("a"), &[1.0, 0.0]);
+        lone.push(PathBuf::from("b"), &[0.8, 0.6]);
+        lone.push(PathBuf::from("c"), &[0.6, 0.8]);
+        let board = Board::collect(&lone, &[0, 0, 0], 0);
+        assert_eq!(board.medoid(), 1);
+    }
+
+    #[test]
+    fn labels_prefer_words_distinctive_for_the_board() {
+        // Word 0 is close to everything; word 1 fits board A, word 2
+        // board B.
+        let words = vec![
+            unit(&[1.0, 1.0, 1.0]),
+            unit(&[1.0, 0.0, 0.2]),
+            unit(&[0.0, 1.0, 0.2]),
+        ];
+        let centroids = vec![
+            Some(unit(&[1.0, 0.3, 1.0])),
+            Some(unit(&[0.3, 1.0, 1.0])),
+            None,
+        ];
+        let picks = pick_labels(&centroids, &words);
+        assert_eq!(picks[0].map(|p| p.0), Some(1));
+        assert_eq!(picks[1].map(|p| p.0), Some(2));
+        assert_eq!(picks[2], None);
+
+        // A single board just takes the best raw score.
+        let picks = pick_labels(&centroids[..1], &words);
+        assert_eq!(picks[0].map(|p| p.0), Some(0));
+    }
+
+    #[test]
+    fn running_mean_matches_the_batch_mean() {
+        let mut mean = vec![1.0, 0.0];
+        fold_into_mean(&mut mean, 3, &[0.0, 2.0], 1);
+        assert_eq!(mean, vec![0.75, 0.5]);
+        assert_eq!(default_k(1), 1);
+        assert_eq!(default_k(200), 10);
+        assert_eq!(default_k(1_000_000), MAX_CLUSTERS);
+    }
+}
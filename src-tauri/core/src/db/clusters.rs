@@ -0,0 +1,425 @@
+//! Visual boards storage: the clusters of the last clustering run,
+//! each image's board, and the running centroids new images are
+//! assigned against (`clusters.rs` does the clustering).
+//!
+//! A run replaces every board at once. Between runs the indexing
+//! pipeline only appends: an image that gains an embedding in the
+//! boards' encoder joins its nearest board and nudges that board's
+//! centroid (`add_cluster_members`).
+
+use rusqlite::{params, Transaction};
+use serde::Serialize;
+
+use super::journal::Recording;
+use super::tags::find_tag;
+use super::{root_filter, ImageDatabase, JournalOp, RootScope, ID};
+use crate::tag_struct::{Tag, DEFAULT_TAG_COLOR};
+
+/// Visible images only — same filter as the grid.
+fn visible() -> String {
+    format!(
+        "i.orphaned = 0 AND {}",
+        root_filter("i.root_id", RootScope::Enabled)
+    )
+}
+
+/// One board as listed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Cluster {
+    pub id: ID,
+    /// Image encoder whose embeddings were clustered.
+    pub encoder_id: String,
+    /// Proposed name; `None` when no text encoder was available.
+    pub label: Option<String>,
+    /// Cosine of the label's text embedding to the board's centroid.
+    pub label_score: Option<f32>,
+    /// Visible members.
+    pub size: i64,
+    /// The medoid, or the visible member closest to the centroid when
+    /// the medoid is hidden.
+    pub cover_image_id: ID,
+    pub cover_path: String,
+    pub cover_thumbnail_path: Option<String>,
+}
+
+/// One image of a board, with what the grid needs to render it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterMember {
+    pub image_id: ID,
+    pub path: String,
+    pub thumbnail_path: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Cosine to the board's centroid when the image was assigned.
+    pub similarity: f32,
+}
+
+/// A board to store, as a clustering run produced it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewCluster {
+    /// Unnormalised mean of the members' unit embeddings.
+    pub centroid: Vec<f32>,
+    pub medoid_image_id: ID,
+    pub label: Option<String>,
+    pub label_score: Option<f32>,
+    /// (image id, cosine to the centroid).
+    pub members: Vec<(ID, f32)>,
+}
+
+/// A stored board's running state, for incremental assignment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterCentroid {
+    pub id: ID,
+    pub encoder_id: String,
+    /// Unnormalised mean of `member_count` unit embeddings.
+    pub centroid: Vec<f32>,
+    /// Every member ever assigned, hidden ones included — the n of the
+    /// running mean.
+    pub member_count: i64,
+    pub medoid_image_id: Option<ID>,
+    /// The medoid's stored cosine to the centroid.
+    pub medoid_similarity: Option<f32>,
+}
+
+impl ImageDatabase {
+    /// Drop every board and store `clusters` in their place, in one
+    /// transaction. Returns the new ids, in `clusters` order.
+    pub fn replace_clusters(
+        &self,
+        encoder_id: &str,
+        clusters: &[NewCluster],
+    ) -> rusqlite::Result<Vec<ID>> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM clusters", [])?;
+        let mut ids = Vec::with_capacity(clusters.len());
+        {
+            let mut insert_cluster = tx.prepare(
+                "INSERT INTO clusters
+                    (encoder_id, centroid, member_count, medoid_image_id, label, label_score)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            let mut insert_member = tx.prepare(
+                "INSERT OR REPLACE INTO cluster_images (image_id, cluster_id, similarity)
+                 VALUES (?1, ?2, ?3)",
+            )?;
+            for c in clusters {
+                insert_cluster.execute(params![
+                    encoder_id,
+                    bytemuck::cast_slice::<f32, u8>(&c.centroid),
+                    c.members.len() as i64,
+                    c.medoid_image_id,
+                    c.label,
+                    c.label_score.map(f64::from),
+                ])?;
+                let id = tx.last_insert_rowid();
+                for &(image_id, similarity) in &c.members {
+                    insert_member.execute(params![image_id, id, f64::from(similarity)])?;
+                }
+                ids.push(id);
+            }
+        }
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    /// Boards with at least one visible member, largest first.
+    pub fn list_clusters(&self) -> rusqlite::Result<Vec<Cluster>> {
+        self.query_clusters(None)
+    }
+
+    /// One board; `QueryReturnedNoRows` if it doesn't exist or has no
+    /// visible member.
+    pub fn get_cluster(&self, cluster_id: ID) -> rusqlite::Result<Cluster> {
+        self.query_clusters(Some(cluster_id))?
+            .pop()
+            .ok_or(rusqlite::Error::QueryReturnedNoRows)
+    }
+
+    fn query_clusters(&self, cluster_id: Option<ID>) -> rusqlite::Result<Vec<Cluster>> {
+        let conn = self.read_lock();
+        let visible = visible();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT b.id, b.encoder_id, b.label, b.label_score, b.size, b.cover,
+                    cover.path, cover.thumbnail_path
+             FROM (
+                 SELECT c.id, c.encoder_id, c.label, c.label_score, COUNT(*) AS size,
+                        (SELECT m.image_id FROM cluster_images m
+                         JOIN images i ON i.id = m.image_id
+                         WHERE m.cluster_id = c.id AND {visible}
+                         ORDER BY m.image_id IS (SELECT medoid_image_id FROM clusters
+                                                 WHERE id = m.cluster_id) DESC,
+                                  m.similarity DESC
+                         LIMIT 1) AS cover
+                 FROM clusters c
+                 JOIN cluster_images ci ON ci.cluster_id = c.id
+                 JOIN images i ON i.id = ci.image_id
+                 WHERE (?1 IS NULL OR c.id = ?1) AND {visible}
+                 GROUP BY c.id
+             ) b
+             JOIN images cover ON cover.id = b.cover
+             ORDER BY b.size DESC, b.id"
+        ))?;
+        let rows = stmt.query_map([cluster_id], |r| {
+            Ok(Cluster {
+                id: r.get(0)?,
+                encoder_id: r.get(1)?,
+                label: r.get(2)?,
+                label_score: r.get::<_, Option<f64>>(3)?.map(|s| s as f32),
+                size: r.get(4)?,
+                cover_image_id: r.get(5)?,
+                cover_path: r.get(6)?,
+                cover_thumbnail_path: r.get::<_, Option<String>>(7)?.filter(|p| !p.is_empty()),
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// A board's visible images, closest to its centroid first.
+    pub fn get_cluster_members(
+        &self,
+        cluster_id: ID,
+        limit: usize,
+        offset: usize,
+    ) -> rusqlite::Result<Vec<ClusterMember>> {
+        let conn = self.read_lock();
+        let visible = visible();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT i.id, i.path, i.thumbnail_path, i.width, i.height, m.similarity
+             FROM cluster_images m
+             JOIN images i ON i.id = m.image_id
+             WHERE m.cluster_id = ?1 AND {visible}
+             ORDER BY m.similarity DESC, i.id
+             LIMIT ?2 OFFSET ?3"
+        ))?;
+        let rows = stmt.query_map(params![cluster_id, limit as i64, offset as i64], |r| {
+            Ok(ClusterMember {
+                image_id: r.get(0)?,
+                path: r.get(1)?,
+                thumbnail_path: r.get::<_, Option<String>>(2)?.filter(|p| !p.is_empty()),
+                width: r.get::<_, Option<i64>>(3)?.map(|w| w as u32),
+                height: r.get::<_, Option<i64>>(4)?.map(|h| h as u32),
+                similarity: r.get::<_, f64>(5)? as f32,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Every board's running centroid, by id.
+    pub fn get_cluster_centroids(&self) -> rusqlite::Result<Vec<ClusterCentroid>> {
+        let conn = self.read_lock();
+        let mut stmt = conn.prepare(
+            "SELECT c.id, c.encoder_id, c.centroid, c.member_count, c.medoid_image_id,
+                    (SELECT similarity FROM cluster_images
+                     WHERE image_id = c.medoid_image_id AND cluster_id = c.id)
+             FROM clusters c
+             ORDER BY c.id",
+        )?;
+        let rows = stmt.query_map([], |r| {
+            Ok(ClusterCentroid {
+                id: r.get(0)?,
+                encoder_id: r.get(1)?,
+                centroid: bytemuck::pod_collect_to_vec(&r.get::<_, Vec<u8>>(2)?),
+                member_count: r.get(3)?,
+                medoid_image_id: r.get(4)?,
+                medoid_similarity: r.get::<_, Option<f64>>(5)?.map(|s| s as f32),
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Visible images with an `encoder_id` embedding but no board.
+    pub fn get_unclustered_embeddings(
+        &self,
+        encoder_id: &str,
+    ) -> rusqlite::Result<Vec<(ID, Vec<f32>)>> {
+        let conn = self.read_lock();
+        let visible = visible();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT i.id, e.embedding
+             FROM embeddings e
+             JOIN images i ON i.id = e.image_id
+             WHERE e.encoder_id = ?1 AND {visible}
+               AND NOT EXISTS (SELECT 1 FROM cluster_images m WHERE m.image_id = i.id)
+             ORDER BY i.id"
+        ))?;
+        let rows = stmt.query_map([encoder_id], |r| {
+            Ok((
+                r.get(0)?,
+                bytemuck::pod_collect_to_vec(&r.get::<_, Vec<u8>>(1)?),
+            ))
+        })?;
+        rows.collect()
+    }
+
+    /// Record incremental assignments — (board, image, cosine) — and
+    /// the boards' updated running state, in one transaction.
+    pub fn add_cluster_members(
+        &self,
+        assignments: &[(ID, ID, f32)],
+        updated: &[ClusterCentroid],
+    ) -> rusqlite::Result<()> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR REPLACE INTO cluster_images (image_id, cluster_id, similarity)
+                 VALUES (?1, ?2, ?3)",
+            )?;
+            for &(cluster_id, image_id, similarity) in assignments {
+                insert.execute(params![image_id, cluster_id, f64::from(similarity)])?;
+            }
+            let mut update = tx.prepare(
+                "UPDATE clusters SET centroid = ?2, member_count = ?3, medoid_image_id = ?4
+                 WHERE id = ?1",
+            )?;
+            for c in updated {
+                update.execute(params![
+                    c.id,
+                    bytemuck::cast_slice::<f32, u8>(&c.centroid),
+                    c.member_count,
+                    c.medoid_image_id,
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Tag every visible image of a board with the tag called `name`,
+    /// creating it if needed. Returns the tag and how many images
+    /// gained it. Undoable.
+    pub fn tag_cluster(&self, cluster_id: ID, name: &str) -> rusqlite::Result<(Tag, usize)> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut recording = Recording::default();
+        let tag_id = recording.tag_named(&tx, name, DEFAULT_TAG_COLOR)?;
+        let members = visible_members(&tx, cluster_id)?;
+        let tagged = match recording.apply(
+            &tx,
+            JournalOp::TagImages {
+                tag_id,
+                image_ids: members,
+            },
+        )? {
+            Some(JournalOp::TagImages { image_ids, .. }) => image_ids.len(),
+            _ => 0,
+        };
+        recording.record(&tx, &format!("Tag board as \"{name}\""))?;
+        let tag = find_tag(&tx, tag_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        tx.commit()?;
+        Ok((tag, tagged))
+    }
+}
+
+/// A board's visible images, by id.
+fn visible_members(tx: &Transaction<'_>, cluster_id: ID) -> rusqlite::Result<Vec<ID>> {
+    let visible = visible();
+    let mut stmt = tx.prepare(&format!(
+        "SELECT i.id FROM cluster_images m
+         JOIN images i ON i.id = m.image_id
+         WHERE m.cluster_id = ?1 AND {visible}
+         ORDER BY i.id"
+    ))?;
+    let ids = stmt.query_map([cluster_id], |r| r.get(0))?.collect();
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::fresh_db;
+    use super::*;
+
+    fn board(members: &[(ID, f32)], label: Option<&str>) -> NewCluster {
+        NewCluster {
+            centroid: vec![1.0, 0.0],
+            medoid_image_id: members[0].0,
+            label: label.map(str::to_string),
+            label_score: label.map(|_| 0.3),
+            members: members.to_vec(),
+        }
+    }
+
+    #[test]
+    fn boards_round_trip_and_hide_orphans() {
+        let db = fresh_db();
+        for name in ["a", "b", "c", "d"] {
+            db.add_image(format!("/lib/{name}.jpg"), None).unwrap();
+        }
+        let ids = db
+            .replace_clusters(
+                "dinov2_base",
+                &[
+                    board(&[(1, 0.9)], Some("beach")),
+                    board(&[(2, 0.95), (3, 0.8), (4, 0.7)], None),
+                ],
+            )
+            .unwrap();
+
+        let boards = db.list_clusters().unwrap();
+        assert_eq!(
+            boards.iter().map(|b| b.id).collect::<Vec<_>>(),
+            vec![ids[1], ids[0]]
+        );
+        assert_eq!((boards[0].size, boards[0].cover_image_id), (3, 2));
+        assert_eq!(boards[1].label.as_deref(), Some("beach"));
+
+        // A hidden medoid hands the cover to the next-closest member.
+        db.mark_images_orphaned(&[2]).unwrap();
+        let b = db.get_cluster(ids[1]).unwrap();
+        assert_eq!((b.size, b.cover_image_id), (2, 3));
+        let members: Vec<ID> = db
+            .get_cluster_members(ids[1], 10, 0)
+            .unwrap()
+            .iter()
+            .map(|m| m.image_id)
+            .collect();
+        assert_eq!(members, vec![3, 4]);
+
+        // A board with nothing visible drops out of the list.
+        db.mark_images_orphaned(&[1]).unwrap();
+        assert!(matches!(
+            db.get_cluster(ids[0]),
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        ));
+
+        let (tag, tagged) = db.tag_cluster(ids[1], "sunsets").unwrap();
+        assert_eq!((tag.name.as_str(), tagged), ("sunsets", 2));
+        assert_eq!(db.get_image_ids_with_tag(tag.id).unwrap(), vec![3, 4]);
+
+        // Re-clustering replaces everything.
+        db.replace_clusters("dinov2_base", &[]).unwrap();
+        assert!(db.list_clusters().unwrap().is_empty());
+    }
+
+    #[test]
+    fn unclustered_images_join_incrementally() {
+        let db = fresh_db();
+        for id in 1..=3 {
+            db.add_image(format!("/lib/{id}.jpg"), None).unwrap();
+            db.upsert_embedding(id, "dinov2_base", &[1.0, 0.0]).unwrap();
+        }
+        let ids = db
+            .replace_clusters("dinov2_base", &[board(&[(1, 0.9)], None)])
+            .unwrap();
+        let pending: Vec<ID> = db
+            .get_unclustered_embeddings("dinov2_base")
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(pending, vec![2, 3]);
+
+        let mut c = db.get_cluster_centroids().unwrap().remove(0);
+        assert_eq!((c.member_count, c.medoid_similarity), (1, Some(0.9)));
+        c.centroid = vec![0.5, 0.5];
+        c.member_count = 2;
+        db.add_cluster_members(&[(ids[0], 2, 0.99)], std::slice::from_ref(&c))
+            .unwrap();
+        let stored = db.get_cluster_centroids().unwrap().remove(0);
+        assert_eq!((stored.centroid, stored.member_count), (vec![0.5, 0.5], 2));
+        assert_eq!(
+            db.get_unclustered_embeddings("dinov2_base").unwrap().len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! Content-hash identity + move/rename reconciliation.
+//!
+//! `images.path` is still the UNIQUE key the scan inserts by, so a file
+//! that moves (folder renamed, photo dragged into a subfolder) first
+//! shows up as a brand-new row while the old row is flagged orphaned by
+//! `mark_orphaned`. Left alone, that loses every tag, note and
+//! embedding attached to the old row and re-encodes the file from
+//! scratch.
+//!
+//! The fix is a content fingerprint (`content_hash` = BLAKE3 hex,
+//! `file_size`, plus `file_mtime` for the skip-if-unchanged check)
+//! computed during the scan, and a reconcile step that runs right after
+//! orphan detection: an orphaned row and a freshly-scanned row with the
+//! same hash + size are the same file. The OLD row is moved onto the
+//! new path and the new row is deleted, so the image keeps its id —
+//! and with it `images_tags`, `embeddings`, notes and the id-keyed
+//! thumbnail — without copying anything.
+
+use std::collections::HashMap;
+
+use rusqlite::{params, OptionalExtension};
+
+use super::{root_filter, ID, ImageDatabase, RootScope};
+use crate::filesystem::FileFingerprint;
+
+/// What the DB currently knows about one image's file, keyed by path
+/// in `get_content_fingerprints`. The fingerprint columns are NULL for
+/// rows the pipeline hasn't hashed yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredFingerprint {
+    pub id: ID,
+    pub hash: Option<String>,
+    pub size: Option<i64>,
+    pub mtime: Option<i64>,
+}
+
+impl StoredFingerprint {
+    /// Whether the file needs (re-)hashing given its current `stat`.
+    pub fn is_stale(&self, size: i64, mtime: i64) -> bool {
+        self.hash.is_none() || self.size != Some(size) || self.mtime != Some(mtime)
+    }
+}
+
+impl ImageDatabase {
+    /// Every image's stored fingerprint, keyed by path. One SELECT, the
+    /// pipeline diffs against the scan in memory (same shape as
+    /// `get_paths_to_root_ids`).
+    pub fn get_content_fingerprints(
+        &self,
+    ) -> rusqlite::Result<HashMap<String, StoredFingerprint>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, path, content_hash, file_size, file_mtime FROM images",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(1)?,
+                StoredFingerprint {
+                    id: row.get(0)?,
+                    hash: row.get(2)?,
+                    size: row.get(3)?,
+                    mtime: row.get(4)?,
+                },
+            ))
+        })?;
+        rows.collect()
+    }
+
+    /// Stored fingerprint for a single path, or `None` when the path
+    /// isn't catalogued. The watcher's per-file counterpart of
+    /// `get_content_fingerprints`.
+    pub fn get_content_fingerprint(
+        &self,
+        path: &str,
+    ) -> rusqlite::Result<Option<StoredFingerprint>> {
+        let conn = self.connection.lock().unwrap();
+        conn.query_row(
+            "SELECT id, content_hash, file_size, file_mtime FROM images WHERE path = ?1",
+            [path],
+            |row| {
+                Ok(StoredFingerprint {
+                    id: row.get(0)?,
+                    hash: row.get(1)?,
+                    size: row.get(2)?,
+                    mtime: row.get(3)?,
+                })
+            },
+        )
+        .optional()
+    }
+
+    /// The file behind `image_id` was rewritten in place: drop what was
+    /// derived from the old bytes — every encoder's embedding and the
+    /// thumbnail pointer — so the thumbnail and encoder phases pick the
+    /// row up again. Tags and notes are the user's and stay.
+    pub fn invalidate_derived_data(&self, image_id: ID) -> rusqlite::Result<()> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM embeddings WHERE image_id = ?1", [image_id])?;
+        tx.execute(
+            "UPDATE images SET embedding = NULL, thumbnail_path = NULL WHERE id = ?1",
+            [image_id],
+        )?;
+        tx.commit()
+    }
+
+    /// Write freshly computed fingerprints in one transaction.
+    pub fn set_content_fingerprints(
+        &self,
+        rows: &[(ID, FileFingerprint)],
+    ) -> rusqlite::Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "UPDATE images SET content_hash = ?2, file_size = ?3, file_mtime = ?4
+                 WHERE id = ?1",
+            )?;
+            for (id, fp) in rows {
+                stmt.execute(params![id, fp.hash, fp.size, fp.mtime])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Re-link orphaned rows to newly-seen paths with identical content.
+    ///
+    /// A "newly-seen" row is a live (non-orphaned) row with the same
+    /// hash + size that carries nothing of its own yet: no tags, no
+    /// notes, no embeddings. That's exactly the state `add_image`
+    /// leaves a moved file in, and it keeps a genuine, already-indexed
+    /// duplicate copy from being swallowed. When several orphans share
+    /// a hash (the user had duplicates, then moved them all) they pair
+    /// with candidates in id order, one-to-one.
+    ///
+    /// For each pair the new row is deleted and the orphan takes over
+    /// its path, root and mtime. If the file crossed roots the
+    /// thumbnail path is cleared so it regenerates under the new
+    /// root's thumbnail folder (the old folder goes away with
+    /// `remove_root`). Orphans of a removed root are left alone; they
+    /// come back with the root.
+    ///
+    /// Runs in one transaction. Returns the ids of the new rows folded
+    /// into their orphans (and deleted), one per re-linked pair.
+    pub fn reconcile_moved_images(&self) -> rusqlite::Result<Vec<ID>> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let orphans: Vec<(ID, String, i64)> = {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT id, content_hash, file_size FROM images
+                 WHERE orphaned = 1 AND content_hash IS NOT NULL AND file_size IS NOT NULL
+                   AND {}
+                 ORDER BY id",
+                root_filter("root_id", RootScope::Live)
+            ))?;
+            let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        let mut groups: HashMap<(String, i64), Vec<ID>> = HashMap::new();
+        for (id, hash, size) in orphans {
+            groups.entry((hash, size)).or_default().push(id);
+        }
+
+        let mut folded = Vec::new();
+        {
+            let mut candidates_stmt = tx.prepare(
+                "SELECT n.id, n.path, n.root_id, n.file_mtime FROM images n
+                 WHERE n.orphaned = 0 AND n.content_hash = ?1 AND n.file_size = ?2
+                   AND n.notes IS NULL AND n.embedding IS NULL
+                   AND NOT EXISTS (SELECT 1 FROM images_tags WHERE image_id = n.id)
+                   AND NOT EXISTS (SELECT 1 FROM embeddings WHERE image_id = n.id)
+                 ORDER BY n.id",
+            )?;
+            let mut delete_stmt = tx.prepare("DELETE FROM images WHERE id = ?1")?;
+            let mut relink_stmt = tx.prepare(
+                "UPDATE images
+                 SET path = ?2, root_id = ?3, file_mtime = ?4, orphaned = 0,
+                     thumbnail_path = CASE WHEN root_id IS ?3 THEN thumbnail_path END
+                 WHERE id = ?1",
+            )?;
+
+            let mut keys: Vec<_> = groups.keys().cloned().collect();
+            keys.sort();
+            for key in keys {
+                let orphans = &groups[&key];
+                let candidates: Vec<(ID, String, Option<ID>, Option<i64>)> = candidates_stmt
+                    .query_map(params![key.0, key.1], |r| {
+                        Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))
+                    })?
+                    .collect::<rusqlite::Result<_>>()?;
+
+                for (old_id, (new_id, new_path, new_root, new_mtime)) in
+                    orphans.iter().zip(candidates)
+                {
+                    delete_stmt.execute([new_id])?;
+                    relink_stmt.execute(params![old_id, new_path, new_root, new_mtime])?;
+                    folded.push(new_id);
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(folded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::fresh_db;
+    use super::*;
+
+    fn fp(hash: &str, size: i64) -> FileFingerprint {
+        FileFingerprint {
+            hash: hash.into(),
+            size,
+            mtime: 1,
+        }
+    }
+
+    /// Insert `path` into `root` and fingerprint it.
+    fn add_hashed(db: &ImageDatabase, path: &str, root: ID, hash: &str) -> ID {
+        db.add_image(path.into(), Some(root)).unwrap();
+        let id = db.get_image_id_by_path(path).unwrap();
+        db.set_content_fingerprints(&[(id, fp(hash, 100))]).unwrap();
+        id
+    }
+
+    fn row(db: &ImageDatabase, id: ID) -> Option<(String, i64)> {
+        db.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT path, orphaned FROM images WHERE id = ?1",
+                [id],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .ok()
+    }
+
+    #[test]
+    fn stale_check_compares_size_and_mtime() {
+        let stored = StoredFingerprint {
+            id: 1,
+            hash: Some("h".into()),
+            size: Some(10),
+            mtime: Some(5),
+        };
+        assert!(!stored.is_stale(10, 5));
+        assert!(stored.is_stale(11, 5));
+        assert!(stored.is_stale(10, 6));
+        assert!(StoredFingerprint { hash: None, ..stored }.is_stale(10, 5));
+    }
+
+    #[test]
+    fn single_path_lookup_matches_bulk_lookup() {
+        let db = fresh_db();
+        let root = db.add_root("/r".into()).unwrap().id;
+        add_hashed(&db, "/r/a.jpg", root, "aaa");
+        db.add_image("/r/b.jpg".into(), Some(root)).unwrap();
+
+        let all = db.get_content_fingerprints().unwrap();
+        for path in ["/r/a.jpg", "/r/b.jpg"] {
+            assert_eq!(db.get_content_fingerprint(path).unwrap().as_ref(), all.get(path));
+        }
+        assert_eq!(db.get_content_fingerprint("/r/missing.jpg").unwrap(), None);
+    }
+
+    #[test]
+    fn invalidate_derived_data_keeps_user_data() {
+        let db = fresh_db();
+        let root = db.add_root("/r".into()).unwrap().id;
+        let id = add_hashed(&db, "/r/a.jpg", root, "aaa");
+        let tag = db.create_tag("keep".into(), "#fff".into()).unwrap().id;
+        db.add_tag_to_image(id, tag).unwrap();
+        db.set_image_notes(id, "hello").unwrap();
+        db.upsert_embedding(id, "dinov2_base", &[0.5, 0.5]).unwrap();
+        db.update_image_thumbnail(id, std::path::Path::new("/t/1.jpg"), 10, 10)
+            .unwrap();
+
+        db.invalidate_derived_data(id).unwrap();
+
+        assert!(db.get_embedding(id, "dinov2_base").is_err());
+        assert_eq!(db.get_image_thumbnail_info(id).unwrap(), None);
+        assert_eq!(db.get_image_notes(id).unwrap().as_deref(), Some("hello"));
+        let tagged = db.get_images_with_thumbnails(vec![tag], "".into(), false).unwrap();
+        assert_eq!(tagged.len(), 1);
+        let needs_thumb = db.get_images_without_thumbnails().unwrap();
+        assert_eq!(needs_thumb.iter().map(|i| i.id).collect::<Vec<_>>(), vec![id]);
+    }
+
+    #[test]
+    fn moved_file_keeps_id_tags_notes_and_embeddings() {
+        let db = fresh_db();
+        let root = db.add_root("/r".into()).unwrap().id;
+        let old = add_hashed(&db, "/r/a.jpg", root, "aaa");
+        let tag = db.create_tag("keep".into(), "#fff".into()).unwrap().id;
+        db.add_tag_to_image(old, tag).unwrap();
+        db.set_image_notes(old, "hello").unwrap();
+        db.upsert_embedding(old, "dinov2_base", &[0.5, 0.5]).unwrap();
+
+        // Scan after the move: new path inserted + hashed, old orphaned.
+        let new = add_hashed(&db, "/r/sub/a.jpg", root, "aaa");
+        db.mark_orphaned(root, &["/r/sub/a.jpg".to_string()]).unwrap();
+
+        assert_eq!(db.reconcile_moved_images().unwrap().len(), 1);
+        assert_eq!(row(&db, old), Some(("/r/sub/a.jpg".into(), 0)));
+        assert_eq!(row(&db, new), None);
+        assert_eq!(db.get_image_notes(old).unwrap().as_deref(), Some("hello"));
+        assert_eq!(db.get_embedding(old, "dinov2_base").unwrap(), vec![0.5, 0.5]);
+        let imgs = db.get_images_with_thumbnails(vec![tag], "".into(), false).unwrap();
+        assert_eq!(imgs.len(), 1);
+        assert_eq!(imgs[0].id, old);
+    }
+
+    #[test]
+    fn cross_root_move_clears_thumbnail_only() {
+        let db = fresh_db();
+        let r1 = db.add_root("/r1".into()).unwrap().id;
+        let r2 = db.add_root("/r2".into()).unwrap().id;
+        let old = add_hashed(&db, "/r1/a.jpg", r1, "aaa");
+        let stay = add_hashed(&db, "/r1/b.jpg", r1, "bbb");
+        db.update_image_thumbnail(old, std::path::Path::new("/t/1.jpg"), 10, 10)
+            .unwrap();
+        add_hashed(&db, "/r2/a.jpg", r2, "aaa");
+        db.mark_orphaned(r1, &["/r1/b.jpg".to_string()]).unwrap();
+
+        assert_eq!(db.reconcile_moved_images().unwrap().len(), 1);
+        assert_eq!(row(&db, old), Some(("/r2/a.jpg".into(), 0)));
+        assert_eq!(row(&db, stay), Some(("/r1/b.jpg".into(), 0)));
+        let thumb: Option<String> = db
+            .connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT thumbnail_path FROM images WHERE id = ?1", [old], |r| r.get(0))
+            .unwrap();
+        assert_eq!(thumb, None);
+    }
+
+    #[test]
+    fn indexed_duplicate_is_not_swallowed() {
+        // The orphan's content also exists as an already-tagged copy:
+        // that copy is a real, separate image, not a move target.
+        let db = fresh_db();
+        let root = db.add_root("/r".into()).unwrap().id;
+        let old = add_hashed(&db, "/r/a.jpg", root, "aaa");
+        let copy = add_hashed(&db, "/r/copy.jpg", root, "aaa");
+        let tag = db.create_tag("t".into(), "#fff".into()).unwrap().id;
+        db.add_tag_to_image(copy, tag).unwrap();
+        db.mark_orphaned(root, &["/r/copy.jpg".to_string()]).unwrap();
+
+        assert_eq!(db.reconcile_moved_images().unwrap().len(), 0);
+        assert_eq!(row(&db, old), Some(("/r/a.jpg".into(), 1)));
+        assert_eq!(row(&db, copy), Some(("/r/copy.jpg".into(), 0)));
+    }
+
+    #[test]
+    fn duplicates_pair_one_to_one_and_different_content_is_ignored() {
+        let db = fresh_db();
+        let root = db.add_root("/r".into()).unwrap().id;
+        let o1 = add_hashed(&db, "/r/x1.jpg", root, "dup");
+        let o2 = add_hashed(&db, "/r/x2.jpg", root, "dup");
+        let o3 = add_hashed(&db, "/r/y.jpg", root, "other");
+        add_hashed(&db, "/r/moved/x1.jpg", root, "dup");
+        add_hashed(&db, "/r/moved/z.jpg", root, "unrelated");
+        db.mark_orphaned(
+            root,
+            &["/r/moved/x1.jpg".to_string(), "/r/moved/z.jpg".to_string()],
+        )
+        .unwrap();
+
+        assert_eq!(db.reconcile_moved_images().unwrap().len(), 1);
+        assert_eq!(row(&db, o1), Some(("/r/moved/x1.jpg".into(), 0)));
+        assert_eq!(row(&db, o2).map(|r| r.1), Some(1));
+        assert_eq!(row(&db, o3).map(|r| r.1), Some(1));
+    }
+}
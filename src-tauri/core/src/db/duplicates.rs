@@ -0,0 +1,123 @@
+//! Rows the duplicate finder (`duplicates.rs`) works from: each visible
+//! image's file fingerprint, original dimensions and thumbnail dHash.
+//!
+//! The perceptual hash is filled in lazily — `set_perceptual_hashes`
+//! after the finder computes the missing ones from thumbnails — and
+//! cleared by `update_image_thumbnail` when a thumbnail is regenerated.
+
+use super::{root_filter, ImageDatabase, RootScope, ID};
+
+/// One image as the duplicate finder sees it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateCandidate {
+    pub id: ID,
+    pub path: String,
+    pub thumbnail_path: Option<String>,
+    /// Original dimensions (NULL until the image is thumbnailed).
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub file_size: Option<i64>,
+    /// BLAKE3 hex of the file (`content_hash.rs`); NULL until hashed.
+    pub content_hash: Option<String>,
+    /// Thumbnail dHash (`thumbnail::phash`); NULL until computed.
+    pub perceptual_hash: Option<u64>,
+}
+
+impl ImageDatabase {
+    /// Every image that isn't orphaned or under a disabled root, by id.
+    pub fn get_duplicate_candidates(&self) -> rusqlite::Result<Vec<DuplicateCandidate>> {
+        let conn = self.read_lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, path, thumbnail_path, width, height, file_size, content_hash,
+                    perceptual_hash
+             FROM images
+             WHERE orphaned = 0 AND {}
+             ORDER BY id",
+            root_filter("root_id", RootScope::Enabled)
+        ))?;
+        let rows = stmt.query_map([], |row| {
+            Ok(DuplicateCandidate {
+                id: row.get(0)?,
+                path: row.get(1)?,
+                thumbnail_path: row.get::<_, Option<String>>(2)?.filter(|p| !p.is_empty()),
+                width: row.get::<_, Option<i64>>(3)?.map(|w| w as u32),
+                height: row.get::<_, Option<i64>>(4)?.map(|h| h as u32),
+                file_size: row.get(5)?,
+                content_hash: row.get(6)?,
+                perceptual_hash: row.get::<_, Option<i64>>(7)?.map(|h| h as u64),
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Store computed thumbnail hashes, one transaction for the batch.
+    pub fn set_perceptual_hashes(&self, hashes: &[(ID, u64)]) -> rusqlite::Result<()> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare("UPDATE images SET perceptual_hash = ?1 WHERE id = ?2")?;
+            for (id, hash) in hashes {
+                stmt.execute(rusqlite::params![*hash as i64, id])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Flag specific images orphaned — their files were deleted by the
+    /// app rather than noticed missing by a scan. Like scan-detected
+    /// orphans, a file that comes back (restored from the trash) is
+    /// un-orphaned by the next scan with its tags and notes intact.
+    pub fn mark_images_orphaned(&self, image_ids: &[ID]) -> rusqlite::Result<usize> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut updated = 0;
+        {
+            let mut stmt = tx.prepare("UPDATE images SET orphaned = 1 WHERE id = ?1")?;
+            for id in image_ids {
+                updated += stmt.execute([id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::fresh_db;
+
+    #[test]
+    fn hashes_round_trip_and_orphans_drop_out() {
+        let db = fresh_db();
+        db.add_image("/lib/a.jpg".into(), None).unwrap();
+        db.add_image("/lib/b.jpg".into(), None).unwrap();
+        let (a, b) = (1, 2);
+        db.update_image_thumbnail(a, std::path::Path::new("/t/a.jpg"), 640, 480)
+            .unwrap();
+
+        // High bit set: stored as a negative INTEGER, read back intact.
+        db.set_perceptual_hashes(&[(a, u64::MAX - 1)]).unwrap();
+        let rows = db.get_duplicate_candidates().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].perceptual_hash, Some(u64::MAX - 1));
+        assert_eq!((rows[0].width, rows[0].height), (Some(640), Some(480)));
+        assert_eq!(rows[1].thumbnail_path, None);
+
+        // A regenerated thumbnail invalidates the hash.
+        db.update_image_thumbnail(a, std::path::Path::new("/t/a.jpg"), 640, 480)
+            .unwrap();
+        assert_eq!(
+            db.get_duplicate_candidates().unwrap()[0].perceptual_hash,
+            None
+        );
+
+        assert_eq!(db.mark_images_orphaned(&[b]).unwrap(), 1);
+        let ids: Vec<_> = db
+            .get_duplicate_candidates()
+            .unwrap()
+            .iter()
+            .map(|c| c.id)
+            .collect();
+        assert_eq!(ids, vec![a]);
+    }
+}
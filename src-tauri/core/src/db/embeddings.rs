@@ -5,7 +5,16 @@
 //! safely — the `Pod` marker on f32 proves the conversion at compile
 //! time, replacing the previous `unsafe slice::from_raw_parts` blocks.
 
-use super::{ID, ImageDatabase};
+use super::{root_filter, ID, ImageDatabase, RootScope};
+
+/// Fingerprint of one encoder's visible embedding set — see
+/// `ImageDatabase::embedding_set_stamp`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EmbeddingSetStamp {
+    pub count: u64,
+    /// BLAKE3 over (embeddings rowid, image path) in rowid order.
+    pub digest: [u8; 32],
+}
 
 impl ImageDatabase {
     // update the embedding of an image
@@ -108,16 +117,14 @@ impl ImageDatabase {
         // cache happily returns them. `root_id IS NULL` is preserved
         // for legacy pre-multi-folder rows (same exception the grid
         // makes).
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare(&format!(
             "SELECT id, path, embedding FROM images
              WHERE embedding IS NOT NULL
                AND length(embedding) > 0
                AND orphaned = 0
-               AND (
-                   root_id IS NULL
-                   OR root_id IN (SELECT id FROM roots WHERE enabled = 1)
-               )",
-        )?;
+               AND {}",
+            root_filter("root_id", RootScope::Enabled)
+        ))?;
         let rows = stmt.query_map([], |row| {
             let id: ID = row.get(0)?;
             let path: String = row.get(1)?;
@@ -276,17 +283,15 @@ impl ImageDatabase {
         // cosine cache must not return images whose root the user has
         // toggled off (or whose file disappeared from disk). The JOIN
         // on `images` is already here, so we just gate the WHERE clause.
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare(&format!(
             "SELECT i.id, i.path, e.embedding
              FROM embeddings e
              JOIN images i ON i.id = e.image_id
              WHERE e.encoder_id = ?1
                AND i.orphaned = 0
-               AND (
-                   i.root_id IS NULL
-                   OR i.root_id IN (SELECT id FROM roots WHERE enabled = 1)
-               )",
-        )?;
+               AND {}",
+            root_filter("i.root_id", RootScope::Enabled)
+        ))?;
         let rows = stmt.query_map(rusqlite::params![encoder_id], |row| {
             Ok((
                 row.get::<_, ID>(0)?,
@@ -308,32 +313,147 @@ impl ImageDatabase {
         Ok(out)
     }
 
+    /// Streaming variant of `get_all_embeddings_for`: hands each
+    /// visible (path, embedding) to `f` without collecting them, so a
+    /// caller that only keeps a compact form of each row (the quantised
+    /// caches in `cosine::quant`) never holds the full-precision set.
+    ///
+    /// `sample = Some(n)` visits at most `n` rows picked at random
+    /// instead of all of them — used to fit quantisation parameters.
+    pub fn for_each_embedding_for(
+        &self,
+        encoder_id: &str,
+        sample: Option<usize>,
+        mut f: impl FnMut(&str, &[f32]),
+    ) -> rusqlite::Result<()> {
+        let conn = self.read_lock();
+        let mut sql = format!(
+            "SELECT i.path, e.embedding
+             FROM embeddings e
+             JOIN images i ON i.id = e.image_id
+             WHERE e.encoder_id = ?1
+               AND i.orphaned = 0
+               AND {}",
+            root_filter("i.root_id", RootScope::Enabled)
+        );
+        if let Some(n) = sample {
+            sql.push_str(&format!(" ORDER BY random() LIMIT {n}"));
+        }
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(rusqlite::params![encoder_id])?;
+        while let Some(row) = rows.next()? {
+            let path = row.get_ref(0)?.as_str()?;
+            let bytes = row.get_ref(1)?.as_blob()?;
+            // BLOB buffers carry no alignment guarantee, so decode
+            // through `pod_collect_to_vec` rather than a borrowed cast.
+            if bytes.is_empty() || bytes.len() % std::mem::size_of::<f32>() != 0 {
+                continue;
+            }
+            let embedding: Vec<f32> = bytemuck::pod_collect_to_vec(bytes);
+            f(path, &embedding);
+        }
+        Ok(())
+    }
+
+    /// Full-precision embeddings for a handful of paths — the re-rank
+    /// step of a quantised query. Paths with no row for `encoder_id`
+    /// (deleted or re-encoded since the codes were built) are absent
+    /// from the result.
+    pub fn get_embeddings_for_paths(
+        &self,
+        encoder_id: &str,
+        paths: &[&str],
+    ) -> rusqlite::Result<Vec<(String, Vec<f32>)>> {
+        let conn = self.read_lock();
+        let mut stmt = conn.prepare_cached(
+            "SELECT e.embedding
+             FROM embeddings e
+             JOIN images i ON i.id = e.image_id
+             WHERE i.path = ?1 AND e.encoder_id = ?2",
+        )?;
+        let mut out = Vec::with_capacity(paths.len());
+        for path in paths {
+            let mut rows = stmt.query(rusqlite::params![path, encoder_id])?;
+            if let Some(row) = rows.next()? {
+                let bytes = row.get_ref(0)?.as_blob()?;
+                if bytes.is_empty() || bytes.len() % std::mem::size_of::<f32>() != 0 {
+                    continue;
+                }
+                out.push(((*path).to_string(), bytemuck::pod_collect_to_vec(bytes)));
+            }
+        }
+        Ok(out)
+    }
+
     /// Return image rows that don't yet have an embedding for the given
     /// encoder. Used by the indexing pipeline to drive the per-encoder
     /// encode loop.
     ///
     /// Returns a Vec of (image_id, path) — the encoder will preprocess
     /// the path and write the result back via `upsert_embedding`.
+    /// Images of an offline root are left out; their files are out of
+    /// reach until the folder is back.
     pub fn get_images_without_embedding_for(
         &self,
         encoder_id: &str,
     ) -> rusqlite::Result<Vec<(ID, String)>> {
         let conn = self.connection.lock().unwrap();
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare(&format!(
             "SELECT i.id, i.path
              FROM images i
              WHERE i.orphaned = 0
+             AND {}
              AND NOT EXISTS (
                  SELECT 1 FROM embeddings e
                  WHERE e.image_id = i.id AND e.encoder_id = ?1
              )",
-        )?;
+            root_filter("i.root_id", RootScope::Online)
+        ))?;
         let rows = stmt.query_map(rusqlite::params![encoder_id], |row| {
             Ok((row.get::<_, ID>(0)?, row.get::<_, String>(1)?))
         })?;
         rows.collect()
     }
 
+    /// Identity of the embedding set `get_all_embeddings_for` would
+    /// return right now, without reading a single BLOB. Stored in the
+    /// header of each on-disk cosine cache; a cache is only trusted
+    /// while the stamp still matches.
+    ///
+    /// The digest covers every visible row's embeddings rowid and image
+    /// path, so it moves when an embedding is (re)written — `INSERT OR
+    /// REPLACE` assigns a new rowid — when a row enters or leaves the
+    /// enabled/non-orphaned set, and when a moved image is re-linked to
+    /// a new path. Tag and note edits don't touch it.
+    pub fn embedding_set_stamp(&self, encoder_id: &str) -> rusqlite::Result<EmbeddingSetStamp> {
+        let conn = self.read_lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT e.rowid, i.path
+             FROM embeddings e
+             JOIN images i ON i.id = e.image_id
+             WHERE e.encoder_id = ?1
+               AND i.orphaned = 0
+               AND {}
+             ORDER BY e.rowid",
+            root_filter("i.root_id", RootScope::Enabled)
+        ))?;
+        let mut rows = stmt.query(rusqlite::params![encoder_id])?;
+        let mut hasher = blake3::Hasher::new();
+        let mut count = 0u64;
+        while let Some(row) = rows.next()? {
+            let rowid: i64 = row.get(0)?;
+            let path: String = row.get(1)?;
+            hasher.update(&rowid.to_le_bytes());
+            hasher.update(&(path.len() as u64).to_le_bytes());
+            hasher.update(path.as_bytes());
+            count += 1;
+        }
+        Ok(EmbeddingSetStamp {
+            count,
+            digest: *hasher.finalize().as_bytes(),
+        })
+    }
+
     /// Count embeddings per encoder. Used by `get_pipeline_stats` to
     /// surface "X images encoded with SigLIP-2, Y with DINOv2".
     pub fn count_embeddings_for(&self, encoder_id: &str) -> rusqlite::Result<i64> {
@@ -644,4 +764,85 @@ mod tests {
         assert_eq!(rows.len(), 1);
         assert_eq!(rows[0].1, "/kept/a.jpg");
     }
+
+    #[test]
+    fn embedding_set_stamp_tracks_rewrites_visibility_and_moves() {
+        let db = fresh_db();
+        let root = db.add_root("/r".into()).unwrap();
+        db.add_image("/r/a.jpg".into(), Some(root.id)).unwrap();
+        db.add_image("/r/b.jpg".into(), Some(root.id)).unwrap();
+        let a = db.get_image_id_by_path("/r/a.jpg").unwrap();
+        let b = db.get_image_id_by_path("/r/b.jpg").unwrap();
+        db.upsert_embedding(a, "clip_vit_b_32", &[1.0, 0.0]).unwrap();
+        db.upsert_embedding(b, "clip_vit_b_32", &[0.0, 1.0]).unwrap();
+
+        let base = db.embedding_set_stamp("clip_vit_b_32").unwrap();
+        assert_eq!(base.count, 2);
+        assert_eq!(db.embedding_set_stamp("clip_vit_b_32").unwrap(), base, "stable");
+        assert_eq!(db.embedding_set_stamp("dinov2_base").unwrap().count, 0);
+
+        // Unrelated writes leave it alone.
+        db.set_image_notes(a, "note").unwrap();
+        assert_eq!(db.embedding_set_stamp("clip_vit_b_32").unwrap(), base);
+
+        // Re-encoding a row moves it.
+        db.upsert_embedding(b, "clip_vit_b_32", &[0.0, 2.0]).unwrap();
+        let rewritten = db.embedding_set_stamp("clip_vit_b_32").unwrap();
+        assert_ne!(rewritten, base);
+
+        // So does a root toggle...
+        db.set_root_enabled(root.id, false).unwrap();
+        assert_eq!(db.embedding_set_stamp("clip_vit_b_32").unwrap().count, 0);
+        db.set_root_enabled(root.id, true).unwrap();
+        assert_eq!(db.embedding_set_stamp("clip_vit_b_32").unwrap(), rewritten);
+
+        // ...and a path change with the embedding untouched.
+        db.connection
+            .lock()
+            .unwrap()
+            .execute("UPDATE images SET path = '/r/moved.jpg' WHERE id = ?1", [a])
+            .unwrap();
+        assert_ne!(db.embedding_set_stamp("clip_vit_b_32").unwrap(), rewritten);
+    }
+
+    #[test]
+    fn streaming_and_by_path_reads_honour_visibility() {
+        let db = fresh_db();
+        let root = db.add_root("/r".into()).unwrap();
+        for name in ["a", "b", "c"] {
+            db.add_image(format!("/r/{name}.jpg"), Some(root.id)).unwrap();
+        }
+        let a = db.get_image_id_by_path("/r/a.jpg").unwrap();
+        let b = db.get_image_id_by_path("/r/b.jpg").unwrap();
+        db.upsert_embedding(a, "clip_vit_b_32", &[1.0, 0.0]).unwrap();
+        db.upsert_embedding(b, "clip_vit_b_32", &[0.0, 1.0]).unwrap();
+        db.upsert_embedding(b, "dinov2_base", &[5.0; 3]).unwrap();
+
+        let mut seen = Vec::new();
+        db.for_each_embedding_for("clip_vit_b_32", None, |p, e| seen.push((p.to_string(), e.to_vec())))
+            .unwrap();
+        seen.sort_by(|x, y| x.0.cmp(&y.0));
+        assert_eq!(
+            seen,
+            vec![
+                ("/r/a.jpg".to_string(), vec![1.0, 0.0]),
+                ("/r/b.jpg".to_string(), vec![0.0, 1.0]),
+            ]
+        );
+        let mut sampled = 0;
+        db.for_each_embedding_for("clip_vit_b_32", Some(1), |_, _| sampled += 1)
+            .unwrap();
+        assert_eq!(sampled, 1);
+
+        let got = db
+            .get_embeddings_for_paths("clip_vit_b_32", &["/r/b.jpg", "/r/c.jpg", "/nope.jpg"])
+            .unwrap();
+        assert_eq!(got, vec![("/r/b.jpg".to_string(), vec![0.0, 1.0])]);
+
+        db.set_root_enabled(root.id, false).unwrap();
+        let mut hidden = 0;
+        db.for_each_embedding_for("clip_vit_b_32", None, |_, _| hidden += 1)
+            .unwrap();
+        assert_eq!(hidden, 0);
+    }
 }
@@ -0,0 +1,135 @@
+//! Full-text filter over the image grid (`images_fts`).
+//!
+//! The grid queries' `filter_string` argument is matched against an
+//! FTS5 virtual table with one row per image (rowid = `images.id`)
+//! and four columns:
+//!
+//! - `name` — the file name (`IMG_0042.jpg`)
+//! - `folders` — the directory part of the path; the tokenizer splits
+//!   on `/` so every folder segment is its own token
+//! - `tags` — space-joined names of the image's tags
+//! - `notes` — the Phase 11 free-text notes
+//!
+//! The table and the triggers that keep it in sync with `images`,
+//! `images_tags` and `tags` are created by schema migration 2
+//! (`schema_migrations.rs`); this file owns the user-input → MATCH
+//! translation and the SQL snippets shared by the migration.
+//!
+//! Query syntax, deliberately small:
+//!
+//! - bare words are prefix matches (`inv` finds `invoice`)
+//! - `"double quoted text"` is an exact phrase match
+//! - every term must match somewhere (implicit AND)
+//!
+//! FTS5's own operators (`OR`, `NEAR`, `col:`, `^`) are NOT exposed —
+//! every term is quoted before it reaches MATCH, so a stray `-` or `:`
+//! in a file name can never turn into an FTS5 syntax error.
+
+/// SQL expression for the file-name part of a path column. Normalises
+/// Windows separators first, then strips everything up to the last
+/// `/` (the `rtrim(p, <p without slashes>)` idiom yields the directory
+/// with its trailing slash).
+pub(super) fn basename_sql(col: &str) -> String {
+    let p = format!("replace({col}, '\\', '/')");
+    format!("substr({p}, length(rtrim({p}, replace({p}, '/', ''))) + 1)")
+}
+
+/// SQL expression for the directory part of a path column.
+pub(super) fn dirname_sql(col: &str) -> String {
+    let p = format!("replace({col}, '\\', '/')");
+    format!("rtrim({p}, replace({p}, '/', ''))")
+}
+
+/// SQL expression for the space-joined tag names of one image.
+pub(super) fn tag_names_sql(image_id: &str) -> String {
+    format!(
+        "COALESCE((SELECT group_concat(t.name, ' ')
+                   FROM images_tags it JOIN tags t ON t.id = it.tag_id
+                   WHERE it.image_id = {image_id}), '')"
+    )
+}
+
+/// Translate the user's search box text into an FTS5 MATCH expression.
+/// Returns `None` when there is nothing searchable (empty, whitespace,
+/// punctuation only) — callers skip the FTS clause entirely then.
+pub(super) fn match_expression(input: &str) -> Option<String> {
+    let mut terms: Vec<String> = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '"' {
+            chars.next();
+            let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+            if has_token_chars(&phrase) {
+                terms.push(quote(&phrase));
+            }
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            if has_token_chars(&word) {
+                terms.push(format!("{}*", quote(&word)));
+            }
+        }
+    }
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// FTS5 string literal: wrap in double quotes, double any inside.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+/// Whether the unicode61 tokenizer would produce at least one token.
+/// An all-punctuation term becomes an empty phrase, which FTS5 rejects
+/// when followed by `*`.
+fn has_token_chars(s: &str) -> bool {
+    s.chars().any(char::is_alphanumeric)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_and_punctuation_only_input_yields_no_filter() {
+        assert_eq!(match_expression(""), None);
+        assert_eq!(match_expression("   "), None);
+        assert_eq!(match_expression("- / \"\""), None);
+    }
+
+    #[test]
+    fn bare_words_become_prefix_terms() {
+        assert_eq!(match_expression("inv  cat"), Some("\"inv\"* \"cat\"*".into()));
+    }
+
+    #[test]
+    fn quoted_text_becomes_a_phrase() {
+        assert_eq!(
+            match_expression("\"red car\" 2024"),
+            Some("\"red car\" \"2024\"*".into())
+        );
+        // Unterminated quote runs to the end of the input.
+        assert_eq!(match_expression("\"red car"), Some("\"red car\"".into()));
+    }
+
+    #[test]
+    fn fts_operators_are_neutralised() {
+        assert_eq!(
+            match_expression("name:foo OR -bar"),
+            Some("\"name:foo\"* \"OR\"* \"-bar\"*".into())
+        );
+    }
+}
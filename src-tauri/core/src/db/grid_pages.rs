@@ -0,0 +1,459 @@
+//! Keyset-paginated grid query.
+//!
+//! `get_images_with_thumbnails` returns the whole filtered library in
+//! one go, which at 100k images is megabytes over IPC. `get_images_page`
+//! returns one page in a stable order instead, continuing from the last
+//! row of the previous page (`GridCursor`) rather than from an offset,
+//! so each page costs the same and rows added or removed meanwhile
+//! don't shift what comes next.
+//!
+//! Every sort breaks ties on `images.id`, which makes `(key, id)` unique
+//! and the keyset comparison exact. The key expressions are indexed
+//! (schema migration 7) so a page is an index range scan.
+
+use std::collections::HashMap;
+
+use rusqlite::params_from_iter;
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+
+use super::images_query::text_filter_clause;
+use super::tag_tree::tagged_images_sql;
+use super::{root_filter, ImageDatabase, RootScope, ID};
+use crate::{image_struct::ImageData, tag_struct::Tag};
+
+/// Same visibility rule as the grid.
+fn visible() -> String {
+    format!(
+        "images.orphaned = 0 AND {}",
+        root_filter("images.root_id", RootScope::Enabled)
+    )
+}
+
+/// Sort keys. These exact expressions are indexed by migration 7; keep
+/// them in step.
+const MTIME_KEY: &str = "COALESCE(images.file_mtime, 0)";
+const SIZE_KEY: &str = "COALESCE(images.file_size, 0)";
+/// Lower-cased file name: the path after its last `/` or `\`.
+const NAME_KEY: &str = "LOWER(SUBSTR(REPLACE(images.path, '\\', '/'), \
+    LENGTH(RTRIM(REPLACE(images.path, '\\', '/'), \
+    REPLACE(REPLACE(images.path, '\\', '/'), '/', ''))) + 1))";
+
+/// Grid orders. `Random` is a seeded shuffle: the same seed gives the
+/// same order on every page and every launch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GridSort {
+    #[default]
+    Id,
+    Mtime,
+    Name,
+    Size,
+    Random,
+}
+
+impl GridSort {
+    fn key_expr(self, seed: u32) -> String {
+        match self {
+            GridSort::Id => "images.id".to_string(),
+            GridSort::Mtime => MTIME_KEY.to_string(),
+            GridSort::Name => NAME_KEY.to_string(),
+            GridSort::Size => SIZE_KEY.to_string(),
+            // (id XOR seed) times an odd constant, mod 2^32: a bijection
+            // on ids below 2^31, so no ties and a different order per
+            // seed. SQLite has no XOR; a ^ b = (a | b) - (a & b). The
+            // seed is an integer we format ourselves.
+            GridSort::Random => {
+                let seed = seed & 0x7fff_ffff;
+                format!(
+                    "((((images.id | {seed}) - (images.id & {seed})) * 2654435761) % 4294967296)"
+                )
+            }
+        }
+    }
+}
+
+/// What the grid shows and in which order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GridQuery {
+    pub filter_tag_ids: Vec<ID>,
+    /// Full-text filter, as in `get_images_with_thumbnails`.
+    pub filter_string: String,
+    pub match_all_tags: bool,
+    pub sort: GridSort,
+    pub descending: bool,
+    /// Shuffle seed for `GridSort::Random`.
+    pub seed: u32,
+}
+
+/// A sort-key value: integer for every sort but `Name`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SortValue {
+    Int(i64),
+    Text(String),
+}
+
+impl From<SortValue> for Value {
+    fn from(v: SortValue) -> Self {
+        match v {
+            SortValue::Int(i) => Value::Integer(i),
+            SortValue::Text(s) => Value::Text(s),
+        }
+    }
+}
+
+/// The last row of a page, and the order it was read in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GridCursor {
+    sort: GridSort,
+    descending: bool,
+    seed: u32,
+    key: SortValue,
+    id: ID,
+}
+
+impl GridCursor {
+    /// Whether this cursor continues a listing in `query`'s order.
+    pub fn matches(&self, query: &GridQuery) -> bool {
+        self.sort == query.sort
+            && self.descending == query.descending
+            && (self.sort != GridSort::Random || self.seed == query.seed)
+    }
+}
+
+impl ImageDatabase {
+    /// Up to `limit` visible images matching `query`, in its order,
+    /// starting after `after`. Also returns the cursor for the next
+    /// page, `None` on the last one.
+    pub fn get_images_page(
+        &self,
+        query: &GridQuery,
+        after: Option<&GridCursor>,
+        limit: usize,
+    ) -> rusqlite::Result<(Vec<ImageData>, Option<GridCursor>)> {
+        let key = query.sort.key_expr(query.seed);
+        let (dir, cmp) = if query.descending {
+            ("DESC", "<")
+        } else {
+            ("ASC", ">")
+        };
+
+        let mut params: Vec<Value> = Vec::new();
+        let tag_filter = if query.filter_tag_ids.is_empty() {
+            String::new()
+        } else {
+            params.extend(query.filter_tag_ids.iter().map(|id| Value::Integer(*id)));
+            format!(
+                "AND images.id IN ({})",
+                tagged_images_sql(&query.filter_tag_ids, query.match_all_tags)
+            )
+        };
+        let (text_filter, text_param) = text_filter_clause(&query.filter_string);
+        params.extend(text_param);
+        let keyset = match after {
+            Some(cursor) => {
+                params.push(cursor.key.clone().into());
+                params.push(Value::Integer(cursor.id));
+                format!("AND ({key}, images.id) {cmp} (?, ?)")
+            }
+            None => String::new(),
+        };
+        // One extra row tells whether there is a next page.
+        params.push(Value::Integer(limit as i64 + 1));
+
+        let conn = self.read_lock();
+        let visible = visible();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT images.id, images.path, images.thumbnail_path, images.width,
+                    images.height, {key} AS sort_key,
+                    images.root_id IN (SELECT id FROM roots WHERE offline = 1) AS offline
+             FROM images
+             WHERE {visible} {tag_filter} {text_filter} {keyset}
+             ORDER BY {key} {dir}, images.id {dir}
+             LIMIT ?"
+        ))?;
+        let mut rows = stmt
+            .query_map(params_from_iter(params), |row| {
+                let key = match row.get::<_, Value>(5)? {
+                    Value::Text(s) => SortValue::Text(s),
+                    Value::Integer(i) => SortValue::Int(i),
+                    _ => SortValue::Int(0),
+                };
+                Ok((
+                    row.get::<_, ID>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                    row.get::<_, Option<i64>>(4)?,
+                    key,
+                    row.get::<_, Option<bool>>(6)?.unwrap_or(false),
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let next = if rows.len() > limit {
+            rows.truncate(limit);
+            rows.last().map(|(id, _, _, _, _, key, _)| GridCursor {
+                sort: query.sort,
+                descending: query.descending,
+                seed: query.seed,
+                key: key.clone(),
+                id: *id,
+            })
+        } else {
+            None
+        };
+
+        let mut tags = page_tags(&conn, rows.iter().map(|r| r.0))?;
+        let images = rows
+            .into_iter()
+            .map(|(id, path, thumbnail_path, width, height, _, offline)| {
+                let mut img = ImageData::new(
+                    id,
+                    std::path::Path::new(&path),
+                    tags.remove(&id).unwrap_or_default(),
+                );
+                img.thumbnail_path = thumbnail_path;
+                img.width = width.map(|w| w as u32);
+                img.height = height.map(|h| h as u32);
+                img.offline = offline;
+                img
+            })
+            .collect();
+        Ok((images, next))
+    }
+}
+
+/// Tags of the images in one page.
+fn page_tags(
+    conn: &rusqlite::Connection,
+    ids: impl Iterator<Item = ID>,
+) -> rusqlite::Result<HashMap<ID, Vec<Tag>>> {
+    let ids: Vec<Value> = ids.map(Value::Integer).collect();
+    let mut tags: HashMap<ID, Vec<Tag>> = HashMap::new();
+    if ids.is_empty() {
+        return Ok(tags);
+    }
+    let mut stmt = conn.prepare(&format!(
+        "SELECT it.image_id, t.id, t.name, t.color, t.parent_id FROM images_tags it
+         JOIN tags t ON t.id = it.tag_id
+         WHERE it.image_id IN ({})
+         ORDER BY t.id",
+        vec!["?"; ids.len()].join(", ")
+    ))?;
+    let rows = stmt.query_map(params_from_iter(ids), |row| {
+        Ok((
+            row.get::<_, ID>(0)?,
+            Tag {
+                id: row.get(1)?,
+                name: row.get(2)?,
+                color: row.get(3)?,
+                parent_id: row.get(4)?,
+            },
+        ))
+    })?;
+    for row in rows {
+        let (image_id, tag) = row?;
+        tags.entry(image_id).or_default().push(tag);
+    }
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    fn db() -> ImageDatabase {
+        let db = ImageDatabase::new(":memory:").unwrap();
+        db.initialize().unwrap();
+        // (path, mtime, size): names and sizes in a different order
+        // from the ids.
+        let rows = [
+            ("/a/Delta.jpg", 400, 10),
+            ("C:\\b\\alpha.jpg", 100, 30),
+            ("/a/charlie.png", 300, 30),
+            ("/c/bravo.jpg", 200, 20),
+            ("/c/echo.jpg", 100, 50),
+        ];
+        for (path, mtime, size) in rows {
+            db.add_image(path.to_string(), None).unwrap();
+            db.connection
+                .lock()
+                .unwrap()
+                .execute(
+                    "UPDATE images SET file_mtime = ?2, file_size = ?3 WHERE path = ?1",
+                    params![path, mtime, size],
+                )
+                .unwrap();
+        }
+        db
+    }
+
+    /// Every page of `query`, `limit` rows at a time, as file names.
+    fn walk(db: &ImageDatabase, query: &GridQuery, limit: usize) -> Vec<Vec<String>> {
+        let mut pages = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (images, next) = db.get_images_page(query, cursor.as_ref(), limit).unwrap();
+            pages.push(images.into_iter().map(|i| i.name).collect());
+            match next {
+                Some(next) => cursor = Some(next),
+                None => return pages,
+            }
+        }
+    }
+
+    fn flat(pages: Vec<Vec<String>>) -> Vec<String> {
+        pages.into_iter().flatten().collect()
+    }
+
+    #[test]
+    fn pages_follow_each_sort_with_id_tie_breaks() {
+        let db = db();
+        let sorted = |sort, descending| {
+            flat(walk(
+                &db,
+                &GridQuery {
+                    sort,
+                    descending,
+                    ..Default::default()
+                },
+                2,
+            ))
+        };
+        // Names as `ImageData::new` reports them; the backslash path has
+        // no `/` to split on here.
+        assert_eq!(
+            walk(&db, &GridQuery::default(), 2),
+            [
+                vec!["Delta.jpg", "C:\\b\\alpha.jpg"],
+                vec!["charlie.png", "bravo.jpg"],
+                vec!["echo.jpg"],
+            ]
+        );
+        assert_eq!(
+            sorted(GridSort::Name, false),
+            [
+                "C:\\b\\alpha.jpg",
+                "bravo.jpg",
+                "charlie.png",
+                "Delta.jpg",
+                "echo.jpg"
+            ]
+        );
+        // Equal mtimes (alpha, echo) fall back to id order, reversed
+        // when descending.
+        assert_eq!(
+            sorted(GridSort::Mtime, true),
+            [
+                "Delta.jpg",
+                "charlie.png",
+                "bravo.jpg",
+                "echo.jpg",
+                "C:\\b\\alpha.jpg"
+            ]
+        );
+        assert_eq!(
+            sorted(GridSort::Size, false),
+            [
+                "Delta.jpg",
+                "bravo.jpg",
+                "C:\\b\\alpha.jpg",
+                "charlie.png",
+                "echo.jpg"
+            ]
+        );
+    }
+
+    #[test]
+    fn a_seeded_shuffle_is_stable_across_pages() {
+        let db = db();
+        let shuffled = |seed| GridQuery {
+            sort: GridSort::Random,
+            seed,
+            ..Default::default()
+        };
+        let one_page = flat(walk(&db, &shuffled(7), 10));
+        assert_eq!(flat(walk(&db, &shuffled(7), 2)), one_page);
+        let mut names = one_page.clone();
+        names.sort();
+        assert_eq!(names.len(), 5);
+        assert!(
+            (0..20).any(|seed| flat(walk(&db, &shuffled(seed), 10)) != one_page),
+            "seeds give different orders"
+        );
+    }
+
+    #[test]
+    fn cursors_continue_past_deleted_rows_and_filters_apply() {
+        let db = db();
+        let (first, next) = db.get_images_page(&GridQuery::default(), None, 2).unwrap();
+        assert_eq!(first.len(), 2);
+        // Deleting the last row read doesn't shift the next page.
+        db.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM images WHERE id = ?1", [first[1].id])
+            .unwrap();
+        let (second, _) = db
+            .get_images_page(&GridQuery::default(), next.as_ref(), 2)
+            .unwrap();
+        assert_eq!(
+            second.iter().map(|i| i.name.as_str()).collect::<Vec<_>>(),
+            ["charlie.png", "bravo.jpg"]
+        );
+
+        let tag = db.create_tag("keep".into(), "#fff".into()).unwrap().id;
+        db.add_tag_to_image(second[1].id, tag).unwrap();
+        let tagged = GridQuery {
+            filter_tag_ids: vec![tag],
+            ..Default::default()
+        };
+        let (images, next) = db.get_images_page(&tagged, None, 10).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].tags[0].name, "keep");
+        assert!(next.is_none());
+    }
+
+    #[test]
+    fn cursors_only_continue_their_own_order() {
+        let db = db();
+        let by_name = GridQuery {
+            sort: GridSort::Name,
+            ..Default::default()
+        };
+        let (_, next) = db.get_images_page(&by_name, None, 1).unwrap();
+        let next = next.unwrap();
+        assert!(next.matches(&by_name));
+        assert!(!next.matches(&GridQuery::default()));
+        assert!(!next.matches(&GridQuery {
+            descending: true,
+            ..by_name.clone()
+        }));
+        // The seed only matters to the shuffle.
+        assert!(next.matches(&GridQuery { seed: 9, ..by_name }));
+    }
+
+    #[test]
+    fn images_of_an_offline_root_stay_listed_and_flagged() {
+        let db = db();
+        let usb = db.add_root("/usb".into()).unwrap();
+        db.add_image("/usb/f.jpg".into(), Some(usb.id)).unwrap();
+        db.set_root_offline(usb.id, true).unwrap();
+
+        let (images, _) = db.get_images_page(&GridQuery::default(), None, 10).unwrap();
+        let offline: Vec<&str> = images
+            .iter()
+            .filter(|i| i.offline)
+            .map(|i| i.name.as_str())
+            .collect();
+        assert_eq!(images.len(), 6);
+        assert_eq!(offline, ["f.jpg"]);
+
+        let all = db.get_images_with_thumbnails(vec![], "".into(), false).unwrap();
+        assert_eq!(all.iter().filter(|i| i.offline).count(), 1);
+    }
+}
@@ -8,12 +8,15 @@
 //! HashMap-keyed-by-image-id roll-up so each caller stays focused on
 //! its WHERE clause.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use rusqlite::params_from_iter;
+use rusqlite::types::Value;
 use serde::Serialize;
 
-use super::{ID, ImageDatabase};
+use super::fulltext::match_expression;
+use super::tag_tree::tagged_images_sql;
+use super::{root_filter, ID, ImageDatabase, RootScope};
 use crate::{image_struct::ImageData, tag_struct::Tag};
 
 /// Pipeline progress snapshot — counts of images at each stage.
@@ -28,7 +31,8 @@ use crate::{image_struct::ImageData, tag_struct::Tag};
 /// counts. Acceptable cost given the typical ~3 encoder configurations.
 #[derive(Debug, Serialize, Clone, PartialEq, Eq)]
 pub struct PipelineStats {
-    /// Every row in the `images` table (including orphaned).
+    /// Every row in the `images` table (including orphaned), bar those
+    /// of removed roots.
     pub total_images: i64,
     /// Rows with a non-empty `thumbnail_path`.
     pub with_thumbnail: i64,
@@ -64,7 +68,8 @@ pub struct EncoderEmbeddingCount {
 ///   thumbnail_path, width, height — nullable, OK to be absent in the
 ///     SELECT (treated as NULL in that case via the COALESCE pattern
 ///     each caller uses),
-///   tag_id, tag_name, tag_color — nullable LEFT JOIN columns.
+///   tag_id, tag_name, tag_color, tag_parent_id — nullable LEFT JOIN
+///   columns.
 ///
 /// Callers that don't need the thumbnail columns simply discard them
 /// from the returned tuples; callers that don't include the columns in
@@ -101,6 +106,7 @@ fn aggregate_image_rows(
                 id: tag_id,
                 name: row.get("tag_name")?,
                 color: row.get("tag_color")?,
+                parent_id: row.get("tag_parent_id")?,
             });
         }
     }
@@ -110,14 +116,53 @@ fn aggregate_image_rows(
         .collect())
 }
 
+/// Ids of the images whose root is offline, for the grid's offline
+/// badge. Served by the `(root_id, orphaned)` index, so it costs next
+/// to nothing while every root is online.
+fn offline_image_ids(conn: &rusqlite::Connection) -> rusqlite::Result<HashSet<ID>> {
+    let mut stmt = conn.prepare(
+        "SELECT id FROM images WHERE root_id IN (SELECT id FROM roots WHERE offline = 1)",
+    )?;
+    let ids = stmt.query_map([], |r| r.get(0))?;
+    ids.collect()
+}
+
+/// `filter_string` → extra WHERE clause + its bind parameter, appended
+/// after every other placeholder in the grid queries. Empty clause and
+/// no parameter when the string has nothing searchable (see
+/// `fulltext::match_expression`).
+pub(super) fn text_filter_clause(filter_string: &str) -> (&'static str, Option<Value>) {
+    match match_expression(filter_string) {
+        Some(expr) => (
+            "AND images.id IN (SELECT rowid FROM images_fts WHERE images_fts MATCH ?)",
+            Some(Value::Text(expr)),
+        ),
+        None => ("", None),
+    }
+}
+
+/// Positional parameters for a grid query: tag ids first (they appear
+/// first in the SQL), then the optional full-text MATCH expression.
+fn grid_params(filter_tag_ids: Vec<ID>, text_param: Option<Value>) -> Vec<Value> {
+    filter_tag_ids
+        .into_iter()
+        .map(Value::Integer)
+        .chain(text_param)
+        .collect()
+}
+
 impl ImageDatabase {
     pub fn get_images(
         &self,
         filter_tag_ids: Vec<ID>,
-        _filter_string: String,
+        filter_string: String,
     ) -> rusqlite::Result<Vec<ImageData>> {
         // R2 — foreground SELECT, route through the reader.
         let conn = self.read_lock();
+        let (text_filter, text_param) = text_filter_clause(&filter_string);
+        // Disabled and orphaned rows stay in (search resolves paths
+        // through here), but a removed root's rows are gone everywhere.
+        let live = root_filter("images.root_id", RootScope::Live);
 
         // Always SELECT the thumbnail columns as NULL aliases so the
         // shared `aggregate_image_rows` helper can read by name. This
@@ -125,33 +170,32 @@ impl ImageDatabase {
         // shape — but the helper's contract is uniform across all
         // four callers.
         let sql = if !filter_tag_ids.is_empty() {
-            let placeholders = vec!["?"; filter_tag_ids.len()].join(", ");
+            let tagged = tagged_images_sql(&filter_tag_ids, false);
             format!(
                 "SELECT images.id AS img_id, images.path AS img_path,
                 NULL AS thumbnail_path, NULL AS width, NULL AS height,
-                tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color
+                tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color, tags.parent_id AS tag_parent_id
                 FROM images
                 LEFT JOIN images_tags ON images.id = images_tags.image_id
                 LEFT JOIN tags ON tags.id = images_tags.tag_id
-                WHERE EXISTS (
-                    SELECT 1
-                    FROM images_tags it2
-                    WHERE it2.image_id = images.id
-                    AND it2.tag_id IN ({})
-                );",
-                placeholders
+                WHERE {live}
+                AND images.id IN ({tagged})
+                {text_filter};"
             )
         } else {
-            "SELECT images.id AS img_id, images.path AS img_path,
-            NULL AS thumbnail_path, NULL AS width, NULL AS height,
-            tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color
-            FROM images
-            LEFT JOIN images_tags ON images.id = images_tags.image_id
-            LEFT JOIN tags ON tags.id = images_tags.tag_id;"
-                .to_string()
+            format!(
+                "SELECT images.id AS img_id, images.path AS img_path,
+                NULL AS thumbnail_path, NULL AS width, NULL AS height,
+                tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color, tags.parent_id AS tag_parent_id
+                FROM images
+                LEFT JOIN images_tags ON images.id = images_tags.image_id
+                LEFT JOIN tags ON tags.id = images_tags.tag_id
+                WHERE {live} {text_filter};"
+            )
         };
         let mut stmt = conn.prepare(&sql)?;
-        let mut rows = stmt.query(params_from_iter(filter_tag_ids))?;
+        let mut rows =
+            stmt.query(params_from_iter(grid_params(filter_tag_ids, text_param)))?;
         let aggregated = aggregate_image_rows(&mut rows)?;
 
         let mut images: Vec<ImageData> = aggregated
@@ -169,18 +213,20 @@ impl ImageDatabase {
         self.get_images(Vec::new(), "".to_string())
     }
 
-    // Get images that don't have embeddings yet
+    // Get images that don't have embeddings yet (offline roots wait)
     pub fn get_images_without_embeddings(&self) -> rusqlite::Result<Vec<ImageData>> {
         let conn = self.connection.lock().unwrap();
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare(&format!(
             "SELECT images.id AS img_id, images.path AS img_path,
             NULL AS thumbnail_path, NULL AS width, NULL AS height,
-            tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color
+            tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color, tags.parent_id AS tag_parent_id
             FROM images
             LEFT JOIN images_tags ON images.id = images_tags.image_id
             LEFT JOIN tags ON tags.id = images_tags.tag_id
-            WHERE images.embedding IS NULL;",
-        )?;
+            WHERE images.embedding IS NULL
+            AND {};",
+            root_filter("images.root_id", RootScope::Online)
+        ))?;
         let mut rows = stmt.query([])?;
         let aggregated = aggregate_image_rows(&mut rows)?;
 
@@ -195,18 +241,21 @@ impl ImageDatabase {
         Ok(images)
     }
 
-    /// Get images that don't have thumbnails yet
+    /// Get images that don't have thumbnails yet. Images of an offline
+    /// root wait until its folder is back.
     pub fn get_images_without_thumbnails(&self) -> rusqlite::Result<Vec<ImageData>> {
         let conn = self.connection.lock().unwrap();
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare(&format!(
             "SELECT images.id AS img_id, images.path AS img_path,
             images.thumbnail_path, images.width, images.height,
-            tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color
+            tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color, tags.parent_id AS tag_parent_id
             FROM images
             LEFT JOIN images_tags ON images.id = images_tags.image_id
             LEFT JOIN tags ON tags.id = images_tags.tag_id
-            WHERE images.thumbnail_path IS NULL OR images.thumbnail_path = '';",
-        )?;
+            WHERE (images.thumbnail_path IS NULL OR images.thumbnail_path = '')
+            AND {};",
+            root_filter("images.root_id", RootScope::Online)
+        ))?;
         let mut rows = stmt.query([])?;
         let aggregated = aggregate_image_rows(&mut rows)?;
 
@@ -228,7 +277,7 @@ impl ImageDatabase {
     /// Get images with their thumbnail info included.
     ///
     /// Filters out:
-    /// - rows whose root is disabled (multi-folder, Phase 6)
+    /// - rows whose root is disabled (multi-folder, Phase 6) or removed
     /// - rows marked orphaned (file removed from disk, Phase 7)
     ///
     /// Rows with NULL root_id are kept — those are legacy un-migrated
@@ -237,11 +286,18 @@ impl ImageDatabase {
     /// `match_all_tags` controls multi-tag semantics: false (default)
     /// matches images with ANY of the selected tags (OR), true requires
     /// ALL of them (AND). Threaded through from the user's tagFilterMode
-    /// preference via the get_images Tauri command.
+    /// preference via the get_images Tauri command. A selected tag is
+    /// carried by an image that has it or any tag below it in the
+    /// hierarchy (`tag_tree.rs`).
+    ///
+    /// `filter_string` is the search box text, matched against file
+    /// name, folder segments, tag names and notes through the
+    /// `images_fts` index (see `fulltext.rs` for the syntax). Empty
+    /// string = no text filter.
     pub fn get_images_with_thumbnails(
         &self,
         filter_tag_ids: Vec<ID>,
-        _filter_string: String,
+        filter_string: String,
         match_all_tags: bool,
     ) -> rusqlite::Result<Vec<ImageData>> {
         // Sub-span instrumentation for the `get_images` IPC path.
@@ -283,67 +339,56 @@ impl ImageDatabase {
 
         // Common WHERE for root-and-orphan filtering. Used both with
         // and without tag-filter SQL.
-        let root_filter = "(
-            images.orphaned = 0
-            AND (
-                images.root_id IS NULL
-                OR images.root_id IN (SELECT id FROM roots WHERE enabled = 1)
-            )
-        )";
+        let visible = format!(
+            "(images.orphaned = 0 AND {})",
+            root_filter("images.root_id", RootScope::Enabled)
+        );
+        let (text_filter, text_param) = text_filter_clause(&filter_string);
 
         let sql = if !filter_tag_ids.is_empty() {
-            let placeholders = vec!["?"; filter_tag_ids.len()].join(", ");
+            let tagged = tagged_images_sql(&filter_tag_ids, match_all_tags);
             if match_all_tags {
-                // AND semantic: image must have EVERY selected tag.
-                // GROUP BY image_id with HAVING COUNT = number of distinct
-                // selected tags. Note we COUNT(DISTINCT tag_id) so a tag
-                // appearing twice for the same image (impossible given
-                // the PK but defensive) doesn't satisfy the constraint
-                // for two different selected tags.
-                let n = filter_tag_ids.len();
+                // AND semantic: image must have EVERY selected tag (or
+                // a descendant of it). `tagged_images_sql` groups by
+                // image and counts distinct selected tags hit, so two
+                // descendants of one selected tag don't satisfy the
+                // constraint for two different selected tags.
                 format!(
                     "SELECT images.id AS img_id, images.path AS img_path,
                     images.thumbnail_path, images.width, images.height,
-                    tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color
+                    tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color, tags.parent_id AS tag_parent_id
                     FROM images
                     LEFT JOIN images_tags ON images.id = images_tags.image_id
                     LEFT JOIN tags ON tags.id = images_tags.tag_id
-                    WHERE {root_filter}
-                    AND images.id IN (
-                        SELECT it2.image_id
-                        FROM images_tags it2
-                        WHERE it2.tag_id IN ({placeholders})
-                        GROUP BY it2.image_id
-                        HAVING COUNT(DISTINCT it2.tag_id) = {n}
-                    );"
+                    WHERE {visible}
+                    AND images.id IN ({tagged})
+                    {text_filter};"
                 )
             } else {
-                // OR semantic: image must have ANY selected tag.
+                // OR semantic: image must have ANY selected tag (or a
+                // descendant of one).
                 format!(
                     "SELECT images.id AS img_id, images.path AS img_path,
                     images.thumbnail_path, images.width, images.height,
-                    tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color
+                    tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color, tags.parent_id AS tag_parent_id
                     FROM images
                     LEFT JOIN images_tags ON images.id = images_tags.image_id
                     LEFT JOIN tags ON tags.id = images_tags.tag_id
-                    WHERE {root_filter}
-                    AND EXISTS (
-                        SELECT 1
-                        FROM images_tags it2
-                        WHERE it2.image_id = images.id
-                        AND it2.tag_id IN ({placeholders})
-                    );"
+                    WHERE {visible}
+                    AND images.id IN ({tagged})
+                    {text_filter};"
                 )
             }
         } else {
             format!(
                 "SELECT images.id AS img_id, images.path AS img_path,
                 images.thumbnail_path, images.width, images.height,
-                tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color
+                tags.id AS tag_id, tags.name AS tag_name, tags.color AS tag_color, tags.parent_id AS tag_parent_id
                 FROM images
                 LEFT JOIN images_tags ON images.id = images_tags.image_id
                 LEFT JOIN tags ON tags.id = images_tags.tag_id
-                WHERE {root_filter};"
+                WHERE {visible}
+                {text_filter};"
             )
         };
         let mut stmt = {
@@ -363,7 +408,7 @@ impl ImageDatabase {
         let mut rows = {
             let _row_iter_span =
                 tracing::info_span!("get_images.row_iter").entered();
-            stmt.query(params_from_iter(filter_tag_ids))?
+            stmt.query(params_from_iter(grid_params(filter_tag_ids, text_param)))?
         };
         let aggregated = {
             let _aggregate_span =
@@ -377,6 +422,7 @@ impl ImageDatabase {
         let images = {
             let _materialise_span =
                 tracing::info_span!("get_images.materialise").entered();
+            let offline = offline_image_ids(&conn)?;
             let mut images: Vec<ImageData> = aggregated
                 .into_iter()
                 .map(|(id, path, tags, thumbnail_path, width, height)| {
@@ -384,6 +430,7 @@ impl ImageDatabase {
                     img.thumbnail_path = thumbnail_path;
                     img.width = width.map(|w| w as u32);
                     img.height = height.map(|h| h as u32);
+                    img.offline = offline.contains(&id);
                     img
                 })
                 .collect();
@@ -436,6 +483,20 @@ impl ImageDatabase {
         }
     }
 
+    /// Inverse of `get_image_id_by_path`. `QueryReturnedNoRows` for an
+    /// unknown id or an image of a removed root.
+    pub fn get_image_path(&self, id: ID) -> rusqlite::Result<String> {
+        let conn = self.read_lock();
+        conn.query_row(
+            &format!(
+                "SELECT path FROM images WHERE id = ?1 AND {}",
+                root_filter("root_id", RootScope::Live)
+            ),
+            [id],
+            |row| row.get(0),
+        )
+    }
+
     /// Snapshot of the pipeline's progress — base counts plus
     /// per-encoder embedding counts.
     ///
@@ -457,7 +518,7 @@ impl ImageDatabase {
         let conn = self.read_lock();
 
         // Base counts: total + thumbnail + legacy-CLIP + orphaned.
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare(&format!(
             "SELECT
                 COUNT(*) AS total,
                 SUM(CASE
@@ -469,8 +530,10 @@ impl ImageDatabase {
                     THEN 1 ELSE 0
                 END) AS with_legacy_clip,
                 SUM(CASE WHEN orphaned = 1 THEN 1 ELSE 0 END) AS orphaned
-            FROM images",
-        )?;
+            FROM images
+            WHERE {}",
+            root_filter("root_id", RootScope::Live)
+        ))?;
         let (total, thumb, legacy_clip, orphaned) = stmt.query_row([], |row| {
             Ok((
                 row.get::<_, i64>(0)?,
@@ -489,9 +552,11 @@ impl ImageDatabase {
         let known_encoders = ["clip_vit_b_32", "siglip2_base", "dinov2_base"];
         let mut counts: Vec<EncoderEmbeddingCount> = Vec::with_capacity(known_encoders.len());
         for encoder_id in known_encoders {
-            let mut stmt = conn.prepare(
-                "SELECT COUNT(*) FROM embeddings WHERE encoder_id = ?1",
-            )?;
+            let mut stmt = conn.prepare(&format!(
+                "SELECT COUNT(*) FROM embeddings e JOIN images i ON i.id = e.image_id
+                 WHERE e.encoder_id = ?1 AND {}",
+                root_filter("i.root_id", RootScope::Live)
+            ))?;
             let new_count: i64 =
                 stmt.query_row(rusqlite::params![encoder_id], |row| row.get(0))?;
             // For the CLIP entry, prefer the larger of (legacy column,
@@ -716,4 +781,112 @@ mod tests {
         assert_eq!(and_match.len(), 1);
         assert_eq!(and_match[0].id, id_c);
     }
+
+    // =====================================================================
+    //  Full-text filter (images_fts)
+    // =====================================================================
+
+    fn search(db: &ImageDatabase, q: &str) -> Vec<String> {
+        let mut paths: Vec<String> = db
+            .get_images_with_thumbnails(vec![], q.into(), false)
+            .unwrap()
+            .into_iter()
+            .map(|i| i.path)
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    fn setup_searchable(db: &ImageDatabase) -> (ID, ID) {
+        db.add_image("/photos/Trips/Lisbon/IMG_0042.jpg".into(), None).unwrap();
+        db.add_image("/photos/work/scan_invoice.png".into(), None).unwrap();
+        db.add_image("/photos/misc/screenshot.png".into(), None).unwrap();
+        let shot = db.get_image_id_by_path("/photos/misc/screenshot.png").unwrap();
+        let lisbon = db.get_image_id_by_path("/photos/Trips/Lisbon/IMG_0042.jpg").unwrap();
+        (shot, lisbon)
+    }
+
+    #[test]
+    fn text_filter_matches_file_name_prefix() {
+        let db = fresh_db();
+        setup_searchable(&db);
+        assert_eq!(search(&db, "scan_inv"), vec!["/photos/work/scan_invoice.png"]);
+        assert_eq!(search(&db, "img"), vec!["/photos/Trips/Lisbon/IMG_0042.jpg"]);
+    }
+
+    #[test]
+    fn text_filter_matches_folder_segments() {
+        let db = fresh_db();
+        setup_searchable(&db);
+        assert_eq!(search(&db, "lisbon"), vec!["/photos/Trips/Lisbon/IMG_0042.jpg"]);
+        assert_eq!(search(&db, "photos").len(), 3);
+    }
+
+    #[test]
+    fn text_filter_tracks_notes_edits() {
+        let db = fresh_db();
+        let (shot, _) = setup_searchable(&db);
+        db.set_image_notes(shot, "paid the invoice from ACME").unwrap();
+        assert_eq!(
+            search(&db, "invoice"),
+            vec!["/photos/misc/screenshot.png", "/photos/work/scan_invoice.png"]
+        );
+        assert_eq!(search(&db, "\"the invoice\""), vec!["/photos/misc/screenshot.png"]);
+        // Phrase must be contiguous.
+        assert!(search(&db, "\"paid invoice\"").is_empty());
+
+        db.set_image_notes(shot, "").unwrap();
+        assert_eq!(search(&db, "acme"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn text_filter_tracks_tag_attach_rename_and_delete() {
+        let db = fresh_db();
+        let (_, lisbon) = setup_searchable(&db);
+        let tag = db.create_tag("holiday".into(), "#0f0".into()).unwrap().id;
+        db.add_tag_to_image(lisbon, tag).unwrap();
+        assert_eq!(search(&db, "holi"), vec!["/photos/Trips/Lisbon/IMG_0042.jpg"]);
+
+        db.connection
+            .lock()
+            .unwrap()
+            .execute("UPDATE tags SET name = 'vacation' WHERE id = ?1", [tag])
+            .unwrap();
+        assert!(search(&db, "holiday").is_empty());
+        assert_eq!(search(&db, "vacation").len(), 1);
+
+        db.delete_tag(tag).unwrap();
+        assert!(search(&db, "vacation").is_empty());
+    }
+
+    #[test]
+    fn text_filter_combines_with_tag_filter_and_skips_orphans() {
+        let db = fresh_db();
+        let (shot, lisbon) = setup_searchable(&db);
+        let tag = db.create_tag("keep".into(), "#00f".into()).unwrap().id;
+        db.add_tag_to_image(shot, tag).unwrap();
+        db.add_tag_to_image(lisbon, tag).unwrap();
+
+        let imgs = db
+            .get_images_with_thumbnails(vec![tag], "screen".into(), true)
+            .unwrap();
+        assert_eq!(imgs.len(), 1);
+        assert_eq!(imgs[0].id, shot);
+
+        db.connection
+            .lock()
+            .unwrap()
+            .execute("UPDATE images SET orphaned = 1 WHERE id = ?1", [shot])
+            .unwrap();
+        assert!(search(&db, "screen").is_empty());
+    }
+
+    #[test]
+    fn text_filter_applies_to_legacy_get_images_and_ignores_blank_input() {
+        let db = fresh_db();
+        setup_searchable(&db);
+        assert_eq!(db.get_images(vec![], "lisbon".into()).unwrap().len(), 1);
+        assert_eq!(db.get_images(vec![], "  ".into()).unwrap().len(), 3);
+        assert_eq!(search(&db, "-").len(), 3);
+    }
 }
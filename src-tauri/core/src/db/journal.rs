@@ -0,0 +1,818 @@
+//! Undo/redo journal (schema migration 10).
+//!
+//! Deleting a tag cascades through `images_tags`, so a misclick used to
+//! be final. Undoable mutations now go through `journaled`: each is a
+//! list of `JournalOp`s, applied in one transaction with the journal
+//! row that records it. Applying an op yields the op that reverses it —
+//! deleting a tag captures its name, colour, children, aliases and
+//! image links — and both directions are stored. Root removal is a soft
+//! delete (`roots.rs`), so its inverse just clears `removed_at`.
+//!
+//! `undo` replays the newest entry's inverse, `redo` the oldest undone
+//! entry's forward ops. Each replay stores the ops as they applied this
+//! time and their fresh inverses, so the two stay exact however often
+//! an entry flips. Recording a new entry drops the undone ones (the
+//! redo branch) and the oldest beyond `MAX_JOURNAL_ENTRIES`.
+//!
+//! Mutations that build on their own ops — tagging images with a tag
+//! the same entry creates, as accepting suggestions or tagging a board
+//! does — apply them through a `Recording` in their own transaction.
+//!
+//! Every mutation that deletes a tag or adds or removes image links is
+//! journaled, merges included, so replays find the library as the entry
+//! left it. Renames, moves and alias edits aren't: they keep ids, which
+//! is all the ops address. An op whose target has since vanished some
+//! other way (a root purged, a CLI edit) applies as nothing; one that
+//! would recreate a name or id taken since fails with a UNIQUE
+//! violation and leaves the entry where it was.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::notes_orphans::clean_notes;
+use super::roots::{restore_root, soft_remove_root};
+use super::tag_tree::merge_tag;
+use super::tags::{delete_tag_lifting_children, find_tag, link_images, unlink_images};
+use super::{ImageDatabase, ID};
+use crate::tag_struct::Tag;
+
+/// Entries kept, newest first; older ones can no longer be undone.
+pub const MAX_JOURNAL_ENTRIES: usize = 100;
+
+/// One reversible change to the library.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalOp {
+    /// `id: None` picks a fresh id; applied, it holds the one used.
+    CreateTag {
+        id: Option<ID>,
+        name: String,
+        color: String,
+        parent_id: Option<ID>,
+    },
+    /// Children move up to the tag's parent, as `delete_tag`.
+    DeleteTag {
+        id: ID,
+    },
+    /// A deleted tag, with what the delete took from it.
+    RestoreTag {
+        tag: Tag,
+        children: Vec<ID>,
+        aliases: Vec<String>,
+        image_ids: Vec<ID>,
+    },
+    /// `gained` is ignored going in; applied, it holds the images that
+    /// newly carry the target.
+    MergeTag {
+        source_id: ID,
+        target_id: ID,
+        #[serde(default)]
+        gained: Vec<ID>,
+    },
+    /// A merged tag, with what the merge moved from it to `target_id`.
+    SplitTag {
+        tag: Tag,
+        target_id: ID,
+        children: Vec<ID>,
+        aliases: Vec<String>,
+        image_ids: Vec<ID>,
+        gained: Vec<ID>,
+    },
+    /// Applied, `image_ids` holds only the images that gained the tag.
+    TagImages {
+        tag_id: ID,
+        image_ids: Vec<ID>,
+    },
+    /// Applied, `image_ids` holds only the images that lost the tag.
+    UntagImages {
+        tag_id: ID,
+        image_ids: Vec<ID>,
+    },
+    /// Blank notes clear the field, as `set_image_notes`.
+    SetNotes {
+        image_id: ID,
+        notes: Option<String>,
+    },
+    /// Soft delete; the root can be re-added until it is purged.
+    RemoveRoot {
+        id: ID,
+    },
+    RestoreRoot {
+        id: ID,
+    },
+}
+
+/// A journal row, without its ops.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: ID,
+    /// What the user did, e.g. `Delete tag "cat"`.
+    pub label: String,
+    /// Unix epoch seconds.
+    pub created_at: i64,
+    /// Undone and waiting to be redone.
+    pub undone: bool,
+}
+
+/// An entry `undo` or `redo` just replayed, and the ops as applied.
+#[derive(Debug)]
+pub struct Replayed {
+    pub entry: JournalEntry,
+    pub applied: Vec<JournalOp>,
+}
+
+impl ImageDatabase {
+    /// Apply `ops` in one transaction and record them as one undoable
+    /// entry labelled `label`. Returns the ops as applied — with the id
+    /// of a created tag, the images actually (un)tagged. Ops that
+    /// changed nothing aren't recorded; nor is an entry with none left.
+    pub fn journaled(&self, label: &str, ops: Vec<JournalOp>) -> rusqlite::Result<Vec<JournalOp>> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut recording = Recording::default();
+        for op in ops {
+            recording.apply(&tx, op)?;
+        }
+        let applied = recording.record(&tx, label)?;
+        tx.commit()?;
+        Ok(applied)
+    }
+
+    /// Reverse the newest entry not yet undone. `None` when there is
+    /// nothing to undo.
+    pub fn undo(&self) -> rusqlite::Result<Option<Replayed>> {
+        self.replay(true)
+    }
+
+    /// Re-apply the oldest undone entry. `None` when there is nothing
+    /// to redo.
+    pub fn redo(&self) -> rusqlite::Result<Option<Replayed>> {
+        self.replay(false)
+    }
+
+    /// Every entry, newest first.
+    pub fn journal_entries(&self) -> rusqlite::Result<Vec<JournalEntry>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT id, label, created_at, undone FROM journal ORDER BY id DESC")?;
+        let entries = stmt
+            .query_map([], |r| {
+                Ok(JournalEntry {
+                    id: r.get(0)?,
+                    label: r.get(1)?,
+                    created_at: r.get(2)?,
+                    undone: r.get(3)?,
+                })
+            })?
+            .collect();
+        entries
+    }
+
+    fn replay(&self, undoing: bool) -> rusqlite::Result<Option<Replayed>> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        let select = if undoing {
+            "SELECT id, label, created_at, inverse FROM journal
+             WHERE undone = 0 ORDER BY id DESC LIMIT 1"
+        } else {
+            "SELECT id, label, created_at, forward FROM journal
+             WHERE undone = 1 ORDER BY id ASC LIMIT 1"
+        };
+        let Some((entry, ops)) = tx
+            .query_row(select, [], |r| {
+                Ok((
+                    JournalEntry {
+                        id: r.get(0)?,
+                        label: r.get(1)?,
+                        created_at: r.get(2)?,
+                        undone: undoing,
+                    },
+                    r.get::<_, String>(3)?,
+                ))
+            })
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let ops: Vec<JournalOp> = serde_json::from_str(&ops).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(e))
+        })?;
+        let (applied, reversed) = apply_all(&tx, ops)?;
+        let (forward, inverse) = if undoing {
+            (&reversed, &applied)
+        } else {
+            (&applied, &reversed)
+        };
+        tx.execute(
+            "UPDATE journal SET undone = ?2, forward = ?3, inverse = ?4 WHERE id = ?1",
+            params![entry.id, undoing, to_json(forward)?, to_json(inverse)?],
+        )?;
+        tx.commit()?;
+        Ok(Some(Replayed { entry, applied }))
+    }
+}
+
+/// Ops applied so far in a transaction, to be recorded as one entry.
+/// For mutations whose later ops depend on what earlier ones did, such
+/// as tagging images with a tag the same entry creates.
+#[derive(Default)]
+pub(super) struct Recording {
+    applied: Vec<JournalOp>,
+    inverse: Vec<JournalOp>,
+}
+
+impl Recording {
+    /// Apply `op` as part of the entry. Returns it as applied, or
+    /// `None` if it changed nothing.
+    pub(super) fn apply(
+        &mut self,
+        conn: &Connection,
+        op: JournalOp,
+    ) -> rusqlite::Result<Option<&JournalOp>> {
+        let Some((done, undo)) = apply(conn, op)? else {
+            return Ok(None);
+        };
+        self.applied.push(done);
+        self.inverse.push(undo);
+        Ok(self.applied.last())
+    }
+
+    /// The id of the tag called `name`, created with `color` as part of
+    /// the entry if there is none.
+    pub(super) fn tag_named(
+        &mut self,
+        conn: &Connection,
+        name: &str,
+        color: &str,
+    ) -> rusqlite::Result<ID> {
+        if let Some(id) = conn
+            .query_row("SELECT id FROM tags WHERE name = ?1", [name], |r| r.get(0))
+            .optional()?
+        {
+            return Ok(id);
+        }
+        let created = self.apply(
+            conn,
+            JournalOp::CreateTag {
+                id: None,
+                name: name.to_string(),
+                color: color.to_string(),
+                parent_id: None,
+            },
+        )?;
+        match created {
+            Some(JournalOp::CreateTag { id: Some(id), .. }) => Ok(*id),
+            _ => Err(rusqlite::Error::QueryReturnedNoRows),
+        }
+    }
+
+    /// Record the ops applied as one entry labelled `label`, unless
+    /// none changed anything. Returns them as applied.
+    pub(super) fn record(self, conn: &Connection, label: &str) -> rusqlite::Result<Vec<JournalOp>> {
+        let (applied, inverse) = self.finish();
+        if !applied.is_empty() {
+            conn.execute("DELETE FROM journal WHERE undone = 1", [])?;
+            conn.execute(
+                "INSERT INTO journal (label, forward, inverse, created_at) VALUES (?1, ?2, ?3, ?4)",
+                params![label, to_json(&applied)?, to_json(&inverse)?, now()],
+            )?;
+            conn.execute(
+                "DELETE FROM journal WHERE id NOT IN
+                     (SELECT id FROM journal ORDER BY id DESC LIMIT ?1)",
+                [MAX_JOURNAL_ENTRIES as i64],
+            )?;
+        }
+        Ok(applied)
+    }
+
+    /// The ops applied, and their inverses in the order that undoes them.
+    fn finish(mut self) -> (Vec<JournalOp>, Vec<JournalOp>) {
+        self.inverse.reverse();
+        (self.applied, self.inverse)
+    }
+}
+
+/// Apply `ops` in order. Returns those that changed something, as
+/// applied, and their inverses in the order that undoes them.
+fn apply_all(
+    conn: &Connection,
+    ops: Vec<JournalOp>,
+) -> rusqlite::Result<(Vec<JournalOp>, Vec<JournalOp>)> {
+    let mut recording = Recording::default();
+    for op in ops {
+        recording.apply(conn, op)?;
+    }
+    Ok(recording.finish())
+}
+
+/// Apply one op: the op as applied and its inverse, or `None` if it
+/// changed nothing.
+fn apply(conn: &Connection, op: JournalOp) -> rusqlite::Result<Option<(JournalOp, JournalOp)>> {
+    Ok(match op {
+        JournalOp::CreateTag {
+            id,
+            name,
+            color,
+            parent_id,
+        } => {
+            conn.execute(
+                "INSERT INTO tags (id, name, color, parent_id)
+                 VALUES (?1, ?2, ?3, (SELECT id FROM tags WHERE id = ?4))",
+                params![id, name, color, parent_id],
+            )?;
+            let id = conn.last_insert_rowid();
+            Some((
+                JournalOp::CreateTag {
+                    id: Some(id),
+                    name,
+                    color,
+                    parent_id,
+                },
+                JournalOp::DeleteTag { id },
+            ))
+        }
+        JournalOp::DeleteTag { id } => {
+            let Some(tag) = find_tag(conn, id)? else {
+                return Ok(None);
+            };
+            let children = column(
+                conn,
+                "SELECT id FROM tags WHERE parent_id = ?1 ORDER BY id",
+                id,
+            )?;
+            let aliases = column(
+                conn,
+                "SELECT name FROM tag_aliases WHERE tag_id = ?1 ORDER BY name",
+                id,
+            )?;
+            let image_ids = column(
+                conn,
+                "SELECT image_id FROM images_tags WHERE tag_id = ?1 ORDER BY image_id",
+                id,
+            )?;
+            delete_tag_lifting_children(conn, id)?;
+            Some((
+                JournalOp::DeleteTag { id },
+                JournalOp::RestoreTag {
+                    tag,
+                    children,
+                    aliases,
+                    image_ids,
+                },
+            ))
+        }
+        JournalOp::RestoreTag {
+            tag,
+            children,
+            aliases,
+            image_ids,
+        } => {
+            conn.execute(
+                "INSERT INTO tags (id, name, color, parent_id)
+                 VALUES (?1, ?2, ?3, (SELECT id FROM tags WHERE id = ?4))",
+                params![tag.id, tag.name, tag.color, tag.parent_id],
+            )?;
+            for child in &children {
+                conn.execute(
+                    "UPDATE tags SET parent_id = ?1 WHERE id = ?2",
+                    [tag.id, *child],
+                )?;
+            }
+            for alias in &aliases {
+                conn.execute(
+                    "INSERT OR IGNORE INTO tag_aliases (name, tag_id) VALUES (?1, ?2)",
+                    params![alias, tag.id],
+                )?;
+            }
+            let image_ids = link_images(conn, tag.id, &image_ids)?;
+            let id = tag.id;
+            Some((
+                JournalOp::RestoreTag {
+                    tag,
+                    children,
+                    aliases,
+                    image_ids,
+                },
+                JournalOp::DeleteTag { id },
+            ))
+        }
+        JournalOp::MergeTag {
+            source_id,
+            target_id,
+            ..
+        } => {
+            let (Some(tag), Some(_)) = (find_tag(conn, source_id)?, find_tag(conn, target_id)?)
+            else {
+                return Ok(None);
+            };
+            let children = column(
+                conn,
+                "SELECT id FROM tags WHERE parent_id = ?1 ORDER BY id",
+                source_id,
+            )?;
+            let aliases = column(
+                conn,
+                "SELECT name FROM tag_aliases WHERE tag_id = ?1 ORDER BY name",
+                source_id,
+            )?;
+            let image_ids = column(
+                conn,
+                "SELECT image_id FROM images_tags WHERE tag_id = ?1 ORDER BY image_id",
+                source_id,
+            )?;
+            let gained = merge_tag(conn, source_id, target_id)?;
+            Some((
+                JournalOp::MergeTag {
+                    source_id,
+                    target_id,
+                    gained: gained.clone(),
+                },
+                JournalOp::SplitTag {
+                    tag,
+                    target_id,
+                    children,
+                    aliases,
+                    image_ids,
+                    gained,
+                },
+            ))
+        }
+        JournalOp::SplitTag {
+            tag,
+            target_id,
+            children,
+            aliases,
+            image_ids,
+            gained,
+        } => {
+            conn.execute(
+                "INSERT INTO tags (id, name, color, parent_id)
+                 VALUES (?1, ?2, ?3, (SELECT id FROM tags WHERE id = ?4))",
+                params![tag.id, tag.name, tag.color, tag.parent_id],
+            )?;
+            // The merge made the tag's name an alias of the target.
+            conn.execute(
+                "DELETE FROM tag_aliases WHERE name = ?1 AND tag_id = ?2",
+                params![tag.name, target_id],
+            )?;
+            for alias in &aliases {
+                conn.execute(
+                    "UPDATE tag_aliases SET tag_id = ?1 WHERE name = ?2 AND tag_id = ?3",
+                    params![tag.id, alias, target_id],
+                )?;
+            }
+            for child in &children {
+                conn.execute(
+                    "UPDATE tags SET parent_id = ?1 WHERE id = ?2 AND parent_id = ?3",
+                    [tag.id, *child, target_id],
+                )?;
+            }
+            let image_ids = link_images(conn, tag.id, &image_ids)?;
+            let gained = unlink_images(conn, target_id, &gained)?;
+            let source_id = tag.id;
+            Some((
+                JournalOp::SplitTag {
+                    tag,
+                    target_id,
+                    children,
+                    aliases,
+                    image_ids,
+                    gained,
+                },
+                JournalOp::MergeTag {
+                    source_id,
+                    target_id,
+                    gained: Vec::new(),
+                },
+            ))
+        }
+        JournalOp::TagImages { tag_id, image_ids } => {
+            let image_ids = link_images(conn, tag_id, &image_ids)?;
+            (!image_ids.is_empty()).then(|| {
+                (
+                    JournalOp::TagImages {
+                        tag_id,
+                        image_ids: image_ids.clone(),
+                    },
+                    JournalOp::UntagImages { tag_id, image_ids },
+                )
+            })
+        }
+        JournalOp::UntagImages { tag_id, image_ids } => {
+            let image_ids = unlink_images(conn, tag_id, &image_ids)?;
+            (!image_ids.is_empty()).then(|| {
+                (
+                    JournalOp::UntagImages {
+                        tag_id,
+                        image_ids: image_ids.clone(),
+                    },
+                    JournalOp::TagImages { tag_id, image_ids },
+                )
+            })
+        }
+        JournalOp::SetNotes { image_id, notes } => {
+            let Some(old) = conn
+                .query_row("SELECT notes FROM images WHERE id = ?1", [image_id], |r| {
+                    r.get::<_, Option<String>>(0)
+                })
+                .optional()?
+            else {
+                return Ok(None);
+            };
+            let notes = notes.as_deref().and_then(clean_notes).map(str::to_string);
+            if notes == old {
+                return Ok(None);
+            }
+            conn.execute(
+                "UPDATE images SET notes = ?1 WHERE id = ?2",
+                params![notes, image_id],
+            )?;
+            Some((
+                JournalOp::SetNotes { image_id, notes },
+                JournalOp::SetNotes {
+                    image_id,
+                    notes: old,
+                },
+            ))
+        }
+        JournalOp::RemoveRoot { id } => soft_remove_root(conn, id)?
+            .then_some((JournalOp::RemoveRoot { id }, JournalOp::RestoreRoot { id })),
+        JournalOp::RestoreRoot { id } => restore_root(conn, id)?
+            .then_some((JournalOp::RestoreRoot { id }, JournalOp::RemoveRoot { id })),
+    })
+}
+
+/// The first column of `sql` run with `id`, in order.
+fn column<T: rusqlite::types::FromSql>(
+    conn: &Connection,
+    sql: &str,
+    id: ID,
+) -> rusqlite::Result<Vec<T>> {
+    let mut stmt = conn.prepare(sql)?;
+    let values = stmt.query_map([id], |r| r.get(0))?.collect();
+    values
+}
+
+fn to_json(ops: &[JournalOp]) -> rusqlite::Result<String> {
+    serde_json::to_string(ops).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::fresh_db;
+    use super::*;
+
+    fn images(db: &ImageDatabase, root_id: Option<ID>, n: usize) {
+        for i in 1..=n {
+            db.add_image(format!("/lib/{i}.jpg"), root_id).unwrap();
+        }
+    }
+
+    fn create_tag(db: &ImageDatabase, name: &str, parent_id: Option<ID>) -> ID {
+        match &db
+            .journaled(
+                "create",
+                vec![JournalOp::CreateTag {
+                    id: None,
+                    name: name.into(),
+                    color: "#fff".into(),
+                    parent_id,
+                }],
+            )
+            .unwrap()[0]
+        {
+            JournalOp::CreateTag { id, .. } => id.unwrap(),
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn a_deleted_tag_comes_back_with_its_links_children_and_aliases() {
+        let db = fresh_db();
+        images(&db, None, 3);
+        let animals = create_tag(&db, "animals", None);
+        let cat = create_tag(&db, "cat", Some(animals));
+        db.add_tag_alias(animals, "fauna").unwrap();
+        db.journaled(
+            "tag",
+            vec![JournalOp::TagImages {
+                tag_id: animals,
+                image_ids: vec![1, 2, 99],
+            }],
+        )
+        .unwrap();
+
+        db.journaled("delete", vec![JournalOp::DeleteTag { id: animals }])
+            .unwrap();
+        assert!(db.get_tag(animals).is_err());
+        assert_eq!(db.get_tag(cat).unwrap().parent_id, None);
+
+        let undone = db.undo().unwrap().unwrap();
+        assert_eq!(undone.entry.label, "delete");
+        assert_eq!(db.get_tag(animals).unwrap().name, "animals");
+        assert_eq!(db.get_tag(cat).unwrap().parent_id, Some(animals));
+        assert_eq!(db.get_image_ids_with_tag(animals).unwrap(), vec![1, 2]);
+        assert_eq!(db.resolve_tag_name("fauna").unwrap().unwrap().id, animals);
+
+        // Back through the tagging and the creates, then forward again.
+        db.undo().unwrap().unwrap();
+        assert!(db.get_image_ids_with_tag(animals).unwrap().is_empty());
+        db.undo().unwrap().unwrap();
+        db.undo().unwrap().unwrap();
+        assert!(db.get_tags().unwrap().is_empty());
+        assert!(db.undo().unwrap().is_none());
+
+        for _ in 0..3 {
+            db.redo().unwrap().unwrap();
+        }
+        assert_eq!(db.get_image_ids_with_tag(animals).unwrap(), vec![1, 2]);
+        assert_eq!(db.redo().unwrap().unwrap().entry.label, "delete");
+        assert!(db.get_tag(animals).is_err());
+        assert!(db.redo().unwrap().is_none());
+    }
+
+    #[test]
+    fn an_undone_root_removal_brings_the_folder_back_as_it_was() {
+        let db = fresh_db();
+        let root = db.add_root("/lib".into()).unwrap();
+        images(&db, Some(root.id), 2);
+        let cat = create_tag(&db, "cat", None);
+        db.journaled(
+            "tag",
+            vec![JournalOp::TagImages {
+                tag_id: cat,
+                image_ids: vec![1, 2],
+            }],
+        )
+        .unwrap();
+        db.journaled(
+            "notes",
+            vec![JournalOp::SetNotes {
+                image_id: 2,
+                notes: Some("  harbour at dusk ".into()),
+            }],
+        )
+        .unwrap();
+
+        db.journaled("remove", vec![JournalOp::RemoveRoot { id: root.id }])
+            .unwrap();
+        assert!(db.list_roots().unwrap().is_empty());
+        assert!(db.get_all_images().unwrap().is_empty());
+
+        db.undo().unwrap().unwrap();
+        assert_eq!(db.list_roots().unwrap(), vec![root.clone()]);
+        assert_eq!(db.get_all_images().unwrap().len(), 2);
+        assert_eq!(db.get_image_ids_with_tag(cat).unwrap(), vec![1, 2]);
+        assert_eq!(
+            db.get_image_notes(2).unwrap().as_deref(),
+            Some("harbour at dusk")
+        );
+
+        // Removed again and purged: nothing is left to bring back.
+        db.redo().unwrap().unwrap();
+        assert_eq!(db.purge_removed_roots(i64::MAX).unwrap(), vec![root.id]);
+        let replayed = db.undo().unwrap().unwrap();
+        assert!(replayed.applied.is_empty());
+        assert!(db.list_roots().unwrap().is_empty());
+        assert!(db.get_image_ids_with_tag(cat).unwrap().is_empty());
+    }
+
+    #[test]
+    fn an_undone_merge_splits_the_tags_apart_again() {
+        let db = fresh_db();
+        images(&db, None, 3);
+        let bw = create_tag(&db, "black and white", None);
+        let mono = create_tag(&db, "mono", None);
+        let film = create_tag(&db, "film", Some(mono));
+        db.add_tag_alias(mono, "monochrome").unwrap();
+        for (tag_id, image_ids) in [(bw, vec![1]), (mono, vec![1, 2])] {
+            db.journaled("tag", vec![JournalOp::TagImages { tag_id, image_ids }])
+                .unwrap();
+        }
+
+        let applied = db
+            .journaled(
+                "merge",
+                vec![JournalOp::MergeTag {
+                    source_id: mono,
+                    target_id: bw,
+                    gained: Vec::new(),
+                }],
+            )
+            .unwrap();
+        assert!(matches!(&applied[..], [JournalOp::MergeTag { gained, .. }] if gained == &[2]));
+        assert!(db.get_tag(mono).is_err());
+        assert_eq!(db.resolve_tag_name("mono").unwrap().unwrap().id, bw);
+
+        db.undo().unwrap().unwrap();
+        assert_eq!(db.get_tag(mono).unwrap().name, "mono");
+        assert_eq!(db.get_tag(film).unwrap().parent_id, Some(mono));
+        assert_eq!(db.resolve_tag_name("monochrome").unwrap().unwrap().id, mono);
+        assert_eq!(db.resolve_tag_name("mono").unwrap().unwrap().id, mono);
+        assert_eq!(db.get_image_ids_with_tag(mono).unwrap(), vec![1, 2]);
+        assert_eq!(db.get_image_ids_with_tag(bw).unwrap(), vec![1]);
+
+        db.redo().unwrap().unwrap();
+        assert!(db.get_tag(mono).is_err());
+        assert_eq!(db.get_tag(film).unwrap().parent_id, Some(bw));
+        assert_eq!(db.get_image_ids_with_tag(bw).unwrap(), vec![1, 2]);
+        assert_eq!(db.resolve_tag_name("monochrome").unwrap().unwrap().id, bw);
+    }
+
+    #[test]
+    fn accepted_suggestions_and_tagged_boards_can_be_undone() {
+        let db = fresh_db();
+        images(&db, None, 3);
+        let label = db.add_tag_label("dog", &[]).unwrap();
+        db.replace_pending_suggestions(label.id, "clip_vit_b_32", 0.2, &[(1, 0.9), (2, 0.8)])
+            .unwrap();
+        assert_eq!(db.accept_tag_suggestions(label.id, None).unwrap(), 2);
+        let dog = db.resolve_tag_name("dog").unwrap().unwrap().id;
+        assert_eq!(db.get_image_ids_with_tag(dog).unwrap(), vec![1, 2]);
+
+        // The tag the accept created goes too.
+        assert_eq!(
+            db.undo().unwrap().unwrap().entry.label,
+            "Accept suggested tag \"dog\""
+        );
+        assert!(db.get_tags().unwrap().is_empty());
+
+        let cat = create_tag(&db, "cat", None);
+        db.journaled(
+            "tag",
+            vec![JournalOp::TagImages {
+                tag_id: cat,
+                image_ids: vec![1],
+            }],
+        )
+        .unwrap();
+        let ids = db
+            .replace_clusters(
+                "dinov2_base",
+                &[super::super::NewCluster {
+                    centroid: vec![1.0, 0.0],
+                    medoid_image_id: 1,
+                    label: None,
+                    label_score: None,
+                    members: vec![(1, 0.9), (3, 0.8)],
+                }],
+            )
+            .unwrap();
+        assert_eq!(db.tag_cluster(ids[0], "cat").unwrap().1, 1);
+        db.undo().unwrap().unwrap();
+        // Only the image the board tagged loses the tag.
+        assert_eq!(db.get_image_ids_with_tag(cat).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn new_entries_drop_the_redo_branch_and_history_is_bounded() {
+        let db = fresh_db();
+        images(&db, None, 1);
+        let cat = create_tag(&db, "cat", None);
+        let tag = |db: &ImageDatabase| {
+            db.journaled(
+                "tag",
+                vec![JournalOp::TagImages {
+                    tag_id: cat,
+                    image_ids: vec![1],
+                }],
+            )
+            .unwrap()
+        };
+        tag(&db);
+        // Already tagged: nothing changes, nothing is recorded.
+        assert!(tag(&db).is_empty());
+        assert_eq!(db.journal_entries().unwrap().len(), 2);
+
+        db.undo().unwrap().unwrap();
+        assert!(db.journal_entries().unwrap()[0].undone);
+        create_tag(&db, "dog", None);
+        assert!(db.redo().unwrap().is_none());
+        assert!(db.journal_entries().unwrap().iter().all(|e| !e.undone));
+
+        for i in 0..MAX_JOURNAL_ENTRIES {
+            create_tag(&db, &format!("t{i}"), None);
+        }
+        let entries = db.journal_entries().unwrap();
+        assert_eq!(entries.len(), MAX_JOURNAL_ENTRIES);
+        assert_eq!(entries.last().unwrap().id, 3);
+    }
+
+    #[test]
+    fn a_clashing_restore_fails_and_keeps_the_entry() {
+        let db = fresh_db();
+        let cat = create_tag(&db, "cat", None);
+        db.journaled("delete", vec![JournalOp::DeleteTag { id: cat }])
+            .unwrap();
+        db.create_tag("cat".into(), "#000".into()).unwrap();
+        assert!(db.undo().is_err());
+        assert!(!db.journal_entries().unwrap()[0].undone);
+    }
+}
@@ -0,0 +1,51 @@
+//! Generic key/value access to the `meta` table.
+//!
+//! `meta` was created for the migration registry (`schema_version`,
+//! `embedding_pipeline_version`), which reads and writes its own keys
+//! inline. These helpers are for everything else that wants a small
+//! piece of per-library state next to the data it describes — e.g. the
+//! per-encoder quantisation parameters in `cosine::quant`.
+
+use super::ImageDatabase;
+use rusqlite::OptionalExtension;
+
+impl ImageDatabase {
+    /// Value stored under `key`, or `None` if the key was never set.
+    pub fn get_meta(&self, key: &str) -> rusqlite::Result<Option<String>> {
+        let conn = self.read_lock();
+        conn.query_row(
+            "SELECT value FROM meta WHERE key = ?1",
+            rusqlite::params![key],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+    }
+
+    /// Insert or overwrite `key`.
+    pub fn set_meta(&self, key: &str, value: &str) -> rusqlite::Result<()> {
+        let conn = self.connection.lock().unwrap();
+        conn.execute(
+            "INSERT INTO meta (key, value) VALUES (?1, ?2) \
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            rusqlite::params![key, value],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::fresh_db;
+
+    #[test]
+    fn set_meta_inserts_then_overwrites() {
+        let db = fresh_db();
+        assert_eq!(db.get_meta("quant_params:test").unwrap(), None);
+        db.set_meta("quant_params:test", "a").unwrap();
+        db.set_meta("quant_params:test", "b").unwrap();
+        assert_eq!(
+            db.get_meta("quant_params:test").unwrap().as_deref(),
+            Some("b")
+        );
+    }
+}
@@ -13,21 +13,70 @@
 
 use std::sync::{Mutex, OnceLock};
 
+mod clusters;
+pub mod content_hash;
+mod duplicates;
 mod embeddings;
+mod fulltext;
+mod grid_pages;
 pub mod images_query;
+mod journal;
+mod meta;
 mod notes_orphans;
 mod roots;
 mod schema_migrations;
+mod search_filter;
+mod tag_aliases;
+mod tag_suggestions;
+mod tag_tree;
 mod tags;
 mod thumbnails;
 
 #[cfg(test)]
 mod test_helpers;
 
+pub use clusters::{Cluster, ClusterCentroid, ClusterMember, NewCluster};
+pub use duplicates::DuplicateCandidate;
+pub use embeddings::EmbeddingSetStamp;
+pub use grid_pages::{GridCursor, GridQuery, GridSort};
+pub use journal::{JournalEntry, JournalOp, Replayed, MAX_JOURNAL_ENTRIES};
+pub use roots::REMOVED_ROOT_RETENTION_SECS;
+pub use schema_migrations::EMBEDDING_PIPELINE_VERSION;
+pub use search_filter::SearchFilter;
+pub use tag_aliases::TagAlias;
+pub use tag_suggestions::{TagLabel, TagSuggestion};
+
 /// Numeric identifier shared by every row type in this DB (images,
 /// roots, tags). Always SQLite `INTEGER` (i.e. `i64`).
 pub type ID = i64;
 
+/// Which roots' images a query sees. A removed root's images are gone
+/// from every scope; only `roots.rs` looks past `removed_at`.
+#[derive(Debug, Clone, Copy)]
+enum RootScope {
+    /// Every root not removed: by-id lookups, tags, notes, stats.
+    Live,
+    /// Live and enabled: the grid, search, duplicates, boards.
+    Enabled,
+    /// Live and online: the pipeline's work lists.
+    Online,
+}
+
+/// SQL predicate over an images row's `root_id` column (`root_id`,
+/// `i.root_id`, ...): unrooted, or under a root in `scope`. Every image
+/// query filters through this so "removed" means the same everywhere.
+fn root_filter(root_id: &str, scope: RootScope) -> String {
+    let extra = match scope {
+        RootScope::Live => "",
+        RootScope::Enabled => " AND enabled = 1",
+        RootScope::Online => " AND offline = 0",
+    };
+    format!(
+        "({root_id} IS NULL
+         OR {root_id} IN (SELECT id FROM roots WHERE removed_at IS NULL{extra}))"
+    )
+}
+
 /// SQLite-backed image catalogue.
 ///
 /// Two connections per real on-disk database:
@@ -175,116 +224,15 @@ impl ImageDatabase {
             .unwrap()
             .execute("PRAGMA foreign_keys = ON;", [])?;
 
-        // Roots table — created here so the images table's root_id FK
-        // has a target. Multi-folder support (Phase 6); existing single-
-        // folder users get migrated below.
-        self.connection.lock().unwrap().execute(
-            "CREATE TABLE IF NOT EXISTS roots (
-                id INTEGER PRIMARY KEY,
-                path TEXT NOT NULL UNIQUE,
-                enabled INTEGER NOT NULL DEFAULT 1,
-                added_at INTEGER NOT NULL
-            );",
-            [],
-        )?;
-
-        // Images table — `notes` and `orphaned` are Phase 11 / Phase 7
-        // additions; `root_id` is Phase 6. Existing DBs migrate via
-        // ALTER TABLE below.
-        self.connection.lock().unwrap().execute(
-            "CREATE TABLE IF NOT EXISTS images (
-                id INTEGER PRIMARY KEY,
-                path TEXT NOT NULL UNIQUE,
-                embedding BLOB,
-                thumbnail_path TEXT,
-                width INTEGER,
-                height INTEGER,
-                root_id INTEGER REFERENCES roots(id) ON DELETE CASCADE,
-                notes TEXT,
-                orphaned INTEGER NOT NULL DEFAULT 0
-            );",
-            [],
-        )?;
-
-        // Migrations for existing DBs: add thumbnail columns, then
-        // multi-folder columns, then notes/orphaned. Each is gated by
-        // a PRAGMA table_info check so they're idempotent.
-        self.migrate_add_thumbnail_columns()?;
-        self.migrate_add_multifolder_columns()?;
-        self.migrate_add_notes_and_orphaned_columns()?;
-
-        self.connection.lock().unwrap().execute(
-            "CREATE TABLE IF NOT EXISTS tags (
-                id INTEGER PRIMARY KEY,
-                name TEXT NOT NULL UNIQUE,
-                color TEXT NOT NULL
-            );",
-            [],
-        )?;
-
-        self.connection.lock().unwrap().execute(
-            "CREATE TABLE IF NOT EXISTS images_tags (
-                image_id INTEGER NOT NULL,
-                tag_id INTEGER NOT NULL,
-                PRIMARY KEY (image_id, tag_id),
-                FOREIGN KEY (image_id) REFERENCES images(id) ON DELETE CASCADE,
-                FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
-            );",
-            [],
-        )?;
-
-        // Per-encoder embeddings table (Phase 2 of the encoder picker
-        // work). Lets the project hold multiple embeddings per image —
-        // one per encoder model — so the user can search via SigLIP-2,
-        // CLIP, DINOv2, etc., all from the same DB.
-        //
-        // Why a separate table rather than more columns on `images`:
-        // adding a new encoder = inserting rows, not migrating schema.
-        // Storage cost ~2KB per (image × encoder) — negligible.
-        //
-        // The legacy `images.embedding` column is preserved for one
-        // release cycle; the indexing pipeline now also writes the
-        // CLIP embedding to this table with encoder_id="clip_vit_b_32".
-        // A future migration can drop the old column once everyone has
-        // re-indexed.
-        self.connection.lock().unwrap().execute(
-            "CREATE TABLE IF NOT EXISTS embeddings (
-                image_id INTEGER NOT NULL,
-                encoder_id TEXT NOT NULL,
-                embedding BLOB NOT NULL,
-                PRIMARY KEY (image_id, encoder_id),
-                FOREIGN KEY (image_id) REFERENCES images(id) ON DELETE CASCADE
-            );",
-            [],
-        )?;
-        // Helps populate_from_db, which scans by encoder_id, to skip
-        // the table-scan step.
-        self.connection.lock().unwrap().execute(
-            "CREATE INDEX IF NOT EXISTS idx_embeddings_encoder
-             ON embeddings(encoder_id);",
-            [],
-        )?;
-        // R9 — composite index on (root_id, orphaned). Every foreground
-        // SELECT against `images` that powers the grid filters by both
-        // (`orphaned = 0 AND (root_id IS NULL OR root_id IN (...))`).
-        // Without this, SQLite full-scans the images table; once
-        // libraries grow past a few thousand rows the scan cost
-        // becomes visible inside `get_images.row_iter` (one of the
-        // Batch 3 subspans) as the dominant component of the
-        // foreground SELECT. NULL root_id rows still match this index
-        // — SQLite indexes nulls in composite indexes since it can use
-        // the index even when the leading column is NULL. (root_id
-        // first means the OR-NULL branch and the IN-list branch both
-        // benefit; orphaned second means we still get a scan range
-        // narrowing for the `orphaned = 0` predicate.)
-        self.connection.lock().unwrap().execute(
-            "CREATE INDEX IF NOT EXISTS idx_images_root_orphaned
-             ON images(root_id, orphaned);",
-            [],
-        )?;
+        // Every table, column and index comes from the versioned
+        // migration registry (schema_migrations.rs). Fresh installs run
+        // the whole registry; older files run only the steps above the
+        // `schema_version` recorded in `meta`. A file written by a
+        // newer build is refused here rather than opened.
+        self.run_schema_migrations()?;
 
         // One-shot embedding-pipeline invalidation. Runs AFTER the
-        // embeddings table is created (it issues DELETE against that
+        // schema migrations (it issues DELETE against the embeddings
         // table). Bumps when CLIP/DINOv2 pipeline changes invalidate
         // prior embeddings.
         self.migrate_embedding_pipeline_version()?;
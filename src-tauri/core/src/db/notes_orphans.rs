@@ -8,12 +8,14 @@
 //!     text annotations column.
 //!   * `mark_orphaned` is the Phase-7 deleted-from-disk lifecycle —
 //!     called by the indexing pipeline's orphan-detection pass.
+//!     `mark_path_orphaned` / `revive_orphaned_path` are the per-path
+//!     versions the filesystem watcher uses between full scans.
 
 use std::collections::HashSet;
 
 use rusqlite::{params, params_from_iter};
 
-use super::{ID, ImageDatabase};
+use super::{root_filter, ID, ImageDatabase, RootScope};
 
 impl ImageDatabase {
     /// Set or clear the orphaned flag on every image in a given root.
@@ -79,6 +81,37 @@ impl ImageDatabase {
         Ok(updated)
     }
 
+    /// Per-path counterpart of `mark_orphaned` for the filesystem
+    /// watcher: orphan the row at `path` or, when `path` was a
+    /// directory, every row below it. Scoped to one root so a removed
+    /// folder can't touch a nested root's rows. Returns the number of
+    /// rows newly orphaned.
+    pub fn mark_path_orphaned(&self, root_id: ID, path: &str) -> rusqlite::Result<usize> {
+        let dir_prefix = format!(
+            "{}{}",
+            path.trim_end_matches(std::path::MAIN_SEPARATOR),
+            std::path::MAIN_SEPARATOR
+        );
+        // substr rather than LIKE: paths may contain `%` and `_`.
+        self.connection.lock().unwrap().execute(
+            "UPDATE images SET orphaned = 1
+             WHERE root_id = ?1 AND orphaned = 0
+               AND (path = ?2 OR substr(path, 1, length(?3)) = ?3)",
+            params![root_id, path, dir_prefix],
+        )
+    }
+
+    /// Clear the orphaned flag on the row at `path` — the file came
+    /// back (undo of a delete, restore from trash). Returns whether a
+    /// row was revived.
+    pub fn revive_orphaned_path(&self, path: &str) -> rusqlite::Result<bool> {
+        let n = self.connection.lock().unwrap().execute(
+            "UPDATE images SET orphaned = 0 WHERE path = ?1 AND orphaned = 1",
+            [path],
+        )?;
+        Ok(n > 0)
+    }
+
     /// Insert an image path. With multi-folder support each row remembers
     /// which root it came from. Idempotent via `INSERT OR IGNORE` on the
     /// path uniqueness constraint — a re-scan never duplicates rows.
@@ -102,10 +135,17 @@ impl ImageDatabase {
     }
 
     /// Read the free-text annotation for an image. Returns Ok(None)
-    /// when the row exists but the column is NULL (default).
+    /// when the row exists but the column is NULL (default), and
+    /// `QueryReturnedNoRows` for an unknown id or an image of a removed
+    /// root.
     pub fn get_image_notes(&self, image_id: ID) -> rusqlite::Result<Option<String>> {
         let conn = self.connection.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT notes FROM images WHERE id = ?1")?;
+        // A removed root's notes wait, out of reach, until the root is
+        // restored or purged.
+        let mut stmt = conn.prepare(&format!(
+            "SELECT notes FROM images WHERE id = ?1 AND {}",
+            root_filter("root_id", RootScope::Live)
+        ))?;
         let mut rows = stmt.query([image_id])?;
         match rows.next()? {
             Some(row) => row.get::<_, Option<String>>(0),
@@ -116,20 +156,29 @@ impl ImageDatabase {
     /// Set / clear the free-text annotation. Pass an empty string or
     /// "" to clear; we don't bother distinguishing "" from NULL because
     /// the user-facing semantic is the same ("no annotation").
+    /// `QueryReturnedNoRows` for an unknown id or an image of a removed
+    /// root.
     pub fn set_image_notes(&self, image_id: ID, notes: &str) -> rusqlite::Result<()> {
-        let cleaned = notes.trim();
-        let val: Option<&str> = if cleaned.is_empty() { None } else { Some(cleaned) };
-        self.connection
-            .lock()
-            .unwrap()
-            .execute(
-                "UPDATE images SET notes = ?1 WHERE id = ?2",
-                params![val, image_id],
-            )?;
+        let updated = self.connection.lock().unwrap().execute(
+            &format!(
+                "UPDATE images SET notes = ?1 WHERE id = ?2 AND {}",
+                root_filter("root_id", RootScope::Live)
+            ),
+            params![clean_notes(notes), image_id],
+        )?;
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
         Ok(())
     }
 }
 
+/// Notes as stored: trimmed, and `None` when nothing is left.
+pub(super) fn clean_notes(notes: &str) -> Option<&str> {
+    let cleaned = notes.trim();
+    (!cleaned.is_empty()).then_some(cleaned)
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::test_helpers::fresh_db;
@@ -206,6 +255,38 @@ mod tests {
         assert_eq!(visible[0].path, "/b/1.jpg");
     }
 
+    #[test]
+    fn mark_path_orphaned_covers_file_and_directory_removals() {
+        let db = fresh_db();
+        let r = db.add_root("/r".into()).unwrap();
+        let nested = db.add_root("/r/nested".into()).unwrap();
+        for p in ["/r/a.jpg", "/r/album/1.jpg", "/r/album/2.jpg", "/r/album2/x.jpg"] {
+            db.add_image(p.into(), Some(r.id)).unwrap();
+        }
+        db.add_image("/r/nested/n.jpg".into(), Some(nested.id)).unwrap();
+
+        assert_eq!(db.mark_path_orphaned(r.id, "/r/a.jpg").unwrap(), 1);
+        // A directory prefix must not match a sibling that merely
+        // shares the name ("album" vs "album2").
+        assert_eq!(db.mark_path_orphaned(r.id, "/r/album").unwrap(), 2);
+        // Rows belonging to a nested root are out of scope.
+        assert_eq!(db.mark_path_orphaned(r.id, "/r/nested").unwrap(), 0);
+        // Already-orphaned rows aren't counted twice.
+        assert_eq!(db.mark_path_orphaned(r.id, "/r/album/").unwrap(), 0);
+
+        let visible: Vec<String> = db
+            .get_images_with_thumbnails(vec![], "".into(), false)
+            .unwrap()
+            .into_iter()
+            .map(|i| i.path)
+            .collect();
+        assert_eq!(visible.len(), 2);
+        assert!(visible.contains(&"/r/album2/x.jpg".to_string()));
+
+        assert!(db.revive_orphaned_path("/r/a.jpg").unwrap());
+        assert!(!db.revive_orphaned_path("/r/a.jpg").unwrap());
+    }
+
     #[test]
     fn mark_orphaned_chunks_handle_large_libraries() {
         // The chunked-IN logic kicks in above 500 ids. Stress with 1200
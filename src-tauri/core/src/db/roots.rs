@@ -4,21 +4,38 @@
 //! table's `root_id` FK points back here so the grid query can filter
 //! by enabled roots, and `ON DELETE CASCADE` propagates root removal
 //! into the images it owns.
+//!
+//! Removal is a soft delete (schema migration 11): `removed_at` is set
+//! and every image query treats the root's rows as gone, but the rows,
+//! embeddings and thumbnails stay. Re-adding the same path brings them
+//! back as they were; `purge_removed_roots` deletes for real — CASCADE
+//! and all — once a removal is older than the retention window.
+//!
+//! `offline` (migration 12) marks a root whose folder is missing, kept
+//! up to date by `root_availability`. Unlike removal it hides nothing:
+//! the images stay in the grid, served from their thumbnails.
 
+use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use rusqlite::params;
+use rusqlite::{params, Connection, OptionalExtension};
 use tracing::info;
 
 use super::{ID, ImageDatabase};
 use crate::root_struct::Root;
 
+/// How long a removed root stays restorable before
+/// `Library::purge_removed_roots` deletes it: 30 days.
+pub const REMOVED_ROOT_RETENTION_SECS: i64 = 30 * 24 * 60 * 60;
+
 impl ImageDatabase {
     /// List every configured root, ordered by add date (oldest first).
+    /// Removed roots are left out.
     pub fn list_roots(&self) -> rusqlite::Result<Vec<Root>> {
         let conn = self.connection.lock().unwrap();
         let mut stmt = conn.prepare(
-            "SELECT id, path, enabled, added_at FROM roots ORDER BY added_at ASC",
+            "SELECT id, path, enabled, added_at, offline FROM roots
+             WHERE removed_at IS NULL ORDER BY added_at ASC",
         )?;
         let rows = stmt.query_map([], |r| {
             Ok(Root {
@@ -26,6 +43,7 @@ impl ImageDatabase {
                 path: r.get(1)?,
                 enabled: r.get::<_, i64>(2)? != 0,
                 added_at: r.get(3)?,
+                offline: r.get::<_, i64>(4)? != 0,
             })
         })?;
         rows.collect::<rusqlite::Result<Vec<_>>>()
@@ -34,23 +52,62 @@ impl ImageDatabase {
     /// Insert a new root. Returns the populated Root row. The path
     /// uniqueness constraint surfaces as an `Err` to the caller when
     /// the user adds the same path twice.
+    ///
+    /// A removed root with the same path is restored instead — same id,
+    /// enabled, with its images, tags, notes, embeddings and thumbnails.
+    /// A new root adopts the images of removed roots that fall under it,
+    /// so they aren't encoded again either; their thumbnails regenerate
+    /// in the new root's folder, the old one goes with the purge.
     pub fn add_root(&self, path: String) -> rusqlite::Result<Root> {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_secs() as i64)
-            .unwrap_or(0);
-        let conn = self.connection.lock().unwrap();
-        conn.execute(
-            "INSERT INTO roots (path, enabled, added_at) VALUES (?1, 1, ?2)",
-            params![path, now],
-        )?;
-        let id = conn.last_insert_rowid();
-        Ok(Root::new(id, path, true, now))
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        let removed: Option<(ID, i64)> = tx
+            .query_row(
+                "SELECT id, added_at FROM roots WHERE path = ?1 AND removed_at IS NOT NULL",
+                [&path],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .optional()?;
+        let root = match removed {
+            Some((id, added_at)) => {
+                tx.execute(
+                    "UPDATE roots SET removed_at = NULL, enabled = 1, offline = 0 WHERE id = ?1",
+                    [id],
+                )?;
+                info!("restored removed root {id} ({path})");
+                Root::new(id, path, true, added_at)
+            }
+            None => {
+                let now = now();
+                tx.execute(
+                    "INSERT INTO roots (path, enabled, added_at) VALUES (?1, 1, ?2)",
+                    params![path, now],
+                )?;
+                let root = Root::new(tx.last_insert_rowid(), path, true, now);
+                let adopted = adopt_removed_images(&tx, &root)?;
+                if adopted > 0 {
+                    info!(
+                        "root {} adopted {adopted} images of removed roots",
+                        root.path
+                    );
+                }
+                root
+            }
+        };
+        tx.commit()?;
+        Ok(root)
     }
 
-    /// Remove a root. The ON DELETE CASCADE on images.root_id wipes
-    /// every image that came from this root.
+    /// Remove a root: a soft delete that hides it and its images until
+    /// it is re-added or purged. No-op for an unknown or removed root.
     pub fn remove_root(&self, id: ID) -> rusqlite::Result<()> {
+        soft_remove_root(&self.connection.lock().unwrap(), id)?;
+        Ok(())
+    }
+
+    /// Delete a root for good. The ON DELETE CASCADE on images.root_id
+    /// wipes every image that came from this root.
+    pub fn purge_root(&self, id: ID) -> rusqlite::Result<()> {
         self.connection
             .lock()
             .unwrap()
@@ -58,6 +115,23 @@ impl ImageDatabase {
         Ok(())
     }
 
+    /// Purge every root removed at or before `removed_before` (unix
+    /// seconds). Returns the purged ids, for their thumbnail folders.
+    pub fn purge_removed_roots(&self, removed_before: i64) -> rusqlite::Result<Vec<ID>> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        let ids = {
+            let mut stmt = tx.prepare("SELECT id FROM roots WHERE removed_at <= ?1 ORDER BY id")?;
+            let rows = stmt.query_map([removed_before], |r| r.get(0))?;
+            rows.collect::<rusqlite::Result<Vec<ID>>>()?
+        };
+        for id in &ids {
+            tx.execute("DELETE FROM roots WHERE id = ?1", [id])?;
+        }
+        tx.commit()?;
+        Ok(ids)
+    }
+
     /// Toggle a root's enabled flag. Disabled roots keep their image
     /// rows on disk (re-enabling is instant — no re-index) but the
     /// grid filter excludes them.
@@ -69,6 +143,16 @@ impl ImageDatabase {
         Ok(())
     }
 
+    /// Set a root's offline flag. Returns whether it changed, so callers
+    /// can tell a root that just went away or came back.
+    pub fn set_root_offline(&self, id: ID, offline: bool) -> rusqlite::Result<bool> {
+        let changed = self.connection.lock().unwrap().execute(
+            "UPDATE roots SET offline = ?1 WHERE id = ?2 AND offline != ?1",
+            params![offline as i64, id],
+        )?;
+        Ok(changed > 0)
+    }
+
     /// One-shot migration helper — used by the lib.rs setup callback
     /// when an old single-root setup (settings.json::scan_root) needs to
     /// be folded into the new roots table. Returns the new Root, or
@@ -139,6 +223,57 @@ impl ImageDatabase {
     }
 }
 
+/// Mark root `id` removed. Returns whether it was live.
+pub(super) fn soft_remove_root(conn: &Connection, id: ID) -> rusqlite::Result<bool> {
+    let changed = conn.execute(
+        "UPDATE roots SET removed_at = ?2 WHERE id = ?1 AND removed_at IS NULL",
+        params![id, now()],
+    )?;
+    Ok(changed > 0)
+}
+
+/// Bring removed root `id` back as it was. Returns whether it was
+/// removed; a purged root is gone for good.
+pub(super) fn restore_root(conn: &Connection, id: ID) -> rusqlite::Result<bool> {
+    let changed = conn.execute(
+        "UPDATE roots SET removed_at = NULL WHERE id = ?1 AND removed_at IS NOT NULL",
+        [id],
+    )?;
+    Ok(changed > 0)
+}
+
+/// Move the images of removed roots that sit under `root` into it.
+/// Paths are compared by component, so `/a/b` doesn't claim `/a/bc`.
+/// The thumbnail path is cleared, as when a moved file crosses roots.
+fn adopt_removed_images(conn: &Connection, root: &Root) -> rusqlite::Result<usize> {
+    let ids: Vec<ID> = {
+        let mut stmt = conn.prepare(
+            "SELECT id, path FROM images
+             WHERE root_id IN (SELECT id FROM roots WHERE removed_at IS NOT NULL)",
+        )?;
+        let rows = stmt.query_map([], |r| Ok((r.get::<_, ID>(0)?, r.get::<_, String>(1)?)))?;
+        rows.filter_map(|row| match row {
+            Ok((id, path)) if Path::new(&path).starts_with(&root.path) => Some(Ok(id)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+        .collect::<rusqlite::Result<_>>()?
+    };
+    let mut stmt =
+        conn.prepare("UPDATE images SET root_id = ?1, thumbnail_path = NULL WHERE id = ?2")?;
+    for id in &ids {
+        stmt.execute([root.id, *id])?;
+    }
+    Ok(ids.len())
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::test_helpers::fresh_db;
@@ -179,20 +314,102 @@ mod tests {
     }
 
     #[test]
-    fn remove_root_cascades_to_images() {
+    fn remove_root_hides_the_root_and_its_images() {
         let db = fresh_db();
         let r = db.add_root("/x".into()).unwrap();
         db.add_image("/x/a.jpg".into(), Some(r.id)).unwrap();
         db.add_image("/x/b.jpg".into(), Some(r.id)).unwrap();
+        let tag = db.create_tag("cat".into(), "#fff".into()).unwrap();
+        db.add_tag_to_image(1, tag.id).unwrap();
         // Sanity: rows are there
         let imgs = db.get_all_images().unwrap();
         assert_eq!(imgs.len(), 2);
 
         db.remove_root(r.id).unwrap();
-        let after = db.get_all_images().unwrap();
-        assert_eq!(after.len(), 0, "CASCADE should have wiped image rows");
-        let roots = db.list_roots().unwrap();
-        assert!(roots.is_empty());
+        assert!(db.get_all_images().unwrap().is_empty());
+        assert!(db
+            .get_images_with_thumbnails(vec![], "".into(), false)
+            .unwrap()
+            .is_empty());
+        assert!(db.get_images_without_thumbnails().unwrap().is_empty());
+        assert!(db.list_roots().unwrap().is_empty());
+        assert_eq!(db.get_pipeline_stats().unwrap().total_images, 0);
+
+        // Nor can they be reached by id.
+        db.update_image_thumbnail(1, std::path::Path::new("/t/1.jpg"), 10, 10)
+            .unwrap();
+        assert!(matches!(
+            db.get_image_path(1),
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        ));
+        assert_eq!(db.get_image_thumbnail_info(1).unwrap(), None);
+        assert!(matches!(
+            db.get_image_notes(1),
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        ));
+        assert!(matches!(
+            db.set_image_notes(1, "x"),
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        ));
+        // Nor through their tags.
+        assert!(db.get_image_ids_with_tag(tag.id).unwrap().is_empty());
+        assert!(db.get_image_tag_map().unwrap().is_empty());
+    }
+
+    #[test]
+    fn re_adding_a_removed_root_restores_it_without_re_encoding() {
+        let db = fresh_db();
+        let r = db.add_root("/x".into()).unwrap();
+        db.add_image("/x/a.jpg".into(), Some(r.id)).unwrap();
+        db.upsert_embedding(1, "dinov2_base", &[1.0, 0.0]).unwrap();
+        db.set_image_notes(1, "keep").unwrap();
+        let tag = db.create_tag("cat".into(), "#fff".into()).unwrap();
+        db.add_tag_to_image(1, tag.id).unwrap();
+        db.set_root_enabled(r.id, false).unwrap();
+
+        db.remove_root(r.id).unwrap();
+        let back = db.add_root("/x".into()).unwrap();
+        assert_eq!(back, r);
+        assert_eq!(db.list_roots().unwrap(), vec![r]);
+        assert_eq!(db.get_image_notes(1).unwrap().as_deref(), Some("keep"));
+        assert_eq!(db.get_image_ids_with_tag(tag.id).unwrap(), vec![1]);
+        assert!(db
+            .get_images_without_embedding_for("dinov2_base")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn a_new_root_adopts_removed_images_under_it() {
+        let db = fresh_db();
+        let old = db.add_root("/x".into()).unwrap();
+        db.add_image("/x/sub/a.jpg".into(), Some(old.id)).unwrap();
+        db.add_image("/x/subway/b.jpg".into(), Some(old.id)).unwrap();
+        db.remove_root(old.id).unwrap();
+
+        let sub = db.add_root("/x/sub".into()).unwrap();
+        assert_eq!(db.get_root_id_by_path("/x/sub/a.jpg"), Some(sub.id));
+        assert_eq!(db.get_root_id_by_path("/x/subway/b.jpg"), Some(old.id));
+        let visible = db.get_all_images().unwrap();
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].path, "/x/sub/a.jpg");
+    }
+
+    #[test]
+    fn purge_removed_roots_cascades_past_the_cutoff_only() {
+        let db = fresh_db();
+        let a = db.add_root("/a".into()).unwrap();
+        let b = db.add_root("/b".into()).unwrap();
+        db.add_image("/a/x.jpg".into(), Some(a.id)).unwrap();
+        db.remove_root(a.id).unwrap();
+
+        assert!(db.purge_removed_roots(0).unwrap().is_empty());
+        assert_eq!(db.purge_removed_roots(i64::MAX).unwrap(), vec![a.id]);
+        assert_eq!(db.get_root_id_by_path("/a/x.jpg"), None);
+        // Gone for good: the path is a brand-new root again.
+        let again = db.add_root("/a".into()).unwrap();
+        assert!(db.get_all_images().unwrap().is_empty());
+        assert_eq!(db.list_roots().unwrap(), vec![b, again]);
     }
 
     #[test]
@@ -221,6 +438,18 @@ mod tests {
         assert!(listed[0].enabled);
     }
 
+    #[test]
+    fn set_root_offline_reports_only_transitions() {
+        let db = fresh_db();
+        let r = db.add_root("/r".into()).unwrap();
+        assert!(!r.offline);
+        assert!(db.set_root_offline(r.id, true).unwrap());
+        assert!(!db.set_root_offline(r.id, true).unwrap());
+        assert!(db.list_roots().unwrap()[0].offline);
+        assert!(db.set_root_offline(r.id, false).unwrap());
+        assert!(!db.list_roots().unwrap()[0].offline);
+    }
+
     #[test]
     fn migrate_legacy_scan_root_inserts_and_backfills() {
         let db = fresh_db();
@@ -0,0 +1,1006 @@
+//! Versioned schema migrations.
+//!
+//! Every schema change is a numbered entry in [`MIGRATIONS`]. The
+//! highest version that has been applied to a DB file is stored in
+//! `meta` under the key `schema_version`; on launch `initialize()`
+//! (in `mod.rs`) calls `run_schema_migrations`, which applies every
+//! registered migration with a higher version, in order, each inside
+//! its own `BEGIN IMMEDIATE` transaction together with the
+//! `schema_version` bump. A migration either lands completely (schema
+//! change + version row) or not at all, so a crash mid-upgrade leaves
+//! the file at a well-defined earlier version and the next launch
+//! resumes from there.
+//!
+//! Why this replaced the old `migrate_add_*` helpers: each of those
+//! re-ran `PRAGMA table_info(images)` on every launch and ALTERed
+//! whatever looked missing. That was idempotent but not deterministic
+//! — the upgrade path for an old DB file depended on which columns
+//! happened to be present rather than on a recorded version, and a
+//! DB written by a *newer* build was silently "migrated" by an older
+//! one. Now:
+//!
+//! - Version 1 (`baseline`) is the schema as of the last release that
+//!   shipped without a registry. It's the only migration that inspects
+//!   columns, because pre-registry DB files arrive in several shapes
+//!   (pre-thumbnail, pre-multi-folder, pre-notes). After it runs, every
+//!   DB is in exactly the same state regardless of where it came from.
+//! - Every later migration assumes the exact schema of the version
+//!   before it. No `IF NOT EXISTS` guessing.
+//! - A DB whose `schema_version` is above [`LATEST_SCHEMA_VERSION`] is
+//!   refused with an error instead of being touched — opening it would
+//!   mean running an older binary's queries against a schema it
+//!   doesn't know about.
+//!
+//! Adding a migration: append a `Migration` with the next version
+//! number and a `fn(&Transaction) -> rusqlite::Result<()>`. Never edit
+//! or reorder an entry that has shipped. The tests at the bottom build
+//! every historical schema (the pre-registry shapes plus the schema at
+//! each registered version) and migrate it forward, asserting the
+//! result is identical to a fresh install.
+//!
+//! The embedding-pipeline version (`embedding_pipeline_version` in
+//! `meta`) is a separate axis — it invalidates *data* produced by an
+//! older encoder pipeline, not schema — and keeps its own key and
+//! migration below.
+
+use rusqlite::{Connection, OptionalExtension, Transaction, TransactionBehavior};
+use tracing::info;
+
+use super::fulltext::{basename_sql, dirname_sql, tag_names_sql};
+use super::ImageDatabase;
+
+/// `meta` key holding the highest applied migration version.
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Version of the embedding pipeline (models + preprocessing) the
+/// stored embeddings were produced with. Bump it whenever a change
+/// invalidates existing embeddings: `migrate_embedding_pipeline_version`
+/// wipes them, and on-disk cosine caches written under an older
+/// version stop loading.
+pub const EMBEDDING_PIPELINE_VERSION: i64 = 4;
+
+/// One registered schema migration.
+pub(super) struct Migration {
+    /// Strictly increasing, starting at 1, no gaps.
+    pub(super) version: i64,
+    /// Short human-readable label, logged when the migration runs.
+    pub(super) name: &'static str,
+    /// The up-migration. Runs inside a transaction the runner owns;
+    /// it must not commit or roll back itself.
+    pub(super) up: fn(&Transaction<'_>) -> rusqlite::Result<()>,
+}
+
+/// The ordered migration registry. Append-only.
+pub(super) const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "baseline",
+        up: m0001_baseline,
+    },
+    Migration {
+        version: 2,
+        name: "images_fts",
+        up: m0002_images_fts,
+    },
+    Migration {
+        version: 3,
+        name: "content_hash",
+        up: m0003_content_hash,
+    },
+    Migration {
+        version: 4,
+        name: "auto_tagging",
+        up: m0004_auto_tagging,
+    },
+    Migration {
+        version: 5,
+        name: "perceptual_hash",
+        up: m0005_perceptual_hash,
+    },
+    Migration {
+        version: 6,
+        name: "clusters",
+        up: m0006_clusters,
+    },
+    Migration {
+        version: 7,
+        name: "grid_sort_indexes",
+        up: m0007_grid_sort_indexes,
+    },
+    Migration {
+        version: 8,
+        name: "tag_hierarchy",
+        up: m0008_tag_hierarchy,
+    },
+    Migration {
+        version: 9,
+        name: "tag_aliases",
+        up: m0009_tag_aliases,
+    },
+    Migration {
+        version: 10,
+        name: "journal",
+        up: m0010_journal,
+    },
+    Migration {
+        version: 11,
+        name: "root_soft_delete",
+        up: m0011_root_soft_delete,
+    },
+    Migration {
+        version: 12,
+        name: "root_offline",
+        up: m0012_root_offline,
+    },
+];
+
+/// Schema version this binary writes. A DB file above this is refused.
+pub(crate) const LATEST_SCHEMA_VERSION: i64 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+impl ImageDatabase {
+    /// Bring the DB schema up to [`LATEST_SCHEMA_VERSION`]. Called once
+    /// per `initialize()`; a no-op when the file is already current.
+    pub(super) fn run_schema_migrations(&self) -> rusqlite::Result<()> {
+        let mut conn = self.connection.lock().unwrap();
+        migrate_to(&mut conn, LATEST_SCHEMA_VERSION)
+    }
+
+    /// The `schema_version` currently recorded in `meta` (0 for a DB
+    /// that predates the registry or has never been initialized).
+    pub fn schema_version(&self) -> rusqlite::Result<i64> {
+        let conn = self.connection.lock().unwrap();
+        ensure_meta_table(&conn)?;
+        read_schema_version(&conn)
+    }
+}
+
+/// Apply every registered migration with `current < version <= target`.
+/// Split out from `run_schema_migrations` so the tests can stop at an
+/// intermediate version to reconstruct historical schemas.
+pub(super) fn migrate_to(conn: &mut Connection, target: i64) -> rusqlite::Result<()> {
+    ensure_meta_table(conn)?;
+
+    let current = read_schema_version(conn)?;
+    if current > LATEST_SCHEMA_VERSION {
+        return Err(newer_than_binary_error(current));
+    }
+
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current && m.version <= target)
+    {
+        // IMMEDIATE so a second connection initializing the same file
+        // (the indexing pipeline opens its own ImageDatabase) blocks on
+        // busy_timeout here instead of racing us through the same
+        // migration. Re-read the version under the write lock; if the
+        // other connection already applied this step, skip it.
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let applied = read_schema_version(&tx)?;
+        if applied >= migration.version {
+            continue;
+        }
+        info!(
+            "Applying schema migration {} ({}) — from version {}",
+            migration.version, migration.name, applied
+        );
+        (migration.up)(&tx)?;
+        write_schema_version(&tx, migration.version)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// `meta` is the bootstrap table the registry itself lives in, so it
+/// can't be created by a migration.
+fn ensure_meta_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn read_schema_version(conn: &Connection) -> rusqlite::Result<i64> {
+    let stored: Option<String> = conn
+        .query_row(
+            "SELECT value FROM meta WHERE key = ?1",
+            [SCHEMA_VERSION_KEY],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match stored {
+        None => Ok(0),
+        Some(s) => s.parse::<i64>().map_err(|_| {
+            rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT),
+                Some(format!("meta.{SCHEMA_VERSION_KEY} is not an integer: {s:?}")),
+            )
+        }),
+    }
+}
+
+fn write_schema_version(conn: &Connection, version: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO meta (key, value) VALUES (?1, ?2) \
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        rusqlite::params![SCHEMA_VERSION_KEY, version.to_string()],
+    )?;
+    Ok(())
+}
+
+fn newer_than_binary_error(stored: i64) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
+        Some(format!(
+            "database schema version {stored} is newer than this build supports \
+             ({LATEST_SCHEMA_VERSION}); refusing to open it — upgrade the app"
+        )),
+    )
+}
+
+fn column_names(conn: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(columns)
+}
+
+// =====================================================================
+//  Migrations
+// =====================================================================
+
+/// Version 1 — the schema as of the last pre-registry release.
+///
+/// Fresh installs get every table in one shot. Pre-registry DB files
+/// (no `schema_version` row) get the columns that later phases added
+/// via ALTER TABLE: thumbnail columns, `root_id` (Phase 6), `orphaned`
+/// (Phase 7) and `notes` (Phase 11). This is the one migration allowed
+/// to look at `PRAGMA table_info` — see the module docs.
+fn m0001_baseline(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    // Roots table — created first so the images table's root_id FK
+    // has a target. Multi-folder support (Phase 6); existing single-
+    // folder users get their root backfilled by the lib.rs::run setup
+    // callback once it knows what root (if any) was previously
+    // configured via settings.json.
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS roots (
+            id INTEGER PRIMARY KEY,
+            path TEXT NOT NULL UNIQUE,
+            enabled INTEGER NOT NULL DEFAULT 1,
+            added_at INTEGER NOT NULL
+        );",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS images (
+            id INTEGER PRIMARY KEY,
+            path TEXT NOT NULL UNIQUE,
+            embedding BLOB,
+            thumbnail_path TEXT,
+            width INTEGER,
+            height INTEGER,
+            root_id INTEGER REFERENCES roots(id) ON DELETE CASCADE,
+            notes TEXT,
+            orphaned INTEGER NOT NULL DEFAULT 0
+        );",
+        [],
+    )?;
+
+    // Pre-registry files written before each of these phases.
+    let columns = column_names(tx, "images")?;
+    let has = |c: &str| columns.iter().any(|col| col == c);
+    if !has("thumbnail_path") {
+        info!("  adding thumbnail columns to images");
+        tx.execute("ALTER TABLE images ADD COLUMN thumbnail_path TEXT", [])?;
+        tx.execute("ALTER TABLE images ADD COLUMN width INTEGER", [])?;
+        tx.execute("ALTER TABLE images ADD COLUMN height INTEGER", [])?;
+    }
+    if !has("root_id") {
+        // Old rows get root_id = NULL; see the roots comment above.
+        info!("  adding root_id column to images");
+        tx.execute(
+            "ALTER TABLE images ADD COLUMN root_id INTEGER REFERENCES roots(id) ON DELETE CASCADE",
+            [],
+        )?;
+    }
+    if !has("notes") {
+        info!("  adding notes column to images");
+        tx.execute("ALTER TABLE images ADD COLUMN notes TEXT", [])?;
+    }
+    if !has("orphaned") {
+        info!("  adding orphaned column to images");
+        tx.execute(
+            "ALTER TABLE images ADD COLUMN orphaned INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+    }
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            color TEXT NOT NULL
+        );",
+        [],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS images_tags (
+            image_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            PRIMARY KEY (image_id, tag_id),
+            FOREIGN KEY (image_id) REFERENCES images(id) ON DELETE CASCADE,
+            FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+        );",
+        [],
+    )?;
+
+    // Per-encoder embeddings table (Phase 2 of the encoder picker
+    // work). Lets the project hold multiple embeddings per image —
+    // one per encoder model — so the user can search via SigLIP-2,
+    // CLIP, DINOv2, etc., all from the same DB.
+    //
+    // Why a separate table rather than more columns on `images`:
+    // adding a new encoder = inserting rows, not migrating schema.
+    // Storage cost ~2KB per (image × encoder) — negligible.
+    //
+    // The legacy `images.embedding` column is preserved for one
+    // release cycle; the indexing pipeline now also writes the
+    // CLIP embedding to this table with encoder_id="clip_vit_b_32".
+    // A future migration can drop the old column once everyone has
+    // re-indexed.
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS embeddings (
+            image_id INTEGER NOT NULL,
+            encoder_id TEXT NOT NULL,
+            embedding BLOB NOT NULL,
+            PRIMARY KEY (image_id, encoder_id),
+            FOREIGN KEY (image_id) REFERENCES images(id) ON DELETE CASCADE
+        );",
+        [],
+    )?;
+    // Helps populate_from_db, which scans by encoder_id, to skip
+    // the table-scan step.
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_embeddings_encoder
+         ON embeddings(encoder_id);",
+        [],
+    )?;
+    // R9 — composite index on (root_id, orphaned). Every foreground
+    // SELECT against `images` that powers the grid filters by both
+    // (`orphaned = 0 AND (root_id IS NULL OR root_id IN (...))`).
+    // Without this, SQLite full-scans the images table; once
+    // libraries grow past a few thousand rows the scan cost
+    // becomes visible inside `get_images.row_iter` (one of the
+    // Batch 3 subspans) as the dominant component of the
+    // foreground SELECT. NULL root_id rows still match this index
+    // — SQLite indexes nulls in composite indexes since it can use
+    // the index even when the leading column is NULL. (root_id
+    // first means the OR-NULL branch and the IN-list branch both
+    // benefit; orphaned second means we still get a scan range
+    // narrowing for the `orphaned = 0` predicate.)
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS idx_images_root_orphaned
+         ON images(root_id, orphaned);",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Version 2 — FTS5 index behind the grid's `filter_string`.
+///
+/// One row per image, rowid = `images.id`, columns documented in
+/// `fulltext.rs`. A standalone (not external-content) table because
+/// the `tags` column is an aggregate over `images_tags` — there is no
+/// single source row to point FTS5 at. Triggers keep it in sync:
+///
+/// - `images` insert / delete / path-or-notes update
+/// - `images_tags` insert / delete (tag attached or detached; also
+///   fires for the ON DELETE CASCADE when a tag or image goes away)
+/// - `tags` rename
+///
+/// `prefix = '2 3'` builds prefix indexes so the search-as-you-type
+/// `inv*` queries don't have to scan the whole term list.
+fn m0002_images_fts(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    tx.execute_batch(&format!(
+        "CREATE VIRTUAL TABLE images_fts USING fts5(
+            name, folders, tags, notes,
+            tokenize = 'unicode61 remove_diacritics 2',
+            prefix = '2 3'
+        );
+
+        INSERT INTO images_fts (rowid, name, folders, tags, notes)
+        SELECT id, {name}, {folders}, {tags}, COALESCE(notes, '')
+        FROM images;
+
+        CREATE TRIGGER images_fts_after_insert AFTER INSERT ON images BEGIN
+            INSERT INTO images_fts (rowid, name, folders, tags, notes)
+            VALUES (new.id, {new_name}, {new_folders}, {new_tags}, COALESCE(new.notes, ''));
+        END;
+
+        CREATE TRIGGER images_fts_after_delete AFTER DELETE ON images BEGIN
+            DELETE FROM images_fts WHERE rowid = old.id;
+        END;
+
+        CREATE TRIGGER images_fts_after_update AFTER UPDATE OF path, notes ON images BEGIN
+            UPDATE images_fts
+            SET name = {new_name}, folders = {new_folders}, notes = COALESCE(new.notes, '')
+            WHERE rowid = new.id;
+        END;
+
+        CREATE TRIGGER images_tags_fts_after_insert AFTER INSERT ON images_tags BEGIN
+            UPDATE images_fts SET tags = {link_new_tags} WHERE rowid = new.image_id;
+        END;
+
+        CREATE TRIGGER images_tags_fts_after_delete AFTER DELETE ON images_tags BEGIN
+            UPDATE images_fts SET tags = {link_old_tags} WHERE rowid = old.image_id;
+        END;
+
+        CREATE TRIGGER tags_fts_after_rename AFTER UPDATE OF name ON tags BEGIN
+            UPDATE images_fts SET tags = {row_tags}
+            WHERE rowid IN (SELECT image_id FROM images_tags WHERE tag_id = new.id);
+        END;",
+        name = basename_sql("path"),
+        folders = dirname_sql("path"),
+        tags = tag_names_sql("images.id"),
+        new_name = basename_sql("new.path"),
+        new_folders = dirname_sql("new.path"),
+        new_tags = tag_names_sql("new.id"),
+        link_new_tags = tag_names_sql("new.image_id"),
+        link_old_tags = tag_names_sql("old.image_id"),
+        row_tags = tag_names_sql("images_fts.rowid"),
+    ))
+}
+
+/// Version 3 — content fingerprint columns (`content_hash.rs`).
+///
+/// All three start NULL; the next indexing pass hashes every file whose
+/// row has no fingerprint yet. The index is what the reconcile step
+/// joins orphaned rows to freshly-scanned ones on.
+fn m0003_content_hash(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE images ADD COLUMN content_hash TEXT;
+         ALTER TABLE images ADD COLUMN file_size INTEGER;
+         ALTER TABLE images ADD COLUMN file_mtime INTEGER;
+         CREATE INDEX idx_images_content_hash ON images(content_hash, file_size);",
+    )
+}
+
+/// Version 4 — zero-shot auto-tagging (`tag_suggestions.rs`).
+///
+/// - `tag_labels` — the candidate vocabulary. `templates` holds the
+///   prompt templates newline-separated (empty = the default
+///   template); `threshold` is a user override, NULL means calibrated.
+/// - `tag_label_embeddings` — each label's prompt-ensembled text
+///   embedding per text encoder, plus the threshold the last run
+///   calibrated for it in that encoder's score range.
+/// - `tag_suggestions` — one row per (image, label) the scorer put
+///   above threshold. Accepted and rejected rows are kept: they stop
+///   the pair being re-suggested and are the calibration feedback.
+fn m0004_auto_tagging(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE tag_labels (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            templates TEXT NOT NULL DEFAULT '',
+            threshold REAL
+        );
+        CREATE TABLE tag_label_embeddings (
+            label_id INTEGER NOT NULL REFERENCES tag_labels(id) ON DELETE CASCADE,
+            encoder_id TEXT NOT NULL,
+            embedding BLOB NOT NULL,
+            calibrated_threshold REAL,
+            PRIMARY KEY (label_id, encoder_id)
+        );
+        CREATE TABLE tag_suggestions (
+            image_id INTEGER NOT NULL REFERENCES images(id) ON DELETE CASCADE,
+            label_id INTEGER NOT NULL REFERENCES tag_labels(id) ON DELETE CASCADE,
+            encoder_id TEXT NOT NULL,
+            score REAL NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            PRIMARY KEY (image_id, label_id)
+        );
+        CREATE INDEX idx_tag_suggestions_label_status ON tag_suggestions(label_id, status);",
+    )
+}
+
+/// Version 5 — perceptual hash of each thumbnail (`duplicates.rs`).
+///
+/// A 64-bit dHash stored as its two's-complement `INTEGER`. NULL until
+/// the duplicate finder first needs it; `update_image_thumbnail` resets
+/// it so a regenerated thumbnail is re-hashed.
+fn m0005_perceptual_hash(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE images ADD COLUMN perceptual_hash INTEGER;")
+}
+
+/// Version 6 — visual boards (`clusters.rs`).
+///
+/// - `clusters` — one row per board of the last clustering run. All
+///   rows share one `encoder_id`. `centroid` is the *unnormalised*
+///   mean of the members' unit embeddings (f32 BLOB), so an image
+///   joining later updates it as an exact running mean; `member_count`
+///   is the n of that mean. `label` / `label_score` are the proposed
+///   name and its text–centroid cosine, NULL when no text encoder was
+///   available.
+/// - `cluster_images` — each clustered image's board and its cosine to
+///   the centroid at assignment time. One board per image.
+fn m0006_clusters(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE clusters (
+            id INTEGER PRIMARY KEY,
+            encoder_id TEXT NOT NULL,
+            centroid BLOB NOT NULL,
+            member_count INTEGER NOT NULL,
+            medoid_image_id INTEGER REFERENCES images(id) ON DELETE SET NULL,
+            label TEXT,
+            label_score REAL
+        );
+        CREATE TABLE cluster_images (
+            image_id INTEGER PRIMARY KEY REFERENCES images(id) ON DELETE CASCADE,
+            cluster_id INTEGER NOT NULL REFERENCES clusters(id) ON DELETE CASCADE,
+            similarity REAL NOT NULL
+        );
+        CREATE INDEX idx_cluster_images_cluster ON cluster_images(cluster_id, similarity);",
+    )
+}
+
+/// Version 7 — indexes for the paginated grid's sorts
+/// (`grid_pages.rs`). Each is the sort-key expression paired with `id`,
+/// the tie-break, so a page is a range scan from the cursor. The
+/// expressions must stay identical to the ones `GridSort` queries with.
+fn m0007_grid_sort_indexes(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE INDEX idx_images_sort_mtime ON images(COALESCE(file_mtime, 0), id);
+        CREATE INDEX idx_images_sort_size ON images(COALESCE(file_size, 0), id);
+        CREATE INDEX idx_images_sort_name ON images(
+            LOWER(SUBSTR(REPLACE(path, '\\', '/'),
+                LENGTH(RTRIM(REPLACE(path, '\\', '/'),
+                    REPLACE(REPLACE(path, '\\', '/'), '/', ''))) + 1)),
+            id
+        );",
+    )
+}
+
+/// Version 8 — tag hierarchy (`tag_tree.rs`). `parent_id` is NULL for
+/// a top-level tag. `delete_tag` lifts a deleted tag's children to its
+/// parent itself; `ON DELETE SET NULL` only covers deletes that bypass
+/// it, so they never leave a dangling parent.
+fn m0008_tag_hierarchy(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE tags ADD COLUMN parent_id INTEGER REFERENCES tags(id) ON DELETE SET NULL;
+        CREATE INDEX idx_tags_parent ON tags(parent_id);",
+    )
+}
+
+/// Version 9 — tag aliases (`tag_aliases.rs`). An alias dies with its
+/// tag; a merge re-points it first.
+fn m0009_tag_aliases(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE tag_aliases (
+            name TEXT PRIMARY KEY,
+            tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE
+        );
+        CREATE INDEX idx_tag_aliases_tag ON tag_aliases(tag_id);",
+    )
+}
+
+/// Version 10 — undo/redo journal (`journal.rs`). `forward` and
+/// `inverse` are JSON arrays of `JournalOp`; `undone` rows form the
+/// redo branch, always the newest ids.
+fn m0010_journal(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE journal (
+            id INTEGER PRIMARY KEY,
+            label TEXT NOT NULL,
+            forward TEXT NOT NULL,
+            inverse TEXT NOT NULL,
+            undone INTEGER NOT NULL DEFAULT 0,
+            created_at INTEGER NOT NULL
+        );",
+    )
+}
+
+/// Version 11 — soft-deleted roots (`roots.rs`). A removed root keeps
+/// its row, images, embeddings and thumbnails with `removed_at` set
+/// until re-added or purged after the retention window; NULL is live.
+fn m0011_root_soft_delete(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE roots ADD COLUMN removed_at INTEGER;")
+}
+
+/// Version 12 — offline roots (`root_availability.rs`). Set while the
+/// root's folder is missing (unmounted drive, share down) so indexing
+/// keeps its images instead of orphaning them.
+fn m0012_root_offline(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE roots ADD COLUMN offline INTEGER NOT NULL DEFAULT 0;")
+}
+
+impl ImageDatabase {
+    /// Embedding-pipeline version-bump migration. Runs once when
+    /// the version stored in `meta` (key `embedding_pipeline_version`)
+    /// is less than the current version. Wipes embeddings produced
+    /// by the previous pipeline so the next indexing pass re-encodes
+    /// everything with the new model + preprocessing.
+    ///
+    /// Why this is needed:
+    /// - **CLIP**: switched from the combined-graph multilingual model
+    ///   (in a different embedding space than the image branch) to
+    ///   the SEPARATE OpenAI vision_model.onnx + text_model.onnx.
+    ///   New preprocessing (bicubic-shortest-edge-224 + center-crop +
+    ///   L2-normalize) produces a different embedding distribution
+    ///   than the old (resize_exact-224 + Lanczos3 + no-normalize).
+    ///   Mixing the two corrupts cosine similarity rankings.
+    /// - **DINOv2**: switched from `dinov2_small` (384-d, wrong
+    ///   preprocessing) to `dinov2_base` (768-d, correct preprocessing).
+    ///   Old `dinov2_small` rows are abandoned because the encoder_id
+    ///   changed; this just cleans them up to free disk.
+    ///
+    /// Bump `EMBEDDING_PIPELINE_VERSION` whenever a future change
+    /// invalidates existing embeddings. Runs after
+    /// `run_schema_migrations`, which guarantees `meta` and
+    /// `embeddings` exist.
+    pub(super) fn migrate_embedding_pipeline_version(&self) -> rusqlite::Result<()> {
+        // Version 4 — bumped 2026-04-26 with Phase 12e. Each encoder's
+        // preprocessing now uses fast_image_resize Lanczos3 instead of
+        // image-rs's CatmullRom (CLIP, DINOv2) or Triangle (SigLIP-2).
+        // Different filter, different output bytes, different
+        // embeddings. Wipe + re-encode is the cleaner path than living
+        // with hybrid distributions.
+        //
+        // (Version 3 was the Tier-2 thumbnail-only resize change. The
+        // encoders kept their old image-rs paths until Phase 12e
+        // brought them onto fast_image_resize too.)
+        const CURRENT_PIPELINE_VERSION: i64 = EMBEDDING_PIPELINE_VERSION;
+
+        let conn = self.connection.lock().unwrap();
+
+        let stored: Option<i64> = conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'embedding_pipeline_version'",
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok());
+
+        let needs_migration =
+            !matches!(stored, Some(v) if v >= CURRENT_PIPELINE_VERSION);
+
+        if !needs_migration {
+            return Ok(());
+        }
+
+        info!(
+            "Embedding pipeline version migration: stored={:?}, current={} — \
+             wiping legacy embeddings to trigger re-encode",
+            stored, CURRENT_PIPELINE_VERSION
+        );
+
+        // Wipe legacy CLIP from images.embedding column.
+        let cleared_legacy = conn.execute(
+            "UPDATE images SET embedding = NULL WHERE embedding IS NOT NULL",
+            [],
+        )?;
+        info!("  cleared {} legacy CLIP embeddings from images.embedding", cleared_legacy);
+
+        // Wipe per-encoder rows for the encoders whose pipelines changed.
+        // SigLIP-2 rows weren't produced before this version (encoder
+        // wasn't wired), so no cleanup needed there.
+        let cleared_clip = conn.execute(
+            "DELETE FROM embeddings WHERE encoder_id = 'clip_vit_b_32'",
+            [],
+        )?;
+        let cleared_small = conn.execute(
+            "DELETE FROM embeddings WHERE encoder_id = 'dinov2_small'",
+            [],
+        )?;
+        info!(
+            "  cleared {} clip_vit_b_32 + {} dinov2_small embeddings from embeddings table",
+            cleared_clip, cleared_small
+        );
+
+        // Mark migration complete.
+        conn.execute(
+            "INSERT INTO meta (key, value) VALUES ('embedding_pipeline_version', ?1) \
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            rusqlite::params![CURRENT_PIPELINE_VERSION.to_string()],
+        )?;
+
+        info!(
+            "Embedding pipeline migration complete (version → {}). \
+             Next indexing pass will re-encode all images.",
+            CURRENT_PIPELINE_VERSION
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::fresh_db;
+    use super::super::ImageDatabase;
+    use super::*;
+
+    // =====================================================================
+    //  Historical schema fixtures
+    // =====================================================================
+
+    /// Pre-registry DB files, oldest first. Each is the exact DDL an
+    /// older build left behind; they are what version 1 (`baseline`)
+    /// has to absorb.
+    const LEGACY_SCHEMAS: &[(&str, &str)] = &[
+        (
+            "original",
+            "CREATE TABLE images (id INTEGER PRIMARY KEY, path TEXT NOT NULL UNIQUE, embedding BLOB);
+             CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, color TEXT NOT NULL);
+             CREATE TABLE images_tags (
+                image_id INTEGER NOT NULL, tag_id INTEGER NOT NULL,
+                PRIMARY KEY (image_id, tag_id),
+                FOREIGN KEY (image_id) REFERENCES images(id) ON DELETE CASCADE,
+                FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE);",
+        ),
+        (
+            "thumbnails",
+            "CREATE TABLE images (id INTEGER PRIMARY KEY, path TEXT NOT NULL UNIQUE, embedding BLOB,
+                thumbnail_path TEXT, width INTEGER, height INTEGER);
+             CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, color TEXT NOT NULL);
+             CREATE TABLE images_tags (
+                image_id INTEGER NOT NULL, tag_id INTEGER NOT NULL,
+                PRIMARY KEY (image_id, tag_id),
+                FOREIGN KEY (image_id) REFERENCES images(id) ON DELETE CASCADE,
+                FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE);",
+        ),
+        (
+            "multi-folder",
+            "CREATE TABLE roots (id INTEGER PRIMARY KEY, path TEXT NOT NULL UNIQUE,
+                enabled INTEGER NOT NULL DEFAULT 1, added_at INTEGER NOT NULL);
+             CREATE TABLE images (id INTEGER PRIMARY KEY, path TEXT NOT NULL UNIQUE, embedding BLOB,
+                thumbnail_path TEXT, width INTEGER, height INTEGER);
+             ALTER TABLE images ADD COLUMN root_id INTEGER REFERENCES roots(id) ON DELETE CASCADE;
+             CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, color TEXT NOT NULL);
+             CREATE TABLE images_tags (
+                image_id INTEGER NOT NULL, tag_id INTEGER NOT NULL,
+                PRIMARY KEY (image_id, tag_id),
+                FOREIGN KEY (image_id) REFERENCES images(id) ON DELETE CASCADE,
+                FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE);
+             CREATE TABLE embeddings (
+                image_id INTEGER NOT NULL, encoder_id TEXT NOT NULL, embedding BLOB NOT NULL,
+                PRIMARY KEY (image_id, encoder_id),
+                FOREIGN KEY (image_id) REFERENCES images(id) ON DELETE CASCADE);
+             CREATE INDEX idx_embeddings_encoder ON embeddings(encoder_id);",
+        ),
+        (
+            "notes+orphaned",
+            "CREATE TABLE roots (id INTEGER PRIMARY KEY, path TEXT NOT NULL UNIQUE,
+                enabled INTEGER NOT NULL DEFAULT 1, added_at INTEGER NOT NULL);
+             CREATE TABLE images (id INTEGER PRIMARY KEY, path TEXT NOT NULL UNIQUE, embedding BLOB,
+                thumbnail_path TEXT, width INTEGER, height INTEGER,
+                root_id INTEGER REFERENCES roots(id) ON DELETE CASCADE,
+                notes TEXT, orphaned INTEGER NOT NULL DEFAULT 0);
+             CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE, color TEXT NOT NULL);
+             CREATE TABLE images_tags (
+                image_id INTEGER NOT NULL, tag_id INTEGER NOT NULL,
+                PRIMARY KEY (image_id, tag_id),
+                FOREIGN KEY (image_id) REFERENCES images(id) ON DELETE CASCADE,
+                FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE);
+             CREATE TABLE embeddings (
+                image_id INTEGER NOT NULL, encoder_id TEXT NOT NULL, embedding BLOB NOT NULL,
+                PRIMARY KEY (image_id, encoder_id),
+                FOREIGN KEY (image_id) REFERENCES images(id) ON DELETE CASCADE);
+             CREATE INDEX idx_embeddings_encoder ON embeddings(encoder_id);
+             CREATE INDEX idx_images_root_orphaned ON images(root_id, orphaned);
+             CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+             INSERT INTO meta (key, value) VALUES ('embedding_pipeline_version', '4');",
+        ),
+    ];
+
+    /// Table → sorted column names, plus every named index. Column
+    /// order is deliberately ignored: ALTER TABLE appends, so an
+    /// upgraded file legitimately orders columns differently from a
+    /// fresh install.
+    fn schema_fingerprint(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT type, name FROM sqlite_master
+                 WHERE name NOT LIKE 'sqlite_%'
+                 ORDER BY type, name",
+            )
+            .unwrap();
+        let objects: Vec<(String, String)> = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        objects
+            .into_iter()
+            .map(|(kind, name)| {
+                if kind == "table" {
+                    let mut cols = column_names(conn, &name).unwrap();
+                    cols.sort();
+                    format!("table {name}({})", cols.join(","))
+                } else {
+                    format!("{kind} {name}")
+                }
+            })
+            .collect()
+    }
+
+    fn latest_fingerprint() -> Vec<String> {
+        let db = fresh_db();
+        let conn = db.connection.lock().unwrap();
+        schema_fingerprint(&conn)
+    }
+
+    /// Wrap an already-built connection so `initialize()` can run the
+    /// real launch path against it.
+    fn db_from_connection(conn: Connection) -> ImageDatabase {
+        let db = ImageDatabase::new(":memory:").unwrap();
+        *db.connection.lock().unwrap() = conn;
+        db
+    }
+
+    // =====================================================================
+    //  Registry invariants
+    // =====================================================================
+
+    #[test]
+    fn registry_versions_are_contiguous_from_one() {
+        for (i, m) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(m.version, i as i64 + 1, "migration {} out of order", m.name);
+        }
+        assert_eq!(LATEST_SCHEMA_VERSION, MIGRATIONS.len() as i64);
+    }
+
+    #[test]
+    fn fresh_db_is_stamped_with_latest_version() {
+        let db = fresh_db();
+        assert_eq!(db.schema_version().unwrap(), LATEST_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn rerunning_migrations_is_a_noop() {
+        let db = fresh_db();
+        let before = {
+            let conn = db.connection.lock().unwrap();
+            schema_fingerprint(&conn)
+        };
+        db.initialize().unwrap();
+        let conn = db.connection.lock().unwrap();
+        assert_eq!(schema_fingerprint(&conn), before);
+    }
+
+    // =====================================================================
+    //  Historical schemas migrate forward
+    // =====================================================================
+
+    #[test]
+    fn every_legacy_schema_upgrades_to_latest() {
+        let expected = latest_fingerprint();
+        for (label, ddl) in LEGACY_SCHEMAS {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(ddl).unwrap();
+            conn.execute(
+                "INSERT INTO images (id, path) VALUES (7, '/legacy/a.jpg')",
+                [],
+            )
+            .unwrap();
+            conn.execute("INSERT INTO tags (id, name, color) VALUES (3, 'cat', '#f00')", [])
+                .unwrap();
+            conn.execute("INSERT INTO images_tags (image_id, tag_id) VALUES (7, 3)", [])
+                .unwrap();
+
+            let db = db_from_connection(conn);
+            db.initialize()
+                .unwrap_or_else(|e| panic!("legacy schema {label:?} failed to migrate: {e}"));
+
+            assert_eq!(db.schema_version().unwrap(), LATEST_SCHEMA_VERSION, "{label}");
+            let conn = db.connection.lock().unwrap();
+            assert_eq!(schema_fingerprint(&conn), expected, "legacy schema {label:?}");
+            let (path, orphaned, tag_count): (String, i64, i64) = conn
+                .query_row(
+                    "SELECT path, orphaned, (SELECT COUNT(*) FROM images_tags WHERE image_id = 7)
+                     FROM images WHERE id = 7",
+                    [],
+                    |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+                )
+                .unwrap();
+            assert_eq!(path, "/legacy/a.jpg", "{label}");
+            assert_eq!(orphaned, 0, "{label}");
+            assert_eq!(tag_count, 1, "{label}: tag link lost");
+        }
+    }
+
+    #[test]
+    fn every_registered_version_upgrades_to_latest() {
+        let expected = latest_fingerprint();
+        for m in MIGRATIONS {
+            let mut conn = Connection::open_in_memory().unwrap();
+            migrate_to(&mut conn, m.version).unwrap();
+            assert_eq!(read_schema_version(&conn).unwrap(), m.version);
+
+            let db = db_from_connection(conn);
+            db.initialize().unwrap();
+            assert_eq!(db.schema_version().unwrap(), LATEST_SCHEMA_VERSION);
+            let conn = db.connection.lock().unwrap();
+            assert_eq!(
+                schema_fingerprint(&conn),
+                expected,
+                "upgrade from version {} ({})",
+                m.version,
+                m.name
+            );
+        }
+    }
+
+    #[test]
+    fn fts_migration_backfills_existing_rows() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 1).unwrap();
+        conn.execute_batch(
+            "INSERT INTO images (id, path, notes) VALUES (1, '/lib/2019/beach.jpg', 'sunset swim');
+             INSERT INTO tags (id, name, color) VALUES (1, 'family', '#fff');
+             INSERT INTO images_tags (image_id, tag_id) VALUES (1, 1);",
+        )
+        .unwrap();
+        migrate_to(&mut conn, 2).unwrap();
+
+        let row: (String, String, String, String) = conn
+            .query_row(
+                "SELECT name, folders, tags, notes FROM images_fts WHERE rowid = 1",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            row,
+            (
+                "beach.jpg".to_string(),
+                "/lib/2019/".to_string(),
+                "family".to_string(),
+                "sunset swim".to_string()
+            )
+        );
+    }
+
+    // =====================================================================
+    //  Refusal + atomicity
+    // =====================================================================
+
+    #[test]
+    fn newer_db_is_refused_and_left_untouched() {
+        let db = fresh_db();
+        {
+            let conn = db.connection.lock().unwrap();
+            write_schema_version(&conn, LATEST_SCHEMA_VERSION + 1).unwrap();
+        }
+        let err = db.initialize().unwrap_err().to_string();
+        assert!(err.contains("newer than this build"), "unexpected error: {err}");
+        assert_eq!(db.schema_version().unwrap(), LATEST_SCHEMA_VERSION + 1);
+    }
+
+    #[test]
+    fn failed_migration_rolls_back_schema_and_version() {
+        // A legacy file with a view squatting on the index name the
+        // baseline creates: the CREATE INDEX fails after the ALTERs
+        // already ran, so everything before it must roll back.
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE images (id INTEGER PRIMARY KEY, path TEXT NOT NULL UNIQUE, embedding BLOB);
+             CREATE VIEW idx_images_root_orphaned AS SELECT 1;",
+        )
+        .unwrap();
+        let db = db_from_connection(conn);
+        assert!(db.initialize().is_err());
+
+        assert_eq!(db.schema_version().unwrap(), 0);
+        let conn = db.connection.lock().unwrap();
+        let cols = column_names(&conn, "images").unwrap();
+        assert_eq!(cols, vec!["id", "path", "embedding"], "ALTERs must roll back");
+    }
+}
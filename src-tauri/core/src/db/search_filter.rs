@@ -0,0 +1,279 @@
+//! Structured filters for the vector searches: tags, roots,
+//! dimensions, file type, modification date and notes.
+//!
+//! The cosine caches know nothing but paths and embeddings, so a filter
+//! is resolved here into the set of paths it admits
+//! (`filtered_image_paths`). The index then ranks only those rows, which
+//! keeps a filtered top-50 a full 50 instead of whatever survives of an
+//! unfiltered one.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use rusqlite::params_from_iter;
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+
+use super::tag_tree::tagged_images_sql;
+use super::{root_filter, ImageDatabase, RootScope, ID};
+
+/// Constraints on which images a search may return. Every field is
+/// optional; the default filter admits every visible image.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchFilter {
+    /// Images must carry any of these tags (all of them with
+    /// `match_all_tags`). A tag also matches through its descendants.
+    pub include_tag_ids: Vec<ID>,
+    pub match_all_tags: bool,
+    /// Images carrying any of these tags, or a tag below one, are left
+    /// out.
+    pub exclude_tag_ids: Vec<ID>,
+    /// Only images under these roots.
+    pub root_ids: Vec<ID>,
+    pub min_width: Option<i64>,
+    pub max_width: Option<i64>,
+    pub min_height: Option<i64>,
+    pub max_height: Option<i64>,
+    /// Bounds on width / height; 1.0 is square, above it landscape.
+    pub min_aspect_ratio: Option<f64>,
+    pub max_aspect_ratio: Option<f64>,
+    /// File extensions, case-insensitive, with or without the dot.
+    pub file_types: Vec<String>,
+    /// Bounds on the file's modification time, unix seconds, inclusive.
+    pub modified_after: Option<i64>,
+    pub modified_before: Option<i64>,
+    /// `Some(true)` keeps only images with notes, `Some(false)` only
+    /// those without.
+    pub has_notes: Option<bool>,
+}
+
+impl SearchFilter {
+    /// True when the filter admits every visible image.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The filter as a WHERE clause over `images` (aliased `i`) and its
+    /// positional parameters. Dimension, aspect and date bounds leave
+    /// out images whose value isn't known yet.
+    fn where_clause(&self) -> (String, Vec<Value>) {
+        let mut clauses = vec![
+            "i.orphaned = 0".to_string(),
+            root_filter("i.root_id", RootScope::Enabled),
+        ];
+        let mut params = Vec::new();
+        let placeholders = |n: usize| vec!["?"; n].join(", ");
+
+        if !self.include_tag_ids.is_empty() {
+            clauses.push(format!(
+                "i.id IN ({})",
+                tagged_images_sql(&self.include_tag_ids, self.match_all_tags)
+            ));
+            params.extend(self.include_tag_ids.iter().map(|id| Value::Integer(*id)));
+        }
+        if !self.exclude_tag_ids.is_empty() {
+            clauses.push(format!(
+                "i.id NOT IN ({})",
+                tagged_images_sql(&self.exclude_tag_ids, false)
+            ));
+            params.extend(self.exclude_tag_ids.iter().map(|id| Value::Integer(*id)));
+        }
+        if !self.root_ids.is_empty() {
+            clauses.push(format!(
+                "i.root_id IN ({})",
+                placeholders(self.root_ids.len())
+            ));
+            params.extend(self.root_ids.iter().map(|id| Value::Integer(*id)));
+        }
+
+        let bounds = [
+            ("i.width >= ?", self.min_width),
+            ("i.width <= ?", self.max_width),
+            ("i.height >= ?", self.min_height),
+            ("i.height <= ?", self.max_height),
+            ("i.file_mtime >= ?", self.modified_after),
+            ("i.file_mtime <= ?", self.modified_before),
+        ];
+        for (clause, bound) in bounds {
+            if let Some(bound) = bound {
+                clauses.push(clause.to_string());
+                params.push(Value::Integer(bound));
+            }
+        }
+        let aspect = [
+            ("i.width >= ? * i.height", self.min_aspect_ratio),
+            ("i.width <= ? * i.height", self.max_aspect_ratio),
+        ];
+        for (clause, bound) in aspect {
+            if let Some(bound) = bound {
+                clauses.push(format!("i.height > 0 AND {clause}"));
+                params.push(Value::Real(bound));
+            }
+        }
+
+        let extensions: Vec<String> = self
+            .file_types
+            .iter()
+            .map(|t| t.trim().trim_start_matches('.').to_lowercase())
+            .filter(|t| !t.is_empty())
+            .collect();
+        if !extensions.is_empty() {
+            clauses.push(format!(
+                "({})",
+                vec!["LOWER(i.path) LIKE ?"; extensions.len()].join(" OR ")
+            ));
+            params.extend(
+                extensions
+                    .into_iter()
+                    .map(|e| Value::Text(format!("%.{e}"))),
+            );
+        }
+
+        match self.has_notes {
+            Some(true) => clauses.push("TRIM(COALESCE(i.notes, '')) != ''".to_string()),
+            Some(false) => clauses.push("TRIM(COALESCE(i.notes, '')) = ''".to_string()),
+            None => {}
+        }
+        (clauses.join(" AND "), params)
+    }
+}
+
+impl ImageDatabase {
+    /// Paths of the visible images `filter` admits, in the form the
+    /// cosine caches key their rows by.
+    pub fn filtered_image_paths(
+        &self,
+        filter: &SearchFilter,
+    ) -> rusqlite::Result<HashSet<PathBuf>> {
+        let (clause, params) = filter.where_clause();
+        let conn = self.read_lock();
+        let mut stmt = conn.prepare(&format!("SELECT i.path FROM images i WHERE {clause}"))?;
+        let paths = stmt
+            .query_map(params_from_iter(params), |row| row.get::<_, String>(0))?
+            .map(|path| path.map(PathBuf::from))
+            .collect();
+        paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    fn db() -> ImageDatabase {
+        let db = ImageDatabase::new(":memory:").unwrap();
+        db.initialize().unwrap();
+        // (path, width, height, mtime, notes)
+        let rows = [
+            ("/r/wide.jpg", 1600, 900, 100, ""),
+            ("/r/tall.PNG", 900, 1600, 200, "portrait"),
+            ("/r/square.jpg", 1000, 1000, 300, ""),
+            ("/r/unknown.webp", 0, 0, 0, ""),
+        ];
+        for (path, w, h, mtime, notes) in rows {
+            db.add_image(path.to_string(), None).unwrap();
+            let conn = db.connection.lock().unwrap();
+            conn.execute(
+                "UPDATE images SET width = NULLIF(?2, 0), height = NULLIF(?3, 0),
+                 file_mtime = NULLIF(?4, 0), notes = ?5 WHERE path = ?1",
+                params![path, w, h, mtime, notes],
+            )
+            .unwrap();
+        }
+        db
+    }
+
+    fn names(db: &ImageDatabase, filter: &SearchFilter) -> Vec<String> {
+        let mut names: Vec<String> = db
+            .filtered_image_paths(filter)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.file_stem().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn the_empty_filter_admits_everything_visible() {
+        let db = db();
+        assert!(SearchFilter::default().is_empty());
+        assert_eq!(
+            names(&db, &SearchFilter::default()),
+            ["square", "tall", "unknown", "wide"]
+        );
+    }
+
+    #[test]
+    fn dimension_aspect_type_date_and_notes_bounds() {
+        let db = db();
+        let filter = |f: SearchFilter| names(&db, &f);
+        assert_eq!(
+            filter(SearchFilter {
+                min_width: Some(1000),
+                ..Default::default()
+            }),
+            ["square", "wide"]
+        );
+        assert_eq!(
+            filter(SearchFilter {
+                min_aspect_ratio: Some(0.9),
+                max_aspect_ratio: Some(1.1),
+                ..Default::default()
+            }),
+            ["square"]
+        );
+        assert_eq!(
+            filter(SearchFilter {
+                file_types: vec![".png".into(), "WEBP".into()],
+                ..Default::default()
+            }),
+            ["tall", "unknown"]
+        );
+        assert_eq!(
+            filter(SearchFilter {
+                modified_after: Some(150),
+                modified_before: Some(300),
+                ..Default::default()
+            }),
+            ["square", "tall"]
+        );
+        assert_eq!(
+            filter(SearchFilter {
+                has_notes: Some(true),
+                ..Default::default()
+            }),
+            ["tall"]
+        );
+    }
+
+    #[test]
+    fn tag_include_and_exclude() {
+        let db = db();
+        let red = db.create_tag("red".into(), "#f00".into()).unwrap().id;
+        let blue = db.create_tag("blue".into(), "#00f".into()).unwrap().id;
+        let id = |p: &str| db.get_image_id_by_path(p).unwrap();
+        db.add_tag_to_image(id("/r/wide.jpg"), red).unwrap();
+        db.add_tag_to_image(id("/r/wide.jpg"), blue).unwrap();
+        db.add_tag_to_image(id("/r/tall.PNG"), red).unwrap();
+        db.add_tag_to_image(id("/r/square.jpg"), blue).unwrap();
+
+        let any = SearchFilter {
+            include_tag_ids: vec![red, blue],
+            ..Default::default()
+        };
+        assert_eq!(names(&db, &any), ["square", "tall", "wide"]);
+        let all = SearchFilter {
+            match_all_tags: true,
+            ..any.clone()
+        };
+        assert_eq!(names(&db, &all), ["wide"]);
+        let not_blue = SearchFilter {
+            exclude_tag_ids: vec![blue],
+            ..Default::default()
+        };
+        assert_eq!(names(&db, &not_blue), ["tall", "unknown"]);
+    }
+}
@@ -0,0 +1,132 @@
+//! Tag aliases: extra names that resolve to a canonical tag
+//! (schema migration 9).
+//!
+//! "b&w" and "monochrome" can both mean the tag "black and white".
+//! Aliases never appear on images; `resolve_tag_name` maps a typed
+//! name to its tag, whether it is the tag's own name or an alias.
+//! Merging a tag keeps its old name as an alias of the target, so
+//! anything that still uses the old name lands on the merged tag.
+//!
+//! A name is either a tag's or one alias's, never both — the UNIQUE
+//! constraints cover each table, `Library` checks across the two.
+
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::{ImageDatabase, ID};
+use crate::tag_struct::Tag;
+
+/// Another name for tag `tag_id`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagAlias {
+    pub name: String,
+    pub tag_id: ID,
+}
+
+impl ImageDatabase {
+    /// Add `name` as an alias of `tag_id`. A UNIQUE violation if the
+    /// alias already exists.
+    pub fn add_tag_alias(&self, tag_id: ID, name: &str) -> rusqlite::Result<TagAlias> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO tag_aliases (name, tag_id) VALUES (?1, ?2)",
+            params![name, tag_id],
+        )?;
+        Ok(TagAlias {
+            name: name.to_string(),
+            tag_id,
+        })
+    }
+
+    /// Remove an alias; `false` if there was none by that name.
+    pub fn remove_tag_alias(&self, name: &str) -> rusqlite::Result<bool> {
+        let removed = self
+            .connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM tag_aliases WHERE name = ?1", [name])?;
+        Ok(removed > 0)
+    }
+
+    /// Every alias, grouped by tag.
+    pub fn get_tag_aliases(&self) -> rusqlite::Result<Vec<TagAlias>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT name, tag_id FROM tag_aliases ORDER BY tag_id, name")?;
+        let aliases = stmt
+            .query_map([], |r| {
+                Ok(TagAlias {
+                    name: r.get(0)?,
+                    tag_id: r.get(1)?,
+                })
+            })?
+            .collect();
+        aliases
+    }
+
+    /// The tag called `name`, directly or through an alias.
+    pub fn resolve_tag_name(&self, name: &str) -> rusqlite::Result<Option<Tag>> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT id, name, color, parent_id FROM tags
+                 WHERE id = COALESCE(
+                     (SELECT id FROM tags WHERE name = ?1),
+                     (SELECT tag_id FROM tag_aliases WHERE name = ?1)
+                 )",
+                [name],
+                |r| {
+                    Ok(Tag {
+                        id: r.get(0)?,
+                        name: r.get(1)?,
+                        color: r.get(2)?,
+                        parent_id: r.get(3)?,
+                    })
+                },
+            )
+            .optional()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::fresh_db;
+
+    #[test]
+    fn aliases_resolve_and_follow_merges() {
+        let db = fresh_db();
+        let bw = db
+            .create_tag("black and white".into(), "#000".into())
+            .unwrap();
+        let mono = db.create_tag("mono".into(), "#111".into()).unwrap();
+        db.add_tag_alias(bw.id, "b&w").unwrap();
+        assert!(db.add_tag_alias(mono.id, "b&w").is_err());
+
+        assert_eq!(db.resolve_tag_name("b&w").unwrap().unwrap().id, bw.id);
+        assert_eq!(db.resolve_tag_name("mono").unwrap().unwrap().id, mono.id);
+        assert!(db.resolve_tag_name("sepia").unwrap().is_none());
+
+        // A merged tag's name and aliases now point at the target.
+        db.add_tag_alias(mono.id, "monochrome").unwrap();
+        db.merge_tag_into(mono.id, bw.id).unwrap();
+        assert_eq!(db.resolve_tag_name("mono").unwrap().unwrap().id, bw.id);
+        assert_eq!(
+            db.get_tag_aliases()
+                .unwrap()
+                .into_iter()
+                .map(|a| (a.name, a.tag_id))
+                .collect::<Vec<_>>(),
+            [
+                ("b&w".to_string(), bw.id),
+                ("mono".to_string(), bw.id),
+                ("monochrome".to_string(), bw.id)
+            ]
+        );
+
+        assert!(db.remove_tag_alias("mono").unwrap());
+        assert!(!db.remove_tag_alias("mono").unwrap());
+        // Deleting the tag takes its aliases with it.
+        db.delete_tag(bw.id).unwrap();
+        assert!(db.get_tag_aliases().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,438 @@
+//! Zero-shot auto-tagging storage: the label vocabulary, each label's
+//! cached text embedding per encoder, and the suggestions the scorer
+//! produced (`autotag.rs` does the scoring).
+//!
+//! A suggestion moves `pending` → `accepted` (the image gets the tag
+//! named after the label, created on first accept) or `pending` →
+//! `rejected`. Re-running the scorer replaces only the pending rows, so
+//! a decision sticks and the decided rows double as calibration
+//! feedback for the label's threshold.
+
+use rusqlite::{params, OptionalExtension, Transaction};
+use serde::Serialize;
+
+use super::journal::Recording;
+use super::{root_filter, ImageDatabase, JournalOp, RootScope, ID};
+use crate::tag_struct::DEFAULT_TAG_COLOR;
+
+/// One entry of the auto-tagging vocabulary.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TagLabel {
+    pub id: ID,
+    pub name: String,
+    /// Prompt templates, each containing `{}` where the name goes.
+    /// Empty means `autotag::DEFAULT_PROMPT_TEMPLATES`.
+    pub templates: Vec<String>,
+    /// User-set score threshold; `None` lets each run calibrate one.
+    pub threshold: Option<f32>,
+    /// Suggestions for this label still awaiting a decision.
+    pub pending: i64,
+}
+
+/// A pending (image, label) pair, with what the review grid needs to
+/// render it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TagSuggestion {
+    pub image_id: ID,
+    pub path: String,
+    pub thumbnail_path: Option<String>,
+    pub label_id: ID,
+    pub label: String,
+    pub score: f32,
+}
+
+impl ImageDatabase {
+    pub fn add_tag_label(&self, name: &str, templates: &[String]) -> rusqlite::Result<TagLabel> {
+        let conn = self.connection.lock().unwrap();
+        conn.execute(
+            "INSERT INTO tag_labels (name, templates) VALUES (?1, ?2)",
+            params![name, templates.join("\n")],
+        )?;
+        Ok(TagLabel {
+            id: conn.last_insert_rowid(),
+            name: name.to_string(),
+            templates: templates.to_vec(),
+            threshold: None,
+            pending: 0,
+        })
+    }
+
+    /// Replace a label's templates and threshold override. Its cached
+    /// embeddings are dropped — the templates they were built from may
+    /// have changed — so the next run re-encodes and recalibrates.
+    pub fn update_tag_label(
+        &self,
+        label_id: ID,
+        templates: &[String],
+        threshold: Option<f32>,
+    ) -> rusqlite::Result<()> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        let changed = tx.execute(
+            "UPDATE tag_labels SET templates = ?2, threshold = ?3 WHERE id = ?1",
+            params![label_id, templates.join("\n"), threshold.map(f64::from)],
+        )?;
+        if changed == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        tx.execute(
+            "DELETE FROM tag_label_embeddings WHERE label_id = ?1",
+            [label_id],
+        )?;
+        tx.commit()
+    }
+
+    /// Delete a label and (CASCADE) its embeddings and suggestions.
+    /// Tags already accepted from it stay.
+    pub fn delete_tag_label(&self, label_id: ID) -> rusqlite::Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM tag_labels WHERE id = ?1", [label_id])?;
+        Ok(())
+    }
+
+    pub fn list_tag_labels(&self) -> rusqlite::Result<Vec<TagLabel>> {
+        let conn = self.read_lock();
+        let mut stmt = conn.prepare(
+            "SELECT l.id, l.name, l.templates, l.threshold,
+                    (SELECT COUNT(*) FROM tag_suggestions s
+                     WHERE s.label_id = l.id AND s.status = 'pending')
+             FROM tag_labels l
+             ORDER BY l.name COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map([], |r| {
+            let templates: String = r.get(2)?;
+            Ok(TagLabel {
+                id: r.get(0)?,
+                name: r.get(1)?,
+                templates: templates
+                    .lines()
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                threshold: r.get::<_, Option<f64>>(3)?.map(|t| t as f32),
+                pending: r.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// The cached prompt-ensembled embedding of a label for a text
+    /// encoder, if one has been computed since its templates last
+    /// changed.
+    pub fn get_label_embedding(
+        &self,
+        label_id: ID,
+        encoder_id: &str,
+    ) -> rusqlite::Result<Option<Vec<f32>>> {
+        let conn = self.read_lock();
+        let bytes: Option<Vec<u8>> = conn
+            .query_row(
+                "SELECT embedding FROM tag_label_embeddings
+                 WHERE label_id = ?1 AND encoder_id = ?2",
+                params![label_id, encoder_id],
+                |r| r.get(0),
+            )
+            .optional()?;
+        Ok(bytes.map(|b| bytemuck::pod_collect_to_vec(&b)))
+    }
+
+    pub fn set_label_embedding(
+        &self,
+        label_id: ID,
+        encoder_id: &str,
+        embedding: &[f32],
+    ) -> rusqlite::Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO tag_label_embeddings (label_id, encoder_id, embedding)
+             VALUES (?1, ?2, ?3)
+             ON CONFLICT(label_id, encoder_id) DO UPDATE SET
+                embedding = excluded.embedding,
+                calibrated_threshold = NULL",
+            params![label_id, encoder_id, bytemuck::cast_slice::<f32, u8>(embedding)],
+        )?;
+        Ok(())
+    }
+
+    /// Image ids whose suggestion for `label_id` was (accepted, rejected).
+    pub fn label_feedback(&self, label_id: ID) -> rusqlite::Result<(Vec<ID>, Vec<ID>)> {
+        let conn = self.read_lock();
+        let mut stmt = conn.prepare(
+            "SELECT image_id, status FROM tag_suggestions
+             WHERE label_id = ?1 AND status != 'pending'",
+        )?;
+        let mut accepted = Vec::new();
+        let mut rejected = Vec::new();
+        let mut rows = stmt.query([label_id])?;
+        while let Some(r) = rows.next()? {
+            let id: ID = r.get(0)?;
+            match r.get_ref(1)?.as_str()? {
+                "accepted" => accepted.push(id),
+                _ => rejected.push(id),
+            }
+        }
+        Ok((accepted, rejected))
+    }
+
+    /// Swap a label's pending suggestions for `scored` and record the
+    /// threshold they were cut at. Pairs already decided, and images
+    /// that already carry a tag with the label's name, are skipped.
+    /// Returns how many suggestions are now pending.
+    pub fn replace_pending_suggestions(
+        &self,
+        label_id: ID,
+        encoder_id: &str,
+        threshold: f32,
+        scored: &[(ID, f32)],
+    ) -> rusqlite::Result<usize> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM tag_suggestions WHERE label_id = ?1 AND status = 'pending'",
+            [label_id],
+        )?;
+        tx.execute(
+            "UPDATE tag_label_embeddings SET calibrated_threshold = ?3
+             WHERE label_id = ?1 AND encoder_id = ?2",
+            params![label_id, encoder_id, f64::from(threshold)],
+        )?;
+        let mut inserted = 0;
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO tag_suggestions (image_id, label_id, encoder_id, score)
+                 SELECT ?1, ?2, ?3, ?4
+                 WHERE NOT EXISTS (
+                     SELECT 1 FROM images_tags it
+                     JOIN tags t ON t.id = it.tag_id
+                     JOIN tag_labels l ON l.name = t.name
+                     WHERE it.image_id = ?1 AND l.id = ?2
+                 )
+                 ON CONFLICT(image_id, label_id) DO NOTHING",
+            )?;
+            for &(image_id, score) in scored {
+                inserted += insert.execute(params![image_id, label_id, encoder_id, f64::from(score)])?;
+            }
+        }
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    /// Pending suggestions, best first — for one label, or across all
+    /// of them. Images that are orphaned or under a disabled root are
+    /// left out, matching the grid.
+    pub fn get_tag_suggestions(
+        &self,
+        label_id: Option<ID>,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<TagSuggestion>> {
+        let conn = self.read_lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT s.image_id, i.path, i.thumbnail_path, s.label_id, l.name, s.score
+             FROM tag_suggestions s
+             JOIN images i ON i.id = s.image_id
+             JOIN tag_labels l ON l.id = s.label_id
+             WHERE s.status = 'pending'
+               AND (?1 IS NULL OR s.label_id = ?1)
+               AND i.orphaned = 0
+               AND {}
+             ORDER BY s.score DESC
+             LIMIT ?2",
+            root_filter("i.root_id", RootScope::Enabled)
+        ))?;
+        let rows = stmt.query_map(params![label_id, limit as i64], |r| {
+            Ok(TagSuggestion {
+                image_id: r.get(0)?,
+                path: r.get(1)?,
+                thumbnail_path: r.get(2)?,
+                label_id: r.get(3)?,
+                label: r.get(4)?,
+                score: r.get::<_, f64>(5)? as f32,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Accept a label's pending suggestions — all of them, or only those
+    /// for `image_ids` — by tagging each image with the tag named after
+    /// the label (created if missing). Returns how many were accepted.
+    /// The tagging is undoable; undo leaves the suggestions accepted.
+    pub fn accept_tag_suggestions(
+        &self,
+        label_id: ID,
+        image_ids: Option<&[ID]>,
+    ) -> rusqlite::Result<usize> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        let name: String = tx.query_row(
+            "SELECT name FROM tag_labels WHERE id = ?1",
+            [label_id],
+            |r| r.get(0),
+        )?;
+        let mut accepted = Vec::new();
+        decide(&tx, label_id, image_ids, "accepted", |_, image_id| {
+            accepted.push(image_id);
+            Ok(())
+        })?;
+        if !accepted.is_empty() {
+            let mut recording = Recording::default();
+            let tag_id = recording.tag_named(&tx, &name, DEFAULT_TAG_COLOR)?;
+            recording.apply(
+                &tx,
+                JournalOp::TagImages {
+                    tag_id,
+                    image_ids: accepted.clone(),
+                },
+            )?;
+            recording.record(&tx, &format!("Accept suggested tag \"{name}\""))?;
+        }
+        tx.commit()?;
+        Ok(accepted.len())
+    }
+
+    /// Reject a label's pending suggestions — all of them, or only those
+    /// for `image_ids`. Returns how many were rejected.
+    pub fn reject_tag_suggestions(
+        &self,
+        label_id: ID,
+        image_ids: Option<&[ID]>,
+    ) -> rusqlite::Result<usize> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        let rejected = decide(&tx, label_id, image_ids, "rejected", |_, _| Ok(()))?;
+        tx.commit()?;
+        Ok(rejected)
+    }
+}
+
+/// Move the selected pending suggestions of `label_id` to `status`,
+/// calling `apply` for each image first.
+fn decide(
+    tx: &Transaction<'_>,
+    label_id: ID,
+    image_ids: Option<&[ID]>,
+    status: &str,
+    mut apply: impl FnMut(&Transaction<'_>, ID) -> rusqlite::Result<()>,
+) -> rusqlite::Result<usize> {
+    let selected: Vec<ID> = match image_ids {
+        Some(ids) => ids.to_vec(),
+        None => {
+            let mut stmt = tx.prepare(
+                "SELECT image_id FROM tag_suggestions WHERE label_id = ?1 AND status = 'pending'",
+            )?;
+            let ids = stmt.query_map([label_id], |r| r.get(0))?;
+            ids.collect::<rusqlite::Result<_>>()?
+        }
+    };
+    let mut update = tx.prepare(
+        "UPDATE tag_suggestions SET status = ?3
+         WHERE image_id = ?1 AND label_id = ?2 AND status = 'pending'",
+    )?;
+    let mut decided = 0;
+    for image_id in selected {
+        if update.execute(params![image_id, label_id, status])? == 1 {
+            apply(tx, image_id)?;
+            decided += 1;
+        }
+    }
+    Ok(decided)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::fresh_db;
+    use super::*;
+
+    fn db_with_images(n: i64) -> ImageDatabase {
+        let db = fresh_db();
+        for i in 1..=n {
+            db.add_image(format!("/lib/{i}.jpg"), None).unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn rerun_replaces_pending_but_keeps_decisions() {
+        let db = db_with_images(3);
+        let label = db.add_tag_label("beach", &[]).unwrap();
+        db.set_label_embedding(label.id, "clip_vit_b_32", &[1.0, 0.0]).unwrap();
+        assert_eq!(
+            db.replace_pending_suggestions(label.id, "clip_vit_b_32", 0.2, &[(1, 0.9), (2, 0.5)])
+                .unwrap(),
+            2
+        );
+        assert_eq!(db.reject_tag_suggestions(label.id, Some(&[2])).unwrap(), 1);
+
+        // Image 2 stays rejected; image 1 is re-scored, 3 is new.
+        let pending = db
+            .replace_pending_suggestions(
+                label.id,
+                "clip_vit_b_32",
+                0.3,
+                &[(1, 0.8), (2, 0.7), (3, 0.6)],
+            )
+            .unwrap();
+        assert_eq!(pending, 2);
+        let suggestions = db.get_tag_suggestions(Some(label.id), 10).unwrap();
+        let got: Vec<(ID, f32)> = suggestions.iter().map(|s| (s.image_id, s.score)).collect();
+        assert_eq!(got, vec![(1, 0.8), (3, 0.6)]);
+        assert_eq!(db.label_feedback(label.id).unwrap(), (vec![], vec![2]));
+        assert_eq!(db.list_tag_labels().unwrap()[0].pending, 2);
+    }
+
+    #[test]
+    fn accept_tags_images_and_creates_the_tag_once() {
+        let db = db_with_images(3);
+        let label = db.add_tag_label("dog", &["a photo of a {}".into()]).unwrap();
+        db.replace_pending_suggestions(label.id, "clip_vit_b_32", 0.2, &[(1, 0.9), (2, 0.8), (3, 0.7)])
+            .unwrap();
+
+        assert_eq!(db.accept_tag_suggestions(label.id, Some(&[1, 99])).unwrap(), 1);
+        assert_eq!(db.accept_tag_suggestions(label.id, None).unwrap(), 2);
+        // Nothing left to accept.
+        assert_eq!(db.accept_tag_suggestions(label.id, None).unwrap(), 0);
+
+        let tags = db.get_tags().unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "dog");
+        let conn = db.connection.lock().unwrap();
+        let tagged: i64 = conn
+            .query_row("SELECT COUNT(*) FROM images_tags WHERE tag_id = ?1", [tags[0].id], |r| r.get(0))
+            .unwrap();
+        assert_eq!(tagged, 3);
+    }
+
+    #[test]
+    fn already_tagged_images_are_not_suggested() {
+        let db = db_with_images(2);
+        let tag = db.create_tag("cat".into(), "#fff".into()).unwrap();
+        db.add_tag_to_image(1, tag.id).unwrap();
+        let label = db.add_tag_label("cat", &[]).unwrap();
+        let pending = db
+            .replace_pending_suggestions(label.id, "clip_vit_b_32", 0.2, &[(1, 0.9), (2, 0.8)])
+            .unwrap();
+        assert_eq!(pending, 1);
+        assert_eq!(db.get_tag_suggestions(None, 10).unwrap()[0].image_id, 2);
+    }
+
+    #[test]
+    fn editing_a_label_drops_its_cached_embeddings() {
+        let db = fresh_db();
+        let label = db.add_tag_label("sunset", &[]).unwrap();
+        db.set_label_embedding(label.id, "siglip2_base", &[0.5, 0.5]).unwrap();
+        assert_eq!(
+            db.get_label_embedding(label.id, "siglip2_base").unwrap(),
+            Some(vec![0.5, 0.5])
+        );
+
+        db.update_tag_label(label.id, &["{} over the sea".into()], Some(0.1)).unwrap();
+        assert_eq!(db.get_label_embedding(label.id, "siglip2_base").unwrap(), None);
+        let stored = &db.list_tag_labels().unwrap()[0];
+        assert_eq!(stored.templates, vec!["{} over the sea".to_string()]);
+        assert_eq!(stored.threshold, Some(0.1));
+        assert!(matches!(
+            db.update_tag_label(404, &[], None),
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        ));
+    }
+}
@@ -0,0 +1,265 @@
+//! Tag hierarchy: `tags.parent_id` (schema migration 8).
+//!
+//! A tag may sit under another ("Reference > Architecture >
+//! Brutalist"). The tree only shapes the catalogue and the filters —
+//! images carry the tags they were given, never their ancestors. A
+//! filter on a parent tag matches images carrying the tag itself or
+//! any tag below it (`tagged_images_sql`), in the grid, the paged grid
+//! and `SearchFilter`.
+//!
+//! Cycle checks belong to the caller (`Library::move_tag`,
+//! `Library::merge_tags`): the methods here write what they're given.
+//! The recursive queries use `UNION`, so even a cycle written by hand
+//! terminates.
+
+use std::collections::BTreeSet;
+
+use rusqlite::{params, Connection};
+
+use super::tags::link_images;
+use super::{ImageDatabase, ID};
+use crate::tag_struct::Tag;
+
+/// SELECT of the ids of images carrying, for each of `tag_ids`, that
+/// tag or one of its descendants — for every one of them with
+/// `match_all`, any one without. Binds `tag_ids` as its positional
+/// parameters, in order; a subquery for `images.id IN (…)`.
+pub(super) fn tagged_images_sql(tag_ids: &[ID], match_all: bool) -> String {
+    let placeholders = vec!["?"; tag_ids.len()].join(", ");
+    let subtree = format!(
+        "WITH RECURSIVE subtree(root, id) AS (
+            SELECT id, id FROM tags WHERE id IN ({placeholders})
+            UNION
+            SELECT subtree.root, tags.id FROM tags JOIN subtree ON tags.parent_id = subtree.id
+        )"
+    );
+    if match_all {
+        // One hit per selected tag, whichever descendant supplied it.
+        let distinct_tags = tag_ids.iter().collect::<BTreeSet<_>>().len();
+        format!(
+            "{subtree}
+            SELECT it.image_id FROM images_tags it JOIN subtree ON subtree.id = it.tag_id
+            GROUP BY it.image_id HAVING COUNT(DISTINCT subtree.root) = {distinct_tags}"
+        )
+    } else {
+        format!("{subtree} SELECT it.image_id FROM images_tags it WHERE it.tag_id IN (SELECT id FROM subtree)")
+    }
+}
+
+impl ImageDatabase {
+    /// Create a tag, optionally under `parent_id`.
+    pub fn create_tag_under(
+        &self,
+        name: String,
+        color: String,
+        parent_id: Option<ID>,
+    ) -> rusqlite::Result<Tag> {
+        let conn = self.connection.lock().unwrap();
+        conn.execute(
+            "INSERT INTO tags (name, color, parent_id) VALUES (?1, ?2, ?3)",
+            params![name, color, parent_id],
+        )?;
+        Ok(Tag {
+            id: conn.last_insert_rowid(),
+            name,
+            color,
+            parent_id,
+        })
+    }
+
+    /// `tag_id` and every tag below it.
+    pub fn get_tag_subtree(&self, tag_id: ID) -> rusqlite::Result<BTreeSet<ID>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "WITH RECURSIVE subtree(id) AS (
+                SELECT id FROM tags WHERE id = ?1
+                UNION
+                SELECT tags.id FROM tags JOIN subtree ON tags.parent_id = subtree.id
+            )
+            SELECT id FROM subtree",
+        )?;
+        let ids = stmt.query_map([tag_id], |r| r.get(0))?.collect();
+        ids
+    }
+
+    /// Move `tag_id`, with its subtree, under `parent_id` (`None`: to
+    /// the top level). `QueryReturnedNoRows` if the tag doesn't exist.
+    pub fn move_tag(&self, tag_id: ID, parent_id: Option<ID>) -> rusqlite::Result<()> {
+        let moved = self.connection.lock().unwrap().execute(
+            "UPDATE tags SET parent_id = ?2 WHERE id = ?1",
+            params![tag_id, parent_id],
+        )?;
+        if moved == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    /// Fold `source_id` into `target_id` in one transaction — see
+    /// `merge_tag`. Returns how many images newly gained the target.
+    pub fn merge_tag_into(&self, source_id: ID, target_id: ID) -> rusqlite::Result<usize> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        let gained = merge_tag(&tx, source_id, target_id)?;
+        tx.commit()?;
+        Ok(gained.len())
+    }
+}
+
+/// Fold `source_id` into `target_id`: its images gain the target, its
+/// children move under the target, its name and aliases become aliases
+/// of the target, and the source is deleted. Returns the images that
+/// newly gained the target, in id order.
+pub(super) fn merge_tag(
+    conn: &Connection,
+    source_id: ID,
+    target_id: ID,
+) -> rusqlite::Result<Vec<ID>> {
+    conn.execute(
+        "UPDATE tag_aliases SET tag_id = ?2 WHERE tag_id = ?1",
+        [source_id, target_id],
+    )?;
+    conn.execute(
+        "INSERT OR IGNORE INTO tag_aliases (name, tag_id)
+         SELECT name, ?2 FROM tags WHERE id = ?1",
+        [source_id, target_id],
+    )?;
+    let image_ids: Vec<ID> = {
+        let mut stmt =
+            conn.prepare("SELECT image_id FROM images_tags WHERE tag_id = ?1 ORDER BY image_id")?;
+        let ids = stmt.query_map([source_id], |r| r.get(0))?;
+        ids.collect::<rusqlite::Result<_>>()?
+    };
+    let gained = link_images(conn, target_id, &image_ids)?;
+    conn.execute(
+        "UPDATE tags SET parent_id = ?2 WHERE parent_id = ?1",
+        [source_id, target_id],
+    )?;
+    // The source's own links go with it (ON DELETE CASCADE).
+    conn.execute("DELETE FROM tags WHERE id = ?1", [source_id])?;
+    Ok(gained)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::fresh_db;
+    use super::*;
+
+    /// reference > architecture > brutalist, plus a top-level "cat".
+    fn tree(db: &ImageDatabase) -> [ID; 4] {
+        let tag = |name: &str, parent| {
+            db.create_tag_under(name.into(), "#fff".into(), parent)
+                .unwrap()
+                .id
+        };
+        let reference = tag("reference", None);
+        let architecture = tag("architecture", Some(reference));
+        let brutalist = tag("brutalist", Some(architecture));
+        let cat = tag("cat", None);
+        [reference, architecture, brutalist, cat]
+    }
+
+    #[test]
+    fn subtrees_move_and_parents_survive_in_get_tags() {
+        let db = fresh_db();
+        let [reference, architecture, brutalist, cat] = tree(&db);
+        assert_eq!(
+            db.get_tag_subtree(reference).unwrap(),
+            BTreeSet::from([reference, architecture, brutalist])
+        );
+
+        db.move_tag(architecture, Some(cat)).unwrap();
+        assert_eq!(
+            db.get_tag_subtree(reference).unwrap(),
+            BTreeSet::from([reference])
+        );
+        assert!(db.get_tag_subtree(cat).unwrap().contains(&brutalist));
+        let tags = db.get_tags().unwrap();
+        let parent_of = |id| tags.iter().find(|t| t.id == id).unwrap().parent_id;
+        assert_eq!(parent_of(architecture), Some(cat));
+        assert_eq!(parent_of(cat), None);
+
+        assert!(matches!(
+            db.move_tag(999, None),
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        ));
+    }
+
+    #[test]
+    fn merging_moves_images_and_children_to_the_target() {
+        let db = fresh_db();
+        let [reference, architecture, brutalist, cat] = tree(&db);
+        for i in 1..=2 {
+            db.add_image(format!("/lib/{i}.jpg"), None).unwrap();
+        }
+        db.add_tag_to_image(1, architecture).unwrap();
+        db.add_tag_to_image(2, architecture).unwrap();
+        db.add_tag_to_image(2, cat).unwrap();
+
+        assert_eq!(db.merge_tag_into(architecture, cat).unwrap(), 1);
+        assert_eq!(db.get_image_ids_with_tag(cat).unwrap(), vec![1, 2]);
+        let tags = db.get_tags().unwrap();
+        assert!(!tags.iter().any(|t| t.id == architecture));
+        assert_eq!(
+            tags.iter().find(|t| t.id == brutalist).unwrap().parent_id,
+            Some(cat)
+        );
+        assert_eq!(db.get_tag_subtree(reference).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn deleting_a_parent_lifts_its_children() {
+        let db = fresh_db();
+        let [reference, architecture, brutalist, _] = tree(&db);
+        db.delete_tag(architecture).unwrap();
+        let tags = db.get_tags().unwrap();
+        assert_eq!(
+            tags.iter().find(|t| t.id == brutalist).unwrap().parent_id,
+            Some(reference)
+        );
+    }
+
+    #[test]
+    fn grid_filters_match_descendants_in_both_modes() {
+        let db = fresh_db();
+        let [reference, architecture, brutalist, cat] = tree(&db);
+        for i in 1..=4 {
+            db.add_image(format!("/lib/{i}.jpg"), None).unwrap();
+        }
+        db.add_tag_to_image(1, brutalist).unwrap();
+        db.add_tag_to_image(2, architecture).unwrap();
+        db.add_tag_to_image(2, cat).unwrap();
+        db.add_tag_to_image(3, cat).unwrap();
+
+        let grid = |tags: Vec<ID>, all| {
+            db.get_images_with_thumbnails(tags, String::new(), all)
+                .unwrap()
+                .into_iter()
+                .map(|i| i.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(grid(vec![reference], false), [1, 2]);
+        assert_eq!(grid(vec![architecture, cat], false), [1, 2, 3]);
+        assert_eq!(grid(vec![reference, cat], true), [2]);
+        // A tag and its own ancestor: both satisfied by the leaf.
+        assert_eq!(grid(vec![reference, brutalist], true), [1]);
+        assert_eq!(grid(vec![brutalist], true), [1]);
+
+        let filtered = |filter: super::super::SearchFilter| {
+            let mut names: Vec<_> = db
+                .filtered_image_paths(&filter)
+                .unwrap()
+                .into_iter()
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            filtered(super::super::SearchFilter {
+                exclude_tag_ids: vec![reference],
+                ..Default::default()
+            }),
+            ["/lib/3.jpg", "/lib/4.jpg"].map(std::path::PathBuf::from)
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! Tag catalogue + image↔tag association table.
+//!
+//! Tags themselves live in the `tags` table; the many-to-many link to
+//! images lives in `images_tags`. Queries that JOIN those two for the
+//! grid live in `images_query.rs`; the methods here are the small
+//! mutation surface for managing the catalogue. The parent/child
+//! hierarchy over `tags` lives in `tag_tree.rs`, alternative names in
+//! `tag_aliases.rs`.
+
+use std::collections::HashMap;
+
+use rusqlite::fallible_iterator::FallibleIterator;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{root_filter, ID, ImageDatabase, RootScope};
+use crate::tag_struct::Tag;
+
+impl ImageDatabase {
+    pub fn create_tag(&self, name: String, color: String) -> rusqlite::Result<Tag> {
+        self.create_tag_under(name, color, None)
+    }
+
+    /// One tag; `QueryReturnedNoRows` if it doesn't exist.
+    pub fn get_tag(&self, tag_id: ID) -> rusqlite::Result<Tag> {
+        find_tag(&self.connection.lock().unwrap(), tag_id)?
+            .ok_or(rusqlite::Error::QueryReturnedNoRows)
+    }
+
+    /// Rename a tag. A UNIQUE violation if another tag has the name;
+    /// `QueryReturnedNoRows` if the tag doesn't exist. Taking a name
+    /// that was the tag's own alias drops the alias.
+    pub fn rename_tag(&self, tag_id: ID, name: &str) -> rusqlite::Result<()> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM tag_aliases WHERE name = ?2 AND tag_id = ?1",
+            params![tag_id, name],
+        )?;
+        let renamed = tx.execute(
+            "UPDATE tags SET name = ?2 WHERE id = ?1",
+            params![tag_id, name],
+        )?;
+        if renamed == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        tx.commit()
+    }
+
+    /// Delete a tag and its image links. Its children move up to its
+    /// parent rather than losing their place in the tree.
+    pub fn delete_tag(&self, tag_id: ID) -> rusqlite::Result<()> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        delete_tag_lifting_children(&tx, tag_id)?;
+        tx.commit()
+    }
+
+    pub fn remove_tag_from_image(&self, image_id: ID, tag_id: ID) -> rusqlite::Result<()> {
+        self.connection.lock().unwrap().execute(
+            "DELETE FROM images_tags WHERE image_id = ?1 AND tag_id = ?2",
+            [image_id, tag_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn add_tag_to_image(&self, image_id: ID, tag_id: ID) -> rusqlite::Result<()> {
+        // INSERT OR IGNORE so duplicate (image_id, tag_id) assignments are
+        // a no-op rather than a UNIQUE-constraint error. The frontend
+        // pre-checks selection state, but a future caller that doesn't
+        // shouldn't have to.
+        self.connection.lock().unwrap().execute(
+            "INSERT OR IGNORE INTO images_tags (image_id, tag_id) VALUES (?1, ?2)",
+            [image_id, tag_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_tags(&self) -> rusqlite::Result<Vec<Tag>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT * FROM tags ORDER BY id;")?;
+
+        let rows = stmt.query([])?;
+
+        rows
+            .map(|r| {
+                Ok(Tag {
+                    id: r.get("id")?,
+                    name: r.get("name")?,
+                    color: r.get("color")?,
+                    parent_id: r.get("parent_id")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Tag a batch of images in one transaction. Already-tagged and
+    /// unknown images are skipped; returns how many links were added.
+    pub fn add_tag_to_images(&self, tag_id: ID, image_ids: &[ID]) -> rusqlite::Result<usize> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        let added = link_images(&tx, tag_id, image_ids)?.len();
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Untag a batch of images in one transaction; returns how many
+    /// links were removed.
+    pub fn remove_tag_from_images(&self, tag_id: ID, image_ids: &[ID]) -> rusqlite::Result<usize> {
+        let mut conn = self.connection.lock().unwrap();
+        let tx = conn.transaction()?;
+        let removed = unlink_images(&tx, tag_id, image_ids)?.len();
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Every image→tags link, keyed by image. Images without tags, and
+    /// images of a removed root, are absent. Used by tag propagation to
+    /// find tagged neighbours.
+    pub fn get_image_tag_map(&self) -> rusqlite::Result<HashMap<ID, Vec<ID>>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT it.image_id, it.tag_id FROM images_tags it
+             JOIN images i ON i.id = it.image_id
+             WHERE {}
+             ORDER BY it.image_id, it.tag_id",
+            root_filter("i.root_id", RootScope::Live)
+        ))?;
+        let mut map: HashMap<ID, Vec<ID>> = HashMap::new();
+        let mut rows = stmt.query([])?;
+        while let Some(r) = rows.next()? {
+            map.entry(r.get(0)?).or_default().push(r.get(1)?);
+        }
+        Ok(map)
+    }
+
+    /// Ids of the images carrying `tag_id`, ascending. Images of a
+    /// removed root are left out.
+    pub fn get_image_ids_with_tag(&self, tag_id: ID) -> rusqlite::Result<Vec<ID>> {
+        let conn = self.connection.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT it.image_id FROM images_tags it
+             JOIN images i ON i.id = it.image_id
+             WHERE it.tag_id = ?1 AND {}
+             ORDER BY it.image_id",
+            root_filter("i.root_id", RootScope::Live)
+        ))?;
+        let rows = stmt.query([tag_id])?;
+        rows.map(|r| r.get(0)).collect()
+    }
+}
+
+/// One tag, if it exists.
+pub(super) fn find_tag(conn: &Connection, tag_id: ID) -> rusqlite::Result<Option<Tag>> {
+    conn.query_row(
+        "SELECT id, name, color, parent_id FROM tags WHERE id = ?1",
+        [tag_id],
+        |r| {
+            Ok(Tag {
+                id: r.get(0)?,
+                name: r.get(1)?,
+                color: r.get(2)?,
+                parent_id: r.get(3)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Delete a tag, first moving its children up to its parent. Returns
+/// how many tags were deleted (0 or 1).
+pub(super) fn delete_tag_lifting_children(
+    conn: &Connection,
+    tag_id: ID,
+) -> rusqlite::Result<usize> {
+    conn.execute(
+        "UPDATE tags SET parent_id = (SELECT parent_id FROM tags WHERE id = ?1)
+         WHERE parent_id = ?1",
+        [tag_id],
+    )?;
+    conn.execute("DELETE FROM tags WHERE id = ?1", [tag_id])
+}
+
+/// Link `tag_id` to each of `image_ids` that exists and doesn't carry
+/// it yet; returns those, in order. Nothing is linked if the tag
+/// doesn't exist.
+pub(super) fn link_images(
+    conn: &Connection,
+    tag_id: ID,
+    image_ids: &[ID],
+) -> rusqlite::Result<Vec<ID>> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT OR IGNORE INTO images_tags (image_id, tag_id)
+         SELECT images.id, tags.id FROM images, tags WHERE images.id = ?1 AND tags.id = ?2",
+    )?;
+    let mut linked = Vec::new();
+    for &image_id in image_ids {
+        if stmt.execute([image_id, tag_id])? > 0 {
+            linked.push(image_id);
+        }
+    }
+    Ok(linked)
+}
+
+/// Unlink `tag_id` from each of `image_ids`; returns the images that
+/// carried it, in order.
+pub(super) fn unlink_images(
+    conn: &Connection,
+    tag_id: ID,
+    image_ids: &[ID],
+) -> rusqlite::Result<Vec<ID>> {
+    let mut stmt =
+        conn.prepare_cached("DELETE FROM images_tags WHERE image_id = ?1 AND tag_id = ?2")?;
+    let mut unlinked = Vec::new();
+    for &image_id in image_ids {
+        if stmt.execute([image_id, tag_id])? > 0 {
+            unlinked.push(image_id);
+        }
+    }
+    Ok(unlinked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::fresh_db;
+
+    #[test]
+    fn batch_tagging_skips_existing_links() {
+        let db = fresh_db();
+        for i in 1..=3 {
+            db.add_image(format!("/lib/{i}.jpg"), None).unwrap();
+        }
+        let tag = db.create_tag("cat".into(), "#fff".into()).unwrap();
+        db.add_tag_to_image(2, tag.id).unwrap();
+
+        assert_eq!(db.add_tag_to_images(tag.id, &[1, 2, 3, 99]).unwrap(), 2);
+        assert_eq!(db.get_image_ids_with_tag(tag.id).unwrap(), vec![1, 2, 3]);
+
+        let map = db.get_image_tag_map().unwrap();
+        assert_eq!(map.len(), 3);
+        assert_eq!(map[&2], vec![tag.id]);
+
+        assert_eq!(db.remove_tag_from_images(tag.id, &[1, 3, 99]).unwrap(), 2);
+        assert_eq!(db.get_image_ids_with_tag(tag.id).unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn renames_keep_links_and_refuse_taken_names() {
+        let db = fresh_db();
+        db.add_image("/lib/1.jpg".into(), None).unwrap();
+        let cat = db.create_tag("cat".into(), "#fff".into()).unwrap();
+        let dog = db.create_tag("dog".into(), "#000".into()).unwrap();
+        db.add_tag_to_image(1, cat.id).unwrap();
+        db.add_tag_alias(cat.id, "kitty").unwrap();
+
+        db.rename_tag(cat.id, "kitty").unwrap();
+        assert_eq!(db.get_tag(cat.id).unwrap().name, "kitty");
+        assert!(db.get_tag_aliases().unwrap().is_empty());
+        assert_eq!(db.get_image_ids_with_tag(cat.id).unwrap(), vec![1]);
+
+        assert!(db.rename_tag(dog.id, "kitty").is_err());
+        assert_eq!(db.get_tag(dog.id).unwrap().name, "dog");
+        assert!(matches!(
+            db.rename_tag(999, "fox"),
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        ));
+    }
+}
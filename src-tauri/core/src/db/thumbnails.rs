@@ -4,10 +4,12 @@
 //! thumbnail generator); the columns here are just the pointer + the
 //! width/height we use for the masonry layout.
 
-use super::{ID, ImageDatabase};
+use super::{root_filter, ID, ImageDatabase, RootScope};
 
 impl ImageDatabase {
-    /// Update thumbnail path and original dimensions for an image
+    /// Update thumbnail path and original dimensions for an image. The
+    /// stored perceptual hash described the old thumbnail, so it is
+    /// cleared for the duplicate finder to recompute.
     pub fn update_image_thumbnail(
         &self,
         image_id: ID,
@@ -17,20 +19,25 @@ impl ImageDatabase {
     ) -> rusqlite::Result<()> {
         let thumbnail_path_str = thumbnail_path.to_string_lossy().to_string();
         self.connection.lock().unwrap().execute(
-            "UPDATE images SET thumbnail_path = ?1, width = ?2, height = ?3 WHERE id = ?4",
+            "UPDATE images SET thumbnail_path = ?1, width = ?2, height = ?3, perceptual_hash = NULL
+             WHERE id = ?4",
             rusqlite::params![thumbnail_path_str, width as i64, height as i64, image_id],
         )?;
         Ok(())
     }
 
-    /// Get thumbnail info for an image (thumbnail_path, width, height)
+    /// Get thumbnail info for an image (thumbnail_path, width, height).
+    /// `None` until it has a thumbnail, and for an image of a removed
+    /// root.
     pub fn get_image_thumbnail_info(
         &self,
         image_id: ID,
     ) -> rusqlite::Result<Option<(String, u32, u32)>> {
         let conn = self.connection.lock().unwrap();
-        let mut stmt =
-            conn.prepare("SELECT thumbnail_path, width, height FROM images WHERE id = ?1")?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT thumbnail_path, width, height FROM images WHERE id = ?1 AND {}",
+            root_filter("root_id", RootScope::Live)
+        ))?;
 
         let mut rows = stmt.query([image_id])?;
         if let Some(row) = rows.next()? {
@@ -0,0 +1,548 @@
+//! Duplicate finder: group exact and near-duplicate images across every
+//! root, suggest which copy to keep, and send the rest to the OS trash.
+//!
+//! - **Exact** duplicates share a content fingerprint — BLAKE3 hash
+//!   plus file size, the same pair move/rename reconciliation keys on
+//!   (`db/content_hash.rs`).
+//! - **Near** duplicates (resized, re-encoded, lightly cropped) are
+//!   pairs whose DINOv2 cosine similarity is at least `min_similarity`
+//!   *and* whose thumbnail dHashes (`thumbnail::phash`) are within
+//!   `MAX_HAMMING` bits. DINOv2 is the structure-sensitive encoder, so
+//!   it separates "same photo" from "same subject" better than the
+//!   CLIP-family ones; the hash check weeds out the burst shots and
+//!   near-identical scenes the embedding alone still lets through.
+//!   Crops move the dHash a lot, so a pair at `STRICT_SIMILARITY` or
+//!   above passes without it.
+//!
+//! Matching pairs are merged transitively (union-find) into clusters.
+//! The suggested keeper is the largest original by pixel count, then
+//! by file size, then the oldest row.
+//!
+//! The pairwise pass is brute force over the DINOv2 rows — fine for
+//! the tens of thousands of images this app targets, and it runs only
+//! when the user asks for the report.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::db::{DuplicateCandidate, ImageDatabase, ID};
+use crate::error::ApiError;
+use crate::similarity_and_semantic_search::cosine::math::dot;
+use crate::similarity_and_semantic_search::cosine::EmbeddingMatrix;
+use crate::similarity_and_semantic_search::encoder_dinov2::DINOV2_ENCODER_ID;
+use crate::thumbnail::phash;
+
+/// Default DINOv2 cosine floor for a near-duplicate pair.
+pub const DEFAULT_MIN_SIMILARITY: f32 = 0.92;
+
+/// DINOv2 cosine at which a pair counts without the dHash check.
+const STRICT_SIMILARITY: f32 = 0.97;
+
+/// Most differing dHash bits for a near-duplicate pair (of 64).
+const MAX_HAMMING: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateKind {
+    /// Every member has the same content hash.
+    Exact,
+    Near,
+}
+
+/// One member of a cluster.
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateImage {
+    pub id: ID,
+    pub path: String,
+    pub thumbnail_path: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub file_size: Option<i64>,
+    /// DINOv2 cosine to the keeper; 1.0 for the keeper itself and for
+    /// byte-identical copies.
+    pub similarity: f32,
+}
+
+/// A group of duplicates, keeper first.
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateCluster {
+    pub kind: DuplicateKind,
+    pub keeper_id: ID,
+    pub images: Vec<DuplicateImage>,
+}
+
+/// One cluster's cleanup: the copy to keep and the copies to trash.
+/// The keeper may differ from the suggested one but must be in the
+/// same cluster as every image it replaces.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TrashSelection {
+    pub keeper_id: ID,
+    pub image_ids: Vec<ID>,
+}
+
+/// An image `trash_duplicates` couldn't move: its file stayed put, or its
+/// id didn't resolve (`path` is then empty).
+#[derive(Debug, Clone, Serialize)]
+pub struct TrashFailure {
+    pub id: ID,
+    pub path: String,
+    pub error: String,
+}
+
+/// Outcome of `trash_duplicates`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TrashReport {
+    pub trashed: Vec<ID>,
+    pub failed: Vec<TrashFailure>,
+}
+
+/// Every duplicate cluster in the visible library, largest first.
+/// Thumbnails without a stored dHash are hashed (and the hash saved)
+/// on the way.
+pub fn find_duplicates(
+    db: &ImageDatabase,
+    min_similarity: f32,
+) -> Result<Vec<DuplicateCluster>, ApiError> {
+    let started = std::time::Instant::now();
+    let mut candidates = db.get_duplicate_candidates()?;
+    backfill_perceptual_hashes(db, &mut candidates)?;
+    let index_of: HashMap<ID, usize> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (c.id, i))
+        .collect();
+
+    let mut sets = UnionFind::new(candidates.len());
+
+    // Exact: same (hash, size).
+    let mut by_hash: HashMap<(&str, i64), usize> = HashMap::new();
+    for (i, c) in candidates.iter().enumerate() {
+        if let (Some(hash), Some(size)) = (c.content_hash.as_deref(), c.file_size) {
+            match by_hash.get(&(hash, size)) {
+                Some(&first) => sets.union(first, i),
+                None => {
+                    by_hash.insert((hash, size), i);
+                }
+            }
+        }
+    }
+
+    // Near: DINOv2 rows, aligned with `candidates` through `row_owner`.
+    let mut matrix = EmbeddingMatrix::new();
+    let mut row_owner = Vec::new();
+    for (id, path, embedding) in db.get_all_embeddings_for(DINOV2_ENCODER_ID)? {
+        if let Some(&i) = index_of.get(&id) {
+            if matrix.push(PathBuf::from(path), &embedding) {
+                row_owner.push(i);
+            }
+        }
+    }
+    let row_hashes: Vec<Option<u64>> = row_owner
+        .iter()
+        .map(|&i| candidates[i].perceptual_hash)
+        .collect();
+    let pairs = near_duplicate_pairs(&matrix, &row_hashes, min_similarity);
+    for &(a, b, _) in &pairs {
+        sets.union(row_owner[a], row_owner[b]);
+    }
+
+    let row_of: HashMap<usize, usize> = row_owner
+        .iter()
+        .enumerate()
+        .map(|(row, &i)| (i, row))
+        .collect();
+    let similarity = |a: usize, b: usize| -> f32 {
+        match (row_of.get(&a), row_of.get(&b)) {
+            (Some(&ra), Some(&rb)) => {
+                dot(matrix.row(ra), matrix.row(rb)) * matrix.inv_norm(ra) * matrix.inv_norm(rb)
+            }
+            _ => 0.0,
+        }
+    };
+
+    let mut clusters: Vec<DuplicateCluster> = sets
+        .groups()
+        .into_iter()
+        .filter(|g| g.len() > 1)
+        .map(|group| {
+            let members: Vec<&DuplicateCandidate> = group.iter().map(|&i| &candidates[i]).collect();
+            let keeper = group[pick_keeper(&members)];
+            let keeper_fp = fingerprint(&candidates[keeper]);
+            let exact = keeper_fp.is_some()
+                && group
+                    .iter()
+                    .all(|&i| fingerprint(&candidates[i]) == keeper_fp);
+
+            let mut images: Vec<DuplicateImage> = group
+                .iter()
+                .map(|&i| {
+                    let c = &candidates[i];
+                    let identical =
+                        i == keeper || (keeper_fp.is_some() && fingerprint(c) == keeper_fp);
+                    DuplicateImage {
+                        id: c.id,
+                        path: c.path.clone(),
+                        thumbnail_path: c.thumbnail_path.clone(),
+                        width: c.width,
+                        height: c.height,
+                        file_size: c.file_size,
+                        similarity: if identical {
+                            1.0
+                        } else {
+                            similarity(keeper, i)
+                        },
+                    }
+                })
+                .collect();
+            images.sort_by(|a, b| {
+                (b.id == candidates[keeper].id)
+                    .cmp(&(a.id == candidates[keeper].id))
+                    .then(b.similarity.total_cmp(&a.similarity))
+                    .then(a.id.cmp(&b.id))
+            });
+            DuplicateCluster {
+                kind: if exact {
+                    DuplicateKind::Exact
+                } else {
+                    DuplicateKind::Near
+                },
+                keeper_id: candidates[keeper].id,
+                images,
+            }
+        })
+        .collect();
+    clusters.sort_by(|a, b| {
+        b.images
+            .len()
+            .cmp(&a.images.len())
+            .then(a.keeper_id.cmp(&b.keeper_id))
+    });
+
+    info!(
+        "find_duplicates: {} images, {} DINOv2 rows, {} near pairs -> {} clusters in {} ms",
+        candidates.len(),
+        row_owner.len(),
+        pairs.len(),
+        clusters.len(),
+        started.elapsed().as_millis()
+    );
+    Ok(clusters)
+}
+
+/// Trash the duplicates each selection names, keeping its keeper.
+///
+/// The clusters are re-derived at `min_similarity` rather than taken
+/// on trust, and the whole request is refused with `BadInput` before
+/// anything moves if a selection would trash a keeper (its own or
+/// another's) or an image outside its keeper's cluster. Every cluster
+/// keeps a live copy that way, so trashing never takes the last live
+/// copy of a content hash.
+pub fn trash_duplicates(
+    db: &ImageDatabase,
+    selections: &[TrashSelection],
+    min_similarity: f32,
+) -> Result<TrashReport, ApiError> {
+    let clusters = find_duplicates(db, min_similarity)?;
+    let cluster_of: HashMap<ID, usize> = clusters
+        .iter()
+        .enumerate()
+        .flat_map(|(c, cluster)| cluster.images.iter().map(move |img| (img.id, c)))
+        .collect();
+    let keepers: HashSet<ID> = selections.iter().map(|s| s.keeper_id).collect();
+
+    let mut image_ids = Vec::new();
+    for selection in selections {
+        let keeper = selection.keeper_id;
+        let Some(&cluster) = cluster_of.get(&keeper) else {
+            return Err(ApiError::BadInput(format!(
+                "image {keeper} is not in a duplicate cluster"
+            )));
+        };
+        for &id in &selection.image_ids {
+            if keepers.contains(&id) {
+                return Err(ApiError::BadInput(format!(
+                    "image {id} is a keeper and can't be trashed"
+                )));
+            }
+            if cluster_of.get(&id) != Some(&cluster) {
+                return Err(ApiError::BadInput(format!(
+                    "image {id} is not a duplicate of image {keeper}"
+                )));
+            }
+            image_ids.push(id);
+        }
+    }
+    image_ids.sort_unstable();
+    image_ids.dedup();
+    trash_images(db, &image_ids)
+}
+
+/// Move each image's file to the OS trash and flag the rows orphaned.
+/// Files that can't be moved, and ids that don't resolve to an image,
+/// are reported and left alone; the rest go through regardless.
+fn trash_images(db: &ImageDatabase, image_ids: &[ID]) -> Result<TrashReport, ApiError> {
+    let mut report = TrashReport::default();
+    for &id in image_ids {
+        // A failed lookup must not abort the loop: files already moved
+        // to the trash still need their rows orphaned below.
+        let path = match db.get_image_path(id) {
+            Ok(path) => path,
+            Err(e) => {
+                warn!("could not look up image {id} to trash it: {e}");
+                report.failed.push(TrashFailure {
+                    id,
+                    path: String::new(),
+                    error: ApiError::from(e).to_string(),
+                });
+                continue;
+            }
+        };
+        match trash::delete(Path::new(&path)) {
+            Ok(()) => report.trashed.push(id),
+            Err(e) => {
+                warn!("could not move {path} to the trash: {e}");
+                report.failed.push(TrashFailure {
+                    id,
+                    path,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+    db.mark_images_orphaned(&report.trashed)?;
+    info!(
+        "trash_images: {} moved to the trash, {} failed",
+        report.trashed.len(),
+        report.failed.len()
+    );
+    Ok(report)
+}
+
+/// Hash every thumbnail that has no stored dHash yet, in parallel, and
+/// save the results. Unreadable thumbnails stay unhashed.
+fn backfill_perceptual_hashes(
+    db: &ImageDatabase,
+    candidates: &mut [DuplicateCandidate],
+) -> Result<(), ApiError> {
+    let computed: Vec<(usize, u64)> = candidates
+        .par_iter()
+        .enumerate()
+        .filter(|(_, c)| c.perceptual_hash.is_none())
+        .filter_map(|(i, c)| {
+            let thumb = c.thumbnail_path.as_deref()?;
+            phash::dhash_file(Path::new(thumb)).map(|h| (i, h))
+        })
+        .collect();
+    if computed.is_empty() {
+        return Ok(());
+    }
+    let rows: Vec<(ID, u64)> = computed
+        .iter()
+        .map(|&(i, h)| (candidates[i].id, h))
+        .collect();
+    db.set_perceptual_hashes(&rows)?;
+    for (i, h) in computed {
+        candidates[i].perceptual_hash = Some(h);
+    }
+    Ok(())
+}
+
+fn fingerprint(c: &DuplicateCandidate) -> Option<(&str, i64)> {
+    Some((c.content_hash.as_deref()?, c.file_size?))
+}
+
+/// Row pairs `(a, b, cosine)` with `a < b` that count as near
+/// duplicates: cosine at least `min_similarity` and dHashes within
+/// `MAX_HAMMING` bits, or cosine at least `STRICT_SIMILARITY`.
+/// `hashes[i]` is row `i`'s dHash; a missing one needs the strict bar.
+pub fn near_duplicate_pairs(
+    rows: &EmbeddingMatrix,
+    hashes: &[Option<u64>],
+    min_similarity: f32,
+) -> Vec<(usize, usize, f32)> {
+    (0..rows.len())
+        .into_par_iter()
+        .flat_map_iter(|a| {
+            let row_a = rows.row(a);
+            let inv_a = rows.inv_norm(a);
+            (a + 1..rows.len()).filter_map(move |b| {
+                let cosine = dot(row_a, rows.row(b)) * inv_a * rows.inv_norm(b);
+                if cosine < min_similarity {
+                    return None;
+                }
+                let hashes_close = match (hashes[a], hashes[b]) {
+                    (Some(ha), Some(hb)) => phash::hamming(ha, hb) <= MAX_HAMMING,
+                    _ => false,
+                };
+                (hashes_close || cosine >= STRICT_SIMILARITY).then_some((a, b, cosine))
+            })
+        })
+        .collect()
+}
+
+/// Index into `members` of the copy to keep: most pixels, then the
+/// largest file, then the lowest id (the first one indexed). Unknown
+/// dimensions and sizes count as zero.
+pub fn pick_keeper(members: &[&DuplicateCandidate]) -> usize {
+    let pixels =
+        |c: &DuplicateCandidate| u64::from(c.width.unwrap_or(0)) * u64::from(c.height.unwrap_or(0));
+    members
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| {
+            pixels(a)
+                .cmp(&pixels(b))
+                .then(a.file_size.unwrap_or(0).cmp(&b.file_size.unwrap_or(0)))
+                .then(b.id.cmp(&a.id))
+        })
+        .map_or(0, |(i, _)| i)
+}
+
+/// Disjoint sets over `0..n` with path halving and union by size.
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        Self {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+
+    /// Every set's members, ascending; sets ordered by smallest member.
+    fn groups(&mut self) -> Vec<Vec<usize>> {
+        let mut by_root: HashMap<usize, usize> = HashMap::new();
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for x in 0..self.parent.len() {
+            let root = self.find(x);
+            let slot = *by_root.entry(root).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[slot].push(x);
+        }
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(id: ID, dims: (u32, u32), size: i64) -> DuplicateCandidate {
+        DuplicateCandidate {
+            id,
+            path: format!("/lib/{id}.jpg"),
+            thumbnail_path: None,
+            width: Some(dims.0),
+            height: Some(dims.1),
+            file_size: Some(size),
+            content_hash: None,
+            perceptual_hash: None,
+        }
+    }
+
+    #[test]
+    fn keeper_is_largest_then_heaviest_then_oldest() {
+        let small = candidate(1, (800, 600), 900_000);
+        let big = candidate(2, (4000, 3000), 2_000_000);
+        let big_heavier = candidate(3, (4000, 3000), 3_000_000);
+        let big_twin = candidate(4, (4000, 3000), 3_000_000);
+        assert_eq!(pick_keeper(&[&small, &big]), 1);
+        assert_eq!(pick_keeper(&[&big, &big_heavier, &small]), 1);
+        assert_eq!(pick_keeper(&[&big_twin, &big_heavier]), 1);
+    }
+
+    #[test]
+    fn near_pairs_need_the_hash_unless_strict() {
+        let mut rows = EmbeddingMatrix::new();
+        rows.push(PathBuf::from("a"), &[1.0, 0.0, 0.0]);
+        rows.push(PathBuf::from("b"), &[0.95, 0.31, 0.0]); // cos ≈ 0.95
+        rows.push(PathBuf::from("c"), &[0.99, 0.1, 0.0]); // cos ≈ 0.995 to a
+        rows.push(PathBuf::from("d"), &[0.0, 0.0, 1.0]);
+
+        let close = Some(0b1111);
+        let far = Some(!0b1111u64);
+        let pairs = near_duplicate_pairs(&rows, &[close, close, far, close], 0.92);
+        let ids: Vec<(usize, usize)> = pairs.iter().map(|&(a, b, _)| (a, b)).collect();
+        // a–b: similar enough and hashes match. a–c, b–c: c's hash is
+        // far, but a–c clears the strict bar. d matches nothing.
+        assert!(ids.contains(&(0, 1)));
+        assert!(ids.contains(&(0, 2)));
+        assert!(!ids.iter().any(|&(a, b)| a == 3 || b == 3));
+
+        let without_hashes = near_duplicate_pairs(&rows, &[None; 4], 0.92);
+        assert!(without_hashes
+            .iter()
+            .all(|&(_, _, cos)| cos >= STRICT_SIMILARITY));
+    }
+
+    #[test]
+    fn union_find_groups_transitively() {
+        let mut sets = UnionFind::new(5);
+        sets.union(0, 3);
+        sets.union(3, 4);
+        assert_eq!(sets.groups(), vec![vec![0, 3, 4], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn trashing_carries_on_past_an_unknown_id() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = ImageDatabase::new(tmp.path().join("t.db").to_str().unwrap()).unwrap();
+        db.initialize().unwrap();
+        for i in 1..=2 {
+            let f = tmp.path().join(format!("{i}.jpg"));
+            std::fs::write(&f, [i as u8; 16]).unwrap();
+            db.add_image(f.to_string_lossy().into_owned(), None).unwrap();
+        }
+
+        // 99 sits between two real images: the first may already be in
+        // the trash by the time it's looked up.
+        let report = trash_images(&db, &[1, 99, 2]).unwrap();
+
+        let unknown = report.failed.iter().find(|f| f.id == 99).unwrap();
+        assert!(unknown.error.starts_with("not found"), "{}", unknown.error);
+        // Whether the sandbox has a usable trash or not, both real
+        // images are accounted for, and every one that went is
+        // flagged orphaned.
+        for id in [1, 2] {
+            let trashed = report.trashed.contains(&id);
+            let failed = report.failed.iter().any(|f| f.id == id);
+            assert!(trashed != failed, "image {id}: {report:?}");
+        }
+        let remaining: Vec<ID> = db
+            .get_duplicate_candidates()
+            .unwrap()
+            .iter()
+            .map(|c| c.id)
+            .collect();
+        assert!(report.trashed.iter().all(|id| !remaining.contains(id)));
+    }
+}
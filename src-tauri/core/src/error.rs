@@ -1,4 +1,4 @@
-//! Typed error returned by every Tauri command.
+//! Typed error returned by every Tauri command and `Library` call.
 //!
 //! Replaces the previous `Result<T, String>` pattern where every
 //! command did `.map_err(|e| e.to_string())` and the frontend got back
@@ -64,6 +64,10 @@ pub enum ApiError {
     /// negative top_n, etc.).
     BadInput(String),
 
+    /// The HTTP API (`http_api.rs`) got a request without a valid
+    /// token. Never produced over Tauri IPC.
+    Unauthorized(String),
+
     /// Filesystem operations — file read errors, missing thumbnails,
     /// I/O failures during the indexing pipeline.
     Io(String),
@@ -86,6 +90,7 @@ impl std::fmt::Display for ApiError {
             ApiError::Cosine(m) => write!(f, "cosine error: {m}"),
             ApiError::NotFound(r) => write!(f, "not found: {r}"),
             ApiError::BadInput(m) => write!(f, "bad input: {m}"),
+            ApiError::Unauthorized(m) => write!(f, "unauthorized: {m}"),
             ApiError::Io(m) => write!(f, "io error: {m}"),
             ApiError::Internal(m) => write!(f, "internal error: {m}"),
         }
@@ -112,6 +117,24 @@ impl From<rusqlite::Error> for ApiError {
     }
 }
 
+impl ApiError {
+    /// `e` as usual, except that a UNIQUE / PRIMARY KEY violation
+    /// becomes `BadInput(message())` — for writes where the clash is
+    /// the caller's mistake (a tag name already taken) rather than a
+    /// database fault.
+    pub fn unique_as_bad_input(e: rusqlite::Error, message: impl FnOnce() -> String) -> Self {
+        match &e {
+            rusqlite::Error::SqliteFailure(f, _)
+                if f.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE
+                    || f.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_PRIMARYKEY =>
+            {
+                ApiError::BadInput(message())
+            }
+            _ => e.into(),
+        }
+    }
+}
+
 impl From<std::io::Error> for ApiError {
     fn from(e: std::io::Error) -> Self {
         ApiError::Io(e.to_string())
@@ -160,6 +183,23 @@ mod tests {
         assert!(matches!(e, ApiError::Db(_)));
     }
 
+    #[test]
+    fn unique_violations_can_become_bad_input() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE t (name TEXT UNIQUE); INSERT INTO t VALUES ('a');")
+            .unwrap();
+        let clash = conn.execute("INSERT INTO t VALUES ('a')", []).unwrap_err();
+        assert!(matches!(
+            ApiError::unique_as_bad_input(clash, || "taken".into()),
+            ApiError::BadInput(m) if m == "taken"
+        ));
+        let other = rusqlite::Error::InvalidColumnIndex(1);
+        assert!(matches!(
+            ApiError::unique_as_bad_input(other, || "taken".into()),
+            ApiError::Db(_)
+        ));
+    }
+
     #[test]
     fn display_includes_kind_label() {
         let e = ApiError::Encoder("ONNX failed".into());
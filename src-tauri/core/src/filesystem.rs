@@ -0,0 +1,164 @@
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+const SUPPORTED_IMAGE_EXTENSIONS: [&str; 7] = ["jpg", "png", "gif", "jpeg", "bmp", "tiff", "webp"];
+
+pub(crate) fn is_supported_image(path: &Path) -> bool {
+    if let Some(extension) = std::path::Path::new(path).extension() {
+        if let Some(ext_str) = extension.to_str() {
+            return SUPPORTED_IMAGE_EXTENSIONS.contains(&ext_str.to_lowercase().as_str());
+        }
+    }
+    false
+}
+
+pub struct ImageScanner {}
+
+impl Default for ImageScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ImageScanner {
+    pub fn new() -> Self {
+        ImageScanner {}
+    }
+
+    // CAN USE WALKDIR
+    pub fn scan_directory(&self, root: &Path) -> Result<Vec<String>, std::io::Error> {
+        self.scan_directory_cancellable(root, &|| false)
+    }
+
+    /// `scan_directory` with a cancellation hook polled before every
+    /// directory entry. Returns an `ErrorKind::Interrupted` error as
+    /// soon as `is_cancelled()` is true — a partial path list is never
+    /// returned, since orphan detection treats it as the full alive set.
+    #[tracing::instrument(name = "filesystem.scan", skip(self, is_cancelled), fields(root = %root.display()))]
+    pub fn scan_directory_cancellable(
+        &self,
+        root: &Path,
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Result<Vec<String>, std::io::Error> {
+        let mut img_paths: Vec<String> = Vec::new();
+
+        for entry_res in std::fs::read_dir(root)? {
+            if is_cancelled() {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "scan cancelled"));
+            }
+            let entry = entry_res?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+
+            if file_type.is_dir() {
+                let mut nested = self.scan_directory_cancellable(&path, is_cancelled)?;
+                img_paths.append(&mut nested);
+            } else if file_type.is_file()
+                && is_supported_image(&path) {
+                    img_paths.push(path.to_string_lossy().to_string());
+                }
+        }
+
+        Ok(img_paths)
+    }
+}
+
+/// Content identity of a file on disk: BLAKE3 of the bytes plus the
+/// byte length, alongside the mtime the hash was taken at.
+///
+/// `hash` + `size` are what move/rename reconciliation matches on (see
+/// `db/content_hash.rs`). `mtime` is bookkeeping only — the indexing
+/// pipeline skips re-hashing a file whose size and mtime still match
+/// the stored fingerprint, so steady-state launches cost one `stat`
+/// per file instead of a full read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileFingerprint {
+    /// Lower-case hex BLAKE3 digest.
+    pub hash: String,
+    pub size: i64,
+    /// Modification time, milliseconds since the Unix epoch.
+    pub mtime: i64,
+}
+
+impl FileFingerprint {
+    /// Read and hash the whole file.
+    pub fn compute(path: &Path) -> io::Result<Self> {
+        let (size, mtime) = file_size_and_mtime(path)?;
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(std::fs::File::open(path)?)?;
+        Ok(FileFingerprint {
+            hash: hasher.finalize().to_hex().to_string(),
+            size,
+            mtime,
+        })
+    }
+}
+
+/// `stat`-only half of the fingerprint: (size in bytes, mtime in ms).
+/// Filesystems without mtime support report 0, which just means the
+/// file is re-hashed whenever its size changes.
+pub fn file_size_and_mtime(path: &Path) -> io::Result<(i64, i64)> {
+    let meta = std::fs::metadata(path)?;
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    Ok((meta.len() as i64, mtime))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_supported_extensions() {
+        assert!(is_supported_image(Path::new("photo.jpg")));
+        assert!(is_supported_image(Path::new("image.PNG"))); // case insensitive
+        assert!(!is_supported_image(Path::new("document.pdf")));
+        assert!(!is_supported_image(Path::new("video.mp4")));
+    }
+
+    #[test]
+    fn fingerprint_depends_on_content_not_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let a = tmp.path().join("a.jpg");
+        let b = tmp.path().join("renamed.jpg");
+        let c = tmp.path().join("other.jpg");
+        std::fs::write(&a, b"same bytes").unwrap();
+        std::fs::write(&b, b"same bytes").unwrap();
+        std::fs::write(&c, b"different").unwrap();
+
+        let fa = FileFingerprint::compute(&a).unwrap();
+        let fb = FileFingerprint::compute(&b).unwrap();
+        let fc = FileFingerprint::compute(&c).unwrap();
+        assert_eq!((fa.hash.as_str(), fa.size), (fb.hash.as_str(), fb.size));
+        assert_ne!(fa.hash, fc.hash);
+        assert_eq!(fa.size, 10);
+        assert_eq!(fa.hash.len(), 64);
+    }
+
+    #[test]
+    fn cancelled_scan_returns_interrupted_not_a_partial_list() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmp.path().join("nested")).unwrap();
+        std::fs::write(tmp.path().join("a.jpg"), b"x").unwrap();
+        std::fs::write(tmp.path().join("nested").join("b.png"), b"x").unwrap();
+
+        let scanner = ImageScanner::new();
+        assert_eq!(scanner.scan_directory(tmp.path()).unwrap().len(), 2);
+
+        // Trip after the first entry so the cancel lands mid-walk.
+        let polls = std::cell::Cell::new(0);
+        let err = scanner
+            .scan_directory_cancellable(tmp.path(), &|| {
+                polls.set(polls.get() + 1);
+                polls.get() > 1
+            })
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
+}
@@ -0,0 +1,563 @@
+//! Opt-in loopback HTTP/JSON API over the library.
+//!
+//! Lets local tools (scripts, a browser extension, Obsidian) query the
+//! catalogue without driving the GUI. The endpoints mirror the Tauri
+//! commands — same names for query parameters and JSON fields, same
+//! result types — and call the same `Library` methods underneath:
+//!
+//! | Method   | Path                               | Mirrors                    |
+//! |----------|------------------------------------|----------------------------|
+//! | `GET`    | `/api/images`                      | `get_images`               |
+//! | `GET`    | `/api/search?query=`               | `get_fused_semantic_search`|
+//! | `GET`    | `/api/images/{id}/similar`         | `get_fused_similar_images` |
+//! | `GET`    | `/api/images/{id}/thumbnail`       | the grid's thumbnail JPEG  |
+//! | `GET`    | `/api/images/{id}/original`        | the original file          |
+//! | `GET`    | `/api/images/{id}/notes`           | `get_image_notes`          |
+//! | `PUT`    | `/api/images/{id}/notes`           | `set_image_notes`          |
+//! | `GET`    | `/api/tags`                        | `get_tags`                 |
+//! | `POST`   | `/api/tags`                        | `create_tag`               |
+//! | `DELETE` | `/api/tags/{id}`                   | `delete_tag`               |
+//! | `PUT`    | `/api/images/{id}/tags/{tag_id}`   | `add_tag_to_image`         |
+//! | `DELETE` | `/api/images/{id}/tags/{tag_id}`   | `remove_tag_from_image`    |
+//!
+//! The two searches take an optional `filter` parameter: a
+//! `SearchFilter` as URL-encoded JSON, e.g.
+//! `filter={"include_tag_ids":[3],"min_width":1920}`.
+//!
+//! Errors come back as the `ApiError` tagged JSON the frontend already
+//! understands (`{"kind": "not_found", "details": "..."}`), with a
+//! matching HTTP status.
+//!
+//! Security model: binds 127.0.0.1 only, and every request must carry
+//! the token from `paths::http_api_token_path()` — as
+//! `Authorization: Bearer <token>`, or `?token=` for clients that can't
+//! set headers (an `<img src>`). The token keeps other users' processes
+//! and web pages off the port; anything that can read the app-data
+//! directory can read the library anyway.
+//!
+//! Blocking `tiny_http` with a small worker pool, the same thread-based
+//! style as the indexing pipeline and watcher. Runs inside the app when
+//! `settings.json` has `http_api_port` — sharing the app's `Library`, so
+//! searches hit the GUI's warm caches — or standalone via
+//! `image-browser-cli serve`.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::db::{SearchFilter, ID};
+use crate::error::ApiError;
+use crate::{paths, Library};
+
+/// Port `image-browser-cli serve` listens on without `--port`.
+pub const DEFAULT_PORT: u16 = 7457;
+
+/// Requests are short (a search is the slowest at ~100 ms warm), so a
+/// handful of workers covers several tools polling at once.
+const WORKER_THREADS: usize = 4;
+
+/// Cap on JSON request bodies. The largest legitimate body is a note.
+const MAX_BODY_BYTES: u64 = 1 << 20;
+
+/// The token stored at `paths::http_api_token_path()`, generating one
+/// on first use. Readable by the owner only on Unix.
+pub fn load_or_create_token() -> io::Result<String> {
+    let path = paths::http_api_token_path();
+    match std::fs::read_to_string(&path) {
+        Ok(existing) if !existing.trim().is_empty() => return Ok(existing.trim().to_string()),
+        // An empty file holds no secret; replace it.
+        Ok(_) => std::fs::remove_file(&path)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let token = create_token_file(&path)?;
+    info!("generated HTTP API token at {}", path.display());
+    Ok(token)
+}
+
+/// Write a fresh random token to `path`, which must not exist yet.
+/// Created owner-only on Unix rather than chmod-ed afterwards, so the
+/// secret is never readable by anyone else, not even briefly.
+fn create_token_file(path: &Path) -> io::Result<String> {
+    let bytes: [u8; 32] = rand::random();
+    let token: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+/// A running server. Dropping it stops the workers; `wait` blocks until
+/// they stop on their own (they don't, short of process exit — it's
+/// what `image-browser-cli serve` parks on).
+pub struct HttpApiHandle {
+    server: Arc<Server>,
+    shutting_down: Arc<AtomicBool>,
+    workers: Vec<thread::JoinHandle<()>>,
+    port: u16,
+}
+
+impl HttpApiHandle {
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn wait(mut self) {
+        for w in self.workers.drain(..) {
+            let _ = w.join();
+        }
+    }
+}
+
+impl Drop for HttpApiHandle {
+    fn drop(&mut self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        for _ in 0..self.workers.len() {
+            self.server.unblock();
+        }
+    }
+}
+
+/// Bind `127.0.0.1:port` (0 picks a free port) and start the workers.
+pub fn spawn(library: Arc<Library>, port: u16, token: String) -> io::Result<HttpApiHandle> {
+    let server = Arc::new(Server::http(("127.0.0.1", port)).map_err(io::Error::other)?);
+    let port = server
+        .server_addr()
+        .to_ip()
+        .map(|addr| addr.port())
+        .unwrap_or(port);
+    let token: Arc<str> = token.into();
+    let shutting_down = Arc::new(AtomicBool::new(false));
+
+    let workers = (0..WORKER_THREADS)
+        .map(|_| {
+            let server = server.clone();
+            let library = library.clone();
+            let token = token.clone();
+            let shutting_down = shutting_down.clone();
+            thread::spawn(move || loop {
+                match server.recv() {
+                    Ok(request) => handle(&library, &token, request),
+                    Err(_) if shutting_down.load(Ordering::SeqCst) => break,
+                    Err(e) => warn!("http api: accept failed: {e}"),
+                }
+            })
+        })
+        .collect();
+
+    info!("HTTP API listening on 127.0.0.1:{port}");
+    Ok(HttpApiHandle {
+        server,
+        shutting_down,
+        workers,
+        port,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+enum Route {
+    Images,
+    Search,
+    Similar(ID),
+    Thumbnail(ID),
+    Original(ID),
+    GetNotes(ID),
+    SetNotes(ID),
+    Tags,
+    CreateTag,
+    DeleteTag(ID),
+    AddImageTag(ID, ID),
+    RemoveImageTag(ID, ID),
+}
+
+impl Route {
+    /// `None` for unknown paths, unsupported methods and non-numeric ids
+    /// alike — all three are a 404 to the client.
+    fn parse(method: &Method, segments: &[&str]) -> Option<Route> {
+        let ["api", rest @ ..] = segments else {
+            return None;
+        };
+        let id = |s: &str| s.parse::<ID>().ok();
+        Some(match (method, rest) {
+            (Method::Get, ["images"]) => Route::Images,
+            (Method::Get, ["search"]) => Route::Search,
+            (Method::Get, ["images", i, "similar"]) => Route::Similar(id(i)?),
+            (Method::Get, ["images", i, "thumbnail"]) => Route::Thumbnail(id(i)?),
+            (Method::Get, ["images", i, "original"]) => Route::Original(id(i)?),
+            (Method::Get, ["images", i, "notes"]) => Route::GetNotes(id(i)?),
+            (Method::Put, ["images", i, "notes"]) => Route::SetNotes(id(i)?),
+            (Method::Get, ["tags"]) => Route::Tags,
+            (Method::Post, ["tags"]) => Route::CreateTag,
+            (Method::Delete, ["tags", t]) => Route::DeleteTag(id(t)?),
+            (Method::Put, ["images", i, "tags", t]) => Route::AddImageTag(id(i)?, id(t)?),
+            (Method::Delete, ["images", i, "tags", t]) => Route::RemoveImageTag(id(i)?, id(t)?),
+            _ => return None,
+        })
+    }
+}
+
+enum Reply {
+    Json(Vec<u8>),
+    File(File, &'static str),
+    NoContent,
+}
+
+#[derive(Deserialize)]
+struct CreateTagBody {
+    name: String,
+    color: String,
+    #[serde(default)]
+    parent_id: Option<ID>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct NotesBody {
+    notes: String,
+}
+
+fn handle(library: &Library, token: &str, mut request: Request) {
+    if *request.method() == Method::Options {
+        // CORS preflight from a browser extension. Carries no token.
+        respond(request, with_cors(Response::empty(204)));
+        return;
+    }
+
+    let url = match Url::parse("http://127.0.0.1").and_then(|base| base.join(request.url())) {
+        Ok(url) => url,
+        Err(e) => {
+            respond_error(request, ApiError::BadInput(format!("malformed URL: {e}")));
+            return;
+        }
+    };
+    let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+    if !is_authorized(&request, &query, token) {
+        respond_error(
+            request,
+            ApiError::Unauthorized("missing or invalid API token".into()),
+        );
+        return;
+    }
+
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|s| s.filter(|seg| !seg.is_empty()).collect())
+        .unwrap_or_default();
+    let Some(route) = Route::parse(request.method(), &segments) else {
+        let msg = format!("no endpoint {} {}", request.method(), url.path());
+        respond_error(request, ApiError::NotFound(msg));
+        return;
+    };
+    debug!("http api: {route:?}");
+
+    match dispatch(library, route, &query, request.as_reader()) {
+        Ok(Reply::Json(body)) => respond(
+            request,
+            with_cors(Response::from_data(body).with_header(content_type("application/json"))),
+        ),
+        Ok(Reply::File(file, mime)) => respond(
+            request,
+            with_cors(Response::from_file(file).with_header(content_type(mime))),
+        ),
+        Ok(Reply::NoContent) => respond(request, with_cors(Response::empty(204))),
+        Err(e) => respond_error(request, e),
+    }
+}
+
+fn dispatch(
+    library: &Library,
+    route: Route,
+    query: &HashMap<String, String>,
+    body: &mut dyn Read,
+) -> Result<Reply, ApiError> {
+    let db = library.db();
+    match route {
+        Route::Images => {
+            let tag_ids = match query.get("filter_tag_ids").map(String::as_str) {
+                None | Some("") => Vec::new(),
+                Some(list) => list
+                    .split(',')
+                    .map(|t| {
+                        t.trim().parse::<ID>().map_err(|_| {
+                            ApiError::BadInput(format!("filter_tag_ids: not an id: '{t}'"))
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            };
+            let filter = query.get("filter_string").cloned().unwrap_or_default();
+            let match_all = param::<bool>(query, "match_all_tags")?.unwrap_or(false);
+            json(&db.get_images_with_thumbnails(tag_ids, filter, match_all)?)
+        }
+        Route::Search => {
+            let text = query
+                .get("query")
+                .ok_or_else(|| ApiError::BadInput("missing query parameter 'query'".into()))?;
+            json(&library.search(
+                text,
+                top_n(query)?,
+                param(query, "per_encoder_top_k")?,
+                search_filter(query)?.as_ref(),
+            )?)
+        }
+        Route::Similar(id) => json(&library.similar(
+            id,
+            top_n(query)?,
+            param(query, "per_encoder_top_k")?,
+            search_filter(query)?.as_ref(),
+        )?),
+        Route::Thumbnail(id) => {
+            let (thumb, _, _) = db
+                .get_image_thumbnail_info(id)?
+                .ok_or_else(|| ApiError::NotFound(format!("thumbnail for image {id}")))?;
+            open_file(&thumb)
+        }
+        Route::Original(id) => open_file(&db.get_image_path(id)?),
+        Route::GetNotes(id) => json(&NotesBody {
+            notes: db.get_image_notes(id)?.unwrap_or_default(),
+        }),
+        Route::SetNotes(id) => {
+            let NotesBody { notes } = read_json(body)?;
+            library.set_image_notes(id, &notes)?;
+            Ok(Reply::NoContent)
+        }
+        Route::Tags => json(&db.get_tags()?),
+        Route::CreateTag => {
+            let CreateTagBody {
+                name,
+                color,
+                parent_id,
+            } = read_json(body)?;
+            json(&library.create_tag(&name, &color, parent_id)?)
+        }
+        Route::DeleteTag(tag_id) => {
+            library.delete_tag(tag_id)?;
+            Ok(Reply::NoContent)
+        }
+        Route::AddImageTag(image_id, tag_id) => {
+            library.add_tag_to_image(image_id, tag_id)?;
+            Ok(Reply::NoContent)
+        }
+        Route::RemoveImageTag(image_id, tag_id) => {
+            library.remove_tag_from_image(image_id, tag_id)?;
+            Ok(Reply::NoContent)
+        }
+    }
+}
+
+/// Header token or `?token=`, compared in constant time.
+fn is_authorized(request: &Request, query: &HashMap<String, String>, token: &str) -> bool {
+    let from_header = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| h.value.as_str().strip_prefix("Bearer "));
+    from_header
+        .or(query.get("token").map(String::as_str))
+        .is_some_and(|given| constant_time_eq(given.trim().as_bytes(), token.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn param<T: std::str::FromStr>(
+    query: &HashMap<String, String>,
+    name: &str,
+) -> Result<Option<T>, ApiError> {
+    query
+        .get(name)
+        .map(|raw| {
+            raw.parse()
+                .map_err(|_| ApiError::BadInput(format!("{name}: invalid value '{raw}'")))
+        })
+        .transpose()
+}
+
+/// `top_n` as the search commands take it; 30 matches the grid's page.
+fn top_n(query: &HashMap<String, String>) -> Result<usize, ApiError> {
+    Ok(param(query, "top_n")?.unwrap_or(30))
+}
+
+/// The optional `filter` parameter, JSON-encoded.
+fn search_filter(query: &HashMap<String, String>) -> Result<Option<SearchFilter>, ApiError> {
+    query
+        .get("filter")
+        .filter(|raw| !raw.is_empty())
+        .map(|raw| {
+            serde_json::from_str(raw)
+                .map_err(|e| ApiError::BadInput(format!("filter: invalid search filter: {e}")))
+        })
+        .transpose()
+}
+
+fn json<T: Serialize>(value: &T) -> Result<Reply, ApiError> {
+    serde_json::to_vec(value)
+        .map(Reply::Json)
+        .map_err(|e| ApiError::Internal(e.to_string()))
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(body: &mut dyn Read) -> Result<T, ApiError> {
+    let mut buf = Vec::new();
+    body.take(MAX_BODY_BYTES + 1).read_to_end(&mut buf)?;
+    if buf.len() as u64 > MAX_BODY_BYTES {
+        return Err(ApiError::BadInput("request body too large".into()));
+    }
+    serde_json::from_slice(&buf).map_err(|e| ApiError::BadInput(format!("invalid JSON body: {e}")))
+}
+
+/// Only ever called with paths read from the `images` table, so the
+/// API can't be used to read arbitrary files.
+fn open_file(path: &str) -> Result<Reply, ApiError> {
+    let file = File::open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => ApiError::NotFound(format!("file {path}")),
+        _ => ApiError::from(e),
+    })?;
+    Ok(Reply::File(file, mime_for(Path::new(path))))
+}
+
+fn mime_for(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match ext.as_deref() {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        Some("bmp") => "image/bmp",
+        Some("tif" | "tiff") => "image/tiff",
+        _ => "application/octet-stream",
+    }
+}
+
+fn status_for(e: &ApiError) -> u16 {
+    match e {
+        ApiError::BadInput(_) => 400,
+        ApiError::Unauthorized(_) => 401,
+        ApiError::NotFound(_) => 404,
+        ApiError::TokenizerMissing(_)
+        | ApiError::TextModelMissing(_)
+        | ApiError::ImageModelMissing(_) => 503,
+        _ => 500,
+    }
+}
+
+fn content_type(mime: &str) -> Header {
+    Header::from_bytes("Content-Type", mime).expect("static header is valid")
+}
+
+fn with_cors<R: Read>(response: Response<R>) -> Response<R> {
+    [
+        ("Access-Control-Allow-Origin", "*"),
+        ("Access-Control-Allow-Headers", "Authorization, Content-Type"),
+        ("Access-Control-Allow-Methods", "GET, POST, PUT, DELETE"),
+    ]
+    .into_iter()
+    .fold(response, |r, (k, v)| {
+        r.with_header(Header::from_bytes(k, v).expect("static header is valid"))
+    })
+}
+
+fn respond_error(request: Request, e: ApiError) {
+    let body = serde_json::to_vec(&e).unwrap_or_default();
+    respond(
+        request,
+        with_cors(
+            Response::from_data(body)
+                .with_status_code(status_for(&e))
+                .with_header(content_type("application/json")),
+        ),
+    );
+}
+
+fn respond<R: Read>(request: Request, response: Response<R>) {
+    if let Err(e) = request.respond(response) {
+        // Client hung up mid-response; nothing to recover.
+        debug!("http api: response not delivered: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_file_is_created_owner_only_and_never_overwritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("http_api_token");
+        let token = create_token_file(&path).unwrap();
+        assert_eq!(token.len(), 64);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), token);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert!(create_token_file(&path).is_err());
+    }
+
+    #[test]
+    fn routes_mirror_the_commands() {
+        assert_eq!(Route::parse(&Method::Get, &["api", "images"]), Some(Route::Images));
+        assert_eq!(
+            Route::parse(&Method::Get, &["api", "images", "7", "similar"]),
+            Some(Route::Similar(7))
+        );
+        assert_eq!(
+            Route::parse(&Method::Delete, &["api", "images", "7", "tags", "3"]),
+            Some(Route::RemoveImageTag(7, 3))
+        );
+        assert_eq!(Route::parse(&Method::Post, &["api", "tags"]), Some(Route::CreateTag));
+        // Wrong method, non-numeric id, unknown path.
+        assert_eq!(Route::parse(&Method::Post, &["api", "images"]), None);
+        assert_eq!(Route::parse(&Method::Get, &["api", "images", "x", "notes"]), None);
+        assert_eq!(Route::parse(&Method::Get, &["images"]), None);
+    }
+
+    #[test]
+    fn token_comparison_requires_exact_match() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+        assert!(!constant_time_eq(b"", b"abc"));
+    }
+
+    #[test]
+    fn search_filter_parameter_is_json() {
+        let query = |raw: &str| HashMap::from([("filter".to_string(), raw.to_string())]);
+        assert_eq!(search_filter(&HashMap::new()).unwrap(), None);
+        assert_eq!(
+            search_filter(&query(r#"{"include_tag_ids": [3], "min_width": 1920}"#)).unwrap(),
+            Some(SearchFilter {
+                include_tag_ids: vec![3],
+                min_width: Some(1920),
+                ..Default::default()
+            })
+        );
+        assert!(matches!(search_filter(&query("3")), Err(ApiError::BadInput(_))));
+    }
+
+    #[test]
+    fn errors_map_to_http_statuses() {
+        assert_eq!(status_for(&ApiError::NotFound("x".into())), 404);
+        assert_eq!(status_for(&ApiError::BadInput("x".into())), 400);
+        assert_eq!(status_for(&ApiError::Unauthorized("x".into())), 401);
+        assert_eq!(status_for(&ApiError::Db("x".into())), 500);
+    }
+}
@@ -18,6 +18,9 @@ pub struct ImageData {
     /// Original image height in pixels
     #[serde(skip_serializing_if = "Option::is_none")]
     pub height: Option<u32>,
+    /// The image's root is offline: only the thumbnail can be shown
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub offline: bool,
 }
 
 impl ImageData {
@@ -43,6 +46,7 @@ impl ImageData {
             thumbnail_path: None,
             width: None,
             height: None,
+            offline: false,
         }
     }
 
@@ -0,0 +1,415 @@
+//! Path-level catalog updates for the filesystem watcher.
+//!
+//! A full indexing run walks every enabled root, diffs the result
+//! against the DB for orphan detection and re-checks every thumbnail
+//! and embedding. That's the right tool at launch and after a root
+//! change, but on a large share (tens of thousands of files on a NAS)
+//! it turns "one file saved" into minutes of I/O.
+//!
+//! The watcher instead hands the set of paths it saw change to
+//! `apply_path_changes`, which only touches those paths:
+//!
+//! - an image file that exists and isn't catalogued is inserted and
+//!   fingerprinted;
+//! - a catalogued file whose size/mtime moved is re-hashed, and if the
+//!   bytes really changed its embeddings + thumbnail are dropped so the
+//!   thumbnail and encoder phases redo it;
+//! - a catalogued file that reappeared is un-orphaned;
+//! - a path that no longer exists orphans its row, or every row below
+//!   it when it was a directory — unless the root folder itself is
+//!   gone, which flags the root offline instead (`root_availability`);
+//! - a directory that appeared (moved in, unzipped) is scanned.
+//!
+//! Each path is attributed to the innermost enabled root containing it,
+//! so only that root's rows are touched. Move/rename reconciliation
+//! runs at the end, exactly as in the full pipeline — a rename arrives
+//! as "old path gone" + "new path appeared".
+//!
+//! Events are classified by `stat`ing the path now rather than by the
+//! event kind, so a create-then-delete inside one debounce window is a
+//! no-op and duplicate events cost nothing.
+
+use std::collections::{BTreeSet, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::db::{ImageDatabase, ID};
+use crate::filesystem::{file_size_and_mtime, is_supported_image, FileFingerprint, ImageScanner};
+use crate::root_availability::is_available;
+use crate::root_struct::Root;
+
+/// Above this many distinct paths in one batch the watcher gives up on
+/// per-path updates and asks for a full rescan — past that point the
+/// walk is cheaper than thousands of individual lookups, and a batch
+/// that large usually means a bulk operation (folder restored, sync
+/// client catching up) the full pass handles better anyway.
+pub const MAX_INCREMENTAL_PATHS: usize = 5_000;
+
+/// What one batch changed. `ignored` counts paths outside every
+/// enabled root or that aren't supported images; `went_offline` counts
+/// roots whose folder disappeared.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ChangeSummary {
+    pub added: usize,
+    pub modified: usize,
+    pub revived: usize,
+    pub orphaned: usize,
+    pub relinked: usize,
+    pub ignored: usize,
+    pub went_offline: usize,
+}
+
+impl ChangeSummary {
+    /// Whether the catalog changed at all.
+    pub fn is_empty(&self) -> bool {
+        self.added
+            + self.modified
+            + self.revived
+            + self.orphaned
+            + self.relinked
+            + self.went_offline
+            == 0
+    }
+}
+
+/// Apply a batch of changed paths to the catalog. `roots` is the full
+/// root list; disabled roots are skipped the same way the full scan
+/// skips them.
+///
+/// Returns an `io::ErrorKind::Interrupted` error as soon as
+/// `is_cancelled()` is true. Whatever was applied before that point is
+/// committed and consistent — each path is handled on its own.
+pub fn apply_path_changes(
+    database: &ImageDatabase,
+    roots: &[Root],
+    paths: &[PathBuf],
+    is_cancelled: &dyn Fn() -> bool,
+) -> Result<ChangeSummary, Box<dyn std::error::Error>> {
+    let mut summary = ChangeSummary::default();
+    // Rows this batch inserted, to tell its adds from earlier batches'.
+    let mut inserted = HashSet::new();
+    let scanner = ImageScanner::new();
+
+    // Sorted + deduplicated so a directory is handled before the files
+    // inside it and the order is deterministic.
+    let unique: BTreeSet<&PathBuf> = paths.iter().collect();
+    for path in unique {
+        if is_cancelled() {
+            return Err(interrupted());
+        }
+        let Some(root) = owning_root(roots, path) else {
+            summary.ignored += 1;
+            continue;
+        };
+
+        match std::fs::metadata(path) {
+            Ok(meta) if meta.is_dir() => {
+                for file in scanner.scan_directory_cancellable(path, is_cancelled)? {
+                    if is_cancelled() {
+                        return Err(interrupted());
+                    }
+                    inserted.extend(upsert_file(database, root, &file, &mut summary)?);
+                }
+            }
+            Ok(meta) if meta.is_file() && is_supported_image(path) => {
+                inserted.extend(upsert_file(database, root, &path.to_string_lossy(), &mut summary)?);
+            }
+            Ok(_) => summary.ignored += 1,
+            // The whole root went away (drive unmounted): its files
+            // aren't deleted, just out of reach.
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !is_available(root) => {
+                if database.set_root_offline(root.id, true)? {
+                    warn!("watcher: root {} is offline; keeping its images", root.path);
+                    summary.went_offline += 1;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                summary.orphaned +=
+                    database.mark_path_orphaned(root.id, &path.to_string_lossy())?;
+            }
+            Err(e) => {
+                warn!("watcher: could not stat {}: {e}", path.display());
+                summary.ignored += 1;
+            }
+        }
+    }
+
+    // Either half of a move may have landed in an earlier batch, so any
+    // insert or orphan is worth a reconcile pass.
+    if summary.added > 0 || summary.orphaned > 0 {
+        let folded = database.reconcile_moved_images()?;
+        summary.relinked = folded.len();
+        // A relinked pair was counted as an add (+ an orphan, when both
+        // halves were in this batch); it's really a move. Only this
+        // batch's inserts were counted here.
+        summary.added -= folded.iter().filter(|id| inserted.contains(*id)).count();
+        summary.orphaned = summary.orphaned.saturating_sub(summary.relinked);
+    }
+    Ok(summary)
+}
+
+/// The innermost enabled root that contains `path`.
+fn owning_root<'a>(roots: &'a [Root], path: &Path) -> Option<&'a Root> {
+    roots
+        .iter()
+        .filter(|r| r.enabled && path.starts_with(&r.path))
+        .max_by_key(|r| Path::new(&r.path).components().count())
+}
+
+/// Insert, revive or re-fingerprint one existing image file. Returns
+/// the new row's id when it inserted one.
+fn upsert_file(
+    database: &ImageDatabase,
+    root: &Root,
+    path: &str,
+    summary: &mut ChangeSummary,
+) -> Result<Option<ID>, Box<dyn std::error::Error>> {
+    let (size, mtime) = match file_size_and_mtime(Path::new(path)) {
+        Ok(stat) => stat,
+        // Vanished between the directory listing and now; the removal
+        // arrives as its own event.
+        Err(_) => return Ok(None),
+    };
+
+    let stored = match database.get_content_fingerprint(path)? {
+        Some(stored) => stored,
+        None => {
+            database.add_image(path.to_string(), Some(root.id))?;
+            summary.added += 1;
+            let id = database.get_image_id_by_path(path)?;
+            fingerprint(database, id, path);
+            return Ok(Some(id));
+        }
+    };
+
+    if database.revive_orphaned_path(path)? {
+        summary.revived += 1;
+    }
+    if !stored.is_stale(size, mtime) {
+        return Ok(None);
+    }
+    let Some(fresh) = fingerprint(database, stored.id, path) else {
+        return Ok(None);
+    };
+    // A NULL stored hash just means the file predates fingerprinting;
+    // only a real hash mismatch says the pixels changed.
+    if stored.hash.as_deref().is_some_and(|h| h != fresh.hash) {
+        database.invalidate_derived_data(stored.id)?;
+        summary.modified += 1;
+    }
+    Ok(None)
+}
+
+/// Hash + store. Failures are logged and skipped, same as the full
+/// pipeline's fingerprint pass.
+fn fingerprint(database: &ImageDatabase, id: ID, path: &str) -> Option<FileFingerprint> {
+    let fp = match FileFingerprint::compute(Path::new(path)) {
+        Ok(fp) => fp,
+        Err(e) => {
+            warn!("fingerprint of {path} failed: {e}");
+            return None;
+        }
+    };
+    if let Err(e) = database.set_content_fingerprints(&[(id, fp.clone())]) {
+        warn!("could not store fingerprint of {path}: {e}");
+    }
+    Some(fp)
+}
+
+fn interrupted() -> Box<dyn std::error::Error> {
+    Box::new(io::Error::new(io::ErrorKind::Interrupted, "incremental update cancelled"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Temp dir with one root registered; returns (tmp, db, root dir, roots).
+    fn setup() -> (tempfile::TempDir, ImageDatabase, PathBuf, Vec<Root>) {
+        let tmp = tempfile::tempdir().unwrap();
+        let root_dir = tmp.path().join("photos");
+        fs::create_dir(&root_dir).unwrap();
+        let db = ImageDatabase::new(tmp.path().join("t.db").to_str().unwrap()).unwrap();
+        db.initialize().unwrap();
+        db.add_root(root_dir.to_string_lossy().into_owned()).unwrap();
+        let roots = db.list_roots().unwrap();
+        (tmp, db, root_dir, roots)
+    }
+
+    fn apply(db: &ImageDatabase, roots: &[Root], paths: &[&Path]) -> ChangeSummary {
+        let paths: Vec<PathBuf> = paths.iter().map(|p| p.to_path_buf()).collect();
+        apply_path_changes(db, roots, &paths, &|| false).unwrap()
+    }
+
+    fn visible(db: &ImageDatabase) -> Vec<String> {
+        let mut v: Vec<String> = db
+            .get_images_with_thumbnails(vec![], "".into(), false)
+            .unwrap()
+            .into_iter()
+            .map(|i| i.path)
+            .collect();
+        v.sort();
+        v
+    }
+
+    #[test]
+    fn created_file_is_inserted_and_fingerprinted() {
+        let (_tmp, db, root_dir, roots) = setup();
+        let a = root_dir.join("a.jpg");
+        fs::write(&a, b"pixels").unwrap();
+        fs::write(root_dir.join("notes.txt"), b"not an image").unwrap();
+
+        let s = apply(&db, &roots, &[&a, &root_dir.join("notes.txt")]);
+        assert_eq!((s.added, s.ignored), (1, 1));
+        let fp = db.get_content_fingerprint(&a.to_string_lossy()).unwrap().unwrap();
+        assert!(fp.hash.is_some());
+        assert_eq!(visible(&db), vec![a.to_string_lossy().into_owned()]);
+    }
+
+    #[test]
+    fn removed_file_and_directory_are_orphaned() {
+        let (_tmp, db, root_dir, roots) = setup();
+        let album = root_dir.join("album");
+        fs::create_dir(&album).unwrap();
+        let a = root_dir.join("a.jpg");
+        fs::write(&a, b"a").unwrap();
+        fs::write(album.join("1.jpg"), b"1").unwrap();
+        fs::write(album.join("2.jpg"), b"2").unwrap();
+        assert_eq!(apply(&db, &roots, &[&a, &album]).added, 3);
+
+        fs::remove_file(&a).unwrap();
+        fs::remove_dir_all(&album).unwrap();
+        let s = apply(&db, &roots, &[&a, &album]);
+        assert_eq!(s.orphaned, 3);
+        assert!(visible(&db).is_empty());
+    }
+
+    #[test]
+    fn vanished_root_goes_offline_instead_of_orphaning() {
+        let (_tmp, db, root_dir, roots) = setup();
+        let a = root_dir.join("a.jpg");
+        fs::write(&a, b"a").unwrap();
+        apply(&db, &roots, &[&a]);
+
+        // Unmount: the events name the root and the files under it.
+        fs::remove_dir_all(&root_dir).unwrap();
+        let s = apply(&db, &roots, &[&root_dir, &a]);
+        assert_eq!((s.orphaned, s.went_offline), (0, 1));
+        assert!(!s.is_empty());
+        assert!(db.list_roots().unwrap()[0].offline);
+        assert_eq!(visible(&db), vec![a.to_string_lossy().into_owned()]);
+    }
+
+    #[test]
+    fn rewritten_file_drops_derived_data_but_touch_does_not() {
+        let (_tmp, db, root_dir, roots) = setup();
+        let a = root_dir.join("a.jpg");
+        fs::write(&a, b"v1").unwrap();
+        apply(&db, &roots, &[&a]);
+        let id = db.get_image_id_by_path(&a.to_string_lossy()).unwrap();
+        db.upsert_embedding(id, "dinov2_base", &[1.0]).unwrap();
+
+        // Same bytes, new mtime (e.g. `touch`): re-hashed, nothing lost.
+        let f = fs::File::options().write(true).open(&a).unwrap();
+        f.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(5))
+            .unwrap();
+        drop(f);
+        assert!(apply(&db, &roots, &[&a]).is_empty());
+        assert!(db.get_embedding(id, "dinov2_base").is_ok());
+
+        // Different bytes: same row, embeddings dropped for re-encode.
+        fs::write(&a, b"v2, longer").unwrap();
+        let s = apply(&db, &roots, &[&a]);
+        assert_eq!(s.modified, 1);
+        assert_eq!(db.get_image_id_by_path(&a.to_string_lossy()).unwrap(), id);
+        assert!(db.get_embedding(id, "dinov2_base").is_err());
+    }
+
+    #[test]
+    fn rename_relinks_the_existing_row() {
+        let (_tmp, db, root_dir, roots) = setup();
+        let old = root_dir.join("a.jpg");
+        let new = root_dir.join("renamed.jpg");
+        fs::write(&old, b"same").unwrap();
+        apply(&db, &roots, &[&old]);
+        let id = db.get_image_id_by_path(&old.to_string_lossy()).unwrap();
+
+        fs::rename(&old, &new).unwrap();
+        let s = apply(&db, &roots, &[&old, &new]);
+        assert_eq!((s.relinked, s.added, s.orphaned), (1, 0, 0));
+        assert_eq!(db.get_image_id_by_path(&new.to_string_lossy()).unwrap(), id);
+    }
+
+    #[test]
+    fn moves_whose_new_half_came_first_are_relinked_later() {
+        let (_tmp, db, root_dir, roots) = setup();
+        let (a, b) = (root_dir.join("a.jpg"), root_dir.join("b.jpg"));
+        fs::write(&a, b"first").unwrap();
+        fs::write(&b, b"second").unwrap();
+        apply(&db, &roots, &[&a, &b]);
+        let ids = [&a, &b].map(|p| db.get_image_id_by_path(&p.to_string_lossy()).unwrap());
+
+        // Copy-then-delete moves: the copies show up one batch...
+        let moved = [root_dir.join("a2.jpg"), root_dir.join("b2.jpg")];
+        fs::copy(&a, &moved[0]).unwrap();
+        fs::copy(&b, &moved[1]).unwrap();
+        assert_eq!(apply(&db, &roots, &[&moved[0], &moved[1]]).added, 2);
+
+        // ...the deletes the next, alongside an unrelated new file.
+        fs::remove_file(&a).unwrap();
+        fs::remove_file(&b).unwrap();
+        let c = root_dir.join("c.jpg");
+        fs::write(&c, b"third").unwrap();
+        let s = apply(&db, &roots, &[&a, &b, &c]);
+        assert_eq!((s.relinked, s.added, s.orphaned), (2, 1, 0));
+        for (id, path) in ids.iter().zip(&moved) {
+            assert_eq!(db.get_image_id_by_path(&path.to_string_lossy()).unwrap(), *id);
+        }
+    }
+
+    #[test]
+    fn paths_outside_enabled_roots_are_ignored() {
+        let (tmp, db, root_dir, roots) = setup();
+        let outside = tmp.path().join("elsewhere.jpg");
+        fs::write(&outside, b"x").unwrap();
+        let inside = root_dir.join("a.jpg");
+        fs::write(&inside, b"y").unwrap();
+
+        let disabled: Vec<Root> = roots
+            .iter()
+            .cloned()
+            .map(|r| Root { enabled: false, ..r })
+            .collect();
+        assert_eq!(apply(&db, &disabled, &[&inside]).ignored, 1);
+        assert_eq!(apply(&db, &roots, &[&outside]).ignored, 1);
+        assert!(visible(&db).is_empty());
+    }
+
+    #[test]
+    fn innermost_root_owns_nested_paths() {
+        let roots = vec![
+            Root::new(1, "/r".into(), true, 0),
+            Root::new(2, "/r/nested".into(), true, 0),
+            Root::new(3, "/r/off".into(), false, 0),
+        ];
+        let owner = |p: &str| owning_root(&roots, Path::new(p)).map(|r| r.id);
+        assert_eq!(owner("/r/a.jpg"), Some(1));
+        assert_eq!(owner("/r/nested/b.jpg"), Some(2));
+        assert_eq!(owner("/r/off/c.jpg"), Some(1));
+        assert_eq!(owner("/r2/c.jpg"), None);
+    }
+
+    #[test]
+    fn cancellation_stops_before_touching_the_db() {
+        let (_tmp, db, root_dir, roots) = setup();
+        let a = root_dir.join("a.jpg");
+        fs::write(&a, b"x").unwrap();
+        let err = apply_path_changes(&db, &roots, &[a], &|| true).unwrap_err();
+        let io_err = err.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io_err.kind(), io::ErrorKind::Interrupted);
+        assert!(visible(&db).is_empty());
+    }
+}
@@ -5,7 +5,7 @@
 //! opens immediately and the user sees progress over the IPC event
 //! channel rather than staring at a blank terminal.
 //!
-//! Two trigger paths:
+//! Three trigger paths:
 //!
 //! 1. App startup (`run` in lib.rs) — if settings.json has a scan_root
 //!    and the model files exist on disk, the setup callback spawns
@@ -13,53 +13,202 @@
 //!    user reopens the app.
 //! 2. `set_scan_root` IPC command — the user picks a new folder; the
 //!    DB is wiped and the same pipeline is spawned to populate it.
+//! 3. The filesystem watcher — hands over the paths it saw change via
+//!    `try_spawn_incremental`. That run skips the root walk and orphan
+//!    pass and only applies those paths (`incremental.rs`) before the
+//!    usual thumbnail/encode phases. A full trigger queued alongside
+//!    path batches wins, since it covers them.
 //!
 //! Concurrency model: a single AtomicBool guards "one indexing run at
-//! a time". Trying to start a second run while one is in flight returns
-//! `Err(IndexingError::AlreadyRunning)` to the caller. This is a
-//! deliberately simple single-flight policy — Pass 5b's UI surfaces
-//! the rejection cleanly. A future pass can add cooperative
-//! cancellation (cancel-and-restart on rapid root switches) once we
-//! see the need.
+//! a time". A trigger that arrives while a run is in flight doesn't
+//! start a second one and isn't dropped either — it sets the
+//! `rerun_requested` dirty flag, and the worker thread loops once more
+//! when the current run ends. Any number of triggers during one run
+//! coalesce into exactly one follow-up run.
 //!
-//! Events: every state change emits a `indexing-progress` Tauri event
-//! with an `IndexingProgress` payload. The frontend hook in Pass 5b
-//! listens and renders a status pill.
+//! Cancellation is cooperative: `IndexingState::cancel` is a
+//! `CancelToken` the pipeline checks between scan entries, thumbnail
+//! chunks and encoder batches (plus at every phase boundary). A
+//! cancelled run unwinds with `IndexingError::Cancelled` and emits
+//! `Phase::Cancelled`. Two ways to trip it:
+//!
+//! - `cancel_indexing` (user pressed stop) — cancels and clears any
+//!   pending follow-up.
+//! - `restart_pipeline` (roots changed) — cancels AND requests a
+//!   follow-up, so work on the now-stale root set stops at the next
+//!   checkpoint and a fresh run picks up the new one.
+//!
+//! Events: every state change is reported to a `ProgressSink`. The app's
+//! sink emits an `indexing-progress` Tauri event with an
+//! `IndexingProgress` payload; the frontend hook in Pass 5b listens and
+//! renders a status pill. The headless CLI passes its own sink and
+//! calls `run_pipeline` on the foreground thread.
 
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 use rayon::prelude::*;
 use serde::Serialize;
-use tauri::{AppHandle, Emitter, Manager};
 use tracing::{error, info, warn};
 
-use crate::db::ImageDatabase;
-use crate::filesystem::ImageScanner;
+use crate::db::{ImageDatabase, ID};
+use crate::filesystem::{file_size_and_mtime, FileFingerprint, ImageScanner};
+use crate::incremental::{self, MAX_INCREMENTAL_PATHS};
 use crate::model_download;
 use crate::paths;
+use crate::similarity_and_semantic_search::cosine::cache;
 use crate::similarity_and_semantic_search::cosine_similarity::CosineIndex;
 use crate::similarity_and_semantic_search::encoder::ClipImageEncoder;
 use crate::similarity_and_semantic_search::encoder_text::ClipTextEncoder;
 use crate::thumbnail::ThumbnailGenerator;
 use crate::TextEncoderState;
 
-/// The single-flight guard. Wrap in Arc and stash in a Tauri state
-/// struct so commands and the setup callback can both reach it.
+/// Cooperative cancellation flag shared between the pipeline thread,
+/// its per-encoder threads and whoever wants to stop them. Cloning
+/// shares the same flag.
+#[derive(Clone, Default, Debug)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// `?`-friendly checkpoint: `Err(IndexingError::Cancelled)` once
+    /// the token has been tripped.
+    pub fn check(&self) -> Result<(), IndexingError> {
+        if self.is_cancelled() {
+            Err(IndexingError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Re-arm for the next run. Only the worker thread calls this, at
+    /// the top of each run.
+    fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// The single-flight guard plus the follow-up + cancellation flags.
+/// Wrapped in Arc and owned by the `Library` so commands, the watcher
+/// and the setup callback can all reach it.
 #[derive(Default)]
 pub struct IndexingState {
     pub is_running: AtomicBool,
+    /// Dirty flag: set by every trigger, consumed by the worker at the
+    /// top of each run. Still set when a run ends → one more run.
+    pub rerun_requested: AtomicBool,
+    /// Checked by the in-flight run; reset at the start of each run.
+    pub cancel: CancelToken,
+    /// What the next run should cover. Written by triggers BEFORE they
+    /// raise `rerun_requested`, drained by the worker after clearing it.
+    pending: Mutex<PendingScope>,
+}
+
+/// Accumulated trigger scope between runs.
+#[derive(Debug, Default)]
+struct PendingScope {
+    full: bool,
+    paths: HashSet<PathBuf>,
+}
+
+/// What one run does, resolved from `PendingScope` at the top of the
+/// worker loop.
+#[derive(Debug, PartialEq, Eq)]
+enum RunScope {
+    Full,
+    Paths(Vec<PathBuf>),
 }
 
 impl IndexingState {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Stop the in-flight run at its next checkpoint and drop any
+    /// queued follow-up. Returns whether a run was in flight.
+    pub fn request_cancel(&self) -> bool {
+        self.rerun_requested.store(false, Ordering::SeqCst);
+        *self.pending.lock().unwrap() = PendingScope::default();
+        self.cancel.cancel();
+        self.is_running.load(Ordering::SeqCst)
+    }
+
+    fn request_full(&self) {
+        self.pending.lock().unwrap().full = true;
+    }
+
+    fn request_paths(&self, paths: impl IntoIterator<Item = PathBuf>) {
+        self.pending.lock().unwrap().paths.extend(paths);
+    }
+
+    /// Drain the pending scope. `None` when a previous iteration
+    /// already consumed everything a racing trigger queued. Path
+    /// batches that piled up past `MAX_INCREMENTAL_PATHS` while a run
+    /// was in flight fall back to a full run, same as the watcher's own
+    /// overflow rule.
+    fn take_scope(&self) -> Option<RunScope> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.full || pending.paths.len() > MAX_INCREMENTAL_PATHS {
+            Some(RunScope::Full)
+        } else if pending.paths.is_empty() {
+            None
+        } else {
+            let mut paths: Vec<PathBuf> = pending.paths.into_iter().collect();
+            paths.sort();
+            Some(RunScope::Paths(paths))
+        }
+    }
+}
+
+/// What a trigger did. Either way the catalog will be rescanned after
+/// the trigger — `Queued` just means it piggybacks on the in-flight
+/// run's follow-up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnOutcome {
+    /// No run was in flight; a worker thread was started.
+    Started,
+    /// A run is in flight; it will loop once more when it ends.
+    Queued,
+}
+
+/// Receives progress from a pipeline run, plus the in-process state the
+/// run keeps warm for the host.
+///
+/// Called from the pipeline thread, the thumbnail rayon workers and the
+/// per-encoder threads, so implementations must be cheap and `Sync`.
+pub trait ProgressSink: Send + Sync {
+    fn report(&self, progress: &IndexingProgress);
+
+    /// The host's fusion caches. Warmed from the cache files at the
+    /// start of a full run and reloaded at the end of every run.
+    /// `None` when nothing searches in-process.
+    fn fusion_state(&self) -> Option<&crate::FusionIndexState> {
+        None
+    }
+
+    /// The host's text encoders, pre-warmed so the first semantic search
+    /// doesn't pay the model load. `None` skips the pre-warm.
+    fn text_encoders(&self) -> Option<&TextEncoderState> {
+        None
+    }
 }
 
-/// Tauri event payload broadcast as the pipeline progresses.
+/// Progress payload reported as the pipeline progresses; broadcast to
+/// the frontend as the `indexing-progress` Tauri event.
 #[derive(Serialize, Clone, Debug)]
 pub struct IndexingProgress {
     pub phase: Phase,
@@ -88,82 +237,202 @@ pub enum Phase {
     /// a human-readable string. `is_running` has already been cleared
     /// — the user can retry by switching folders or restarting.
     Error,
+    /// The run was cancelled at a checkpoint. Work committed before the
+    /// checkpoint (rows, thumbnails, embedding batches) is kept; the
+    /// next run picks up where this one stopped.
+    Cancelled,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum IndexingError {
-    AlreadyRunning,
+    /// The run's `CancelToken` was tripped; the pipeline stopped at a
+    /// checkpoint. Not a failure — surfaces as `Phase::Cancelled`.
+    Cancelled,
 }
 
 impl std::fmt::Display for IndexingError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            IndexingError::AlreadyRunning => {
-                write!(f, "Indexing is already in progress; wait for it to finish")
-            }
+            IndexingError::Cancelled => write!(f, "Indexing was cancelled"),
         }
     }
 }
 
 impl std::error::Error for IndexingError {}
 
-/// Try to start a background indexing run.
+/// Trigger a full background indexing run.
 ///
-/// Returns `Ok(())` immediately if a thread was spawned. Returns
-/// `Err(AlreadyRunning)` without doing anything if a run is already in
-/// flight.
+/// Returns `Started` if a worker thread was spawned, or `Queued` if a
+/// run is already in flight — in which case that run loops once more
+/// when it finishes, so the trigger is never lost.
 ///
-/// The spawned thread:
-/// 1. Sets `is_running = true` (already done before spawn — see below).
+/// The worker thread:
+/// 1. Clears `rerun_requested` and re-arms the cancel token.
 /// 2. Runs scan, model download, thumbnail, encode in order, emitting
 ///    events between phases and periodically inside long phases.
 /// 3. Repopulates the cosine index from the DB so similarity search
 ///    works without waiting for the next user-triggered query.
-/// 4. Emits `Phase::Ready` with the final image count.
-/// 5. Sets `is_running = false`.
-///
-/// On any error inside the pipeline, the thread emits `Phase::Error`
-/// with a message and clears `is_running`.
-pub fn try_spawn_pipeline(
-    app: AppHandle,
+/// 4. Emits `Phase::Ready` with the final image count (or
+///    `Phase::Cancelled` / `Phase::Error`).
+/// 5. Loops back to 1 if `rerun_requested` was set meanwhile;
+///    otherwise clears `is_running`.
+pub fn try_spawn_pipeline<S: ProgressSink + 'static>(
+    sink: S,
+    state: Arc<IndexingState>,
+    db_path: String,
+    cosine_index: Arc<std::sync::Mutex<CosineIndex>>,
+    cosine_current_encoder: Arc<std::sync::Mutex<String>>,
+) -> SpawnOutcome {
+    state.request_full();
+    spawn_or_queue(sink, state, db_path, cosine_index, cosine_current_encoder)
+}
+
+/// Trigger an incremental run over `paths` (files or directories that
+/// changed on disk). Queues onto an in-flight run like
+/// `try_spawn_pipeline`; batches queued during one run merge into one
+/// follow-up.
+pub fn try_spawn_incremental<S: ProgressSink + 'static>(
+    sink: S,
     state: Arc<IndexingState>,
     db_path: String,
     cosine_index: Arc<std::sync::Mutex<CosineIndex>>,
     cosine_current_encoder: Arc<std::sync::Mutex<String>>,
-) -> Result<(), IndexingError> {
-    // Acquire the single-flight slot atomically.
+    paths: Vec<PathBuf>,
+) -> SpawnOutcome {
+    state.request_paths(paths);
+    spawn_or_queue(sink, state, db_path, cosine_index, cosine_current_encoder)
+}
+
+fn spawn_or_queue<S: ProgressSink + 'static>(
+    sink: S,
+    state: Arc<IndexingState>,
+    db_path: String,
+    cosine_index: Arc<std::sync::Mutex<CosineIndex>>,
+    cosine_current_encoder: Arc<std::sync::Mutex<String>>,
+) -> SpawnOutcome {
+    // Raise the dirty flag BEFORE trying for the slot. If the worker is
+    // just finishing, either it sees the flag and loops, or it has
+    // already released the slot and our compare_exchange wins.
+    state.rerun_requested.store(true, Ordering::SeqCst);
     if state
         .is_running
         .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
         .is_err()
     {
-        return Err(IndexingError::AlreadyRunning);
+        return SpawnOutcome::Queued;
     }
 
     thread::spawn(move || {
-        // RAII guard ensures is_running gets cleared even if the body
+        // RAII guard ensures is_running gets cleared if the body
         // panics, so a panic doesn't leave the app permanently locked.
+        // Normal exits release the slot explicitly below — by then
+        // another trigger may legitimately own it, so the guard must
+        // not touch it.
         struct RunningGuard(Arc<IndexingState>);
         impl Drop for RunningGuard {
             fn drop(&mut self) {
-                self.0.is_running.store(false, Ordering::SeqCst);
+                if thread::panicking() {
+                    self.0.is_running.store(false, Ordering::SeqCst);
+                }
             }
         }
         let _guard = RunningGuard(state.clone());
 
-        if let Err(e) = run_pipeline_inner(&app, &db_path, &cosine_index, &cosine_current_encoder) {
-            error!("pipeline error: {e}");
-            emit(
-                &app,
-                Phase::Error,
-                0,
-                0,
-                Some(format!("Indexing failed: {e}")),
-            );
+        loop {
+            state.rerun_requested.store(false, Ordering::SeqCst);
+            state.cancel.reset();
+
+            let result = match state.take_scope() {
+                Some(RunScope::Full) => run_pipeline_inner(
+                    &sink,
+                    &state.cancel,
+                    &db_path,
+                    &cosine_index,
+                    &cosine_current_encoder,
+                ),
+                Some(RunScope::Paths(paths)) => run_incremental_inner(
+                    &sink,
+                    &state.cancel,
+                    &db_path,
+                    &paths,
+                    &cosine_index,
+                    &cosine_current_encoder,
+                ),
+                None => Ok(()),
+            };
+            match result {
+                Ok(()) => {}
+                Err(e) if e.downcast_ref::<IndexingError>() == Some(&IndexingError::Cancelled) => {
+                    info!("pipeline cancelled");
+                    emit(&sink, Phase::Cancelled, 0, 0, Some("Indexing cancelled".into()));
+                }
+                Err(e) => {
+                    error!("pipeline error: {e}");
+                    emit(
+                        &sink,
+                        Phase::Error,
+                        0,
+                        0,
+                        Some(format!("Indexing failed: {e}")),
+                    );
+                }
+            }
+
+            if state.rerun_requested.load(Ordering::SeqCst) {
+                info!("pipeline: follow-up run requested mid-run; rescanning");
+                continue;
+            }
+            state.is_running.store(false, Ordering::SeqCst);
+            // A trigger that landed between the load above and the
+            // store saw is_running = true and only raised the flag.
+            // Take the slot back for it — unless that trigger already
+            // won the race and spawned its own worker.
+            if !state.rerun_requested.load(Ordering::SeqCst)
+                || state
+                    .is_running
+                    .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_err()
+            {
+                break;
+            }
         }
     });
 
-    Ok(())
+    SpawnOutcome::Started
+}
+
+/// Cancel-and-restart: stop the in-flight run at its next checkpoint
+/// and start a fresh one. Used when the root set changes — the running
+/// pass is working from a stale root list, so finishing it first would
+/// only delay the user's change.
+pub fn restart_pipeline<S: ProgressSink + 'static>(
+    sink: S,
+    state: Arc<IndexingState>,
+    db_path: String,
+    cosine_index: Arc<std::sync::Mutex<CosineIndex>>,
+    cosine_current_encoder: Arc<std::sync::Mutex<String>>,
+) -> SpawnOutcome {
+    if state.is_running.load(Ordering::SeqCst) {
+        state.cancel.cancel();
+    }
+    try_spawn_pipeline(sink, state, db_path, cosine_index, cosine_current_encoder)
+}
+
+/// Run one full pipeline pass on the calling thread, reporting to
+/// `sink`. The foreground counterpart of `try_spawn_pipeline` for hosts
+/// without a window — no single-flight guard or follow-up queue, so the
+/// caller owns making sure two runs don't overlap.
+///
+/// Returns `IndexingError::Cancelled` (boxed) if `cancel` trips; the
+/// terminal `Phase::Ready` is reported only on success.
+pub fn run_pipeline(
+    sink: &dyn ProgressSink,
+    cancel: &CancelToken,
+    db_path: &str,
+    cosine_index: &Arc<std::sync::Mutex<CosineIndex>>,
+    cosine_current_encoder: &Arc<std::sync::Mutex<String>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    run_pipeline_inner(sink, cancel, db_path, cosine_index, cosine_current_encoder)
 }
 
 /// The actual pipeline body. Errors propagate up and become a
@@ -174,25 +443,37 @@ pub fn try_spawn_pipeline(
 /// it after the priority encoder's phase finishes, so the in-memory
 /// cache and the "what's loaded" marker stay in sync without the next
 /// search command needing to repopulate.
-#[tracing::instrument(name = "pipeline.run", skip(app, cosine_index, cosine_current_encoder))]
+#[tracing::instrument(name = "pipeline.run", skip(sink, cancel, cosine_index, cosine_current_encoder))]
 fn run_pipeline_inner(
-    app: &AppHandle,
+    sink: &dyn ProgressSink,
+    cancel: &CancelToken,
     db_path: &str,
     cosine_index: &Arc<std::sync::Mutex<CosineIndex>>,
     cosine_current_encoder: &Arc<std::sync::Mutex<String>>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // 0. Try the on-disk cosine cache before doing anything else. On a
-    //    typical second-launch with no new images, the cache is fresh
-    //    and similarity / semantic search become available essentially
-    //    immediately — the user can act on the catalog while the rest
-    //    of the pipeline (rescan, model verification) finishes in the
-    //    background. The cache is invalidated automatically if the DB
-    //    file is newer than it.
+    // 0. Warm the cosine caches from disk before doing anything else.
+    //    On a typical second launch with no new images every enabled
+    //    encoder's cache file is fresh, so similarity, semantic and
+    //    fusion search become available essentially immediately — the
+    //    user can act on the catalog while the rest of the pipeline
+    //    (rescan, model verification) finishes in the background. Each
+    //    file is only trusted if its header matches the encoder, the
+    //    pipeline version and the DB's current embedding set.
     {
-        let db_path_buf = std::path::PathBuf::from(db_path);
-        if let Ok(mut idx) = cosine_index.lock() {
-            if idx.cached_images.is_empty() {
-                idx.load_from_disk_if_fresh(&db_path_buf);
+        cache::remove_legacy_cache_file();
+        if let Ok(database) = ImageDatabase::new(db_path) {
+            let priority = priority_encoder();
+            // Lock order: current_encoder_id first, then index.
+            if let (Ok(mut cur), Ok(mut idx)) =
+                (cosine_current_encoder.lock(), cosine_index.lock())
+            {
+                if idx.cached_images.is_empty() && idx.warm_from_cache_file(&database, &priority) {
+                    *cur = priority;
+                }
+            }
+            let enabled = crate::settings::Settings::load().resolved_enabled_encoders();
+            if let Some(fusion) = sink.fusion_state() {
+                fusion.warm_from_cache_files(&database, &enabled);
             }
         }
     }
@@ -202,8 +483,7 @@ fn run_pipeline_inner(
     //    render a real determinate bar across the ~1 GB of downloads
     //    instead of the previous "Checking models..." flash followed
     //    by a multi-minute silent stretch.
-    emit(app, Phase::ModelDownload, 0, 0, Some("Checking models...".into()));
-    let app_for_progress = app.clone();
+    emit(sink, Phase::ModelDownload, 0, 0, Some("Checking models...".into()));
     let progress_cb = move |processed: u64, total: u64, current_file: Option<&str>| {
         let msg = current_file.map(|f| {
             if total > 0 {
@@ -220,14 +500,14 @@ fn run_pipeline_inner(
         // capped at ~1.2 GB so usize::MAX is not in play even on 32-bit.
         let processed = processed.min(usize::MAX as u64) as usize;
         let total = total.min(usize::MAX as u64) as usize;
-        emit(&app_for_progress, Phase::ModelDownload, processed, total, msg);
+        emit(sink, Phase::ModelDownload, processed, total, msg);
     };
     if let Err(e) = model_download::download_models_if_missing(progress_cb) {
         // Non-fatal: scan + thumbnail still work without models. Encode
         // gets skipped further down.
         warn!("model download skipped: {e}");
         emit(
-            app,
+            sink,
             Phase::ModelDownload,
             0,
             0,
@@ -242,14 +522,10 @@ fn run_pipeline_inner(
     //     the user (the indexing pill is already showing), whereas
     //     paying it later means the user types a query and stares at
     //     a spinner.
-    {
+    //     Skipped when the host has no in-process search to warm for
+    //     (the headless CLI).
+    if let Some(text_encoder_state) = sink.text_encoders() {
         let models_dir = paths::models_dir();
-        // Bind the State separately so its lifetime extends across
-        // the full block. Inlining `app.state::<TextEncoderState>()
-        // .encoder.lock()` produces a temporary that the borrow
-        // checker drops too early.
-        let text_encoder_state: tauri::State<'_, TextEncoderState> =
-            app.state::<TextEncoderState>();
 
         // CLIP text encoder pre-warm (was here before — unchanged).
         let clip_model_path = models_dir.join(crate::model_download::CLIP_TEXT_FILENAME);
@@ -300,8 +576,9 @@ fn run_pipeline_inner(
     }
 
     // 2. Open a fresh DB handle. Mutex<Connection> coexists with the
-    //    Tauri-managed one (rusqlite supports multiple connections to
+    //    `Library`'s one (rusqlite supports multiple connections to
     //    the same file).
+    cancel.check()?;
     let database = ImageDatabase::new(db_path)?;
     database.initialize()?;
 
@@ -312,7 +589,7 @@ fn run_pipeline_inner(
     if enabled_roots.is_empty() {
         // Nothing to do — empty-state UI covers this case.
         emit(
-            app,
+            sink,
             Phase::Ready,
             0,
             0,
@@ -325,7 +602,7 @@ fn run_pipeline_inner(
     //    progress reflects total work, not per-folder progress.
     let _scan_phase = tracing::info_span!("pipeline.scan_phase").entered();
     emit(
-        app,
+        sink,
         Phase::Scan,
         0,
         0,
@@ -333,6 +610,11 @@ fn run_pipeline_inner(
     );
     let scanner = ImageScanner::new();
 
+    // A root whose folder is missing (unmounted drive) is flagged
+    // offline and skipped: it scans as empty, and the orphan pass below
+    // would otherwise orphan every image in it.
+    let availability = crate::root_availability::refresh(&database, &all_roots)?;
+
     // First pass: walk every enabled root, collect (path, root_id)
     // tuples. We keep per-root path sets so we can run the orphan
     // detection pass per-root in a moment.
@@ -340,15 +622,11 @@ fn run_pipeline_inner(
     let mut paths_per_root: std::collections::HashMap<i64, Vec<String>> =
         std::collections::HashMap::new();
     for root in &enabled_roots {
-        let root_path = std::path::Path::new(&root.path);
-        if !root_path.exists() {
-            warn!(
-                "configured root {} no longer exists; skipping",
-                root.path
-            );
+        if availability.offline.contains(&root.id) {
             continue;
         }
-        match scanner.scan_directory(root_path) {
+        let root_path = std::path::Path::new(&root.path);
+        match scanner.scan_directory_cancellable(root_path, &|| cancel.is_cancelled()) {
             Ok(paths) => {
                 let entry = paths_per_root.entry(root.id).or_default();
                 for p in paths {
@@ -356,6 +634,9 @@ fn run_pipeline_inner(
                     all_paths.push((p, root.id));
                 }
             }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {
+                return Err(IndexingError::Cancelled.into());
+            }
             Err(e) => {
                 warn!("scan of {} failed: {e}", root.path);
             }
@@ -366,18 +647,32 @@ fn run_pipeline_inner(
     // Second pass: insert into DB. Idempotent — INSERT OR IGNORE on the
     // path uniqueness constraint means existing rows aren't duplicated.
     for (i, (path, root_id)) in all_paths.iter().enumerate() {
+        cancel.check()?;
         database.add_image(path.clone(), Some(*root_id))?;
         if (i + 1) % 100 == 0 || i + 1 == total_found {
-            emit(app, Phase::Scan, i + 1, total_found, None);
+            emit(sink, Phase::Scan, i + 1, total_found, None);
         }
     }
 
+    // Content fingerprints for move/rename reconciliation. Must land
+    // before orphan detection + reconcile below so freshly-inserted
+    // rows are matchable in this same run.
+    fingerprint_scanned_files(sink, cancel, &database, &all_paths);
+
+    // Checkpoint before orphan marking: the alive sets are only
+    // trustworthy if the scan above ran to completion.
+    cancel.check()?;
+
     // Orphan-detection pass: for each enabled root, mark any DB row
     // whose path isn't in the just-scanned alive set as orphaned. The
     // grid query filters orphaned rows out, so the user doesn't see
-    // tiles for files that were deleted between launches.
+    // tiles for files that were deleted between launches. Only roots
+    // that actually scanned have an alive set — an offline root or a
+    // failed walk says nothing about which files are gone.
     for root in &enabled_roots {
-        let alive = paths_per_root.remove(&root.id).unwrap_or_default();
+        let Some(alive) = paths_per_root.remove(&root.id) else {
+            continue;
+        };
         match database.mark_orphaned(root.id, &alive) {
             Ok(n) if n > 0 => {
                 info!("orphan-detection: {} rows marked orphaned in root {}", n, root.path);
@@ -387,9 +682,117 @@ fn run_pipeline_inner(
         }
     }
 
-    emit(app, Phase::Scan, total_found, total_found, None);
+    // Move/rename reconciliation: an orphan whose content hash matches
+    // a just-inserted row is the same file at a new path. Re-linking
+    // keeps its id, so tags, notes, embeddings and the thumbnail carry
+    // over and the encoder phase below has nothing new to do for it.
+    match database.reconcile_moved_images() {
+        Ok(folded) if !folded.is_empty() => {
+            info!(
+                "move reconciliation: {} orphaned rows re-linked to new paths",
+                folded.len()
+            );
+        }
+        Ok(_) => {}
+        Err(e) => warn!("move reconciliation failed: {e}"),
+    }
+
+    emit(sink, Phase::Scan, total_found, total_found, None);
     drop(_scan_phase);
+    cancel.check()?;
+
+    run_derive_phases(
+        sink,
+        cancel,
+        &database,
+        db_path,
+        cosine_index,
+        cosine_current_encoder,
+    )?;
+
+    // 8. Done — total image count is what the user sees in the grid.
+    let final_count = database.get_all_images().map(|v| v.len()).unwrap_or(0);
+    emit(
+        sink,
+        Phase::Ready,
+        final_count,
+        final_count,
+        Some(format!("{final_count} images indexed")),
+    );
+
+    Ok(())
+}
 
+/// Watcher-triggered run: apply just the changed paths, then the
+/// shared thumbnail/encode/cosine phases. Deliberately quiet when the
+/// batch turns out to change nothing (metadata-only events, a file
+/// created and deleted inside one debounce window) — no pill flash.
+#[tracing::instrument(name = "pipeline.incremental", skip_all, fields(paths = paths.len()))]
+fn run_incremental_inner(
+    sink: &dyn ProgressSink,
+    cancel: &CancelToken,
+    db_path: &str,
+    paths: &[PathBuf],
+    cosine_index: &Arc<std::sync::Mutex<CosineIndex>>,
+    cosine_current_encoder: &Arc<std::sync::Mutex<String>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let database = ImageDatabase::new(db_path)?;
+    database.initialize()?;
+    let roots = database.list_roots()?;
+
+    let summary =
+        match incremental::apply_path_changes(&database, &roots, paths, &|| cancel.is_cancelled()) {
+            Ok(summary) => summary,
+            Err(_) if cancel.is_cancelled() => return Err(IndexingError::Cancelled.into()),
+            Err(e) => return Err(e),
+        };
+    info!("incremental update: {summary:?}");
+    if summary.is_empty() {
+        return Ok(());
+    }
+    cancel.check()?;
+
+    // Embeddings were added, dropped or hidden: both similarity caches
+    // are stale. They're reloaded at the end of run_derive_phases, once
+    // the encoders are done — clearing them here would let a query in
+    // the meantime repopulate them from a half-encoded set.
+    run_derive_phases(
+        sink,
+        cancel,
+        &database,
+        db_path,
+        cosine_index,
+        cosine_current_encoder,
+    )?;
+
+    let changed = summary.added + summary.modified + summary.revived + summary.orphaned
+        + summary.relinked;
+    let message = if summary.went_offline > 0 {
+        format!(
+            "{changed} file change(s) applied, {} folder(s) offline",
+            summary.went_offline
+        )
+    } else {
+        format!("{changed} file change(s) applied")
+    };
+    emit(sink, Phase::Ready, changed, changed, Some(message));
+    Ok(())
+}
+
+/// Steps 5–7 of a run, shared by the full and incremental pipelines:
+/// thumbnails for rows missing one, every enabled encoder for rows
+/// missing its embedding, board assignment for newly embedded rows,
+/// then the cosine cache. All of them work off "what's missing"
+/// queries, so they cost next to nothing when the scan part of the run
+/// changed little.
+fn run_derive_phases(
+    sink: &dyn ProgressSink,
+    cancel: &CancelToken,
+    database: &ImageDatabase,
+    db_path: &str,
+    cosine_index: &Arc<std::sync::Mutex<CosineIndex>>,
+    cosine_current_encoder: &Arc<std::sync::Mutex<String>>,
+) -> Result<(), Box<dyn std::error::Error>> {
     // 5. Thumbnails phase (rayon-parallel, runs to completion before
     //    encoder phase begins).
     //
@@ -422,7 +825,7 @@ fn run_pipeline_inner(
     let needs_thumbs = database.get_images_without_thumbnails()?;
     let total_thumbs = needs_thumbs.len();
     if total_thumbs > 0 {
-        emit(app, Phase::Thumbnail, 0, total_thumbs, None);
+        emit(sink, Phase::Thumbnail, 0, total_thumbs, None);
 
         let completed = AtomicUsize::new(0);
         let last_emit_bucket = AtomicUsize::new(0);
@@ -430,6 +833,9 @@ fn run_pipeline_inner(
         // (across ALL workers combined) so a 1500-image run fires
         // ~60 events rather than ~1500.
         const EMIT_EVERY: usize = 25;
+        // Cancellation granularity: a chunk of this size takes a
+        // second or two on a typical 8-core machine.
+        const THUMBNAIL_CHUNK: usize = 64;
 
         // Build a map from path -> root_id so each thumbnail lands in
         // the right per-root subfolder. Single SELECT — was N+1 before
@@ -444,42 +850,48 @@ fn run_pipeline_inner(
         // back to the legacy flat thumbnail directory (root_id None).
         let path_to_root = database.get_paths_to_root_ids().unwrap_or_default();
 
-        needs_thumbs.par_iter().for_each(|image| {
-            let root_id = path_to_root.get(&image.path).copied().flatten();
-            match thumbnail_generator.generate_thumbnail(
-                Path::new(&image.path),
-                image.id,
-                root_id,
-            ) {
-                Ok(result) => {
-                    if let Err(e) = database.update_image_thumbnail(
-                        image.id,
-                        &result.thumbnail_path,
-                        result.original_width,
-                        result.original_height,
-                    ) {
-                        warn!("DB update for thumbnail of image {} failed: {e}", image.id);
+        // Chunked so cancellation is honoured between chunks; each
+        // chunk still fans out across the whole rayon pool.
+        for chunk in needs_thumbs.chunks(THUMBNAIL_CHUNK) {
+            cancel.check()?;
+            chunk.par_iter().for_each(|image| {
+                let root_id = path_to_root.get(&image.path).copied().flatten();
+                match thumbnail_generator.generate_thumbnail(
+                    Path::new(&image.path),
+                    image.id,
+                    root_id,
+                ) {
+                    Ok(result) => {
+                        if let Err(e) = database.update_image_thumbnail(
+                            image.id,
+                            &result.thumbnail_path,
+                            result.original_width,
+                            result.original_height,
+                        ) {
+                            warn!("DB update for thumbnail of image {} failed: {e}", image.id);
+                        }
+                    }
+                    Err(e) => {
+                        warn!("thumbnail generation failed for {}: {e}", image.path);
                     }
                 }
-                Err(e) => {
-                    warn!("thumbnail generation failed for {}: {e}", image.path);
-                }
-            }
 
-            let done = completed.fetch_add(1, Ordering::Relaxed) + 1;
-            let bucket = done / EMIT_EVERY;
-            let prev = last_emit_bucket.load(Ordering::Relaxed);
-            if bucket > prev
-                && last_emit_bucket
-                    .compare_exchange(prev, bucket, Ordering::Relaxed, Ordering::Relaxed)
-                    .is_ok()
-            {
-                emit(app, Phase::Thumbnail, done, total_thumbs, None);
-            }
-        });
+                let done = completed.fetch_add(1, Ordering::Relaxed) + 1;
+                let bucket = done / EMIT_EVERY;
+                let prev = last_emit_bucket.load(Ordering::Relaxed);
+                if bucket > prev
+                    && last_emit_bucket
+                        .compare_exchange(prev, bucket, Ordering::Relaxed, Ordering::Relaxed)
+                        .is_ok()
+                {
+                    emit(sink, Phase::Thumbnail, done, total_thumbs, None);
+                }
+            });
+        }
     }
-    emit(app, Phase::Thumbnail, total_thumbs, total_thumbs, None);
+    emit(sink, Phase::Thumbnail, total_thumbs, total_thumbs, None);
     drop(_thumb_phase);
+    cancel.check()?;
 
     // 6. Encoder phase (Phase 12b: now strictly after thumbnails). The
     //    inside of `run_encoder_phase` still spawns one thread per
@@ -490,7 +902,8 @@ fn run_pipeline_inner(
     //    total ORT thread count stays at 4 regardless of N.
     if image_model_path.exists() {
         if let Err(e) = run_encoder_phase(
-            app,
+            sink,
+            cancel,
             db_path,
             &image_model_path,
             cosine_index,
@@ -504,57 +917,147 @@ fn run_pipeline_inner(
             crate::model_download::CLIP_VISION_FILENAME
         );
     }
+    cancel.check()?;
 
-    // 7. Final safety-net cosine populate.
-    //
-    //    The per-encoder hot-populate inside run_encoder_phase already
-    //    loaded the priority encoder's cache as soon as that encoder's
-    //    phase finished — so by the time we get here, the cache is
-    //    almost always already correct. This block is a safety net for
-    //    two cases:
-    //      (a) priority encoder didn't get a chance to run (e.g. its
-    //          model file was missing — we fall back to CLIP);
-    //      (b) the cache held stale state from a previous session and
-    //          the priority encoder happened to be one we don't run.
-    //
-    //    Either way we resolve the priority + populate for it. This is
-    //    the canonical "what's loaded matches the user's pick" point
-    //    and the only place that triggers `save_to_disk`, so the next
-    //    launch starts hot.
-    let priority = crate::settings::Settings::load()
-        .priority_image_encoder
-        .filter(|s| !s.is_empty())
-        .unwrap_or_else(|| "clip_vit_b_32".to_string());
+    // 6b. Cache files and ANN graphs. For every enabled encoder, bring
+    //     its on-disk cosine cache and (past ANN_MIN_IMAGES) its HNSW
+    //     graph up to date with the rows the encoder phase just
+    //     upserted, and drop removed ones. Sequential, so only one
+    //     encoder's vectors are resident at a time. A cancel mid-build
+    //     saves the partial graph; the next run carries on from there.
+    {
+        let _refresh_phase = tracing::info_span!("pipeline.cache_refresh").entered();
+        let is_cancelled = || cancel.is_cancelled();
+        for encoder_id in crate::settings::Settings::load().resolved_enabled_encoders() {
+            cancel.check()?;
+            cache::refresh_encoder_files(database, &encoder_id, &is_cancelled);
+        }
+    }
+    cancel.check()?;
+
+    // 6c. Visual boards. Images that just gained an embedding in the
+    //     boards' encoder join their nearest board, so boards stay
+    //     complete between clustering runs. No-op until the user has
+    //     clustered once; failures only cost the assignment.
+    match crate::clusters::assign_new_images(database) {
+        Ok(0) => {}
+        Ok(n) => info!("assigned {n} new image(s) to boards"),
+        Err(e) => warn!("board assignment skipped: {e}"),
+    }
+
+    // 7. Reload the search caches. Step 0 warmed them from the cache
+    //    files as they were before this run, so they miss every image
+    //    the encoder phase just added or dropped. Step 6b rewrote the
+    //    files; reloading maps them instead of reading BLOBs.
+    let priority = priority_encoder();
     let _cosine_phase = tracing::info_span!("pipeline.cosine_repopulate", encoder = %priority).entered();
+    reload_search_caches(
+        database,
+        sink.fusion_state(),
+        cosine_index,
+        cosine_current_encoder,
+        &priority,
+        &crate::settings::Settings::load().resolved_enabled_encoders(),
+    );
+    drop(_cosine_phase);
+
+    Ok(())
+}
+
+/// Load `priority`'s embeddings into the primary cosine cache and swap
+/// every fusion slot for the current cache files, so searches see the
+/// embedding set as the run left it. Fusion encoders without a fresh
+/// file are left to the lazy populate on the next query.
+fn reload_search_caches(
+    database: &ImageDatabase,
+    fusion: Option<&crate::FusionIndexState>,
+    cosine_index: &Arc<std::sync::Mutex<CosineIndex>>,
+    cosine_current_encoder: &Arc<std::sync::Mutex<String>>,
+    priority: &str,
+    enabled: &[String],
+) {
     // Lock order: current_encoder_id first, then index — must match
     // CosineIndexState::ensure_loaded_for to keep the search path
     // deadlock-free.
-    if let (Ok(mut cur), Ok(mut idx)) =
-        (cosine_current_encoder.lock(), cosine_index.lock())
-    {
-        // Skip the DB read if the per-encoder hot-populate inside
-        // run_encoder_phase already loaded this same encoder — a
-        // common case for the priority encoder.
-        let already_loaded = *cur == priority && !idx.cached_images.is_empty();
-        if !already_loaded {
-            idx.populate_from_db_for_encoder(&database, &priority);
-            *cur = priority.clone();
+    if let (Ok(mut cur), Ok(mut idx)) = (cosine_current_encoder.lock(), cosine_index.lock()) {
+        idx.cached_images.clear();
+        idx.populate_from_db_for_encoder(database, priority);
+        *cur = priority.to_string();
+    }
+    if let Some(fusion) = fusion {
+        fusion.invalidate_all();
+        fusion.warm_from_cache_files(database, enabled);
+    }
+}
+
+/// The encoder whose cache `CosineIndexState` should hold: the user's
+/// pick, CLIP when unset.
+fn priority_encoder() -> String {
+    crate::settings::Settings::load()
+        .priority_image_encoder
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "clip_vit_b_32".to_string())
+}
+
+/// Hash every scanned file whose stored fingerprint is missing or stale
+/// (size or mtime changed). Steady-state launches only `stat` each
+/// file; the first pass after upgrading reads the whole library once,
+/// rayon-parallel since it's I/O- and BLAKE3-bound rather than
+/// contending with the encoders (which haven't started yet).
+///
+/// Failures are logged and skipped — a file that can't be hashed just
+/// can't be reconciled if it moves, which is the pre-fingerprint
+/// behaviour.
+fn fingerprint_scanned_files(
+    sink: &dyn ProgressSink,
+    cancel: &CancelToken,
+    database: &ImageDatabase,
+    scanned: &[(String, i64)],
+) {
+    let _span = tracing::info_span!("pipeline.fingerprint").entered();
+    let stored = match database.get_content_fingerprints() {
+        Ok(m) => m,
+        Err(e) => {
+            warn!("could not load content fingerprints: {e}");
+            return;
         }
-        idx.save_to_disk();
+    };
+    let stale: Vec<(ID, &str)> = scanned
+        .par_iter()
+        .filter_map(|(path, _)| {
+            let row = stored.get(path)?;
+            let (size, mtime) = file_size_and_mtime(Path::new(path)).ok()?;
+            row.is_stale(size, mtime).then_some((row.id, path.as_str()))
+        })
+        .collect();
+    if stale.is_empty() {
+        return;
     }
-    drop(_cosine_phase);
 
-    // 8. Done — total image count is what the user sees in the grid.
-    let final_count = database.get_all_images().map(|v| v.len()).unwrap_or(0);
     emit(
-        app,
-        Phase::Ready,
-        final_count,
-        final_count,
-        Some(format!("{final_count} images indexed")),
+        sink,
+        Phase::Scan,
+        0,
+        stale.len(),
+        Some(format!("Fingerprinting {} file(s)", stale.len())),
     );
-
-    Ok(())
+    // Cancellation skips the remaining hashes rather than erroring —
+    // whatever finished is still worth storing, and the caller's next
+    // checkpoint unwinds the run.
+    let fingerprints: Vec<(ID, FileFingerprint)> = stale
+        .par_iter()
+        .filter(|_| !cancel.is_cancelled())
+        .filter_map(|(id, path)| match FileFingerprint::compute(Path::new(path)) {
+            Ok(fp) => Some((*id, fp)),
+            Err(e) => {
+                warn!("fingerprint of {path} failed: {e}");
+                None
+            }
+        })
+        .collect();
+    if let Err(e) = database.set_content_fingerprints(&fingerprints) {
+        warn!("could not store content fingerprints: {e}");
+    }
 }
 
 /// Encoder phase — runs the CLIP image encoder over every row that
@@ -571,7 +1074,8 @@ fn run_pipeline_inner(
 /// disjoint and SQLite's WAL serialises commits at the page level
 /// without blocking readers.
 fn run_encoder_phase(
-    app: &AppHandle,
+    sink: &dyn ProgressSink,
+    cancel: &CancelToken,
     db_path: &str,
     image_model_path: &Path,
     cosine_index: &Arc<std::sync::Mutex<CosineIndex>>,
@@ -639,79 +1143,87 @@ fn run_encoder_phase(
     // Spawn one thread per enabled encoder. Each thread is independent
     // (own DB connection, own ORT session, own progress events) so
     // they don't have to coordinate during the loop.
-    let mut handles: Vec<thread::JoinHandle<Result<(), String>>> = Vec::new();
-
-    for encoder_id in &enabled {
-        let encoder_id = encoder_id.clone();
-        let app = app.clone();
-        let db_path = db_path.to_string();
-        let image_model_path = image_model_path.to_path_buf();
-        let siglip2_path = siglip2_path.clone();
-        let dinov2_path = dinov2_path.clone();
-        let intra = intra_per_encoder;
-
-        handles.push(thread::spawn(move || -> Result<(), String> {
-            // Per-thread DB. Two connections (writer + read-only
-            // secondary) per encoder — at 3 enabled encoders that's
-            // 6 SQLite connections to the same WAL'd file. That's well
-            // within SQLite's healthy concurrency envelope.
-            let database = ImageDatabase::new(&db_path).map_err(|e| e.to_string())?;
-            // Initialise so the read-only secondary opens. Schema-create
-            // is idempotent (`CREATE TABLE IF NOT EXISTS`) so racing
-            // initialise() calls across threads don't corrupt anything;
-            // the first one in the WAL wins, the rest no-op.
-            database.initialize().map_err(|e| e.to_string())?;
-
-            match encoder_id.as_str() {
-                "clip_vit_b_32" => {
-                    run_clip_encoder_with_intra(&app, &database, &image_model_path, intra)
-                }
-                "siglip2_base" => {
-                    if siglip2_path.exists() {
-                        run_trait_encoder(
-                            &app,
-                            &database,
-                            "siglip2_base",
-                            || crate::similarity_and_semantic_search::encoder_siglip2::Siglip2ImageEncoder::new_with_intra(&siglip2_path, intra),
-                        )
-                    } else {
-                        warn!(
-                            "SigLIP-2 image model missing at {}; skipping",
-                            siglip2_path.display()
-                        );
-                        Ok(())
+    //
+    // Scoped so the threads can borrow the progress sink; the scope
+    // joins them all before returning.
+    let results: Vec<thread::Result<Result<(), String>>> = thread::scope(|scope| {
+        let mut handles: Vec<thread::ScopedJoinHandle<'_, Result<(), String>>> = Vec::new();
+
+        for encoder_id in &enabled {
+            let encoder_id = encoder_id.clone();
+            let image_model_path = image_model_path.to_path_buf();
+            let siglip2_path = siglip2_path.clone();
+            let dinov2_path = dinov2_path.clone();
+            let intra = intra_per_encoder;
+            let cancel = cancel.clone();
+
+            handles.push(scope.spawn(move || -> Result<(), String> {
+                // Per-thread DB. Two connections (writer + read-only
+                // secondary) per encoder — at 3 enabled encoders that's
+                // 6 SQLite connections to the same WAL'd file. That's well
+                // within SQLite's healthy concurrency envelope.
+                let database = ImageDatabase::new(db_path).map_err(|e| e.to_string())?;
+                // Initialise so the read-only secondary opens. Schema-create
+                // is idempotent (`CREATE TABLE IF NOT EXISTS`) so racing
+                // initialise() calls across threads don't corrupt anything;
+                // the first one in the WAL wins, the rest no-op.
+                database.initialize().map_err(|e| e.to_string())?;
+
+                match encoder_id.as_str() {
+                    "clip_vit_b_32" => {
+                        run_clip_encoder_with_intra(sink, &cancel, &database, &image_model_path, intra)
                     }
-                }
-                "dinov2_base" => {
-                    if dinov2_path.exists() {
-                        run_trait_encoder(
-                            &app,
-                            &database,
-                            crate::similarity_and_semantic_search::encoder_dinov2::DINOV2_ENCODER_ID,
-                            || crate::similarity_and_semantic_search::encoder_dinov2::Dinov2ImageEncoder::new_with_intra(&dinov2_path, intra),
-                        )
-                    } else {
-                        warn!(
-                            "DINOv2 image model missing at {}; skipping",
-                            dinov2_path.display()
-                        );
+                    "siglip2_base" => {
+                        if siglip2_path.exists() {
+                            run_trait_encoder(
+                                sink,
+                                &cancel,
+                                &database,
+                                "siglip2_base",
+                                || crate::similarity_and_semantic_search::encoder_siglip2::Siglip2ImageEncoder::new_with_intra(&siglip2_path, intra),
+                            )
+                        } else {
+                            warn!(
+                                "SigLIP-2 image model missing at {}; skipping",
+                                siglip2_path.display()
+                            );
+                            Ok(())
+                        }
+                    }
+                    "dinov2_base" => {
+                        if dinov2_path.exists() {
+                            run_trait_encoder(
+                                sink,
+                                &cancel,
+                                &database,
+                                crate::similarity_and_semantic_search::encoder_dinov2::DINOV2_ENCODER_ID,
+                                || crate::similarity_and_semantic_search::encoder_dinov2::Dinov2ImageEncoder::new_with_intra(&dinov2_path, intra),
+                            )
+                        } else {
+                            warn!(
+                                "DINOv2 image model missing at {}; skipping",
+                                dinov2_path.display()
+                            );
+                            Ok(())
+                        }
+                    }
+                    other => {
+                        warn!("encoder phase: ignoring unknown enabled id '{other}'");
                         Ok(())
                     }
                 }
-                other => {
-                    warn!("encoder phase: ignoring unknown enabled id '{other}'");
-                    Ok(())
-                }
-            }
-        }));
-    }
+            }));
+        }
 
-    // Join every thread. We surface the first error encountered but
+        handles.into_iter().map(|h| h.join()).collect()
+    });
+
+    // Every thread has been joined. We surface the first error encountered but
     // wait for the others to finish so a fast-failing CLIP doesn't
     // leave SigLIP-2 / DINOv2 mid-encode.
     let mut first_err: Option<String> = None;
-    for h in handles {
-        match h.join() {
+    for result in results {
+        match result {
             Ok(Ok(())) => {}
             Ok(Err(e)) => {
                 if first_err.is_none() {
@@ -757,7 +1269,8 @@ fn run_encoder_phase(
 /// (The previous `run_clip_encoder(app, db, path)` wrapper was
 /// dropped — every caller goes through the with-intra form now.)
 fn run_clip_encoder_with_intra(
-    app: &AppHandle,
+    sink: &dyn ProgressSink,
+    cancel: &CancelToken,
     database: &ImageDatabase,
     model_path: &Path,
     intra_threads: usize,
@@ -769,7 +1282,7 @@ fn run_clip_encoder_with_intra(
     if total == 0 {
         return Ok(());
     }
-    emit(app, Phase::Encode, 0, total, Some("Encoding (CLIP)".into()));
+    emit(sink, Phase::Encode, 0, total, Some("Encoding (CLIP)".into()));
 
     let run_started = std::time::Instant::now();
     let mut encoder = ClipImageEncoder::new_with_intra(model_path, intra_threads)
@@ -780,6 +1293,11 @@ fn run_clip_encoder_with_intra(
     let mut failed_paths: Vec<String> = Vec::new();
     let mut sample_emitted = false;
     for chunk in needs_embed.chunks(BATCH_SIZE) {
+        // Cancelled: stop between batches. Everything committed so far
+        // stays — the next run only encodes what's still missing.
+        if cancel.is_cancelled() {
+            break;
+        }
         let batch_paths: Vec<&Path> = chunk.iter().map(|i| Path::new(&i.path)).collect();
         match encoder.encode_batch(&batch_paths) {
             Ok(embeddings) => {
@@ -838,7 +1356,7 @@ fn run_clip_encoder_with_intra(
             }
         }
         processed += chunk.len();
-        emit(app, Phase::Encode, processed, total, Some("Encoding (CLIP)".into()));
+        emit(sink, Phase::Encode, processed, total, Some("Encoding (CLIP)".into()));
         // R3 — drain the WAL between batches so it can't grow without
         // bound under wal_autocheckpoint=0. PASSIVE never blocks
         // foreground readers; it just folds whatever pages are clean
@@ -870,7 +1388,8 @@ fn run_clip_encoder_with_intra(
 /// Generic per-encoder loop using the ImageEncoder trait. Used for
 /// SigLIP-2 + DINOv2; each writes only to the new embeddings table.
 fn run_trait_encoder<F, E>(
-    app: &AppHandle,
+    sink: &dyn ProgressSink,
+    cancel: &CancelToken,
     database: &ImageDatabase,
     encoder_id: &str,
     make_encoder: F,
@@ -888,7 +1407,7 @@ where
         return Ok(());
     }
     let label = format!("Encoding ({encoder_id})");
-    emit(app, Phase::Encode, 0, total, Some(label.clone()));
+    emit(sink, Phase::Encode, 0, total, Some(label.clone()));
 
     let run_started = std::time::Instant::now();
     let mut encoder = make_encoder().map_err(|e| e.to_string())?;
@@ -899,6 +1418,9 @@ where
     // Trait default `encode_batch` falls back to one-by-one. Future:
     // override per encoder if batching is faster.
     for chunk in needs.chunks(32) {
+        if cancel.is_cancelled() {
+            break;
+        }
         let paths: Vec<&StdPath> = chunk.iter().map(|(_, p)| StdPath::new(p)).collect();
         match encoder.encode_batch(&paths) {
             Ok(embeddings) => {
@@ -942,7 +1464,7 @@ where
             }
         }
         processed += chunk.len();
-        emit(app, Phase::Encode, processed, total, Some(label.clone()));
+        emit(sink, Phase::Encode, processed, total, Some(label.clone()));
         // R3 — drain WAL between batches under wal_autocheckpoint=0.
         let _ = database.checkpoint_passive();
     }
@@ -1014,29 +1536,61 @@ fn emit_preprocessing_sample(encoder_id: &str, image_path: &str, embedding: &[f3
 }
 
 fn emit(
-    app: &AppHandle,
+    sink: &dyn ProgressSink,
     phase: Phase,
     processed: usize,
     total: usize,
     message: Option<String>,
 ) {
-    let payload = IndexingProgress {
+    sink.report(&IndexingProgress {
         phase,
         processed,
         total,
         message,
-    };
-    if let Err(e) = app.emit("indexing-progress", &payload) {
-        // Don't crash on emit failure — just log. Receivers may have
-        // disconnected (closing window mid-pipeline).
-        warn!("failed to emit event: {e}");
-    }
+    });
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn caches_reloaded_after_encoding_include_the_new_images() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = ImageDatabase::new(tmp.path().join("t.db").to_str().unwrap()).unwrap();
+        db.initialize().unwrap();
+        let encoders = vec!["dinov2_base".to_string()];
+        let embed = |path: &str, v: &[f32]| {
+            db.add_image(path.into(), None).unwrap();
+            let id = db.get_image_id_by_path(path).unwrap();
+            db.upsert_embedding(id, "dinov2_base", v).unwrap();
+        };
+        let index = Arc::new(Mutex::new(CosineIndex::new()));
+        let current = Arc::new(Mutex::new(String::new()));
+        let fusion = crate::FusionIndexState::new();
+        let query = ndarray::Array1::from(vec![0.0f32, 1.0]);
+        let ranked = |fusion: &crate::FusionIndexState| {
+            fusion
+                .ranked_for_encoder(&db, "dinov2_base", &query, 10, None, None)
+                .unwrap()
+        };
+
+        // Warm with the embedding set as it was before the run.
+        embed("/a.jpg", &[1.0, 0.0]);
+        reload_search_caches(&db, Some(&fusion), &index, &current, "dinov2_base", &encoders);
+        assert_eq!(index.lock().unwrap().cached_images.len(), 1);
+        assert_eq!(ranked(&fusion).len(), 1);
+
+        // The encoder phase adds an image; the end-of-run reload must
+        // pick it up even though the same encoder is already loaded.
+        embed("/b.jpg", &[0.0, 1.0]);
+        reload_search_caches(&db, Some(&fusion), &index, &current, "dinov2_base", &encoders);
+        assert_eq!(index.lock().unwrap().cached_images.len(), 2);
+        let ranked = ranked(&fusion);
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].0, PathBuf::from("/b.jpg"));
+    }
+
     #[test]
     fn indexing_state_default_not_running() {
         let s = IndexingState::new();
@@ -1076,7 +1630,7 @@ mod tests {
     fn single_flight_first_acquire_succeeds() {
         // Direct test of the AtomicBool gate semantics that
         // try_spawn_pipeline relies on. We don't actually spawn the
-        // pipeline (it'd need a Tauri app handle) — just exercise
+        // pipeline (it'd need a progress sink) — just exercise
         // the compare_exchange behaviour.
         let state = IndexingState::new();
         let acquired = state
@@ -1105,12 +1659,75 @@ mod tests {
 
     #[test]
     fn indexing_error_displays_human_readable_message() {
-        let err = IndexingError::AlreadyRunning;
+        let err = IndexingError::Cancelled;
         let msg = format!("{err}");
         assert!(
-            msg.contains("already in progress"),
-            "expected human-readable AlreadyRunning message, got {msg}"
+            msg.contains("cancelled"),
+            "expected human-readable Cancelled message, got {msg}"
+        );
+    }
+
+    #[test]
+    fn cancel_token_is_shared_between_clones() {
+        let token = CancelToken::new();
+        let worker_view = token.clone();
+        assert!(worker_view.check().is_ok());
+        token.cancel();
+        assert!(worker_view.is_cancelled());
+        assert_eq!(worker_view.check(), Err(IndexingError::Cancelled));
+        worker_view.reset();
+        assert!(!token.is_cancelled());
+    }
+
+    #[test]
+    fn cancelled_error_survives_boxing() {
+        // run_pipeline_inner returns Box<dyn Error>; the worker loop
+        // tells cancellation apart from failure by downcasting.
+        let boxed: Box<dyn std::error::Error> = IndexingError::Cancelled.into();
+        assert_eq!(
+            boxed.downcast_ref::<IndexingError>(),
+            Some(&IndexingError::Cancelled)
+        );
+    }
+
+    #[test]
+    fn full_trigger_absorbs_queued_path_batches() {
+        let state = IndexingState::new();
+        assert_eq!(state.take_scope(), None);
+
+        state.request_paths([PathBuf::from("/r/b.jpg"), PathBuf::from("/r/a.jpg")]);
+        state.request_paths([PathBuf::from("/r/a.jpg")]);
+        assert_eq!(
+            state.take_scope(),
+            Some(RunScope::Paths(vec!["/r/a.jpg".into(), "/r/b.jpg".into()]))
         );
+        assert_eq!(state.take_scope(), None, "draining empties the scope");
+
+        state.request_paths([PathBuf::from("/r/a.jpg")]);
+        state.request_full();
+        assert_eq!(state.take_scope(), Some(RunScope::Full));
+    }
+
+    #[test]
+    fn oversized_path_backlog_falls_back_to_full_run() {
+        let state = IndexingState::new();
+        state.request_paths((0..=MAX_INCREMENTAL_PATHS).map(|i| PathBuf::from(format!("/r/{i}.jpg"))));
+        assert_eq!(state.take_scope(), Some(RunScope::Full));
+    }
+
+    #[test]
+    fn request_cancel_drops_queued_follow_up() {
+        let state = IndexingState::new();
+        state.is_running.store(true, Ordering::SeqCst);
+        state.rerun_requested.store(true, Ordering::SeqCst);
+        state.request_full();
+        assert!(state.request_cancel(), "a run was in flight");
+        assert!(state.cancel.is_cancelled());
+        assert!(!state.rerun_requested.load(Ordering::SeqCst));
+        assert_eq!(state.take_scope(), None);
+
+        let idle = IndexingState::new();
+        assert!(!idle.request_cancel(), "nothing was running");
     }
 
     #[test]
@@ -1122,6 +1739,7 @@ mod tests {
             (Phase::Encode, "encode"),
             (Phase::Ready, "ready"),
             (Phase::Error, "error"),
+            (Phase::Cancelled, "cancelled"),
         ] {
             let progress = IndexingProgress {
                 phase: variant,
@@ -0,0 +1,366 @@
+//! Everything the image browser does that isn't a window: the SQLite
+//! catalogue, thumbnails, the encoders and cosine caches, the indexing
+//! pipeline and watcher, and the loopback HTTP API.
+//!
+//! No Tauri dependency — the desktop app (`image_browser_lib`) is a thin
+//! layer of `#[tauri::command]` wrappers over `Library`, and the same
+//! `Library` backs `image-browser-cli` and anything else that wants to
+//! embed the search engine.
+
+// 6b — relax `clippy::doc_lazy_continuation`. The lint flags
+// rustdoc-style bullet lists where a continuation line is not
+// indented under its bullet — a stylistic preference that doesn't
+// match this codebase's docstring conventions (we use //! and ///
+// blocks heavily with consistent left-aligned continuations on
+// purpose, for terminal readability with `cargo doc --open` AND
+// when the file is read directly). Re-enabling per-line would
+// require touching ~10 files with no behavioural value.
+#![allow(clippy::doc_lazy_continuation)]
+
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    db::ImageDatabase,
+    similarity_and_semantic_search::cosine::{QuantMode, QuantizedIndex},
+    similarity_and_semantic_search::cosine_similarity::CosineIndex,
+    similarity_and_semantic_search::encoder_text::ClipTextEncoder,
+};
+
+pub mod autotag;
+pub mod clusters;
+pub mod cursor;
+pub mod db;
+pub mod duplicates;
+pub mod error;
+pub mod filesystem;
+pub mod http_api;
+pub mod image_struct;
+pub mod incremental;
+pub mod indexing;
+pub mod library;
+pub mod model_download;
+pub mod paths;
+pub mod perf;
+pub mod perf_report;
+pub mod root_availability;
+pub mod root_struct;
+pub mod search;
+pub mod settings;
+pub mod similarity_and_semantic_search;
+pub mod tag_propagation;
+pub mod tag_struct;
+pub mod thumbnail;
+pub mod watcher;
+
+pub use error::ApiError;
+pub use library::Library;
+
+pub struct CosineIndexState {
+    /// Wrapped in Arc<Mutex<...>> rather than plain Mutex<...> so the
+    /// indexing thread (Pass 5) can hold a clone alongside the
+    /// `Library`. Both point at the same in-memory cache.
+    ///
+    /// The cache holds embeddings from ONE encoder at a time —
+    /// whichever the user's `imageEncoder` setting selects. When the
+    /// setting changes, the cache is wiped + repopulated from the
+    /// embeddings table for the new encoder. Repopulate is fast
+    /// because the embeddings are already on disk; only the new
+    /// encoder's embeddings need DB → memory transfer.
+    pub index: Arc<Mutex<CosineIndex>>,
+    pub db_path: String,
+    /// The encoder_id whose embeddings are currently loaded into
+    /// `index`. Empty string when uninitialised. Read at search-time
+    /// to detect stale cache after a settings change.
+    pub current_encoder_id: Arc<Mutex<String>>,
+}
+
+impl CosineIndexState {
+    /// Empty cache for the database at `db_path`; the first search
+    /// populates it.
+    pub fn new(db_path: String) -> Self {
+        Self {
+            index: Arc::new(Mutex::new(CosineIndex::new())),
+            db_path,
+            current_encoder_id: Arc::new(Mutex::new(String::new())),
+        }
+    }
+
+    /// Ensure `index` holds the cache for `encoder_id`. If it already
+    /// does, returns immediately (no work). Otherwise repopulates
+    /// from `db.get_all_embeddings_for(encoder_id)` — fast because
+    /// the on-disk embeddings are already there from the indexing
+    /// pass; this is just DB→memory transfer.
+    ///
+    /// Returns an error if the DB read fails. Callers should treat
+    /// this as a hard failure for the search command — there's no
+    /// useful "partial cache" state to fall back to.
+    /// Drop the in-memory cache AND the "currently loaded encoder"
+    /// marker so the very next search call repopulates from the DB.
+    ///
+    /// Why both: `ensure_loaded_for` short-circuits when
+    /// `current_encoder_id` matches the requested encoder, and only
+    /// repopulates the cache otherwise. If we cleared the cache but
+    /// left the marker, the next search would short-circuit and run
+    /// against an empty cache (image-image returns 0; semantic search
+    /// only saves itself via a separate empty-cache fallback in
+    /// `search/semantic.rs`). Worse, the previous code's leftover
+    /// pre-toggle entries kept appearing in results because nothing
+    /// forced a reload — exactly the "disabled folder still shows in
+    /// View Similar" bug. Lock order matches `ensure_loaded_for`
+    /// (current_encoder_id then index) to keep the search path
+    /// deadlock-free.
+    pub fn invalidate(&self) {
+        if let (Ok(mut cur), Ok(mut idx)) =
+            (self.current_encoder_id.lock(), self.index.lock())
+        {
+            idx.clear();
+            cur.clear();
+        }
+    }
+
+    pub fn ensure_loaded_for(
+        &self,
+        db: &ImageDatabase,
+        encoder_id: &str,
+    ) -> Result<(), String> {
+        // Two-step lock acquisition: check current id first to short-
+        // circuit the common case (encoder hasn't changed). Acquire
+        // the index Mutex only if a reload is actually needed.
+        {
+            let cur = self
+                .current_encoder_id
+                .lock()
+                .map_err(|e| format!("current_encoder_id mutex poisoned: {e}"))?;
+            if *cur == encoder_id {
+                return Ok(());
+            }
+        }
+        // Need to switch — take both locks. The order is consistent
+        // (id first, then index) across all callers; deadlock-safe.
+        let mut cur = self
+            .current_encoder_id
+            .lock()
+            .map_err(|e| format!("current_encoder_id mutex poisoned: {e}"))?;
+        let mut index = self
+            .index
+            .lock()
+            .map_err(|e| format!("index mutex poisoned: {e}"))?;
+        // Re-check under the lock (another thread might've switched).
+        if *cur == encoder_id {
+            return Ok(());
+        }
+        index.populate_from_db_for_encoder(db, encoder_id);
+        *cur = encoder_id.to_string();
+        Ok(())
+    }
+}
+
+/// Phase 5 — per-encoder cosine caches for multi-encoder rank fusion.
+///
+/// The primary `CosineIndexState` holds ONE encoder's cache at a time
+/// (the user's "active" image encoder). Fusion needs all three caches
+/// resident simultaneously so it can score the query image in each
+/// encoder's space without paying a populate-roundtrip per fusion call.
+///
+/// Lazy-populated: each encoder's slot stays empty until the first
+/// fusion call asks for it. Memory cost on a 2000-image library is
+/// roughly 2000 × 768 × 4 bytes per slot ≈ 6 MB per encoder, ~18 MB
+/// total — small enough that holding all three resident is the right
+/// trade vs the alternative ~150 ms cold-populate cost on every
+/// fusion call.
+///
+/// `invalidate_all()` clears every slot (and is wired into the same
+/// root-change paths that already invalidate `CosineIndexState`).
+///
+/// With `embedding_quantization` set, slots live in `quantized`
+/// instead: int8 or 1-bit codes per image (4× / 32× smaller than f32),
+/// with each query's top candidates re-ranked against the full-precision
+/// rows in SQLite — see `cosine::quant`. That is what keeps 500k images
+/// × 3 encoders resident on a laptop. Only one of the two maps is
+/// populated at a time; `set_quantization` flips between them.
+pub struct FusionIndexState {
+    pub per_encoder:
+        Arc<Mutex<std::collections::HashMap<String, CosineIndex>>>,
+    pub quantized: Arc<Mutex<std::collections::HashMap<String, QuantizedIndex>>>,
+    quantization: Mutex<Option<QuantMode>>,
+}
+
+impl FusionIndexState {
+    pub fn new() -> Self {
+        Self::with_quantization(None)
+    }
+
+    /// `new()` with the cache representation chosen up front — `run()`
+    /// passes the persisted setting.
+    pub fn with_quantization(mode: Option<QuantMode>) -> Self {
+        Self {
+            per_encoder: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            quantized: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            quantization: Mutex::new(mode),
+        }
+    }
+
+    /// Current cache representation; `None` is full precision.
+    pub fn quantization(&self) -> Option<QuantMode> {
+        self.quantization.lock().map(|m| *m).unwrap_or(None)
+    }
+
+    /// Switch representation. Every slot is dropped so the next query
+    /// (or launch warm-up) rebuilds in the new form; the old form's
+    /// memory is released right away.
+    pub fn set_quantization(&self, mode: Option<QuantMode>) {
+        if let Ok(mut m) = self.quantization.lock() {
+            *m = mode;
+        }
+        self.invalidate_all();
+    }
+
+    /// Clear every per-encoder cache. Called from the same root-change
+    /// IPCs that invalidate `CosineIndexState` so a disabled-root toggle
+    /// flushes the fusion caches too. Without this, fusion would
+    /// happily return images from a now-disabled root because its
+    /// cached entries weren't cleared.
+    pub fn invalidate_all(&self) {
+        if let Ok(mut m) = self.per_encoder.lock() {
+            m.clear();
+        }
+        if let Ok(mut m) = self.quantized.lock() {
+            m.clear();
+        }
+    }
+
+    /// Launch-time warm-up: load each encoder's on-disk cache file into
+    /// its slot, if the file is fresh. Encoders without one are left to
+    /// the lazy populate in `ranked_for_encoder`. The map lock is held
+    /// only for the insert, so a fusion query issued meanwhile isn't
+    /// stalled behind the file reads.
+    ///
+    /// Quantised slots are built outright — codes come from the cache
+    /// file when fresh, otherwise from a streaming DB read.
+    pub fn warm_from_cache_files(&self, db: &ImageDatabase, encoders: &[String]) {
+        for encoder_id in encoders {
+            if let Some(mode) = self.quantization() {
+                let Some(index) = QuantizedIndex::populate(db, encoder_id, mode) else {
+                    continue;
+                };
+                if let Ok(mut m) = self.quantized.lock() {
+                    // A mode switch mid-build makes this slot stale.
+                    if self.quantization() == Some(mode) {
+                        m.entry(encoder_id.clone()).or_insert(index);
+                    }
+                }
+                continue;
+            }
+            let mut index = CosineIndex::new();
+            if !index.warm_from_cache_file(db, encoder_id) {
+                continue;
+            }
+            if let Ok(mut m) = self.per_encoder.lock() {
+                let slot = m.entry(encoder_id.clone()).or_insert_with(CosineIndex::new);
+                if slot.cached_images.is_empty() {
+                    *slot = index;
+                }
+            }
+        }
+    }
+
+    /// Lazy populate (or reuse) the per-encoder cache for `encoder_id`,
+    /// then run the cosine query against it. Returns the top-K
+    /// (path, score) list excluding `exclude_path` and, when `allowed`
+    /// is given, drawn only from those paths (see `SearchFilter`).
+    ///
+    /// Caller hands in `top_k` — fusion tops out at ~50 per encoder
+    /// in practice (the rank-fusion contribution at rank 50 with
+    /// k_rrf=60 is ~0.009, smaller still beyond that).
+    pub fn ranked_for_encoder(
+        &self,
+        db: &ImageDatabase,
+        encoder_id: &str,
+        query: &ndarray::Array1<f32>,
+        top_k: usize,
+        exclude_path: Option<&std::path::PathBuf>,
+        allowed: Option<&std::collections::HashSet<std::path::PathBuf>>,
+    ) -> Result<Vec<(std::path::PathBuf, f32)>, String> {
+        if let Some(mode) = self.quantization() {
+            return self.ranked_quantized(db, encoder_id, mode, query, top_k, exclude_path, allowed);
+        }
+        let mut map = self
+            .per_encoder
+            .lock()
+            .map_err(|e| format!("fusion mutex poisoned: {e}"))?;
+        let entry = map
+            .entry(encoder_id.to_string())
+            .or_insert_with(CosineIndex::new);
+        if entry.cached_images.is_empty() {
+            entry.populate_from_db_for_encoder(db, encoder_id);
+        }
+        if entry.cached_images.is_empty() {
+            // No embeddings available for this encoder — return empty
+            // ranked list. Fusion still works with the other encoders.
+            return Ok(Vec::new());
+        }
+        Ok(entry.get_similar_images_sorted_within(query, top_k, exclude_path, allowed))
+    }
+
+    /// `ranked_for_encoder` over the quantised slot: candidates from
+    /// the codes, scores from the full-precision rows in the DB.
+    #[allow(clippy::too_many_arguments)]
+    fn ranked_quantized(
+        &self,
+        db: &ImageDatabase,
+        encoder_id: &str,
+        mode: QuantMode,
+        query: &ndarray::Array1<f32>,
+        top_k: usize,
+        exclude_path: Option<&std::path::PathBuf>,
+        allowed: Option<&std::collections::HashSet<std::path::PathBuf>>,
+    ) -> Result<Vec<(std::path::PathBuf, f32)>, String> {
+        let mut map = self
+            .quantized
+            .lock()
+            .map_err(|e| format!("fusion mutex poisoned: {e}"))?;
+        if map.get(encoder_id).is_none_or(|q| q.mode() != mode) {
+            match QuantizedIndex::populate(db, encoder_id, mode) {
+                Some(index) => {
+                    map.insert(encoder_id.to_string(), index);
+                }
+                // No embeddings for this encoder (yet) — fusion still
+                // works with the others.
+                None => return Ok(Vec::new()),
+            }
+        }
+        let index = map.get_mut(encoder_id).expect("slot populated above");
+        let query = similarity_and_semantic_search::cosine::math::contiguous(query);
+        index
+            .search_reranked(db, encoder_id, &query, top_k, exclude_path, allowed)
+            .map_err(|e| format!("quantised re-rank for {encoder_id} failed: {e}"))
+    }
+}
+
+impl Default for FusionIndexState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// State for the text encoders used in semantic search.
+///
+/// Each encoder is lazy-loaded on first use. We hold one slot per
+/// supported family (CLIP — 512-d English BPE; SigLIP-2 — 768-d
+/// Gemma SentencePiece) so the user can switch the text encoder in
+/// the picker mid-session without paying the model-load cost again
+/// when they swap back.
+///
+/// Two slots not three because DINOv2 is image-only — there is no
+/// DINOv2 text branch to dispatch through.
+#[derive(Default)]
+pub struct TextEncoderState {
+    /// CLIP English text encoder. 512-d output. Default.
+    pub encoder: Mutex<Option<ClipTextEncoder>>,
+    /// SigLIP-2 base 256 text encoder. 768-d output, Gemma SentencePiece
+    /// tokenizer (256k vocab). The picker dispatches semantic_search
+    /// here when the user has SigLIP-2 selected as the text encoder.
+    pub siglip2_encoder: Mutex<
+        Option<crate::similarity_and_semantic_search::encoder_siglip2::Siglip2TextEncoder>,
+    >,
+}
@@ -0,0 +1,1391 @@
+//! `Library` — one image library and everything kept warm for it.
+//!
+//! Owns the catalogue connection, the single-encoder cosine cache, the
+//! per-encoder fusion caches, the lazily loaded text encoders and the
+//! indexing single-flight guard. The desktop app manages one
+//! `Arc<Library>` as Tauri state and its commands are thin wrappers
+//! over the methods here; the CLI and the HTTP API hold the same type,
+//! so anything embedding the search engine gets the app's behaviour
+//! without a webview.
+//!
+//! Root changes invalidate the caches and restart indexing in one
+//! place, so every host gets the same "no stale results from a removed
+//! folder" guarantee.
+//!
+//! Indexing runs report to a host-supplied `ProgressSink`. The library
+//! wraps it so the pipeline warms and invalidates *this* library's
+//! fusion caches and text encoders rather than asking the host for
+//! them.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use tracing::{info, warn};
+
+use crate::autotag::{self, AutoTagReport};
+use crate::clusters;
+use crate::db::{
+    Cluster, GridCursor, GridQuery, ImageDatabase, JournalEntry, JournalOp, Replayed,
+    SearchFilter, TagAlias, TagLabel, TagSuggestion, REMOVED_ROOT_RETENTION_SECS,
+};
+use crate::duplicates::{self, DuplicateCluster, TrashReport, TrashSelection};
+use crate::error::ApiError;
+use crate::image_struct::ImageData;
+use crate::indexing::{self, CancelToken, IndexingProgress, IndexingState, ProgressSink, SpawnOutcome};
+use crate::root_struct::Root;
+use crate::search::pages::{check_page_size, RANKED_DEPTH};
+use crate::search::{
+    self, FeedbackResults, FeedbackSessions, ImageSearchResult, QueryTerm, SearchPage, SearchPages,
+    SearchRequest,
+};
+use crate::tag_struct::Tag;
+use crate::tag_propagation::{self, TagVote};
+use crate::watcher::{self, WatcherHandle};
+use crate::{cursor, paths, perf, settings, CosineIndexState, FusionIndexState, TextEncoderState};
+
+pub struct Library {
+    db: ImageDatabase,
+    cosine: CosineIndexState,
+    fusion: FusionIndexState,
+    text_encoders: TextEncoderState,
+    feedback: FeedbackSessions,
+    search_pages: SearchPages,
+    indexing: Arc<IndexingState>,
+}
+
+/// One page of the grid.
+#[derive(serde::Serialize)]
+pub struct ImagePage {
+    pub images: Vec<ImageData>,
+    /// Opaque; pass back to `images_page` for the next page. `None` on
+    /// the last one.
+    pub next_cursor: Option<String>,
+}
+
+impl Library {
+    /// Open (creating if needed) the catalogue at `db_path` and bring
+    /// its schema up to date. Caches start empty and fill on first use;
+    /// the fusion caches take the persisted quantisation setting.
+    pub fn open(db_path: impl Into<String>) -> rusqlite::Result<Self> {
+        let db_path = db_path.into();
+        let db = ImageDatabase::new(&db_path)?;
+        db.initialize()?;
+        Ok(Self {
+            db,
+            cosine: CosineIndexState::new(db_path),
+            fusion: FusionIndexState::with_quantization(
+                settings::Settings::load().resolved_quantization(),
+            ),
+            text_encoders: TextEncoderState::default(),
+            feedback: FeedbackSessions::default(),
+            search_pages: SearchPages::default(),
+            indexing: Arc::new(IndexingState::new()),
+        })
+    }
+
+    /// `open` on `ImageDatabase::default_database_path()` — the library
+    /// the desktop app uses.
+    pub fn open_default() -> rusqlite::Result<Self> {
+        Self::open(ImageDatabase::default_database_path())
+    }
+
+    pub fn db(&self) -> &ImageDatabase {
+        &self.db
+    }
+
+    pub fn db_path(&self) -> &str {
+        &self.cosine.db_path
+    }
+
+    pub fn cosine(&self) -> &CosineIndexState {
+        &self.cosine
+    }
+
+    pub fn fusion(&self) -> &FusionIndexState {
+        &self.fusion
+    }
+
+    pub fn text_encoders(&self) -> &TextEncoderState {
+        &self.text_encoders
+    }
+
+    pub fn indexing_state(&self) -> &Arc<IndexingState> {
+        &self.indexing
+    }
+
+    // ---- Search -------------------------------------------------------
+
+    /// Text query fused across every enabled text-capable encoder,
+    /// restricted to the images `filter` admits.
+    pub fn search(
+        &self,
+        query: &str,
+        top_n: usize,
+        per_encoder_top_k: Option<usize>,
+        filter: Option<&SearchFilter>,
+    ) -> Result<Vec<ImageSearchResult>, ApiError> {
+        search::fused_semantic_search(
+            &self.db,
+            &self.fusion,
+            &self.text_encoders,
+            query,
+            top_n,
+            per_encoder_top_k,
+            filter,
+        )
+    }
+
+    /// Images similar to `image_id`, fused across every enabled encoder.
+    pub fn similar(
+        &self,
+        image_id: i64,
+        top_n: usize,
+        per_encoder_top_k: Option<usize>,
+        filter: Option<&SearchFilter>,
+    ) -> Result<Vec<ImageSearchResult>, ApiError> {
+        search::fused_similar_images(
+            &self.db,
+            &self.fusion,
+            image_id,
+            top_n,
+            per_encoder_top_k,
+            filter,
+        )
+    }
+
+    /// Weighted blend of text phrases and example images, negatives
+    /// included — see `search::composite`.
+    pub fn composite_search(
+        &self,
+        terms: &[QueryTerm],
+        top_n: usize,
+        filter: Option<&SearchFilter>,
+    ) -> Result<Vec<ImageSearchResult>, ApiError> {
+        search::composite_search(
+            &self.db,
+            &self.fusion,
+            &self.text_encoders,
+            terms,
+            top_n,
+            filter,
+        )
+    }
+
+    /// Text query against one text encoder (CLIP unless `text_encoder_id`
+    /// names SigLIP-2).
+    pub fn semantic_search(
+        &self,
+        query: &str,
+        top_n: usize,
+        text_encoder_id: Option<&str>,
+    ) -> Result<Vec<ImageSearchResult>, ApiError> {
+        search::semantic_search(
+            &self.db,
+            &self.cosine,
+            &self.text_encoders,
+            query,
+            top_n,
+            text_encoder_id,
+        )
+    }
+
+    /// Nearest neighbours of `image_id` in one encoder's space.
+    pub fn similar_images(
+        &self,
+        image_id: i64,
+        top_n: usize,
+        encoder_id: Option<&str>,
+    ) -> Result<Vec<ImageSearchResult>, ApiError> {
+        search::similar_images(&self.db, &self.cosine, image_id, top_n, encoder_id)
+    }
+
+    /// Tier-sampled neighbours of `image_id` in one encoder's space.
+    pub fn tiered_similar_images(
+        &self,
+        image_id: i64,
+        encoder_id: Option<&str>,
+    ) -> Result<Vec<ImageSearchResult>, ApiError> {
+        search::tiered_similar_images(&self.db, &self.cosine, image_id, encoder_id)
+    }
+
+    /// Drop every in-memory search cache; the next query of each kind
+    /// repopulates from the DB. Needed whenever the set of searchable
+    /// images changes outside the indexing pipeline (root changes).
+    pub fn invalidate_caches(&self) {
+        self.cosine.invalidate();
+        self.fusion.invalidate_all();
+    }
+
+    // ---- Pagination ---------------------------------------------------
+
+    /// One page of the grid in `query`'s order — see `db::grid_pages`.
+    /// `cursor` is the previous page's `next_cursor`, `None` for the
+    /// first page.
+    pub fn images_page(
+        &self,
+        query: &GridQuery,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<ImagePage, ApiError> {
+        check_page_size(limit)?;
+        let after = match cursor {
+            Some(c) => {
+                let after: GridCursor = cursor::decode(c)
+                    .ok_or_else(|| ApiError::BadInput("malformed grid cursor".into()))?;
+                if !after.matches(query) {
+                    return Err(ApiError::BadInput(
+                        "grid cursor belongs to a different sort order".into(),
+                    ));
+                }
+                Some(after)
+            }
+            None => None,
+        };
+        let (images, next) = self.db.get_images_page(query, after.as_ref(), limit)?;
+        Ok(ImagePage {
+            images,
+            next_cursor: next.map(|n| cursor::encode(&n)),
+        })
+    }
+
+    /// Run `request` once, `RANKED_DEPTH` deep, and return its first
+    /// page. Later pages come from `search_page` without searching
+    /// again — see `search::pages`.
+    pub fn start_paged_search(
+        &self,
+        request: &SearchRequest,
+        page_size: usize,
+        filter: Option<&SearchFilter>,
+    ) -> Result<SearchPage, ApiError> {
+        check_page_size(page_size)?;
+        let results = match request {
+            SearchRequest::Text { query } => self.search(query, RANKED_DEPTH, None, filter)?,
+            SearchRequest::Similar { image_id } => {
+                self.similar(*image_id, RANKED_DEPTH, None, filter)?
+            }
+            SearchRequest::Composite { terms } => {
+                self.composite_search(terms, RANKED_DEPTH, filter)?
+            }
+        };
+        self.search_pages.store(results, page_size)
+    }
+
+    /// The page of a paged search `cursor` points at.
+    pub fn search_page(&self, cursor: &str, page_size: usize) -> Result<SearchPage, ApiError> {
+        self.search_pages.page(cursor, page_size)
+    }
+
+    // ---- Relevance feedback -------------------------------------------
+
+    /// Open a relevance-feedback session on a text query — see
+    /// `search::feedback`. The first page matches `search`, and
+    /// `filter` holds for the session's lifetime.
+    pub fn start_search_session(
+        &self,
+        query: &str,
+        top_n: usize,
+        filter: Option<&SearchFilter>,
+    ) -> Result<FeedbackResults, ApiError> {
+        self.feedback.start_text(
+            &self.db,
+            &self.fusion,
+            &self.text_encoders,
+            query,
+            top_n,
+            filter,
+        )
+    }
+
+    /// Open a relevance-feedback session on a query image. The first
+    /// page matches `similar`.
+    pub fn start_similar_session(
+        &self,
+        image_id: i64,
+        top_n: usize,
+        filter: Option<&SearchFilter>,
+    ) -> Result<FeedbackResults, ApiError> {
+        if let Err(rusqlite::Error::QueryReturnedNoRows) = self.db.get_image_path(image_id) {
+            return Err(ApiError::NotFound(format!("image {image_id}")));
+        }
+        self.feedback
+            .start_image(&self.db, &self.fusion, image_id, top_n, filter)
+    }
+
+    /// Mark images positive or negative (or `neutral` to withdraw a
+    /// mark) and re-rank the session.
+    pub fn refine_search(
+        &self,
+        session_id: u64,
+        positive: &[i64],
+        negative: &[i64],
+        neutral: &[i64],
+        top_n: usize,
+    ) -> Result<FeedbackResults, ApiError> {
+        self.feedback.refine(
+            &self.db,
+            &self.fusion,
+            session_id,
+            positive,
+            negative,
+            neutral,
+            top_n,
+        )
+    }
+
+    /// Close a feedback session; `false` if it was already gone.
+    pub fn end_search_session(&self, session_id: u64) -> bool {
+        self.feedback.end(session_id)
+    }
+
+    // ---- Auto-tagging -------------------------------------------------
+
+    pub fn tag_labels(&self) -> Result<Vec<TagLabel>, ApiError> {
+        Ok(self.db.list_tag_labels()?)
+    }
+
+    /// Add a label to the auto-tagging vocabulary. Every template must
+    /// contain `{}`, where the name is substituted; none means the
+    /// defaults.
+    pub fn add_tag_label(&self, name: &str, templates: Vec<String>) -> Result<TagLabel, ApiError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ApiError::BadInput("label name is empty".into()));
+        }
+        let templates = clean_templates(templates)?;
+        if self.db.list_tag_labels()?.iter().any(|l| l.name == name) {
+            return Err(ApiError::BadInput(format!("label '{name}' already exists")));
+        }
+        Ok(self.db.add_tag_label(name, &templates)?)
+    }
+
+    /// Replace a label's templates and threshold override (`None`:
+    /// calibrate on each run). It is re-encoded on the next run.
+    pub fn update_tag_label(
+        &self,
+        label_id: i64,
+        templates: Vec<String>,
+        threshold: Option<f32>,
+    ) -> Result<(), ApiError> {
+        if threshold.is_some_and(|t| !t.is_finite()) {
+            return Err(ApiError::BadInput("threshold must be a finite number".into()));
+        }
+        let templates = clean_templates(templates)?;
+        match self.db.update_tag_label(label_id, &templates, threshold) {
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(ApiError::NotFound(format!("label {label_id}")))
+            }
+            other => Ok(other?),
+        }
+    }
+
+    pub fn delete_tag_label(&self, label_id: i64) -> Result<(), ApiError> {
+        Ok(self.db.delete_tag_label(label_id)?)
+    }
+
+    /// Score the library against the label vocabulary and refresh the
+    /// pending suggestions — see `autotag::run`.
+    pub fn run_auto_tagging(&self, text_encoder_id: Option<&str>) -> Result<AutoTagReport, ApiError> {
+        autotag::run(&self.db, &self.text_encoders, text_encoder_id)
+    }
+
+    /// Pending suggestions, best first; one label's or all of them.
+    pub fn auto_tag_suggestions(
+        &self,
+        label_id: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<TagSuggestion>, ApiError> {
+        Ok(self.db.get_tag_suggestions(label_id, limit)?)
+    }
+
+    /// Accept a label's pending suggestions (all, or for `image_ids`)
+    /// into `images_tags`. Returns how many were accepted. The tagging
+    /// is undoable.
+    pub fn accept_tag_suggestions(
+        &self,
+        label_id: i64,
+        image_ids: Option<&[i64]>,
+    ) -> Result<usize, ApiError> {
+        match self.db.accept_tag_suggestions(label_id, image_ids) {
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(ApiError::NotFound(format!("label {label_id}")))
+            }
+            other => Ok(other?),
+        }
+    }
+
+    /// Reject a label's pending suggestions (all, or for `image_ids`).
+    pub fn reject_tag_suggestions(
+        &self,
+        label_id: i64,
+        image_ids: Option<&[i64]>,
+    ) -> Result<usize, ApiError> {
+        Ok(self.db.reject_tag_suggestions(label_id, image_ids)?)
+    }
+
+    // ---- Tag hierarchy ------------------------------------------------
+
+    /// Create a tag, under `parent_id` when given — see `db::tag_tree`.
+    /// Undoable.
+    pub fn create_tag(
+        &self,
+        name: &str,
+        color: &str,
+        parent_id: Option<i64>,
+    ) -> Result<Tag, ApiError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ApiError::BadInput("tag name is empty".into()));
+        }
+        if let Some(parent_id) = parent_id {
+            self.require_tag(parent_id)?;
+        }
+        self.check_not_an_alias(name, None)?;
+        let applied = self
+            .db
+            .journaled(
+                &format!("Create tag \"{name}\""),
+                vec![JournalOp::CreateTag {
+                    id: None,
+                    name: name.to_string(),
+                    color: color.to_string(),
+                    parent_id,
+                }],
+            )
+            .map_err(|e| ApiError::unique_as_bad_input(e, || tag_name_taken(name)))?;
+        match applied.first() {
+            Some(JournalOp::CreateTag { id: Some(id), .. }) => self.require_tag(*id),
+            _ => Err(ApiError::Db(format!("creating tag \"{name}\" applied nothing"))),
+        }
+    }
+
+    /// Delete a tag and its image links; its children move up to its
+    /// parent. Undoable.
+    pub fn delete_tag(&self, tag_id: i64) -> Result<(), ApiError> {
+        let tag = self.require_tag(tag_id)?;
+        self.db.journaled(
+            &format!("Delete tag \"{}\"", tag.name),
+            vec![JournalOp::DeleteTag { id: tag_id }],
+        )?;
+        Ok(())
+    }
+
+    /// Move a tag and its subtree under `parent_id`, or to the top level.
+    /// A tag can't move under itself or one of its descendants.
+    pub fn move_tag(&self, tag_id: i64, parent_id: Option<i64>) -> Result<(), ApiError> {
+        self.require_tag(tag_id)?;
+        if let Some(parent_id) = parent_id {
+            self.require_tag(parent_id)?;
+            if self.db.get_tag_subtree(tag_id)?.contains(&parent_id) {
+                return Err(ApiError::BadInput(format!(
+                    "tag {parent_id} is tag {tag_id} or below it"
+                )));
+            }
+        }
+        Ok(self.db.move_tag(tag_id, parent_id)?)
+    }
+
+    /// Merge `source_id` into `target_id`: the source's images gain the
+    /// target, its children move under the target, its name and aliases
+    /// become aliases of the target, and it is deleted. Returns how many
+    /// images newly gained the target. The target can't be the source
+    /// or below it. Undoable.
+    pub fn merge_tags(&self, source_id: i64, target_id: i64) -> Result<usize, ApiError> {
+        let source = self.require_tag(source_id)?;
+        let target = self.require_tag(target_id)?;
+        if self.db.get_tag_subtree(source_id)?.contains(&target_id) {
+            return Err(ApiError::BadInput(format!(
+                "can't merge tag {source_id} into itself or a tag below it"
+            )));
+        }
+        let applied = self.db.journaled(
+            &format!("Merge tag \"{}\" into \"{}\"", source.name, target.name),
+            vec![JournalOp::MergeTag {
+                source_id,
+                target_id,
+                gained: Vec::new(),
+            }],
+        )?;
+        let gained = match applied.first() {
+            Some(JournalOp::MergeTag { gained, .. }) => gained.len(),
+            _ => 0,
+        };
+        info!("merged tag {source_id} into {target_id}: {gained} image(s) gained it");
+        Ok(gained)
+    }
+
+    fn require_tag(&self, tag_id: i64) -> Result<Tag, ApiError> {
+        match self.db.get_tag(tag_id) {
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(ApiError::NotFound(format!("tag {tag_id}")))
+            }
+            other => Ok(other?),
+        }
+    }
+
+    // ---- Tag management -----------------------------------------------
+
+    /// Rename a tag; its image links, children and aliases stay. The
+    /// name can't belong to another tag or to another tag's alias.
+    pub fn rename_tag(&self, tag_id: i64, name: &str) -> Result<Tag, ApiError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ApiError::BadInput("tag name is empty".into()));
+        }
+        self.require_tag(tag_id)?;
+        self.check_not_an_alias(name, Some(tag_id))?;
+        self.db
+            .rename_tag(tag_id, name)
+            .map_err(|e| ApiError::unique_as_bad_input(e, || tag_name_taken(name)))?;
+        self.require_tag(tag_id)
+    }
+
+    /// Every tag alias — see `db::tag_aliases`.
+    pub fn tag_aliases(&self) -> Result<Vec<TagAlias>, ApiError> {
+        Ok(self.db.get_tag_aliases()?)
+    }
+
+    /// Make `name` resolve to `tag_id`. The name can't already be a tag
+    /// or an alias.
+    pub fn add_tag_alias(&self, tag_id: i64, name: &str) -> Result<TagAlias, ApiError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ApiError::BadInput("alias is empty".into()));
+        }
+        self.require_tag(tag_id)?;
+        if let Some(tag) = self.db.resolve_tag_name(name)? {
+            return Err(ApiError::BadInput(if tag.name == name {
+                tag_name_taken(name)
+            } else {
+                format!("\"{name}\" is already an alias of \"{}\"", tag.name)
+            }));
+        }
+        self.db.add_tag_alias(tag_id, name).map_err(|e| {
+            ApiError::unique_as_bad_input(e, || format!("\"{name}\" is already an alias"))
+        })
+    }
+
+    pub fn remove_tag_alias(&self, name: &str) -> Result<(), ApiError> {
+        if !self.db.remove_tag_alias(name.trim())? {
+            return Err(ApiError::NotFound(format!("tag alias \"{name}\"")));
+        }
+        Ok(())
+    }
+
+    /// Add `tag_id` to every image in `image_ids` in one transaction.
+    /// Unknown and already-tagged images are skipped; returns how many
+    /// gained the tag. Undoable.
+    pub fn add_tag_to_images(&self, tag_id: i64, image_ids: &[i64]) -> Result<usize, ApiError> {
+        let tag = self.require_tag(tag_id)?;
+        let applied = self.db.journaled(
+            &format!("Add tag \"{}\" to {}", tag.name, count_images(image_ids.len())),
+            vec![JournalOp::TagImages {
+                tag_id,
+                image_ids: image_ids.to_vec(),
+            }],
+        )?;
+        Ok(images_touched(&applied))
+    }
+
+    /// Remove `tag_id` from every image in `image_ids` in one
+    /// transaction; returns how many lost it. Undoable.
+    pub fn remove_tag_from_images(
+        &self,
+        tag_id: i64,
+        image_ids: &[i64],
+    ) -> Result<usize, ApiError> {
+        let tag = self.require_tag(tag_id)?;
+        let applied = self.db.journaled(
+            &format!("Remove tag \"{}\" from {}", tag.name, count_images(image_ids.len())),
+            vec![JournalOp::UntagImages {
+                tag_id,
+                image_ids: image_ids.to_vec(),
+            }],
+        )?;
+        Ok(images_touched(&applied))
+    }
+
+    /// `add_tag_to_images` for one image.
+    pub fn add_tag_to_image(&self, image_id: i64, tag_id: i64) -> Result<(), ApiError> {
+        self.add_tag_to_images(tag_id, &[image_id]).map(drop)
+    }
+
+    /// `remove_tag_from_images` for one image.
+    pub fn remove_tag_from_image(&self, image_id: i64, tag_id: i64) -> Result<(), ApiError> {
+        self.remove_tag_from_images(tag_id, &[image_id]).map(drop)
+    }
+
+    /// `BadInput` if `name` is an alias of a tag other than `tag_id`.
+    fn check_not_an_alias(&self, name: &str, tag_id: Option<i64>) -> Result<(), ApiError> {
+        match self.db.resolve_tag_name(name)? {
+            Some(tag) if tag.name != name && Some(tag.id) != tag_id => Err(ApiError::BadInput(
+                format!("\"{name}\" is an alias of \"{}\"", tag.name),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // ---- Notes --------------------------------------------------------
+
+    /// Set an image's notes; blank clears them. Undoable.
+    pub fn set_image_notes(&self, image_id: i64, notes: &str) -> Result<(), ApiError> {
+        let path = match self.db.get_image_path(image_id) {
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                return Err(ApiError::NotFound(format!("image {image_id}")))
+            }
+            other => other?,
+        };
+        let name = Path::new(&path)
+            .file_name()
+            .map_or(path.clone(), |n| n.to_string_lossy().into_owned());
+        self.db.journaled(
+            &format!("Edit notes on {name}"),
+            vec![JournalOp::SetNotes {
+                image_id,
+                notes: Some(notes.to_string()),
+            }],
+        )?;
+        Ok(())
+    }
+
+    // ---- Undo / redo --------------------------------------------------
+
+    /// Reverse the newest journaled change — see `db::journal`. Returns
+    /// it, or `None` when there is nothing to undo. A folder brought
+    /// back is rescanned by an indexing run started here; its images
+    /// kept their thumbnails and embeddings.
+    pub fn undo<S: ProgressSink + 'static>(
+        self: &Arc<Self>,
+        sink: S,
+    ) -> Result<Option<JournalEntry>, ApiError> {
+        let replayed = self.db.undo().map_err(|e| {
+            ApiError::unique_as_bad_input(e, || {
+                "can't undo: a tag it would bring back has been created again".into()
+            })
+        })?;
+        Ok(self.replayed(replayed, sink))
+    }
+
+    /// Re-apply the change `undo` last reversed. Returns it, or `None`
+    /// when there is nothing to redo.
+    pub fn redo<S: ProgressSink + 'static>(
+        self: &Arc<Self>,
+        sink: S,
+    ) -> Result<Option<JournalEntry>, ApiError> {
+        let replayed = self.db.redo().map_err(|e| {
+            ApiError::unique_as_bad_input(e, || {
+                "can't redo: a tag it would bring back has been created again".into()
+            })
+        })?;
+        Ok(self.replayed(replayed, sink))
+    }
+
+    /// The undo history, newest first; `undone` entries can be redone.
+    pub fn journal(&self) -> Result<Vec<JournalEntry>, ApiError> {
+        Ok(self.db.journal_entries()?)
+    }
+
+    /// Follow-up for root changes an undo or redo just made.
+    fn replayed<S: ProgressSink + 'static>(
+        self: &Arc<Self>,
+        replayed: Option<Replayed>,
+        sink: S,
+    ) -> Option<JournalEntry> {
+        let Replayed { entry, applied } = replayed?;
+        let mut reindex = false;
+        for op in &applied {
+            match op {
+                JournalOp::RemoveRoot { .. } => {
+                    reindex |= self.indexing.is_running.load(Ordering::SeqCst);
+                    self.invalidate_caches();
+                }
+                JournalOp::RestoreRoot { .. } => {
+                    self.invalidate_caches();
+                    reindex = true;
+                }
+                _ => {}
+            }
+        }
+        if reindex {
+            self.restart_indexing(sink);
+        }
+        let verb = if entry.undone { "undid" } else { "redid" };
+        info!("{verb} journal entry {}: {}", entry.id, entry.label);
+        Some(entry)
+    }
+
+    // ---- Tag propagation ----------------------------------------------
+
+    /// Tags suggested for `image_id` by its nearest tagged neighbours —
+    /// see `tag_propagation::suggest_tags`. `voters` defaults to
+    /// `tag_propagation::DEFAULT_VOTERS`.
+    pub fn suggest_tags_for_image(
+        &self,
+        image_id: i64,
+        voters: Option<usize>,
+    ) -> Result<Vec<TagVote>, ApiError> {
+        if let Err(rusqlite::Error::QueryReturnedNoRows) = self.db.get_image_path(image_id) {
+            return Err(ApiError::NotFound(format!("image {image_id}")));
+        }
+        let voters = voters.unwrap_or(tag_propagation::DEFAULT_VOTERS).max(1);
+        tag_propagation::suggest_tags(&self.db, &self.fusion, image_id, voters)
+    }
+
+    /// Tag the `top_n` closest look-alikes of the images already tagged
+    /// `tag_id`; returns the newly tagged images. Undoable.
+    pub fn propagate_tag(&self, tag_id: i64, top_n: usize) -> Result<Vec<ImageSearchResult>, ApiError> {
+        self.require_tag(tag_id)?;
+        tag_propagation::propagate_tag(&self.db, &self.fusion, tag_id, top_n)
+    }
+
+    // ---- Duplicates ---------------------------------------------------
+
+    /// Exact and near-duplicate clusters with a suggested keeper — see
+    /// `duplicates::find_duplicates`. `min_similarity` is the DINOv2
+    /// cosine floor for near duplicates (default
+    /// `duplicates::DEFAULT_MIN_SIMILARITY`).
+    pub fn find_duplicates(
+        &self,
+        min_similarity: Option<f32>,
+    ) -> Result<Vec<DuplicateCluster>, ApiError> {
+        duplicates::find_duplicates(&self.db, duplicate_floor(min_similarity)?)
+    }
+
+    /// Move the selected duplicates to the OS trash and drop them from
+    /// the library — see `duplicates::trash_duplicates`.
+    /// `min_similarity` must match the report the selections came from.
+    /// The caches are flushed so search stops returning them.
+    pub fn trash_duplicates(
+        &self,
+        selections: &[TrashSelection],
+        min_similarity: Option<f32>,
+    ) -> Result<TrashReport, ApiError> {
+        let min_similarity = duplicate_floor(min_similarity)?;
+        let report = duplicates::trash_duplicates(&self.db, selections, min_similarity)?;
+        if !report.trashed.is_empty() {
+            self.invalidate_caches();
+        }
+        Ok(report)
+    }
+
+    // ---- Boards -------------------------------------------------------
+
+    /// Re-cluster the library into visual boards — see `clusters::run`.
+    /// `encoder_id` defaults to `clusters::DEFAULT_ENCODER`, `k` to
+    /// `clusters::default_k` of the library size.
+    pub fn run_clustering(
+        &self,
+        encoder_id: Option<&str>,
+        k: Option<usize>,
+    ) -> Result<Vec<Cluster>, ApiError> {
+        let encoder_id = encoder_id.unwrap_or(clusters::DEFAULT_ENCODER);
+        if !settings::DEFAULT_ENABLED_ENCODERS.contains(&encoder_id) {
+            return Err(ApiError::BadInput(format!("unknown encoder '{encoder_id}'")));
+        }
+        if k.is_some_and(|k| k == 0 || k > clusters::MAX_CLUSTERS) {
+            return Err(ApiError::BadInput(format!(
+                "k must be between 1 and {}",
+                clusters::MAX_CLUSTERS
+            )));
+        }
+        clusters::run(&self.db, &self.text_encoders, encoder_id, k)
+    }
+
+    /// The boards of the last clustering run, largest first.
+    pub fn clusters(&self) -> Result<Vec<Cluster>, ApiError> {
+        Ok(self.db.list_clusters()?)
+    }
+
+    /// A page of a board's images, closest to its centroid first; the
+    /// score is that cosine.
+    pub fn cluster_images(
+        &self,
+        cluster_id: i64,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<ImageSearchResult>, ApiError> {
+        self.cluster(cluster_id)?;
+        Ok(self
+            .db
+            .get_cluster_members(cluster_id, limit, offset)?
+            .into_iter()
+            .map(|m| ImageSearchResult {
+                id: m.image_id,
+                path: m.path,
+                score: m.similarity,
+                thumbnail_path: m.thumbnail_path,
+                width: m.width,
+                height: m.height,
+            })
+            .collect())
+    }
+
+    /// Tag every image of a board with `name` — by default the board's
+    /// proposed label — creating the tag if needed. Undoable.
+    pub fn cluster_to_tag(&self, cluster_id: i64, name: Option<&str>) -> Result<Tag, ApiError> {
+        let cluster = self.cluster(cluster_id)?;
+        let name = match name.map(str::trim) {
+            Some("") => return Err(ApiError::BadInput("tag name is empty".into())),
+            Some(name) => name.to_string(),
+            None => cluster
+                .label
+                .unwrap_or_else(|| format!("board {cluster_id}")),
+        };
+        let (tag, tagged) = self.db.tag_cluster(cluster_id, &name)?;
+        info!("board {cluster_id}: tagged {tagged} image(s) '{}'", tag.name);
+        Ok(tag)
+    }
+
+    fn cluster(&self, cluster_id: i64) -> Result<Cluster, ApiError> {
+        match self.db.get_cluster(cluster_id) {
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                Err(ApiError::NotFound(format!("board {cluster_id}")))
+            }
+            other => Ok(other?),
+        }
+    }
+
+    // ---- Roots --------------------------------------------------------
+
+    /// Replace every configured root with `path` and restart indexing.
+    /// The tag catalogue is preserved.
+    pub fn replace_roots<S: ProgressSink + 'static>(
+        self: &Arc<Self>,
+        path: &str,
+        sink: S,
+    ) -> Result<(), ApiError> {
+        if !Path::new(path).is_dir() {
+            return Err(ApiError::BadInput(format!("Not a directory: {path}")));
+        }
+
+        // Purge existing roots (CASCADE deletes their images), wipe any
+        // orphan rows (NULL root_id from older DBs), then add the new one.
+        // A reset isn't a misclick: nothing is kept to restore.
+        for r in self.db.list_roots()? {
+            self.db.purge_root(r.id)?;
+        }
+        self.db.wipe_images_for_new_root()?;
+        self.db.add_root(path.to_string())?;
+
+        // Both caches hold entries from the now-removed roots.
+        self.invalidate_caches();
+        // Cancel-and-restart: an in-flight run is walking the old roots.
+        self.restart_indexing(sink);
+        Ok(())
+    }
+
+    /// Add a root and restart indexing so it gets scanned. Re-adding a
+    /// removed root's path restores it with its tags, notes, thumbnails
+    /// and embeddings; the run then only picks up what changed on disk.
+    pub fn add_root<S: ProgressSink + 'static>(
+        self: &Arc<Self>,
+        path: String,
+        sink: S,
+    ) -> Result<Root, ApiError> {
+        if !Path::new(&path).is_dir() {
+            return Err(ApiError::BadInput(format!("Not a directory: {path}")));
+        }
+        let root = self.db.add_root(path)?;
+        // Restored or adopted images are visible again.
+        self.invalidate_caches();
+        self.restart_indexing(sink);
+        Ok(root)
+    }
+
+    /// Remove a root: a soft delete that hides it and its images (see
+    /// `db::roots`). Adding the path again, or undoing, restores them
+    /// as they were until `purge_removed_roots` drops them for good. An
+    /// in-flight run is restarted, since it may be thumbnailing or
+    /// encoding the removed root's files.
+    pub fn remove_root<S: ProgressSink + 'static>(
+        self: &Arc<Self>,
+        id: i64,
+        sink: S,
+    ) -> Result<(), ApiError> {
+        let root = self
+            .db
+            .list_roots()?
+            .into_iter()
+            .find(|r| r.id == id)
+            .ok_or_else(|| ApiError::NotFound(format!("root {id}")))?;
+        self.db.journaled(
+            &format!("Remove folder {}", root.path),
+            vec![JournalOp::RemoveRoot { id }],
+        )?;
+        if self.indexing.is_running.load(Ordering::SeqCst) {
+            self.restart_indexing(sink);
+        }
+        // Cheapest way to drop the removed root's cache entries is to
+        // drop the caches and let the next query repopulate.
+        self.invalidate_caches();
+        Ok(())
+    }
+
+    /// Toggle a root's enabled flag. Enabling also restarts indexing:
+    /// the root may never have been scanned, or files changed while it
+    /// was off (the pipeline skips disabled roots).
+    pub fn set_root_enabled<S: ProgressSink + 'static>(
+        self: &Arc<Self>,
+        id: i64,
+        enabled: bool,
+        sink: S,
+    ) -> Result<(), ApiError> {
+        self.db.set_root_enabled(id, enabled)?;
+        self.invalidate_caches();
+        if enabled {
+            self.restart_indexing(sink);
+        }
+        Ok(())
+    }
+
+    // ---- Indexing -----------------------------------------------------
+
+    /// Start a background full run, or queue one onto the run in flight.
+    pub fn spawn_indexing<S: ProgressSink + 'static>(self: &Arc<Self>, sink: S) -> SpawnOutcome {
+        indexing::try_spawn_pipeline(
+            self.sink(sink),
+            self.indexing.clone(),
+            self.cosine.db_path.clone(),
+            self.cosine.index.clone(),
+            self.cosine.current_encoder_id.clone(),
+        )
+    }
+
+    /// Cancel the run in flight (if any) and start a fresh full run.
+    pub fn restart_indexing<S: ProgressSink + 'static>(self: &Arc<Self>, sink: S) -> SpawnOutcome {
+        indexing::restart_pipeline(
+            self.sink(sink),
+            self.indexing.clone(),
+            self.cosine.db_path.clone(),
+            self.cosine.index.clone(),
+            self.cosine.current_encoder_id.clone(),
+        )
+    }
+
+    /// Stop the run in flight at its next checkpoint and drop any
+    /// queued follow-up. Returns whether a run was in flight.
+    pub fn cancel_indexing(&self) -> bool {
+        self.indexing.request_cancel()
+    }
+
+    /// One full run on the calling thread — see `indexing::run_pipeline`.
+    /// The caches this library keeps warm are left alone; a foreground
+    /// caller is about to exit or will query cold anyway.
+    pub fn run_indexing(
+        &self,
+        sink: &dyn ProgressSink,
+        cancel: &CancelToken,
+    ) -> Result<(), Box<dyn Error>> {
+        indexing::run_pipeline(
+            sink,
+            cancel,
+            &self.cosine.db_path,
+            &self.cosine.index,
+            &self.cosine.current_encoder_id,
+        )
+    }
+
+    /// Watch every enabled root; changes trigger incremental runs
+    /// reported to `sink`. A root whose folder is offline is watched
+    /// once it comes back, and a root added or enabled later by the
+    /// next probe. `None` if the platform backend failed to start.
+    pub fn start_watcher<S: ProgressSink + Clone + 'static>(
+        self: &Arc<Self>,
+        sink: S,
+    ) -> Option<WatcherHandle> {
+        let watch_paths: Vec<PathBuf> = self
+            .db
+            .list_roots()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|r| r.enabled)
+            .map(|r| PathBuf::from(r.path))
+            .collect();
+        watcher::start(
+            self.sink(sink),
+            watch_paths,
+            self.cosine.db_path.clone(),
+            self.indexing.clone(),
+            self.cosine.index.clone(),
+            self.cosine.current_encoder_id.clone(),
+        )
+    }
+
+    fn sink<S: ProgressSink>(self: &Arc<Self>, host: S) -> LibrarySink<S> {
+        LibrarySink {
+            library: self.clone(),
+            host,
+        }
+    }
+
+    // ---- Startup ------------------------------------------------------
+
+    /// One-shot legacy migration: a single-folder build kept its folder
+    /// in settings.json's `scan_root`. Move it into the `roots` table
+    /// (which is all the pipeline reads) and clear the field so it
+    /// doesn't re-migrate on every launch.
+    pub fn migrate_legacy_scan_root(&self) {
+        let user_settings = settings::Settings::load();
+        let Some(legacy_path) = user_settings.scan_root.clone() else {
+            return;
+        };
+        match self
+            .db
+            .migrate_legacy_scan_root(legacy_path.to_string_lossy().into_owned())
+        {
+            Ok(Some(root)) => {
+                info!(
+                    "migrated legacy scan_root -> roots[{}] ({})",
+                    root.id, root.path
+                );
+                let mut s = user_settings;
+                s.scan_root = None;
+                let _ = s.save();
+            }
+            Ok(None) => {} // already migrated previously
+            Err(e) => warn!("legacy migration failed: {e}"),
+        }
+    }
+
+    /// Purge roots removed more than `REMOVED_ROOT_RETENTION_SECS` ago:
+    /// their rows (CASCADE) and thumbnail folders. Run once at startup.
+    pub fn purge_removed_roots(&self) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let ids = match self.db.purge_removed_roots(now - REMOVED_ROOT_RETENTION_SECS) {
+            Ok(ids) => ids,
+            Err(e) => {
+                warn!("purging removed roots failed: {e}");
+                return;
+            }
+        };
+        for id in ids {
+            info!("purged root {id}, removed over the retention window ago");
+            // Best-effort — if the remove fails (permissions, file
+            // locked) we log and move on; the user can clean the
+            // directory by hand.
+            let thumbnail_dir = paths::thumbnails_dir_for_root(id);
+            if thumbnail_dir.exists() {
+                if let Err(e) = std::fs::remove_dir_all(&thumbnail_dir) {
+                    warn!(
+                        "could not remove thumbnail dir {}: {e}",
+                        thumbnail_dir.display()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Startup diagnostics for the profiling report: what's on disk and
+    /// already encoded, plus a cosine-math sanity check. No-op unless
+    /// profiling is enabled.
+    pub fn record_startup_diagnostics(&self) {
+        if !perf::is_profiling_enabled() {
+            return;
+        }
+
+        // Snapshot of what's on disk + what's already encoded. Lets the
+        // on-exit report's Diagnostics section show "this session
+        // started with X CLIP embeddings, Y SigLIP-2, Z DINOv2" — very
+        // useful for the "I selected DINOv2 but got 0 results" bug
+        // class.
+        let stats = self.db.get_pipeline_stats().ok();
+        let models_dir = paths::models_dir();
+        let model_files: Vec<String> = std::fs::read_dir(&models_dir)
+            .ok()
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok())
+                    .map(|e| e.file_name().to_string_lossy().into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        perf::record_diagnostic(
+            "startup_state",
+            serde_json::json!({
+                "db_path": self.db_path(),
+                "models_dir": models_dir.display().to_string(),
+                "model_files_present": model_files,
+                "embedding_counts_per_encoder": stats.as_ref().map(|s| {
+                    s.with_embedding_per_encoder.iter().map(|e| {
+                        serde_json::json!({
+                            "encoder_id": e.encoder_id,
+                            "count": e.count,
+                        })
+                    }).collect::<Vec<_>>()
+                }),
+                "total_images": stats.as_ref().map(|s| s.total_images),
+                "with_thumbnail": stats.as_ref().map(|s| s.with_thumbnail),
+                "orphaned": stats.as_ref().map(|s| s.orphaned),
+            }),
+        );
+
+        // Cosine math sanity check — synthetic vectors with known
+        // expected outputs. If this ever returns a surprising number,
+        // EVERY semantic-search / similarity result downstream is
+        // suspect because the math itself is broken. Cheap (~µs).
+        {
+            use crate::similarity_and_semantic_search::cosine::CosineIndex;
+            use ndarray::Array1;
+            let a = Array1::from_vec(vec![1.0_f32, 0.0, 0.0]);
+            let b = Array1::from_vec(vec![0.0_f32, 1.0, 0.0]);
+            let c = Array1::from_vec(vec![1.0_f32, 0.0, 0.0]);
+            let d = Array1::from_vec(vec![-1.0_f32, 0.0, 0.0]);
+            let zero = Array1::from_vec(vec![0.0_f32, 0.0, 0.0]);
+            let high_dim_a: Array1<f32> = Array1::from_vec((0..512).map(|i| (i as f32).sin()).collect());
+            let high_dim_b: Array1<f32> = Array1::from_vec((0..512).map(|i| (i as f32).cos()).collect());
+
+            let orthogonal = CosineIndex::cosine_similarity(&a, &b);
+            let parallel = CosineIndex::cosine_similarity(&a, &c);
+            let opposite = CosineIndex::cosine_similarity(&a, &d);
+            let zero_vec = CosineIndex::cosine_similarity(&a, &zero);
+            let dim_mismatch = CosineIndex::cosine_similarity(&a, &high_dim_a);
+            let high_dim_random = CosineIndex::cosine_similarity(&high_dim_a, &high_dim_b);
+
+            perf::record_diagnostic(
+                "cosine_math_sanity",
+                serde_json::json!({
+                    "orthogonal_3d":   { "got": orthogonal,    "expected": 0.0,  "passes": orthogonal.abs() < 1e-5 },
+                    "parallel_3d":     { "got": parallel,      "expected": 1.0,  "passes": (parallel - 1.0).abs() < 1e-5 },
+                    "opposite_3d":     { "got": opposite,      "expected": -1.0, "passes": (opposite + 1.0).abs() < 1e-5 },
+                    "zero_vector_3d":  { "got": zero_vec,      "expected": 0.0,  "passes": zero_vec.abs() < 1e-5 },
+                    "dim_mismatch":    { "got": dim_mismatch,  "expected": 0.0,  "passes": dim_mismatch.abs() < 1e-5, "note": "3-d vs 512-d should return 0 via guard, not panic" },
+                    "high_dim_random": { "got": high_dim_random, "expected_range": "[-0.1, 0.1] for sin/cos quasi-orthogonal", "passes": high_dim_random.abs() < 0.2 },
+                    "interpretation": "All passes=true means cosine math is correct — bad search results are an encoder/data issue, not math.",
+                }),
+            );
+        }
+    }
+}
+
+/// "1 image", "3 images" — for journal labels.
+fn count_images(n: usize) -> String {
+    if n == 1 {
+        "1 image".into()
+    } else {
+        format!("{n} images")
+    }
+}
+
+/// Images a journaled (un)tagging actually changed.
+fn images_touched(applied: &[JournalOp]) -> usize {
+    applied
+        .iter()
+        .map(|op| match op {
+            JournalOp::TagImages { image_ids, .. } | JournalOp::UntagImages { image_ids, .. } => {
+                image_ids.len()
+            }
+            _ => 0,
+        })
+        .sum()
+}
+
+/// The near-duplicate cosine floor: `DEFAULT_MIN_SIMILARITY` unless
+/// given, and then it must be in (0, 1].
+fn duplicate_floor(min_similarity: Option<f32>) -> Result<f32, ApiError> {
+    let min_similarity = min_similarity.unwrap_or(duplicates::DEFAULT_MIN_SIMILARITY);
+    if !(min_similarity > 0.0 && min_similarity <= 1.0) {
+        return Err(ApiError::BadInput(format!(
+            "min_similarity must be in (0, 1], got {min_similarity}"
+        )));
+    }
+    Ok(min_similarity)
+}
+
+fn tag_name_taken(name: &str) -> String {
+    format!("a tag named \"{name}\" already exists")
+}
+
+/// Trim templates, drop blank ones, and require the `{}` placeholder.
+fn clean_templates(templates: Vec<String>) -> Result<Vec<String>, ApiError> {
+    let templates: Vec<String> = templates
+        .into_iter()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+    if let Some(t) = templates.iter().find(|t| !t.contains("{}")) {
+        return Err(ApiError::BadInput(format!(
+            "template '{t}' has no {{}} placeholder for the label"
+        )));
+    }
+    Ok(templates)
+}
+
+/// The host's sink, plus this library's caches for the pipeline to
+/// warm (full runs) and invalidate (incremental runs).
+struct LibrarySink<S> {
+    library: Arc<Library>,
+    host: S,
+}
+
+impl<S: Clone> Clone for LibrarySink<S> {
+    fn clone(&self) -> Self {
+        Self {
+            library: self.library.clone(),
+            host: self.host.clone(),
+        }
+    }
+}
+
+impl<S: ProgressSink> ProgressSink for LibrarySink<S> {
+    fn report(&self, progress: &IndexingProgress) {
+        self.host.report(progress);
+    }
+
+    fn fusion_state(&self) -> Option<&FusionIndexState> {
+        Some(&self.library.fusion)
+    }
+
+    fn text_encoders(&self) -> Option<&TextEncoderState> {
+        Some(&self.library.text_encoders)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl ProgressSink for Recorder {
+        fn report(&self, progress: &IndexingProgress) {
+            self.0.lock().unwrap().push(format!("{:?}", progress.phase));
+        }
+    }
+
+    fn temp_library() -> (tempfile::TempDir, Arc<Library>) {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("library.db");
+        let library = Library::open(db_path.to_string_lossy().into_owned()).unwrap();
+        (dir, Arc::new(library))
+    }
+
+    #[test]
+    fn open_creates_an_empty_catalogue() {
+        let (_dir, library) = temp_library();
+        assert!(library.db().list_roots().unwrap().is_empty());
+        assert!(library.db().get_tags().unwrap().is_empty());
+        assert!(!library.indexing_state().is_running.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn add_root_rejects_a_missing_directory() {
+        let (dir, library) = temp_library();
+        let missing = dir.path().join("nope").to_string_lossy().into_owned();
+        match library.add_root(missing, Recorder::default()) {
+            Err(ApiError::BadInput(msg)) => assert!(msg.contains("nope")),
+            other => panic!("expected BadInput, got {other:?}"),
+        }
+        assert!(library.db().list_roots().unwrap().is_empty());
+    }
+
+    #[test]
+    fn tag_labels_are_validated() {
+        let (_dir, library) = temp_library();
+        let label = library
+            .add_tag_label("  beach ", vec!["a photo of a {} ".into(), " ".into()])
+            .unwrap();
+        assert_eq!(label.name, "beach");
+        assert_eq!(label.templates, vec!["a photo of a {}".to_string()]);
+
+        assert!(matches!(library.add_tag_label("beach", vec![]), Err(ApiError::BadInput(_))));
+        assert!(matches!(library.add_tag_label(" ", vec![]), Err(ApiError::BadInput(_))));
+        assert!(matches!(
+            library.add_tag_label("dog", vec!["a photo".into()]),
+            Err(ApiError::BadInput(_))
+        ));
+        assert!(matches!(
+            library.update_tag_label(label.id, vec![], Some(f32::NAN)),
+            Err(ApiError::BadInput(_))
+        ));
+        assert!(matches!(
+            library.update_tag_label(label.id + 1, vec![], None),
+            Err(ApiError::NotFound(_))
+        ));
+        assert!(matches!(
+            library.accept_tag_suggestions(label.id + 1, None),
+            Err(ApiError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn tag_names_stay_unique_across_tags_and_aliases() {
+        let (_dir, library) = temp_library();
+        let cat = library.create_tag("cat", "#fff", None).unwrap();
+        let dog = library.create_tag("dog", "#000", None).unwrap();
+        library.add_tag_alias(cat.id, "kitty").unwrap();
+
+        for taken in ["dog", "kitty"] {
+            assert!(matches!(library.create_tag(taken, "#fff", None), Err(ApiError::BadInput(_))));
+            assert!(matches!(library.add_tag_alias(dog.id, taken), Err(ApiError::BadInput(_))));
+        }
+        assert!(matches!(library.rename_tag(cat.id, " dog "), Err(ApiError::BadInput(_))));
+        assert!(matches!(library.rename_tag(dog.id, "kitty"), Err(ApiError::BadInput(_))));
+        assert_eq!(library.rename_tag(cat.id, "kitty").unwrap().name, "kitty");
+        assert!(matches!(library.rename_tag(999, "fox"), Err(ApiError::NotFound(_))));
+        assert!(matches!(library.remove_tag_alias("kitty"), Err(ApiError::NotFound(_))));
+        assert!(matches!(library.add_tag_to_images(999, &[1]), Err(ApiError::NotFound(_))));
+    }
+
+    #[test]
+    fn tag_edits_are_undone_and_redone() {
+        let (_dir, library) = temp_library();
+        let cat = library.create_tag("cat", "#fff", None).unwrap();
+        library.delete_tag(cat.id).unwrap();
+        assert!(matches!(library.delete_tag(cat.id), Err(ApiError::NotFound(_))));
+
+        let undone = library.undo(Recorder::default()).unwrap().unwrap();
+        assert_eq!(undone.label, "Delete tag \"cat\"");
+        assert!(undone.undone);
+        assert_eq!(library.db().get_tag(cat.id).unwrap().name, "cat");
+        let redone = library.redo(Recorder::default()).unwrap().unwrap();
+        assert_eq!(redone.id, undone.id);
+        assert!(library.db().get_tags().unwrap().is_empty());
+        assert!(library.redo(Recorder::default()).unwrap().is_none());
+        assert_eq!(library.journal().unwrap().len(), 2);
+
+        assert!(matches!(
+            library.remove_root(7, Recorder::default()),
+            Err(ApiError::NotFound(_))
+        ));
+        assert!(matches!(library.set_image_notes(7, "x"), Err(ApiError::NotFound(_))));
+    }
+
+    #[test]
+    fn board_requests_are_validated() {
+        let (_dir, library) = temp_library();
+        assert!(matches!(
+            library.run_clustering(Some("resnet50"), None),
+            Err(ApiError::BadInput(_))
+        ));
+        assert!(matches!(library.run_clustering(None, Some(0)), Err(ApiError::BadInput(_))));
+        // Nothing encoded yet.
+        assert!(matches!(library.run_clustering(None, None), Err(ApiError::BadInput(_))));
+        assert!(matches!(library.cluster_images(1, 10, 0), Err(ApiError::NotFound(_))));
+        assert!(matches!(library.cluster_to_tag(1, None), Err(ApiError::NotFound(_))));
+    }
+
+    #[test]
+    fn sink_lends_the_library_caches_to_the_pipeline() {
+        let (_dir, library) = temp_library();
+        let recorder = Recorder::default();
+        let sink = library.sink(recorder.clone());
+        assert!(std::ptr::eq(sink.fusion_state().unwrap(), library.fusion()));
+        assert!(std::ptr::eq(
+            sink.text_encoders().unwrap(),
+            library.text_encoders()
+        ));
+        sink.report(&IndexingProgress {
+            phase: indexing::Phase::Ready,
+            processed: 0,
+            total: 0,
+            message: None,
+        });
+        assert_eq!(*recorder.0.lock().unwrap(), vec!["Ready".to_string()]);
+    }
+}
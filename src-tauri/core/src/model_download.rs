@@ -87,7 +87,7 @@ pub const CLIP_TOKENIZER_FILENAME: &str = "clip_tokenizer.json";
 /// downloads in the current call; `current_file` is the filename
 /// being fetched right now (or `None` if no file-specific work is
 /// happening, e.g. during the HEAD-request preflight).
-pub type ProgressFn = dyn Fn(u64, u64, Option<&str>) + Send + Sync;
+pub type ProgressFn<'a> = dyn Fn(u64, u64, Option<&str>) + Send + Sync + 'a;
 
 /// Download every model file that is missing from `paths::models_dir()`.
 /// Already-present files are left alone.
@@ -99,7 +99,7 @@ pub type ProgressFn = dyn Fn(u64, u64, Option<&str>) + Send + Sync;
 #[tracing::instrument(name = "model_download.all", skip(progress))]
 pub fn download_models_if_missing<F>(progress: F) -> Result<(), Box<dyn Error>>
 where
-    F: Fn(u64, u64, Option<&str>) + Send + Sync + 'static,
+    F: Fn(u64, u64, Option<&str>) + Send + Sync,
 {
     let models_dir = paths::models_dir();
 
@@ -248,7 +248,7 @@ fn download_to_file(
     dest: &Path,
     bytes_so_far: &mut u64,
     total_bytes: u64,
-    progress: &ProgressFn,
+    progress: &ProgressFn<'_>,
 ) -> Result<(), Box<dyn Error>> {
     let filename = dest
         .file_name()
@@ -8,7 +8,8 @@
 //! <platform_data_dir>/com.ataca.image-browser/
 //!   images.db
 //!   settings.json
-//!   cosine_cache.bin
+//!   cosine_cache_<encoder_id>.bin
+//!   ann_<encoder_id>.bin
 //!   models/
 //!     model_image.onnx
 //!     model_text.onnx
@@ -151,18 +152,39 @@ pub fn settings_path() -> PathBuf {
     app_data_dir().join("settings.json")
 }
 
-/// Path to the on-disk cached cosine index (a bincode-encoded
-/// Vec<(PathBuf, Vec<f32>)>). Loaded eagerly at app startup if it
-/// exists and is fresher than the SQLite DB; populated again from
-/// the DB whenever the indexing pipeline finishes encoding.
+/// Path to the on-disk cosine cache for one encoder: a fixed header
+/// (encoder id, dim, row count, pipeline version, embedding-set stamp,
+/// checksum), the path table, then a row-major f32 matrix that is
+/// mmap'd on load (see `cosine/cache.rs`). Rewritten by the indexing
+/// pipeline whenever that encoder's embeddings change.
 ///
 /// Lives in app_data_dir rather than in the DB itself because the
 /// embedding BLOB column already holds the canonical data — the cache
 /// just speeds up the load path.
-pub fn cosine_cache_path() -> PathBuf {
+pub fn cosine_cache_path(encoder_id: &str) -> PathBuf {
+    app_data_dir().join(format!("cosine_cache_{encoder_id}.bin"))
+}
+
+/// The single encoder-agnostic cache file earlier versions wrote.
+/// Only referenced to delete it.
+pub fn legacy_cosine_cache_path() -> PathBuf {
     app_data_dir().join("cosine_cache.bin")
 }
 
+/// Path to the persisted HNSW graph for one encoder (bincode-encoded
+/// `HnswGraph`). Written by the indexing pipeline once an encoder has
+/// `ANN_MIN_IMAGES` embeddings; read whenever that encoder's cosine
+/// cache is populated. Links only — the vectors come from the DB.
+pub fn ann_graph_path(encoder_id: &str) -> PathBuf {
+    app_data_dir().join(format!("ann_{encoder_id}.bin"))
+}
+
+/// Bearer token the HTTP API (`http_api.rs`) checks. Generated on
+/// first use; other local tools read it from here.
+pub fn http_api_token_path() -> PathBuf {
+    app_data_dir().join("http_api_token")
+}
+
 /// User-facing exports directory. Anything the user might want to
 /// share, archive, or compare goes here:
 ///   - perf-<unix-ts>/ — profiling sessions written by perf_report
@@ -220,7 +242,7 @@ mod tests {
     #[test]
     fn thumbnails_dir_for_root_creates_subfolder() {
         // Phase 9 reorganisation — each root gets its own thumbnail
-        // subfolder so remove_root can rm -rf it cleanly.
+        // subfolder so purging a removed root can rm -rf it cleanly.
         let dir = thumbnails_dir_for_root(42);
         assert!(dir.exists(), "thumbnails_dir_for_root should create the dir");
         assert_eq!(
@@ -231,12 +253,13 @@ mod tests {
 
     #[test]
     fn cosine_cache_path_is_under_app_data_dir() {
-        let cache = cosine_cache_path();
+        let cache = cosine_cache_path("dinov2_base");
         assert!(cache.starts_with(app_data_dir()));
         assert_eq!(
             cache.file_name().and_then(|s| s.to_str()),
-            Some("cosine_cache.bin")
+            Some("cosine_cache_dinov2_base.bin")
         );
+        assert_ne!(cache, cosine_cache_path("clip_vit_b_32"));
     }
 
     #[test]
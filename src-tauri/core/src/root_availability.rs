@@ -0,0 +1,101 @@
+//! Offline roots: folders whose drive or network share isn't there.
+//!
+//! An unmounted root scans as empty, and an empty alive set would make
+//! orphan detection mark every image in it orphaned — tags, notes and
+//! embeddings then sit waiting for the 30-day cleanup while the user
+//! just has the USB drive in a drawer. So a root whose folder itself is
+//! missing is flagged `offline` instead and left out of scanning and
+//! orphan marking. Its images stay in the grid with their cached
+//! thumbnails and an offline badge; thumbnail and embedding work for
+//! them waits until the folder is back.
+//!
+//! Missing means the root path isn't a directory. A file deleted inside
+//! a mounted root is still an orphan as before.
+//!
+//! Checked at the start of every full pipeline run and by the watcher's
+//! probe thread, which re-watches and rescans a root that reappears.
+//! The incremental path flags a root offline when one of its events
+//! turns out to be the whole folder going away.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use tracing::{info, warn};
+
+use crate::db::{ImageDatabase, ID};
+use crate::root_struct::Root;
+
+/// Which roots are offline after a check, and which changed.
+#[derive(Debug, Default)]
+pub struct Availability {
+    /// Roots whose folder is missing right now.
+    pub offline: HashSet<ID>,
+    pub went_offline: Vec<ID>,
+    pub came_online: Vec<ID>,
+}
+
+impl Availability {
+    pub fn changed(&self) -> bool {
+        !self.went_offline.is_empty() || !self.came_online.is_empty()
+    }
+}
+
+/// Whether the root's folder is there to be scanned.
+pub fn is_available(root: &Root) -> bool {
+    Path::new(&root.path).is_dir()
+}
+
+/// Check every root's folder and bring the `offline` flags up to date.
+pub fn refresh(database: &ImageDatabase, roots: &[Root]) -> rusqlite::Result<Availability> {
+    let mut availability = Availability::default();
+    for root in roots {
+        let offline = !is_available(root);
+        if offline {
+            availability.offline.insert(root.id);
+        }
+        if !database.set_root_offline(root.id, offline)? {
+            continue;
+        }
+        if offline {
+            warn!("root {} is offline; keeping its images", root.path);
+            availability.went_offline.push(root.id);
+        } else {
+            info!("root {} is back online", root.path);
+            availability.came_online.push(root.id);
+        }
+    }
+    Ok(availability)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh_flags_missing_folders_and_reports_transitions() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = ImageDatabase::new(":memory:").unwrap();
+        db.initialize().unwrap();
+        db.add_root(dir.path().to_string_lossy().into_owned())
+            .unwrap();
+        let gone = db
+            .add_root(dir.path().join("unmounted").to_string_lossy().into_owned())
+            .unwrap();
+
+        let first = refresh(&db, &db.list_roots().unwrap()).unwrap();
+        assert_eq!(first.offline, HashSet::from([gone.id]));
+        assert_eq!(first.went_offline, vec![gone.id]);
+        assert!(first.came_online.is_empty());
+
+        // Nothing changed: still offline, no transition.
+        let second = refresh(&db, &db.list_roots().unwrap()).unwrap();
+        assert_eq!(second.offline, HashSet::from([gone.id]));
+        assert!(!second.changed());
+
+        std::fs::create_dir(dir.path().join("unmounted")).unwrap();
+        let third = refresh(&db, &db.list_roots().unwrap()).unwrap();
+        assert!(third.offline.is_empty());
+        assert_eq!(third.came_online, vec![gone.id]);
+        assert!(db.list_roots().unwrap().iter().all(|r| !r.offline));
+    }
+}
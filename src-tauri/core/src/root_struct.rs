@@ -13,6 +13,10 @@ pub struct Root {
     pub enabled: bool,
     /// Unix epoch seconds. Useful for "Recently added" sort order.
     pub added_at: i64,
+    /// The folder was missing at the last check (unmounted drive, share
+    /// down). Its images stay indexed and browsable from thumbnails.
+    #[serde(default)]
+    pub offline: bool,
 }
 
 impl Root {
@@ -22,6 +26,7 @@ impl Root {
             path,
             enabled,
             added_at,
+            offline: false,
         }
     }
 }
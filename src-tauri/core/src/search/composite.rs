@@ -0,0 +1,338 @@
+//! Composite queries: several weighted text and image terms searched
+//! as one.
+//!
+//! "Like this image, but at night, no people" is three terms — an
+//! image, a phrase, and a phrase with a negative weight. In each
+//! enabled encoder the terms' unit embeddings are summed with their
+//! weights,
+//!
+//! ```text
+//! q = Σ wᵢ · unit(termᵢ)
+//! ```
+//!
+//! and that encoder's cache is ranked against `unit(q)`. The per-encoder
+//! lists are fused with `reciprocal_rank_fusion`, as in the one-shot
+//! searches.
+//!
+//! - Text terms are encoded by each text-capable encoder (CLIP,
+//!   SigLIP-2). An encoder that fails on any phrase sits the query out
+//!   rather than searching a partial blend.
+//! - DINOv2 has no text branch, so it blends only the image terms and
+//!   sits out a text-only query.
+//! - An encoder needs at least one positive term it can represent; a
+//!   blend of negatives alone points nowhere useful.
+//! - Example images never appear in the results.
+//! - A `SearchFilter` narrows every encoder's cache before its top-K,
+//!   as in the one-shot searches.
+
+use std::time::Instant;
+
+use ndarray::Array1;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::semantic::encode_text;
+use super::semantic_fused::TEXT_CAPABLE_ENCODERS;
+use super::{allowed_paths, resolve_fused, unit, ImageSearchResult};
+use crate::db::{ImageDatabase, SearchFilter, ID};
+use crate::error::ApiError;
+use crate::perf;
+use crate::similarity_and_semantic_search::cosine::rrf::{
+    reciprocal_rank_fusion, RankedList, DEFAULT_K_RRF,
+};
+use crate::{FusionIndexState, TextEncoderState};
+
+/// One term of a composite query. A negative `weight` steers away from
+/// the term: `{"kind": "text", "text": "people", "weight": -1}` is
+/// "no people".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QueryTerm {
+    Text {
+        text: String,
+        #[serde(default = "default_weight")]
+        weight: f32,
+    },
+    Image {
+        image_id: ID,
+        #[serde(default = "default_weight")]
+        weight: f32,
+    },
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+impl QueryTerm {
+    pub fn weight(&self) -> f32 {
+        match self {
+            QueryTerm::Text { weight, .. } | QueryTerm::Image { weight, .. } => *weight,
+        }
+    }
+}
+
+/// Search the library for the weighted blend of `terms`, fused across
+/// every enabled encoder.
+pub fn composite_search(
+    db: &ImageDatabase,
+    fusion_state: &FusionIndexState,
+    text_encoders: &TextEncoderState,
+    terms: &[QueryTerm],
+    top_n: usize,
+    filter: Option<&SearchFilter>,
+) -> Result<Vec<ImageSearchResult>, ApiError> {
+    validate(db, terms)?;
+    let started = Instant::now();
+    let examples: Vec<ID> = terms
+        .iter()
+        .filter_map(|t| match t {
+            QueryTerm::Image { image_id, .. } => Some(*image_id),
+            QueryTerm::Text { .. } => None,
+        })
+        .collect();
+    // Over-fetch by the example images so dropping them still fills
+    // the page.
+    let depth = top_n.saturating_mul(5).max(50) + examples.len();
+    let allowed = allowed_paths(db, filter)?;
+
+    let enabled = crate::settings::Settings::load().resolved_enabled_encoders();
+    let mut ranked_lists: Vec<RankedList> = Vec::with_capacity(enabled.len());
+    let mut per_encoder_diag: Vec<serde_json::Value> = Vec::new();
+    for enc in &enabled {
+        let parts = match encoder_parts(db, text_encoders, terms, enc) {
+            Ok(parts) => parts,
+            Err(e) => {
+                warn!("composite search: {enc} sits out: {e}");
+                per_encoder_diag.push(serde_json::json!({
+                    "encoder_id": enc,
+                    "status": "encode_failed",
+                    "error": e.to_string(),
+                }));
+                continue;
+            }
+        };
+        let Some(query) = blend(&parts) else {
+            per_encoder_diag.push(serde_json::json!({
+                "encoder_id": enc,
+                "status": "no_positive_term",
+            }));
+            continue;
+        };
+        let items = fusion_state
+            .ranked_for_encoder(
+                db,
+                enc,
+                &Array1::from_vec(query),
+                depth,
+                None,
+                allowed.as_ref(),
+            )
+            .map_err(ApiError::Cosine)?;
+        per_encoder_diag.push(serde_json::json!({
+            "encoder_id": enc,
+            "status": "ok",
+            "terms_blended": parts.len(),
+            "ranked_count": items.len(),
+        }));
+        if !items.is_empty() {
+            ranked_lists.push(RankedList {
+                encoder_id: enc.clone(),
+                items,
+            });
+        }
+    }
+
+    if ranked_lists.is_empty() {
+        info!("composite search: no encoder produced a ranked list — returning empty");
+        return Ok(Vec::new());
+    }
+    let fused = reciprocal_rank_fusion(&ranked_lists, DEFAULT_K_RRF, top_n + examples.len());
+    let results = resolve_fused(db, &fused, top_n, |id| examples.contains(&id))?;
+
+    perf::record_diagnostic(
+        "search_query",
+        serde_json::json!({
+            "type": "composite",
+            "top_n": top_n,
+            "per_encoder_top_k": depth,
+            "k_rrf": DEFAULT_K_RRF,
+            "terms": terms,
+            "filter": filter,
+            "per_encoder": per_encoder_diag,
+            "resolved_count": results.len(),
+            "total_elapsed_ms": started.elapsed().as_millis() as u64,
+        }),
+    );
+    Ok(results)
+}
+
+/// Reject queries that can't mean anything: no terms, blank phrases,
+/// zero or non-finite weights, nothing positive, unknown images.
+fn validate(db: &ImageDatabase, terms: &[QueryTerm]) -> Result<(), ApiError> {
+    if terms.is_empty() {
+        return Err(ApiError::BadInput(
+            "a composite query needs at least one term".into(),
+        ));
+    }
+    for term in terms {
+        let weight = term.weight();
+        if !weight.is_finite() || weight == 0.0 {
+            return Err(ApiError::BadInput(format!(
+                "term weights must be non-zero numbers, got {weight}"
+            )));
+        }
+        match term {
+            QueryTerm::Text { text, .. } if text.trim().is_empty() => {
+                return Err(ApiError::BadInput("a text term is empty".into()));
+            }
+            QueryTerm::Image { image_id, .. } => {
+                if let Err(rusqlite::Error::QueryReturnedNoRows) = db.get_image_path(*image_id) {
+                    return Err(ApiError::NotFound(format!("image {image_id}")));
+                }
+            }
+            QueryTerm::Text { .. } => {}
+        }
+    }
+    if !terms.iter().any(|t| t.weight() > 0.0) {
+        return Err(ApiError::BadInput(
+            "a composite query needs at least one positive term".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// `(weight, embedding)` for every term `encoder_id` can represent:
+/// text terms only in a text-capable encoder, image terms wherever the
+/// image has an embedding.
+fn encoder_parts(
+    db: &ImageDatabase,
+    text_encoders: &TextEncoderState,
+    terms: &[QueryTerm],
+    encoder_id: &str,
+) -> Result<Vec<(f32, Vec<f32>)>, ApiError> {
+    let has_text = TEXT_CAPABLE_ENCODERS.contains(&encoder_id);
+    let mut parts = Vec::with_capacity(terms.len());
+    for term in terms {
+        match term {
+            QueryTerm::Text { text, weight } if has_text => {
+                let (embedding, _, _) = encode_text(text_encoders, text.trim(), encoder_id)?;
+                parts.push((*weight, embedding));
+            }
+            QueryTerm::Text { .. } => {}
+            QueryTerm::Image { image_id, weight } => {
+                if let Ok(embedding) = db.get_embedding(*image_id, encoder_id) {
+                    if !embedding.is_empty() {
+                        parts.push((*weight, embedding));
+                    }
+                }
+            }
+        }
+    }
+    Ok(parts)
+}
+
+/// `unit(Σ wᵢ · unit(vᵢ))` over the parts sharing the first positive
+/// part's dimension. `None` without a positive part or when the terms
+/// cancel out.
+pub fn blend(parts: &[(f32, Vec<f32>)]) -> Option<Vec<f32>> {
+    let dim = parts.iter().find(|(w, _)| *w > 0.0)?.1.len();
+    let mut sum = vec![0.0f32; dim];
+    for (weight, v) in parts.iter().filter(|(_, v)| v.len() == dim) {
+        for (s, x) in sum.iter_mut().zip(unit(v)) {
+            *s += weight * x;
+        }
+    }
+    let norm = sum.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if !norm.is_finite() || norm <= f32::EPSILON {
+        return None;
+    }
+    Some(sum.iter().map(|x| x / norm).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terms_deserialise_with_a_default_weight() {
+        let terms: Vec<QueryTerm> = serde_json::from_str(
+            r#"[{"kind": "image", "image_id": 4},
+                {"kind": "text", "text": "at night", "weight": 0.5},
+                {"kind": "text", "text": "people", "weight": -1}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            terms,
+            vec![
+                QueryTerm::Image {
+                    image_id: 4,
+                    weight: 1.0
+                },
+                QueryTerm::Text {
+                    text: "at night".into(),
+                    weight: 0.5
+                },
+                QueryTerm::Text {
+                    text: "people".into(),
+                    weight: -1.0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn blend_weights_unit_terms() {
+        // Magnitude is ignored: the 10× longer vector counts as much as
+        // the unit one at equal weight.
+        let q = blend(&[(1.0, vec![10.0, 0.0]), (1.0, vec![0.0, 1.0])]).unwrap();
+        assert!((q[0] - q[1]).abs() < 1e-6);
+
+        let q = blend(&[(1.0, vec![1.0, 0.0]), (0.5, vec![0.0, 1.0])]).unwrap();
+        assert!(q[0] > q[1] && q[1] > 0.0);
+
+        let q = blend(&[(1.0, vec![1.0, 1.0]), (-1.0, vec![0.0, 1.0])]).unwrap();
+        assert!(q[0] > 0.9, "the negative term cancels the shared direction");
+    }
+
+    #[test]
+    fn blend_needs_a_positive_term() {
+        assert!(blend(&[]).is_none());
+        assert!(blend(&[(-1.0, vec![1.0, 0.0])]).is_none());
+        assert!(blend(&[(1.0, vec![1.0, 0.0]), (-1.0, vec![2.0, 0.0])]).is_none());
+        // A part of another dimension is left out.
+        assert_eq!(
+            blend(&[(1.0, vec![0.0, 3.0]), (1.0, vec![1.0])]),
+            Some(vec![0.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn meaningless_queries_are_rejected() {
+        let db = ImageDatabase::new(":memory:").unwrap();
+        db.initialize().unwrap();
+        let text = |text: &str, weight| QueryTerm::Text {
+            text: text.into(),
+            weight,
+        };
+        let bad_input =
+            |terms: &[QueryTerm]| matches!(validate(&db, terms), Err(ApiError::BadInput(_)));
+
+        assert!(bad_input(&[]));
+        assert!(bad_input(&[text(" ", 1.0)]));
+        assert!(bad_input(&[text("dusk", 0.0)]));
+        assert!(bad_input(&[text("dusk", f32::NAN)]));
+        assert!(bad_input(&[text("people", -1.0)]));
+        assert!(validate(&db, &[text("dusk", 1.0), text("people", -1.0)]).is_ok());
+        assert!(matches!(
+            validate(
+                &db,
+                &[QueryTerm::Image {
+                    image_id: 99,
+                    weight: 1.0
+                }]
+            ),
+            Err(ApiError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,429 @@
+//! Relevance feedback: refine a fused search with thumbs-up /
+//! thumbs-down.
+//!
+//! A session starts from a text query (like `fused_semantic_search`)
+//! or a query image (like `fused_similar_images`) and keeps, per
+//! encoder, the original query vector plus the images the user marked
+//! positive or negative. Every refinement rebuilds each encoder's
+//! query Rocchio-style,
+//!
+//! ```text
+//! q' = q + α·mean(positive) − β·mean(negative)
+//! ```
+//!
+//! ranks that encoder's cache against `q'` and fuses the lists with
+//! the same RRF as the one-shot searches. Vectors are unit-normalised
+//! before mixing so no encoder or image dominates by magnitude.
+//!
+//! Text sessions have no query vector for image-only encoders
+//! (DINOv2); those join the fusion as soon as there is a positive
+//! example, with `mean(positive)` as their query. Negatives never
+//! appear in the results.
+//!
+//! A session may carry a `SearchFilter`, fixed when it starts and
+//! applied inside every encoder's cache on each refinement.
+//!
+//! Sessions live in memory on the `Library` — they are cheap to
+//! rebuild and meaningless across launches. The least recently used
+//! one is dropped beyond `MAX_SESSIONS`.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use ndarray::Array1;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use super::semantic::encode_text;
+use super::semantic_fused::TEXT_CAPABLE_ENCODERS;
+use super::{allowed_paths, resolve_fused, unit, ImageSearchResult};
+use crate::db::{ImageDatabase, SearchFilter, ID};
+use crate::error::ApiError;
+use crate::perf;
+use crate::similarity_and_semantic_search::cosine::rrf::{
+    reciprocal_rank_fusion, RankedList, DEFAULT_K_RRF,
+};
+use crate::{FusionIndexState, TextEncoderState};
+
+/// Weight of the positive examples' mean.
+pub const ALPHA: f32 = 0.75;
+
+/// Weight of the negative examples' mean. Smaller than `ALPHA`: "not
+/// this" is a weaker signal than "more like this", and a large β
+/// pushes the query towards whatever is furthest from the negatives.
+pub const BETA: f32 = 0.25;
+
+/// Sessions kept before the least recently used is dropped.
+pub const MAX_SESSIONS: usize = 16;
+
+/// One page of a feedback session's results, with the judgements that
+/// produced it.
+#[derive(Serialize)]
+pub struct FeedbackResults {
+    pub session_id: u64,
+    pub positive: Vec<ID>,
+    pub negative: Vec<ID>,
+    pub results: Vec<ImageSearchResult>,
+}
+
+#[derive(Clone)]
+struct Session {
+    /// Encoders fused over, fixed when the session starts.
+    encoders: Vec<String>,
+    /// Unit query vector per encoder that has one.
+    queries: HashMap<String, Vec<f32>>,
+    /// The query image of an image session, kept out of the results.
+    exclude_path: Option<PathBuf>,
+    filter: Option<SearchFilter>,
+    positive: BTreeSet<ID>,
+    negative: BTreeSet<ID>,
+    last_used: Instant,
+}
+
+/// The open feedback sessions of one library.
+#[derive(Default)]
+pub struct FeedbackSessions {
+    sessions: Mutex<HashMap<u64, Session>>,
+    next_id: AtomicU64,
+}
+
+impl Session {
+    fn new(
+        encoders: Vec<String>,
+        queries: HashMap<String, Vec<f32>>,
+        exclude_path: Option<PathBuf>,
+        filter: Option<&SearchFilter>,
+    ) -> Self {
+        Session {
+            encoders,
+            queries,
+            exclude_path,
+            filter: filter.filter(|f| !f.is_empty()).cloned(),
+            positive: BTreeSet::new(),
+            negative: BTreeSet::new(),
+            last_used: Instant::now(),
+        }
+    }
+}
+
+impl FeedbackSessions {
+    /// Open a session on a text query across every enabled
+    /// text-capable encoder and return its first page.
+    pub fn start_text(
+        &self,
+        db: &ImageDatabase,
+        fusion_state: &FusionIndexState,
+        text_encoders: &TextEncoderState,
+        query: &str,
+        top_n: usize,
+        filter: Option<&SearchFilter>,
+    ) -> Result<FeedbackResults, ApiError> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Err(ApiError::BadInput("search query is empty".into()));
+        }
+        let encoders = crate::settings::Settings::load().resolved_enabled_encoders();
+        let mut queries = HashMap::new();
+        for &enc in TEXT_CAPABLE_ENCODERS {
+            if !encoders.iter().any(|e| e == enc) {
+                continue;
+            }
+            match encode_text(text_encoders, query, enc) {
+                Ok((embedding, _, _)) => {
+                    queries.insert(enc.to_string(), unit(&embedding));
+                }
+                Err(e) => warn!("feedback session: {enc} could not encode '{query}': {e}"),
+            }
+        }
+        if queries.is_empty() {
+            return Err(ApiError::Encoder(
+                "no enabled text encoder could encode the query".into(),
+            ));
+        }
+        let session = Session::new(encoders, queries, None, filter);
+        self.open(db, fusion_state, session, top_n)
+    }
+
+    /// Open a session on a query image across every enabled encoder
+    /// and return its first page.
+    pub fn start_image(
+        &self,
+        db: &ImageDatabase,
+        fusion_state: &FusionIndexState,
+        image_id: ID,
+        top_n: usize,
+        filter: Option<&SearchFilter>,
+    ) -> Result<FeedbackResults, ApiError> {
+        let path = db.get_image_path(image_id)?;
+        let encoders = crate::settings::Settings::load().resolved_enabled_encoders();
+        let queries: HashMap<String, Vec<f32>> = encoders
+            .iter()
+            .filter_map(|enc| {
+                let embedding = db.get_embedding(image_id, enc).ok()?;
+                (!embedding.is_empty()).then(|| (enc.clone(), unit(&embedding)))
+            })
+            .collect();
+        if queries.is_empty() {
+            return Err(ApiError::BadInput(format!(
+                "image {image_id} has no embeddings yet"
+            )));
+        }
+        let session = Session::new(encoders, queries, Some(PathBuf::from(path)), filter);
+        self.open(db, fusion_state, session, top_n)
+    }
+
+    /// Record judgements and re-rank. An image listed here replaces
+    /// any earlier judgement of it; `neutral` withdraws one.
+    #[allow(clippy::too_many_arguments)]
+    pub fn refine(
+        &self,
+        db: &ImageDatabase,
+        fusion_state: &FusionIndexState,
+        session_id: u64,
+        positive: &[ID],
+        negative: &[ID],
+        neutral: &[ID],
+        top_n: usize,
+    ) -> Result<FeedbackResults, ApiError> {
+        if let Some(id) = positive.iter().find(|id| negative.contains(id)) {
+            return Err(ApiError::BadInput(format!(
+                "image {id} is marked both positive and negative"
+            )));
+        }
+        let snapshot = {
+            let mut sessions = self.sessions.lock()?;
+            let session = sessions
+                .get_mut(&session_id)
+                .ok_or_else(|| ApiError::NotFound(format!("search session {session_id}")))?;
+            for id in positive.iter().chain(negative).chain(neutral) {
+                session.positive.remove(id);
+                session.negative.remove(id);
+            }
+            session.positive.extend(positive);
+            session.negative.extend(negative);
+            session.last_used = Instant::now();
+            session.clone()
+        };
+        // Rank outside the lock so one slow session doesn't stall the
+        // others.
+        let results = rank(db, fusion_state, &snapshot, top_n)?;
+        Ok(FeedbackResults {
+            session_id,
+            positive: snapshot.positive.into_iter().collect(),
+            negative: snapshot.negative.into_iter().collect(),
+            results,
+        })
+    }
+
+    /// Close a session. `false` if it was unknown (or already evicted).
+    pub fn end(&self, session_id: u64) -> bool {
+        self.sessions
+            .lock()
+            .map(|mut s| s.remove(&session_id).is_some())
+            .unwrap_or(false)
+    }
+
+    fn open(
+        &self,
+        db: &ImageDatabase,
+        fusion_state: &FusionIndexState,
+        session: Session,
+        top_n: usize,
+    ) -> Result<FeedbackResults, ApiError> {
+        let results = rank(db, fusion_state, &session, top_n)?;
+        let session_id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut sessions = self.sessions.lock()?;
+        if sessions.len() >= MAX_SESSIONS {
+            if let Some(oldest) = sessions
+                .iter()
+                .min_by_key(|(_, s)| s.last_used)
+                .map(|(id, _)| *id)
+            {
+                sessions.remove(&oldest);
+            }
+        }
+        sessions.insert(session_id, session);
+        info!(
+            "feedback session {session_id} opened ({} open)",
+            sessions.len()
+        );
+        Ok(FeedbackResults {
+            session_id,
+            positive: Vec::new(),
+            negative: Vec::new(),
+            results,
+        })
+    }
+}
+
+/// One fused page for the session's current judgements, negatives
+/// left out.
+fn rank(
+    db: &ImageDatabase,
+    fusion_state: &FusionIndexState,
+    session: &Session,
+    top_n: usize,
+) -> Result<Vec<ImageSearchResult>, ApiError> {
+    let started = Instant::now();
+    // Over-fetch by the negatives so dropping them still fills the page.
+    let depth = top_n.saturating_mul(5).max(50) + session.negative.len();
+    let allowed = allowed_paths(db, session.filter.as_ref())?;
+    let mut ranked_lists: Vec<RankedList> = Vec::with_capacity(session.encoders.len());
+    for enc in &session.encoders {
+        let positive = embeddings(db, &session.positive, enc);
+        let negative = embeddings(db, &session.negative, enc);
+        let base = session.queries.get(enc).map(Vec::as_slice);
+        let Some(query) = rocchio(base, &positive, &negative, ALPHA, BETA) else {
+            continue;
+        };
+        let items = fusion_state
+            .ranked_for_encoder(
+                db,
+                enc,
+                &Array1::from_vec(query),
+                depth,
+                session.exclude_path.as_ref(),
+                allowed.as_ref(),
+            )
+            .map_err(ApiError::Cosine)?;
+        if !items.is_empty() {
+            ranked_lists.push(RankedList {
+                encoder_id: enc.clone(),
+                items,
+            });
+        }
+    }
+    let fused =
+        reciprocal_rank_fusion(&ranked_lists, DEFAULT_K_RRF, top_n + session.negative.len());
+
+    let results = resolve_fused(db, &fused, top_n, |id| session.negative.contains(&id))?;
+
+    perf::record_diagnostic(
+        "search_query",
+        serde_json::json!({
+            "type": "feedback",
+            "top_n": top_n,
+            "per_encoder_top_k": depth,
+            "k_rrf": DEFAULT_K_RRF,
+            "positive_count": session.positive.len(),
+            "negative_count": session.negative.len(),
+            "filter": session.filter,
+            "encoders_used": ranked_lists
+                .iter()
+                .map(|r| r.encoder_id.clone())
+                .collect::<Vec<_>>(),
+            "resolved_count": results.len(),
+            "total_elapsed_ms": started.elapsed().as_millis() as u64,
+        }),
+    );
+    Ok(results)
+}
+
+/// `encoder_id` embeddings of the judged images that have one.
+fn embeddings(db: &ImageDatabase, ids: &BTreeSet<ID>, encoder_id: &str) -> Vec<Vec<f32>> {
+    ids.iter()
+        .filter_map(|id| db.get_embedding(*id, encoder_id).ok())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// The Rocchio query `q + α·mean(positive) − β·mean(negative)`, every
+/// input unit-normalised first and the result normalised again.
+/// `query` may be absent (an image-only encoder in a text session):
+/// then the positives alone form the query. `None` when there is
+/// nothing to search with.
+pub fn rocchio(
+    query: Option<&[f32]>,
+    positive: &[Vec<f32>],
+    negative: &[Vec<f32>],
+    alpha: f32,
+    beta: f32,
+) -> Option<Vec<f32>> {
+    if query.is_none() && positive.is_empty() {
+        return None;
+    }
+    let dim = query.map_or_else(|| positive[0].len(), <[f32]>::len);
+    let mut out = query.map_or_else(|| vec![0.0; dim], unit);
+    add_mean(&mut out, positive, alpha);
+    add_mean(&mut out, negative, -beta);
+    let norm = out.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if !norm.is_finite() || norm <= f32::EPSILON {
+        return None;
+    }
+    out.iter_mut().for_each(|x| *x /= norm);
+    Some(out)
+}
+
+/// `out += weight · mean(unit(v))` over the vectors of `out`'s
+/// dimension; others (a stale embedding from an old model) are
+/// skipped.
+fn add_mean(out: &mut [f32], vectors: &[Vec<f32>], weight: f32) {
+    let usable: Vec<&Vec<f32>> = vectors.iter().filter(|v| v.len() == out.len()).collect();
+    if usable.is_empty() {
+        return;
+    }
+    let scale = weight / usable.len() as f32;
+    for v in usable {
+        for (o, x) in out.iter_mut().zip(unit(v)) {
+            *o += scale * x;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dot(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    #[test]
+    fn rocchio_moves_towards_positives_and_away_from_negatives() {
+        let query = [1.0, 0.0, 0.0];
+        let positive = vec![vec![0.0, 2.0, 0.0]];
+        let negative = vec![vec![0.0, 0.0, 5.0]];
+
+        let plain = rocchio(Some(&query), &[], &[], ALPHA, BETA).unwrap();
+        assert!((dot(&plain, &query) - 1.0).abs() < 1e-6);
+
+        let refined = rocchio(Some(&query), &positive, &negative, ALPHA, BETA).unwrap();
+        assert!((dot(&refined, &refined) - 1.0).abs() < 1e-5);
+        assert!(refined[1] > 0.0, "pulled towards the positive");
+        assert!(refined[2] < 0.0, "pushed away from the negative");
+        // Magnitudes don't matter, only directions: α > β here.
+        assert!(refined[1] > -refined[2]);
+    }
+
+    #[test]
+    fn rocchio_without_a_query_uses_the_positives() {
+        let positive = vec![vec![3.0, 0.0], vec![0.0, 1.0]];
+        let q = rocchio(None, &positive, &[], ALPHA, BETA).unwrap();
+        assert!((q[0] - q[1]).abs() < 1e-6);
+
+        assert!(rocchio(None, &[], &[vec![1.0, 0.0]], ALPHA, BETA).is_none());
+        // A mismatched dimension is ignored rather than mixed in.
+        let q = rocchio(Some(&[1.0, 0.0]), &[vec![0.0, 1.0, 0.0]], &[], ALPHA, BETA).unwrap();
+        assert_eq!(q, vec![1.0, 0.0]);
+    }
+
+    #[test]
+    fn unknown_sessions_are_not_found() {
+        let db = ImageDatabase::new(":memory:").unwrap();
+        db.initialize().unwrap();
+        let sessions = FeedbackSessions::default();
+        let fusion = FusionIndexState::default();
+        assert!(matches!(
+            sessions.refine(&db, &fusion, 7, &[1], &[], &[], 10),
+            Err(ApiError::NotFound(_))
+        ));
+        assert!(matches!(
+            sessions.refine(&db, &fusion, 7, &[1], &[1], &[], 10),
+            Err(ApiError::BadInput(_))
+        ));
+        assert!(!sessions.end(7));
+    }
+}
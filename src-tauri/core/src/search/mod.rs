@@ -0,0 +1,201 @@
+//! Search over the cosine caches, shared by the app's commands, the
+//! HTTP API and the CLI.
+//!
+//! - `semantic` — text query against one text encoder (`semantic_search`).
+//! - `semantic_fused` — text query fused across every enabled
+//!   text-capable encoder (`fused_semantic_search`).
+//! - `similarity` — image-image queries: single encoder, tiered
+//!   sampling, and multi-encoder fusion.
+//! - `composite` — weighted blends of text phrases and example images,
+//!   negatives included (`composite_search`).
+//! - `feedback` — relevance-feedback sessions that refine either fused
+//!   search with thumbs-up / thumbs-down (`FeedbackSessions`).
+//! - `pages` — ranked lists kept server-side so a search can be read
+//!   page by page through a cursor (`SearchPages`).
+//!
+//! The pieces shared across the submodules live here:
+//!
+//! - `ImageSearchResult` — the unified return type for every
+//!   cosine/semantic search. Single struct rather than a per-search
+//!   shape so the frontend deserialises one type.
+//! - `resolve_image_id_for_cosine_path` — maps a cosine-result path
+//!   back to its DB `(id, canonical_path)`, with three lookup
+//!   strategies for the various canonical-form mismatches.
+//! - `resolve_fused` / `unit` — page assembly and vector normalisation
+//!   for the searches that build their own query vectors (`feedback`,
+//!   `composite`).
+//! - `allowed_paths` — a `SearchFilter` resolved to the paths the
+//!   cosine caches may rank, so the fused searches filter before their
+//!   per-encoder top-K rather than after.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::db::{ImageDatabase, SearchFilter, ID};
+use crate::error::ApiError;
+use crate::image_struct::ImageData;
+use crate::paths;
+use crate::similarity_and_semantic_search::cosine::rrf::FusedItem;
+
+pub mod composite;
+pub mod feedback;
+pub mod pages;
+pub mod semantic;
+pub mod semantic_fused;
+pub mod similarity;
+
+pub use composite::{composite_search, QueryTerm};
+pub use feedback::{FeedbackResults, FeedbackSessions};
+pub use pages::{SearchPage, SearchPages, SearchRequest};
+pub use semantic::{semantic_search, CLIP_TEXT_ENCODER_ID, SIGLIP2_TEXT_ENCODER_ID};
+pub use semantic_fused::fused_semantic_search;
+pub use similarity::{fused_similar_images, similar_images, tiered_similar_images};
+
+/// Unified image-search result returned by every cosine/semantic
+/// search (semantic_search, similar_images, tiered_similar_images).
+///
+/// Audit finding: `ImageSearchResult` and `ImageSearchResult` used to be
+/// two near-identical structs — only difference was that the semantic
+/// variant carried thumbnail enrichment. After the
+/// "dimensions-to-backend" finding lands (this commit), all three
+/// commands need the same fields, so they share one type. Field
+/// shape preserved across both legacy struct names — this is a strict
+/// superset of what `ImageSearchResult` used to send.
+#[derive(Clone, serde::Serialize)]
+pub struct ImageSearchResult {
+    pub id: ID,
+    pub path: String,
+    pub score: f32,
+    /// Absolute path to the thumbnail JPEG. None for legacy DB rows
+    /// that pre-date the thumbnail migration; the frontend's
+    /// `getThumbnailPath(id)` fallback covers this case.
+    pub thumbnail_path: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// Map a cosine-result `PathBuf` back to its database `(id, canonical_path)`.
+///
+/// The cosine index returns paths from its in-memory cache; those
+/// paths might have a Windows extended-prefix (`\\?\`) if the
+/// indexing pipeline canonicalised them on Windows, or a different
+/// canonical form than what's stored in the DB. Three lookup
+/// strategies, in order:
+///
+/// 1. Try the path with `\\?\` stripped (the common case — covers
+///    every modern run on every platform).
+/// 2. Fall back to the raw path the cosine index gave us.
+/// 3. As a last resort, walk `all_images_cache` looking for any row
+///    whose path matches under any normalisation. This handles
+///    legacy DBs where some rows were inserted with one canonical
+///    form and the cosine index now returns another.
+///
+/// Returns `Some((id, canonical_path))` if any strategy matches.
+///
+/// Audit finding (extracted from triplicated inline closures + 3
+/// triplicated 60-line lookup blocks across `semantic_search`,
+/// `get_similar_images`, `get_tiered_similar_images`). The project
+/// notes already flagged "don't add a fourth normalisation closure"
+/// — the third one was the redundancy.
+pub(crate) fn resolve_image_id_for_cosine_path(
+    db: &ImageDatabase,
+    cosine_path: &std::path::Path,
+    all_images_cache: Option<&[ImageData]>,
+) -> Option<(ID, String)> {
+    let path_str = cosine_path.to_string_lossy().into_owned();
+    let normalized = paths::strip_windows_extended_prefix(&path_str).into_owned();
+
+    // Strategy 1: direct DB lookup using the normalised path.
+    if let Ok(id) = db.get_image_id_by_path(&normalized) {
+        return Some((id, normalized));
+    }
+    // Strategy 2: direct DB lookup using the raw path.
+    if let Ok(id) = db.get_image_id_by_path(&path_str) {
+        return Some((id, path_str));
+    }
+    // Strategy 3: scan the cached image list for a flexible match.
+    let images = all_images_cache?;
+    let search_path = cosine_path
+        .canonicalize()
+        .ok()
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|| normalized.clone());
+
+    images
+        .iter()
+        .find(|img| {
+            let img_norm = paths::strip_windows_extended_prefix(&img.path);
+            let img_canon = std::path::Path::new(&img.path)
+                .canonicalize()
+                .ok()
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_else(|| img_norm.clone().into_owned());
+
+            img_norm.as_ref() == normalized.as_str()
+                || img_norm.as_ref() == path_str.as_str()
+                || img.path == normalized
+                || img.path == path_str
+                || img_canon == search_path
+        })
+        .map(|img| (img.id, img.path.clone()))
+}
+
+/// Fused items → results, best first, skipping ids `skip` rejects and
+/// paths that no longer resolve, until `top_n` are collected.
+pub(crate) fn resolve_fused(
+    db: &ImageDatabase,
+    fused: &[FusedItem],
+    top_n: usize,
+    skip: impl Fn(ID) -> bool,
+) -> Result<Vec<ImageSearchResult>, ApiError> {
+    let all_images = db.get_all_images()?;
+    let mut results = Vec::with_capacity(top_n.min(fused.len()));
+    for f in fused {
+        if results.len() == top_n {
+            break;
+        }
+        let Some((id, path)) = resolve_image_id_for_cosine_path(db, &f.path, Some(&all_images))
+        else {
+            continue;
+        };
+        if skip(id) {
+            continue;
+        }
+        let (thumbnail_path, width, height) = db
+            .get_image_thumbnail_info(id)
+            .ok()
+            .flatten()
+            .map(|(tp, w, h)| (Some(tp), Some(w), Some(h)))
+            .unwrap_or((None, None, None));
+        results.push(ImageSearchResult {
+            id,
+            path,
+            score: f.fused_score,
+            thumbnail_path,
+            width,
+            height,
+        });
+    }
+    Ok(results)
+}
+
+/// `v` scaled to unit length; a zero vector is returned as is.
+pub(crate) fn unit(v: &[f32]) -> Vec<f32> {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm <= f32::EPSILON {
+        return v.to_vec();
+    }
+    v.iter().map(|x| x / norm).collect()
+}
+
+/// The paths `filter` admits, for `FusionIndexState::ranked_for_encoder`.
+/// `None` when there is nothing to filter on.
+pub(crate) fn allowed_paths(
+    db: &ImageDatabase,
+    filter: Option<&SearchFilter>,
+) -> Result<Option<HashSet<PathBuf>>, ApiError> {
+    match filter.filter(|f| !f.is_empty()) {
+        Some(filter) => Ok(Some(db.filtered_image_paths(filter)?)),
+        None => Ok(None),
+    }
+}
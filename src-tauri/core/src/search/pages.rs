@@ -0,0 +1,245 @@
+//! Paged search results.
+//!
+//! The one-shot searches return a single `top_n` page; asking for more
+//! means running the whole search again at a larger `top_n`. A paged
+//! search runs once, `RANKED_DEPTH` deep, and keeps the ranked list
+//! here. The first page comes back with an opaque cursor, and "load
+//! more" slices the next page out of the kept list — no re-encoding,
+//! no cosine pass, no fusion.
+//!
+//! A list whose results fit on the first page is never kept. Lists
+//! live in memory on the `Library`; the least recently read one is
+//! dropped beyond `MAX_CACHED_LISTS`, and its cursors then come back
+//! not-found, telling the client to start the search again.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ImageSearchResult, QueryTerm};
+use crate::cursor;
+use crate::db::ID;
+use crate::error::ApiError;
+
+/// How deep a paged search ranks: the most results it can page through.
+pub const RANKED_DEPTH: usize = 500;
+
+/// Ranked lists kept before the least recently read is dropped.
+pub const MAX_CACHED_LISTS: usize = 16;
+
+/// Largest page a caller may ask for, grid or search.
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// The search behind a paged listing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SearchRequest {
+    /// As `fused_semantic_search`.
+    Text { query: String },
+    /// As `fused_similar_images`.
+    Similar { image_id: ID },
+    /// As `composite_search`.
+    Composite { terms: Vec<QueryTerm> },
+}
+
+/// One page of a paged search.
+#[derive(Serialize)]
+pub struct SearchPage {
+    pub results: Vec<ImageSearchResult>,
+    /// Hand back to `SearchPages::page` for the next page; `None` on
+    /// the last one.
+    pub next_cursor: Option<String>,
+    /// Results in the whole ranked list.
+    pub total: usize,
+}
+
+struct CachedList {
+    results: Vec<ImageSearchResult>,
+    /// `SearchPages::clock` at the last read; a counter rather than an
+    /// `Instant` so reads in the same tick still order.
+    last_used: u64,
+}
+
+/// What a search cursor points at.
+#[derive(Serialize, Deserialize)]
+struct Position {
+    list: u64,
+    offset: usize,
+}
+
+/// The kept ranked lists of one library.
+#[derive(Default)]
+pub struct SearchPages {
+    lists: Mutex<HashMap<u64, CachedList>>,
+    next_id: AtomicU64,
+    clock: AtomicU64,
+}
+
+impl SearchPages {
+    /// Keep a freshly ranked list and return its first page.
+    pub fn store(
+        &self,
+        results: Vec<ImageSearchResult>,
+        page_size: usize,
+    ) -> Result<SearchPage, ApiError> {
+        check_page_size(page_size)?;
+        let total = results.len();
+        if total <= page_size {
+            return Ok(SearchPage {
+                results,
+                next_cursor: None,
+                total,
+            });
+        }
+        let list = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let first = results[..page_size].to_vec();
+        let mut lists = self.lists.lock()?;
+        if lists.len() >= MAX_CACHED_LISTS {
+            if let Some(oldest) = lists
+                .iter()
+                .min_by_key(|(_, l)| l.last_used)
+                .map(|(id, _)| *id)
+            {
+                lists.remove(&oldest);
+            }
+        }
+        lists.insert(
+            list,
+            CachedList {
+                results,
+                last_used: self.clock.fetch_add(1, Ordering::Relaxed),
+            },
+        );
+        Ok(SearchPage {
+            results: first,
+            next_cursor: Some(cursor::encode(&Position {
+                list,
+                offset: page_size,
+            })),
+            total,
+        })
+    }
+
+    /// The page `cursor` points at. The page size may differ from the
+    /// previous page's.
+    pub fn page(&self, cursor: &str, page_size: usize) -> Result<SearchPage, ApiError> {
+        check_page_size(page_size)?;
+        let position: Position = cursor::decode(cursor)
+            .ok_or_else(|| ApiError::BadInput("malformed search cursor".into()))?;
+        let mut lists = self.lists.lock()?;
+        let list = lists.get_mut(&position.list).ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "search results {} (expired; run the search again)",
+                position.list
+            ))
+        })?;
+        list.last_used = self.clock.fetch_add(1, Ordering::Relaxed);
+        let total = list.results.len();
+        let start = position.offset.min(total);
+        let end = start.saturating_add(page_size).min(total);
+        Ok(SearchPage {
+            results: list.results[start..end].to_vec(),
+            next_cursor: (end < total).then(|| {
+                cursor::encode(&Position {
+                    list: position.list,
+                    offset: end,
+                })
+            }),
+            total,
+        })
+    }
+}
+
+/// Reject page sizes of zero or above `MAX_PAGE_SIZE`.
+pub fn check_page_size(page_size: usize) -> Result<(), ApiError> {
+    if page_size == 0 || page_size > MAX_PAGE_SIZE {
+        return Err(ApiError::BadInput(format!(
+            "page size must be between 1 and {MAX_PAGE_SIZE}, got {page_size}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked(n: i64) -> Vec<ImageSearchResult> {
+        (1..=n)
+            .map(|id| ImageSearchResult {
+                id,
+                path: format!("/r/{id}.jpg"),
+                score: 1.0 / id as f32,
+                thumbnail_path: None,
+                width: None,
+                height: None,
+            })
+            .collect()
+    }
+
+    fn ids(page: &SearchPage) -> Vec<ID> {
+        page.results.iter().map(|r| r.id).collect()
+    }
+
+    #[test]
+    fn cursors_walk_the_kept_list() {
+        let pages = SearchPages::default();
+        let first = pages.store(ranked(5), 2).unwrap();
+        assert_eq!((ids(&first), first.total), (vec![1, 2], 5));
+        let second = pages
+            .page(first.next_cursor.as_deref().unwrap(), 2)
+            .unwrap();
+        assert_eq!(ids(&second), [3, 4]);
+        // Re-reading a cursor gives the same page.
+        let again = pages
+            .page(first.next_cursor.as_deref().unwrap(), 2)
+            .unwrap();
+        assert_eq!(ids(&again), [3, 4]);
+        let last = pages
+            .page(second.next_cursor.as_deref().unwrap(), 10)
+            .unwrap();
+        assert_eq!(ids(&last), [5]);
+        assert!(last.next_cursor.is_none());
+
+        let whole = pages.store(ranked(3), 3).unwrap();
+        assert!(whole.next_cursor.is_none());
+        assert_eq!(
+            pages.lists.lock().unwrap().len(),
+            1,
+            "a single page isn't kept"
+        );
+    }
+
+    #[test]
+    fn bad_cursors_and_sizes_are_rejected() {
+        let pages = SearchPages::default();
+        assert!(matches!(
+            pages.store(ranked(3), 0),
+            Err(ApiError::BadInput(_))
+        ));
+        assert!(matches!(
+            pages.page("not a cursor", 10),
+            Err(ApiError::BadInput(_))
+        ));
+
+        let first = pages.store(ranked(3), 1).unwrap();
+        let cursor = first.next_cursor.unwrap();
+        assert!(matches!(
+            pages.page(&cursor, MAX_PAGE_SIZE + 1),
+            Err(ApiError::BadInput(_))
+        ));
+        for _ in 0..MAX_CACHED_LISTS {
+            pages.store(ranked(3), 1).unwrap();
+        }
+        assert!(matches!(pages.page(&cursor, 1), Err(ApiError::NotFound(_))));
+    }
+
+    #[test]
+    fn requests_deserialise_by_kind() {
+        let request: SearchRequest =
+            serde_json::from_str(r#"{"kind": "similar", "image_id": 4}"#).unwrap();
+        assert_eq!(request, SearchRequest::Similar { image_id: 4 });
+    }
+}
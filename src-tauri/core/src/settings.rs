@@ -14,6 +14,7 @@ use std::path::PathBuf;
 use tracing::error;
 
 use crate::paths;
+use crate::similarity_and_semantic_search::cosine::QuantMode;
 
 /// Persisted user preferences.
 ///
@@ -59,6 +60,22 @@ pub struct Settings {
     /// its embeddings.
     #[serde(default)]
     pub enabled_encoders: Option<Vec<String>>,
+
+    /// Representation of the multi-encoder fusion caches: `"int8"` or
+    /// `"binary"` for quantised codes re-ranked at full precision (see
+    /// `cosine::quant`), `None` for full-precision f32.
+    ///
+    /// Stored as a string rather than `QuantMode` so an unknown value
+    /// from a newer build falls back to full precision instead of
+    /// failing the whole file's parse. Mutated via the
+    /// `set_embedding_quantization` Tauri command.
+    #[serde(default)]
+    pub embedding_quantization: Option<String>,
+
+    /// Loopback port for the HTTP API (`http_api.rs`). `None` keeps the
+    /// server off — it's opt-in, and only ever binds 127.0.0.1.
+    #[serde(default)]
+    pub http_api_port: Option<u16>,
 }
 
 /// Default encoder set when `enabled_encoders` is `None` — every
@@ -85,6 +102,12 @@ impl Settings {
                 .collect(),
         }
     }
+
+    /// The fusion cache representation, `None` (full precision) for an
+    /// unset or unrecognised value.
+    pub fn resolved_quantization(&self) -> Option<QuantMode> {
+        self.embedding_quantization.as_deref().and_then(QuantMode::parse)
+    }
 }
 
 impl Settings {
@@ -222,4 +245,14 @@ mod tests {
         };
         assert_eq!(s.resolved_enabled_encoders(), DEFAULT_ENABLED_ENCODERS);
     }
+
+    #[test]
+    fn test_resolved_quantization_ignores_unknown_values() {
+        let mut s = Settings::default();
+        assert_eq!(s.resolved_quantization(), None);
+        s.embedding_quantization = Some("binary".into());
+        assert_eq!(s.resolved_quantization(), Some(QuantMode::Binary));
+        s.embedding_quantization = Some("pq".into());
+        assert_eq!(s.resolved_quantization(), None);
+    }
 }
@@ -0,0 +1,572 @@
+//! On-disk cosine caches — one file per encoder.
+//!
+//! Layout (little-endian; f32s in native order, which is little-endian
+//! on every target we ship):
+//!
+//! ```text
+//!   0  magic            b"IBCOSINE"
+//!   8  format version   u32
+//!  12  pipeline version u32   EMBEDDING_PIPELINE_VERSION at write time
+//!  16  dim              u32
+//!  20  encoder id len   u32
+//!  24  rows             u64
+//!  32  stamp count      u64   EmbeddingSetStamp the rows were read under
+//!  40  stamp digest     [u8; 32]
+//!  72  path table len   u64
+//!  80  matrix offset    u64   multiple of 64
+//!  88  checksum         [u8; 32]  BLAKE3 of the file, this field zeroed
+//! 120  encoder id       [u8; 40]  zero-padded UTF-8
+//! 160  path table       rows × (u32 len, UTF-8 bytes)
+//!  ..  zero padding
+//!  mo  matrix           rows × dim f32, row-major
+//! ```
+//!
+//! A file is trusted only if its checksum verifies AND its encoder id,
+//! pipeline version and embedding-set stamp match what the DB holds
+//! now — so a cache can no longer be loaded into the wrong encoder's
+//! slot, and tag or note edits (which bump the DB mtime) no longer
+//! invalidate it. The matrix is never deserialised: a loaded
+//! `EmbeddingMatrix` scores straight out of the mmap'd file.
+//!
+//! Files are written by the indexing pipeline (`refresh_encoder_files`)
+//! to a temp name and renamed into place, so a reader never maps a
+//! half-written file.
+
+use super::hnsw;
+use super::index::CosineIndex;
+use super::matrix::EmbeddingMatrix;
+use crate::db::{EmbeddingSetStamp, ImageDatabase, EMBEDDING_PIPELINE_VERSION};
+use crate::paths;
+use memmap2::Mmap;
+use ndarray::ArrayView2;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Instant;
+use tracing::{debug, info, warn};
+
+const MAGIC: &[u8; 8] = b"IBCOSINE";
+const FORMAT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 160;
+const MAX_ENCODER_ID_LEN: usize = 40;
+const MATRIX_ALIGN: usize = 64;
+
+const OFF_FORMAT: usize = 8;
+const OFF_PIPELINE: usize = 12;
+const OFF_DIM: usize = 16;
+const OFF_ENCODER_LEN: usize = 20;
+const OFF_ROWS: usize = 24;
+const OFF_STAMP_COUNT: usize = 32;
+const OFF_STAMP_DIGEST: usize = 40;
+const OFF_PATHS_LEN: usize = 72;
+const OFF_MATRIX: usize = 80;
+const OFF_CHECKSUM: usize = 88;
+const OFF_ENCODER: usize = 120;
+
+/// What a cache file claims to hold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheHeader {
+    pub encoder_id: String,
+    pub dim: usize,
+    pub rows: usize,
+    pub pipeline_version: i64,
+    pub stamp: EmbeddingSetStamp,
+}
+
+/// A validated, memory-mapped cache file.
+pub struct CacheFile {
+    map: Mmap,
+    header: CacheHeader,
+    /// (offset, len) of each path inside `map`.
+    path_spans: Vec<(usize, usize)>,
+    matrix_offset: usize,
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn u32_at(bytes: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(bytes[off..off + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(bytes[off..off + 8].try_into().unwrap())
+}
+
+impl CacheFile {
+    /// Write `rows` under `header` (whose `rows`/`dim` must describe
+    /// them) to `path`, atomically.
+    pub fn write(path: &Path, header: &CacheHeader, rows: &EmbeddingMatrix) -> io::Result<()> {
+        let encoder = header.encoder_id.as_bytes();
+        if encoder.len() > MAX_ENCODER_ID_LEN {
+            return Err(invalid(format!("encoder id too long: {}", header.encoder_id)));
+        }
+        if rows.len() != header.rows || (!rows.is_empty() && rows.dim() != header.dim) {
+            return Err(invalid("rows don't match the header's row count / dim"));
+        }
+
+        let mut path_table: Vec<u8> = Vec::new();
+        for p in rows.paths() {
+            let p = p.to_string_lossy();
+            path_table.extend_from_slice(&(p.len() as u32).to_le_bytes());
+            path_table.extend_from_slice(p.as_bytes());
+        }
+        let matrix_offset = (HEADER_LEN + path_table.len()).next_multiple_of(MATRIX_ALIGN);
+        let padding = vec![0u8; matrix_offset - HEADER_LEN - path_table.len()];
+
+        let mut head = [0u8; HEADER_LEN];
+        head[..8].copy_from_slice(MAGIC);
+        head[OFF_FORMAT..OFF_FORMAT + 4].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        head[OFF_PIPELINE..OFF_PIPELINE + 4]
+            .copy_from_slice(&(header.pipeline_version as u32).to_le_bytes());
+        head[OFF_DIM..OFF_DIM + 4].copy_from_slice(&(header.dim as u32).to_le_bytes());
+        head[OFF_ENCODER_LEN..OFF_ENCODER_LEN + 4]
+            .copy_from_slice(&(encoder.len() as u32).to_le_bytes());
+        head[OFF_ROWS..OFF_ROWS + 8].copy_from_slice(&(header.rows as u64).to_le_bytes());
+        head[OFF_STAMP_COUNT..OFF_STAMP_COUNT + 8]
+            .copy_from_slice(&header.stamp.count.to_le_bytes());
+        head[OFF_STAMP_DIGEST..OFF_STAMP_DIGEST + 32].copy_from_slice(&header.stamp.digest);
+        head[OFF_PATHS_LEN..OFF_PATHS_LEN + 8]
+            .copy_from_slice(&(path_table.len() as u64).to_le_bytes());
+        head[OFF_MATRIX..OFF_MATRIX + 8].copy_from_slice(&(matrix_offset as u64).to_le_bytes());
+        head[OFF_ENCODER..OFF_ENCODER + encoder.len()].copy_from_slice(encoder);
+
+        // Stream the body, hashing as we go; the header (checksum field
+        // still zero) is hashed first and rewritten at the end.
+        let tmp = path.with_extension("bin.tmp");
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&head);
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        out.write_all(&head)?;
+        for chunk in [&path_table[..], &padding[..], bytemuck::cast_slice(rows.data())] {
+            hasher.update(chunk);
+            out.write_all(chunk)?;
+        }
+        head[OFF_CHECKSUM..OFF_CHECKSUM + 32].copy_from_slice(hasher.finalize().as_bytes());
+        out.seek(SeekFrom::Start(0))?;
+        out.write_all(&head)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Map `path` and validate its layout and checksum. Says nothing
+    /// about freshness — compare `header()` against the DB for that.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: cache files are only ever replaced by rename, never
+        // rewritten in place, so the mapped inode doesn't change under
+        // us. Truncation by another process would fault; nothing in the
+        // app does that.
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < HEADER_LEN || &map[..8] != MAGIC {
+            return Err(invalid("not a cosine cache file"));
+        }
+        if u32_at(&map, OFF_FORMAT) != FORMAT_VERSION {
+            return Err(invalid("unsupported cosine cache format version"));
+        }
+        let dim = u32_at(&map, OFF_DIM) as usize;
+        let rows = u64_at(&map, OFF_ROWS) as usize;
+        let encoder_len = u32_at(&map, OFF_ENCODER_LEN) as usize;
+        let paths_len = u64_at(&map, OFF_PATHS_LEN) as usize;
+        let matrix_offset = u64_at(&map, OFF_MATRIX) as usize;
+        if encoder_len > MAX_ENCODER_ID_LEN {
+            return Err(invalid("encoder id length out of range"));
+        }
+        let matrix_len = rows
+            .checked_mul(dim)
+            .and_then(|n| n.checked_mul(std::mem::size_of::<f32>()))
+            .ok_or_else(|| invalid("matrix size overflows"))?;
+        if !matrix_offset.is_multiple_of(MATRIX_ALIGN)
+            || matrix_offset < HEADER_LEN.saturating_add(paths_len)
+            || matrix_offset.checked_add(matrix_len) != Some(map.len())
+        {
+            return Err(invalid("cosine cache truncated or malformed"));
+        }
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&map[..OFF_CHECKSUM]);
+        hasher.update(&[0u8; 32]);
+        hasher.update(&map[OFF_CHECKSUM + 32..]);
+        if hasher.finalize().as_bytes() != &map[OFF_CHECKSUM..OFF_CHECKSUM + 32] {
+            return Err(invalid("cosine cache checksum mismatch"));
+        }
+
+        let encoder_id = std::str::from_utf8(&map[OFF_ENCODER..OFF_ENCODER + encoder_len])
+            .map_err(|_| invalid("encoder id is not UTF-8"))?
+            .to_string();
+        let mut path_spans = Vec::with_capacity(rows);
+        let (mut at, end) = (HEADER_LEN, HEADER_LEN + paths_len);
+        while at < end {
+            if at + 4 > end {
+                return Err(invalid("path table truncated"));
+            }
+            let len = u32_at(&map, at) as usize;
+            let start = at + 4;
+            if start + len > end || std::str::from_utf8(&map[start..start + len]).is_err() {
+                return Err(invalid("path table malformed"));
+            }
+            path_spans.push((start, len));
+            at = start + len;
+        }
+        if path_spans.len() != rows {
+            return Err(invalid("path table row count mismatch"));
+        }
+        if bytemuck::try_cast_slice::<u8, f32>(&map[matrix_offset..]).is_err() {
+            return Err(invalid("matrix misaligned"));
+        }
+
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&map[OFF_STAMP_DIGEST..OFF_STAMP_DIGEST + 32]);
+        let header = CacheHeader {
+            encoder_id,
+            dim,
+            rows,
+            pipeline_version: u32_at(&map, OFF_PIPELINE) as i64,
+            stamp: EmbeddingSetStamp {
+                count: u64_at(&map, OFF_STAMP_COUNT),
+                digest,
+            },
+        };
+        Ok(CacheFile {
+            map,
+            header,
+            path_spans,
+            matrix_offset,
+        })
+    }
+
+    pub fn header(&self) -> &CacheHeader {
+        &self.header
+    }
+
+    pub fn len(&self) -> usize {
+        self.path_spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.path_spans.is_empty()
+    }
+
+    /// Image path of row `i`.
+    pub fn path(&self, i: usize) -> &str {
+        let (start, len) = self.path_spans[i];
+        std::str::from_utf8(&self.map[start..start + len]).unwrap_or_default()
+    }
+
+    /// The embedding matrix, `rows × dim` row-major, in place in the map.
+    pub fn matrix_data(&self) -> &[f32] {
+        bytemuck::try_cast_slice(&self.map[self.matrix_offset..])
+            .expect("alignment checked in open")
+    }
+
+    pub fn matrix(&self) -> ArrayView2<'_, f32> {
+        ArrayView2::from_shape((self.header.rows, self.header.dim), self.matrix_data())
+            .expect("size checked in open")
+    }
+}
+
+impl CosineIndex {
+    /// Persist the in-memory rows as `encoder_id`'s cache, stamped with
+    /// the embedding set they were read under. `stamp` must be taken
+    /// BEFORE the rows were read: if rows change in between, the file
+    /// holds newer rows than its stamp says and is merely reloaded
+    /// once too often — never trusted when it shouldn't be.
+    pub fn save_cache_file(
+        &self,
+        path: &Path,
+        encoder_id: &str,
+        stamp: &EmbeddingSetStamp,
+    ) -> io::Result<()> {
+        let header = CacheHeader {
+            encoder_id: encoder_id.to_string(),
+            dim: self.cached_images.dim(),
+            rows: self.cached_images.len(),
+            pipeline_version: EMBEDDING_PIPELINE_VERSION,
+            stamp: *stamp,
+        };
+        CacheFile::write(path, &header, &self.cached_images)?;
+        info!(
+            "cosine cache for {encoder_id} saved to {} ({} entries)",
+            path.display(),
+            self.cached_images.len()
+        );
+        Ok(())
+    }
+
+    /// Replace the cache with the file at `path` if it verifies and was
+    /// written for this encoder, pipeline version and embedding set.
+    /// Returns false (cache untouched) otherwise; the caller falls back
+    /// to the DB.
+    pub fn load_cache_file_if_fresh(
+        &mut self,
+        path: &Path,
+        encoder_id: &str,
+        stamp: &EmbeddingSetStamp,
+    ) -> bool {
+        let file = match CacheFile::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return false,
+            Err(e) => {
+                warn!("cosine cache {} unusable: {e}; will repopulate", path.display());
+                return false;
+            }
+        };
+        let h = file.header();
+        if h.encoder_id != encoder_id
+            || h.pipeline_version != EMBEDDING_PIPELINE_VERSION
+            || h.stamp != *stamp
+        {
+            debug!("cosine cache for {encoder_id} stale; refusing");
+            return false;
+        }
+
+        self.clear();
+        self.cached_images = EmbeddingMatrix::from_cache_file(file);
+        info!(
+            "cosine cache for {encoder_id} loaded from disk ({} entries)",
+            self.cached_images.len()
+        );
+        true
+    }
+
+    /// `load_cache_file_if_fresh` against `encoder_id`'s file in the
+    /// app data dir and the DB's current stamp.
+    pub fn load_cache_for(&mut self, db: &ImageDatabase, encoder_id: &str) -> bool {
+        match db.embedding_set_stamp(encoder_id) {
+            Ok(stamp) if stamp.count > 0 => self.load_cache_file_if_fresh(
+                &paths::cosine_cache_path(encoder_id),
+                encoder_id,
+                &stamp,
+            ),
+            Ok(_) => false,
+            Err(e) => {
+                warn!("embedding stamp for {encoder_id} failed: {e}");
+                false
+            }
+        }
+    }
+}
+
+/// Indexing-pipeline hook: bring `encoder_id`'s cache file and ANN
+/// graph up to date with the DB. A no-op beyond one stamp query and one
+/// mapped read when nothing changed.
+pub fn refresh_encoder_files(
+    db: &ImageDatabase,
+    encoder_id: &str,
+    is_cancelled: &dyn Fn() -> bool,
+) {
+    refresh_encoder_files_at(
+        db,
+        encoder_id,
+        &paths::cosine_cache_path(encoder_id),
+        &paths::ann_graph_path(encoder_id),
+        hnsw::ANN_MIN_IMAGES,
+        is_cancelled,
+    );
+}
+
+/// Path-explicit variant of `refresh_encoder_files`, for tests.
+pub fn refresh_encoder_files_at(
+    db: &ImageDatabase,
+    encoder_id: &str,
+    cache_path: &Path,
+    graph_path: &Path,
+    ann_min_images: usize,
+    is_cancelled: &dyn Fn() -> bool,
+) {
+    let start = Instant::now();
+    // Stamp first, rows second — see `save_cache_file`.
+    let stamp = match db.embedding_set_stamp(encoder_id) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("embedding stamp for {encoder_id} failed: {e}");
+            return;
+        }
+    };
+    if stamp.count == 0 {
+        for p in [cache_path, graph_path] {
+            if p.exists() {
+                let _ = fs::remove_file(p);
+            }
+        }
+        return;
+    }
+
+    let mut index = CosineIndex::new();
+    let fresh = index.load_cache_file_if_fresh(cache_path, encoder_id, &stamp);
+    if !fresh && !index.fill_from_db(db, encoder_id) {
+        return;
+    }
+
+    // The graph sync may reorder rows into node order; saving after it
+    // means the next load attaches without reordering.
+    let ann = hnsw::sync_graph_file(
+        &mut index.cached_images,
+        encoder_id,
+        graph_path,
+        ann_min_images,
+        is_cancelled,
+    );
+    if !fresh || ann.is_some_and(|s| s.reordered) {
+        if let Err(e) = index.save_cache_file(cache_path, encoder_id, &stamp) {
+            warn!("cosine cache write for {encoder_id} failed: {e}");
+        }
+    }
+    debug!(
+        "refresh({encoder_id}): {} rows, cache was fresh: {fresh}, {:?}",
+        index.cached_images.len(),
+        start.elapsed()
+    );
+}
+
+/// Delete the single encoder-agnostic cache earlier versions wrote.
+pub fn remove_legacy_cache_file() {
+    let legacy = paths::legacy_cosine_cache_path();
+    if legacy.exists() {
+        match fs::remove_file(&legacy) {
+            Ok(()) => info!("removed legacy cosine cache {}", legacy.display()),
+            Err(e) => warn!("could not remove legacy cosine cache: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+    use std::path::PathBuf;
+
+    fn stamp(n: u8) -> EmbeddingSetStamp {
+        EmbeddingSetStamp {
+            count: n as u64,
+            digest: [n; 32],
+        }
+    }
+
+    fn sample() -> CosineIndex {
+        let mut idx = CosineIndex::new();
+        idx.add_image(PathBuf::from("/a.jpg"), array![1.0, 2.0, 3.0]);
+        idx.add_image(PathBuf::from("/dir/ü.jpg"), array![0.5, 0.5, 0.5]);
+        idx
+    }
+
+    #[test]
+    fn cache_save_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cosine.bin");
+        sample().save_cache_file(&path, "clip_vit_b_32", &stamp(2)).unwrap();
+
+        let mut loaded = CosineIndex::new();
+        assert!(loaded.load_cache_file_if_fresh(&path, "clip_vit_b_32", &stamp(2)));
+        assert_eq!(loaded.cached_images.len(), 2);
+        assert_eq!(loaded.cached_images.path(0), &PathBuf::from("/a.jpg"));
+        assert_eq!(loaded.cached_images.path(1), &PathBuf::from("/dir/ü.jpg"));
+        // Embeddings round-trip bit-for-bit, read in place from the map.
+        assert_eq!(loaded.cached_images.row(0), &[1.0, 2.0, 3.0]);
+        assert_eq!(loaded.cached_images.is_mapped(), cfg!(not(windows)));
+
+        let file = CacheFile::open(&path).unwrap();
+        assert_eq!(file.header().dim, 3);
+        assert_eq!(file.header().pipeline_version, EMBEDDING_PIPELINE_VERSION);
+        assert_eq!(file.matrix().row(1).to_vec(), vec![0.5, 0.5, 0.5]);
+        assert_eq!(file.matrix().as_ptr() as usize % MATRIX_ALIGN, 0, "view is in the map");
+    }
+
+    #[test]
+    fn cache_refuses_other_encoder_or_stale_stamp() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cosine.bin");
+        sample().save_cache_file(&path, "dinov2_base", &stamp(2)).unwrap();
+
+        let mut loaded = CosineIndex::new();
+        assert!(!loaded.load_cache_file_if_fresh(&path, "clip_vit_b_32", &stamp(2)));
+        assert!(!loaded.load_cache_file_if_fresh(&path, "dinov2_base", &stamp(3)));
+        assert!(loaded.cached_images.is_empty());
+    }
+
+    #[test]
+    fn cache_returns_false_when_file_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut loaded = CosineIndex::new();
+        assert!(!loaded.load_cache_file_if_fresh(
+            &dir.path().join("does-not-exist.bin"),
+            "clip_vit_b_32",
+            &stamp(1)
+        ));
+    }
+
+    #[test]
+    fn cache_rejects_corrupt_and_tampered_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corrupt.bin");
+        std::fs::write(&path, b"NOT A CACHE FILE").unwrap();
+        let mut loaded = CosineIndex::new();
+        assert!(!loaded.load_cache_file_if_fresh(&path, "clip_vit_b_32", &stamp(2)));
+
+        // One flipped bit in the matrix fails the checksum.
+        sample().save_cache_file(&path, "clip_vit_b_32", &stamp(2)).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        let err = CacheFile::open(&path).err().unwrap();
+        assert!(err.to_string().contains("checksum"), "{err}");
+        assert!(!loaded.load_cache_file_if_fresh(&path, "clip_vit_b_32", &stamp(2)));
+
+        // Truncation is caught before the checksum is even computed.
+        std::fs::write(&path, &bytes[..bytes.len() - 4]).unwrap();
+        assert!(CacheFile::open(&path).is_err());
+        assert!(loaded.cached_images.is_empty());
+    }
+
+    #[test]
+    fn cache_overwrites_on_resave() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cosine.bin");
+        sample().save_cache_file(&path, "clip_vit_b_32", &stamp(2)).unwrap();
+
+        let mut idx2 = CosineIndex::new();
+        idx2.add_image(PathBuf::from("/b.jpg"), array![2.0, 2.0]);
+        idx2.save_cache_file(&path, "clip_vit_b_32", &stamp(1)).unwrap();
+
+        let mut loaded = CosineIndex::new();
+        assert!(loaded.load_cache_file_if_fresh(&path, "clip_vit_b_32", &stamp(1)));
+        assert_eq!(loaded.cached_images.len(), 1);
+        assert_eq!(loaded.cached_images.path(0), &PathBuf::from("/b.jpg"));
+        assert!(!path.with_extension("bin.tmp").exists());
+    }
+
+    #[test]
+    fn refresh_writes_and_then_reuses_the_cache_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join("cosine_cache_clip.bin");
+        let graph_path = dir.path().join("ann_clip.bin");
+        let db = ImageDatabase::new(":memory:").unwrap();
+        db.initialize().unwrap();
+        let root = db.add_root("/r".into()).unwrap();
+        for i in 0..3 {
+            db.add_image(format!("/r/{i}.jpg"), Some(root.id)).unwrap();
+            let id = db.get_image_id_by_path(&format!("/r/{i}.jpg")).unwrap();
+            db.upsert_embedding(id, "clip_vit_b_32", &[i as f32, 1.0]).unwrap();
+        }
+
+        refresh_encoder_files_at(&db, "clip_vit_b_32", &cache_path, &graph_path, 2, &|| false);
+        let stamp = db.embedding_set_stamp("clip_vit_b_32").unwrap();
+        let mut loaded = CosineIndex::new();
+        assert!(loaded.load_cache_file_if_fresh(&cache_path, "clip_vit_b_32", &stamp));
+        assert_eq!(loaded.cached_images.len(), 3);
+        assert!(graph_path.exists(), "ANN graph built past the (test) threshold");
+
+        // Nothing changed: the file is left alone.
+        let written = std::fs::metadata(&cache_path).unwrap().modified().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        refresh_encoder_files_at(&db, "clip_vit_b_32", &cache_path, &graph_path, 2, &|| false);
+        assert_eq!(std::fs::metadata(&cache_path).unwrap().modified().unwrap(), written);
+
+        // Disabling the root empties the set: both files go.
+        db.set_root_enabled(root.id, false).unwrap();
+        refresh_encoder_files_at(&db, "clip_vit_b_32", &cache_path, &graph_path, 2, &|| false);
+        assert!(!cache_path.exists() && !graph_path.exists());
+    }
+}
@@ -16,17 +16,24 @@
 //!   distribution — if all distances cluster around 0.7, the
 //!   encoder isn't discriminating; if there's a wide spread, it is)
 //! - "Is the encoder deterministic?" (self-similarity should be 1.0)
+//! - "Is the ANN graph returning what brute force would?" (recall@k,
+//!   recorded when an HNSW graph is built or attached)
 
-use ndarray::Array1;
 use serde_json::{json, Value};
-use std::path::PathBuf;
+use std::time::Instant;
 
-use super::math::cosine_similarity;
+use super::hnsw::HnswGraph;
+use super::math::{cosine_similarity, dot, score_cmp_desc};
+use super::matrix::EmbeddingMatrix;
 
 /// Maximum number of embeddings to sample for the pairwise-distance
 /// histogram. C(50, 2) = 1225 pair computations — fast even on CPU.
 const PAIRWISE_SAMPLE_SIZE: usize = 50;
 
+/// Queries per ANN recall check. Each one is a full brute-force pass,
+/// ~25 ms at 250k × 768-d, so the check stays well under a second.
+const ANN_RECALL_SAMPLE: usize = 16;
+
 /// Compute embedding-quality stats for a populated cache.
 ///
 /// Returns a JSON value with:
@@ -41,13 +48,13 @@ const PAIRWISE_SAMPLE_SIZE: usize = 50;
 ///
 /// Called once per encoder at populate time. ~few ms for a 1842
 /// embedding library.
-pub fn embedding_stats(cached_images: &[(PathBuf, Array1<f32>)]) -> Value {
+pub fn embedding_stats(cached_images: &EmbeddingMatrix) -> Value {
     if cached_images.is_empty() {
         return json!({ "count": 0, "note": "cache empty — encoder has no embeddings" });
     }
 
     let count = cached_images.len();
-    let dim = cached_images[0].1.len();
+    let dim = cached_images.dim();
 
     // L2 norms — should be ~1.0 for normalised CLIP-family encoders.
     // If image encoder norms differ wildly from text encoder norms,
@@ -56,7 +63,7 @@ pub fn embedding_stats(cached_images: &[(PathBuf, Array1<f32>)]) -> Value {
     let mut norms: Vec<f32> = Vec::with_capacity(count);
     let mut nan_count = 0usize;
     let mut inf_count = 0usize;
-    for (_, emb) in cached_images {
+    for emb in cached_images.rows() {
         let norm: f32 = emb.iter().map(|x| x * x).sum::<f32>().sqrt();
         norms.push(norm);
         for x in emb.iter() {
@@ -75,7 +82,7 @@ pub fn embedding_stats(cached_images: &[(PathBuf, Array1<f32>)]) -> Value {
     // degenerate spaces (e.g., all dims have mean ≈ 0 std ≈ 0 →
     // encoder is producing constant outputs).
     let mut dim_means: Vec<f32> = vec![0.0; dim];
-    for (_, emb) in cached_images {
+    for emb in cached_images.rows() {
         for (j, x) in emb.iter().enumerate() {
             dim_means[j] += x;
         }
@@ -84,7 +91,7 @@ pub fn embedding_stats(cached_images: &[(PathBuf, Array1<f32>)]) -> Value {
         *d /= count as f32;
     }
     let mut dim_vars: Vec<f32> = vec![0.0; dim];
-    for (_, emb) in cached_images {
+    for emb in cached_images.rows() {
         for (j, x) in emb.iter().enumerate() {
             let diff = x - dim_means[j];
             dim_vars[j] += diff * diff;
@@ -148,9 +155,7 @@ pub fn embedding_stats(cached_images: &[(PathBuf, Array1<f32>)]) -> Value {
 ///   - Wide spread [0.0, 1.0] → encoder discriminates well
 ///   - All mass in [0.95, 1.0] → likely a bug (every embedding
 ///     identical or near-identical)
-pub fn pairwise_distance_distribution(
-    cached_images: &[(PathBuf, Array1<f32>)],
-) -> Value {
+pub fn pairwise_distance_distribution(cached_images: &EmbeddingMatrix) -> Value {
     if cached_images.len() < 2 {
         return json!({ "note": "need at least 2 embeddings for pairwise — skipping" });
     }
@@ -163,7 +168,9 @@ pub fn pairwise_distance_distribution(
     let mut max_seen = f32::NEG_INFINITY;
     for i in 0..n {
         for j in (i + 1)..n {
-            let s = cosine_similarity(&cached_images[i].1, &cached_images[j].1);
+            let s = dot(cached_images.row(i), cached_images.row(j))
+                * cached_images.inv_norm(i)
+                * cached_images.inv_norm(j);
             pair_count += 1;
             sum += s as f64;
             min_seen = min_seen.min(s);
@@ -217,14 +224,12 @@ pub fn pairwise_distance_distribution(
 /// 1.0 for any embedding. If it's not, something is fundamentally
 /// wrong with either the cosine_similarity math or the embedding
 /// itself (NaN, all zeros, etc.).
-pub fn self_similarity_check(
-    cached_images: &[(PathBuf, Array1<f32>)],
-) -> Value {
+pub fn self_similarity_check(cached_images: &EmbeddingMatrix) -> Value {
     if cached_images.is_empty() {
         return json!({ "note": "cache empty — skipped" });
     }
-    let (_, emb) = &cached_images[0];
-    let s = cosine_similarity(emb, emb);
+    let emb = cached_images.row_view(0).to_owned();
+    let s = cosine_similarity(&emb, &emb);
     json!({
         "embedding_norm": (emb.iter().map(|x| x * x).sum::<f32>()).sqrt(),
         "self_cosine": s,
@@ -234,6 +239,76 @@ pub fn self_similarity_check(
     })
 }
 
+/// Recall@k of an HNSW graph against brute force, over up to
+/// `ANN_RECALL_SAMPLE` cached embeddings used as queries (evenly
+/// spaced, so the same library gives the same sample).
+///
+/// Recorded whenever a graph is built or attached. Recall drifting
+/// below ~0.9 means the graph needs a larger `ef_search` or a rebuild;
+/// the timings show what the graph is buying per query.
+pub fn ann_recall(
+    cached_images: &EmbeddingMatrix,
+    graph: &HnswGraph,
+    k: usize,
+) -> Value {
+    if cached_images.is_empty() || !graph.is_aligned_with(cached_images) {
+        return json!({ "note": "graph not attached to this cache — skipped" });
+    }
+    let n = cached_images.len();
+    let queries = n.min(ANN_RECALL_SAMPLE);
+    let ef = graph.params().ef_search;
+    let mut recalls: Vec<f32> = Vec::with_capacity(queries);
+    let mut scores: Vec<f32> = Vec::new();
+    let mut brute_us = 0u128;
+    let mut ann_us = 0u128;
+    for q in 0..queries {
+        let query = cached_images.row(q * n / queries);
+
+        let started = Instant::now();
+        cached_images.scores_into(query, &mut scores);
+        let mut exact: Vec<(usize, f32)> = scores.iter().copied().enumerate().collect();
+        let want = k.min(exact.len());
+        if want == 0 {
+            break;
+        }
+        exact.select_nth_unstable_by(want - 1, score_cmp_desc);
+        exact.truncate(want);
+        brute_us += started.elapsed().as_micros();
+
+        let started = Instant::now();
+        let approx = graph.search(cached_images, query, want, ef);
+        ann_us += started.elapsed().as_micros();
+
+        let hits = exact
+            .iter()
+            .filter(|(i, _)| approx.iter().any(|(j, _)| j == i))
+            .count();
+        recalls.push(hits as f32 / want as f32);
+    }
+    if recalls.is_empty() {
+        return json!({ "note": "k = 0 — skipped" });
+    }
+    let count = recalls.len();
+    let mean = recalls.iter().sum::<f32>() / count as f32;
+    let min = recalls.iter().cloned().fold(f32::INFINITY, f32::min);
+    json!({
+        "k": k,
+        "ef_search": ef,
+        "queries": count,
+        "recall_mean": mean,
+        "recall_min": min,
+        "brute_force_us_per_query": brute_us / count as u128,
+        "ann_us_per_query": ann_us / count as u128,
+        "interpretation": if mean >= 0.95 {
+            "good — ANN results match brute force"
+        } else if mean >= 0.85 {
+            "acceptable — a few true neighbours missed per query"
+        } else {
+            "LOW — graph degraded or ef_search too small; worth a rebuild"
+        },
+    })
+}
+
 /// Compute summary statistics for a list of search-result scores.
 /// Embedded in the search_query diagnostic so the report has a
 /// quick read on "are these scores high or noise-floor".
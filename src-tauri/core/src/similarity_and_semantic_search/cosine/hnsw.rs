@@ -0,0 +1,861 @@
+//! Approximate nearest-neighbour graph (HNSW) over one encoder's
+//! cosine cache.
+//!
+//! Brute force is one cosine per cached image per query — ~2 ms at
+//! 2k images, but linear, and fusion pays it once per enabled encoder.
+//! At 250k images "View Similar" spends most of its time here. A
+//! Hierarchical Navigable Small World graph (Malkov & Yashunin, 2016)
+//! answers the same top-K query by walking a few hundred nodes.
+//!
+//! Design points:
+//!
+//! - The graph stores only links, never vectors. Node `i` *is* row
+//!   `i` of the `EmbeddingMatrix` it is attached to; scores are
+//!   computed against that matrix (and its precomputed norms), so
+//!   there is one copy of every embedding in memory. `sync` reorders
+//!   the matrix into node order to keep that true.
+//! - Each node remembers its path and a checksum of its vector. That's
+//!   what lets a persisted graph be reconciled with the DB: unchanged
+//!   rows keep their links, removed or re-encoded rows are unlinked
+//!   (their neighbours are re-wired through the removed node's own
+//!   neighbours), new rows are inserted. Only a large churn forces a
+//!   rebuild.
+//! - Built and persisted by the indexing pipeline after each encoder
+//!   phase (`sync_graph_file`), one file per encoder next to the
+//!   cosine cache. Search-time populates only attach a persisted
+//!   graph, patching small drift in memory; anything bigger stays on
+//!   brute force until the next pipeline run catches the file up.
+//! - Below `ANN_MIN_IMAGES` no graph is built or attached. Brute force
+//!   is exact and, at that size, fast enough.
+
+use super::diagnostics;
+use super::math::{dot, inv_norm};
+use super::matrix::EmbeddingMatrix;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use tracing::{info, warn};
+
+/// Libraries with fewer embeddings than this (per encoder) stay on
+/// brute force.
+pub const ANN_MIN_IMAGES: usize = 20_000;
+
+/// A search-time attach patches at most this fraction of drift in
+/// memory; beyond it the cache stays on brute force until the next
+/// pipeline run syncs the file.
+pub const ANN_ATTACH_MAX_DRIFT: f32 = 0.02;
+
+/// Bumped whenever the on-disk layout changes; older files are
+/// ignored and rebuilt.
+const FORMAT_VERSION: u32 = 1;
+
+/// Cap on node levels. With m=16 the expected top level at 250k nodes
+/// is ~4; the cap only guards against a pathological level draw.
+const MAX_LEVEL: usize = 16;
+
+/// How often a long build polls its cancellation hook.
+const CANCEL_POLL_EVERY: usize = 256;
+
+/// A persisted graph whose drift (removed + new rows) exceeds this
+/// fraction of the library is rebuilt rather than patched.
+const MAX_PATCH_FRACTION: f32 = 0.3;
+
+/// Graph construction and search parameters. Persisted with the graph;
+/// a file built with different parameters is rebuilt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HnswParams {
+    /// Links per node on the upper layers. Layer 0 keeps `2 * m`.
+    pub m: usize,
+    /// Candidate list size while inserting. Higher = better graph,
+    /// slower build.
+    pub ef_construction: usize,
+    /// Default candidate list size while searching. Raised to `k` when
+    /// a query asks for more.
+    pub ef_search: usize,
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        HnswParams {
+            m: 16,
+            ef_construction: 100,
+            ef_search: 64,
+        }
+    }
+}
+
+/// What a `sync` did to a graph.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SyncStats {
+    pub kept: usize,
+    pub removed: usize,
+    pub inserted: usize,
+    pub rebuilt: bool,
+    /// `rows` were permuted into node order.
+    pub reordered: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HnswGraph {
+    version: u32,
+    params: HnswParams,
+    dim: usize,
+    /// Per node: the image path, as stored in `images.path`.
+    keys: Vec<String>,
+    /// Per node: `vector_checksum` of the embedding it was linked with.
+    checksums: Vec<u64>,
+    /// `links[node][layer]` — neighbour node ids. A node's level is
+    /// `links[node].len() - 1`.
+    links: Vec<Vec<Vec<u32>>>,
+    entry: Option<u32>,
+    /// Set by `build` / `sync`: node ids index the rows they were run
+    /// against. A freshly loaded graph isn't, so never persisted.
+    #[serde(skip)]
+    synced: bool,
+}
+
+/// Similarity tagged with its node, ordered by similarity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Scored {
+    sim: f32,
+    id: u32,
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sim
+            .total_cmp(&other.sim)
+            .then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+/// Fixed-size visited set for one layer search.
+struct Visited(Vec<u64>);
+
+impl Visited {
+    fn new(len: usize) -> Self {
+        Visited(vec![0; len.div_ceil(64)])
+    }
+
+    /// True if `id` was not yet visited.
+    fn insert(&mut self, id: u32) -> bool {
+        let (word, bit) = (id as usize / 64, 1u64 << (id % 64));
+        let fresh = self.0[word] & bit == 0;
+        self.0[word] |= bit;
+        fresh
+    }
+}
+
+/// NaN scores (NaN components in a vector) rank below everything.
+fn finite(sim: f32) -> f32 {
+    if sim.is_nan() {
+        f32::NEG_INFINITY
+    } else {
+        sim
+    }
+}
+
+/// First 8 bytes of the BLAKE3 of the vector's f32 bytes. Detects a
+/// re-encoded image whose path didn't change.
+pub fn vector_checksum(v: &[f32]) -> u64 {
+    let hash = blake3::hash(bytemuck::cast_slice(v));
+    let mut first = [0u8; 8];
+    first.copy_from_slice(&hash.as_bytes()[..8]);
+    u64::from_le_bytes(first)
+}
+
+/// Level for a new node, drawn from the usual exponential distribution
+/// (`mL = 1 / ln m`). Seeded by the node id so builds are
+/// reproducible without persisting RNG state.
+fn level_for(id: usize, m: usize) -> usize {
+    // splitmix64
+    let mut x = (id as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 31;
+    // Uniform in (0, 1].
+    let u = ((x >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+    let ml = 1.0 / (m.max(2) as f64).ln();
+    ((-u.ln() * ml) as usize).min(MAX_LEVEL)
+}
+
+impl HnswGraph {
+    pub fn new(params: HnswParams, dim: usize) -> Self {
+        HnswGraph {
+            version: FORMAT_VERSION,
+            params,
+            dim,
+            keys: Vec::new(),
+            checksums: Vec::new(),
+            links: Vec::new(),
+            entry: None,
+            synced: false,
+        }
+    }
+
+    /// Build a graph over `rows` from scratch. None if `rows` is empty.
+    /// A cancelled build returns the graph over the rows inserted so
+    /// far — still valid, and `sync` picks up the rest next time.
+    pub fn build(
+        params: HnswParams,
+        rows: &EmbeddingMatrix,
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Option<Self> {
+        if rows.is_empty() {
+            return None;
+        }
+        let mut graph = HnswGraph::new(params, rows.dim());
+        graph.synced = true;
+        graph.extend(rows, is_cancelled);
+        Some(graph)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn params(&self) -> HnswParams {
+        self.params
+    }
+
+    /// True when node `i` is `rows[i]` for every row — the only state
+    /// in which `search` may be used against `rows`.
+    pub fn is_aligned_with(&self, rows: &EmbeddingMatrix) -> bool {
+        self.synced && self.len() == rows.len() && rows.dim() == self.dim
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.params.m * 2
+        } else {
+            self.params.m
+        }
+    }
+
+    fn top_level(&self) -> usize {
+        self.entry
+            .map(|e| self.links[e as usize].len() - 1)
+            .unwrap_or(0)
+    }
+
+    fn sim_nodes(&self, rows: &EmbeddingMatrix, a: u32, b: u32) -> f32 {
+        let (a, b) = (a as usize, b as usize);
+        finite(dot(rows.row(a), rows.row(b)) * rows.inv_norm(a) * rows.inv_norm(b))
+    }
+
+    /// Top-K `(row index, cosine similarity)` for `query`, best first.
+    /// `ef` is the candidate list size (raised to `k` if smaller). The
+    /// graph must be aligned with `rows`.
+    pub fn search(
+        &self,
+        rows: &EmbeddingMatrix,
+        query: &[f32],
+        k: usize,
+        ef: usize,
+    ) -> Vec<(usize, f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        if k == 0 || query.len() != self.dim {
+            return Vec::new();
+        }
+        let q_inv = inv_norm(query);
+        let score = |n: u32| {
+            let n = n as usize;
+            finite(dot(query, rows.row(n)) * q_inv * rows.inv_norm(n))
+        };
+
+        let mut ep = vec![Scored {
+            sim: score(entry),
+            id: entry,
+        }];
+        for layer in (1..=self.top_level()).rev() {
+            ep = self.search_layer(&ep, 1, layer, &score);
+        }
+        let mut found = self.search_layer(&ep, ef.max(k), 0, &score);
+        found.truncate(k);
+        found.into_iter().map(|s| (s.id as usize, s.sim)).collect()
+    }
+
+    /// Greedy best-first search of one layer. Returns up to `ef` nodes,
+    /// best first.
+    fn search_layer(
+        &self,
+        entry: &[Scored],
+        ef: usize,
+        layer: usize,
+        score: &dyn Fn(u32) -> f32,
+    ) -> Vec<Scored> {
+        let mut visited = Visited::new(self.links.len());
+        let mut candidates: BinaryHeap<Scored> = BinaryHeap::new();
+        let mut results: BinaryHeap<Reverse<Scored>> = BinaryHeap::new();
+        for e in entry {
+            if visited.insert(e.id) {
+                candidates.push(*e);
+                results.push(Reverse(*e));
+            }
+        }
+        while results.len() > ef {
+            results.pop();
+        }
+
+        while let Some(current) = candidates.pop() {
+            let worst = results.peek().map(|r| r.0.sim).unwrap_or(f32::NEG_INFINITY);
+            if results.len() >= ef && current.sim < worst {
+                break;
+            }
+            let Some(neighbours) = self.links[current.id as usize].get(layer) else {
+                continue;
+            };
+            for &n in neighbours {
+                if !visited.insert(n) {
+                    continue;
+                }
+                let s = Scored { sim: score(n), id: n };
+                let worst = results.peek().map(|r| r.0.sim).unwrap_or(f32::NEG_INFINITY);
+                if results.len() < ef || s.sim > worst {
+                    candidates.push(s);
+                    results.push(Reverse(s));
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        let mut out: Vec<Scored> = results.into_iter().map(|r| r.0).collect();
+        out.sort_unstable_by(|a, b| b.cmp(a));
+        out
+    }
+
+    /// Neighbour selection heuristic (the paper's algorithm 4): take a
+    /// candidate only if it's closer to the base node than to every
+    /// neighbour already taken, which keeps links pointing in different
+    /// directions; then top up with the best of the rest. `candidates`
+    /// are scored against the base node, best first.
+    fn select_neighbours(
+        &self,
+        rows: &EmbeddingMatrix,
+        candidates: &[Scored],
+        m: usize,
+    ) -> Vec<u32> {
+        let mut selected: Vec<Scored> = Vec::with_capacity(m);
+        let mut pruned: Vec<Scored> = Vec::new();
+        for c in candidates {
+            if selected.len() >= m {
+                break;
+            }
+            if selected
+                .iter()
+                .all(|s| self.sim_nodes(rows, c.id, s.id) < c.sim)
+            {
+                selected.push(*c);
+            } else {
+                pruned.push(*c);
+            }
+        }
+        for p in pruned {
+            if selected.len() >= m {
+                break;
+            }
+            selected.push(p);
+        }
+        selected.into_iter().map(|s| s.id).collect()
+    }
+
+    /// Re-select `pool` as the layer-`layer` links of `node`.
+    fn relink(
+        &self,
+        rows: &EmbeddingMatrix,
+        node: u32,
+        layer: usize,
+        mut pool: Vec<u32>,
+    ) -> Vec<u32> {
+        pool.sort_unstable();
+        pool.dedup();
+        pool.retain(|&n| n != node);
+        let mut scored: Vec<Scored> = pool
+            .into_iter()
+            .map(|n| Scored {
+                sim: self.sim_nodes(rows, node, n),
+                id: n,
+            })
+            .collect();
+        scored.sort_unstable_by(|a, b| b.cmp(a));
+        self.select_neighbours(rows, &scored, self.max_links(layer))
+    }
+
+    /// Insert every row past the end of the graph.
+    fn extend(&mut self, rows: &EmbeddingMatrix, is_cancelled: &dyn Fn() -> bool) {
+        for id in self.len()..rows.len() {
+            if id % CANCEL_POLL_EVERY == 0 && is_cancelled() {
+                info!("hnsw build cancelled at {id}/{} nodes", rows.len());
+                return;
+            }
+            self.insert(rows, id);
+        }
+    }
+
+    /// Insert `rows[id]` as node `id` (which must be `self.len()`).
+    fn insert(&mut self, rows: &EmbeddingMatrix, id: usize) {
+        debug_assert_eq!(id, self.len());
+        self.keys.push(rows.path(id).to_string_lossy().into_owned());
+        self.checksums.push(vector_checksum(rows.row(id)));
+        let level = level_for(id, self.params.m);
+        self.links.push(vec![Vec::new(); level + 1]);
+        let node = id as u32;
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(node);
+            return;
+        };
+        let top = self.top_level();
+
+        // Find neighbours first (read-only), link afterwards.
+        let mut chosen: Vec<(usize, Vec<u32>)> = Vec::with_capacity(level.min(top) + 1);
+        {
+            let score = |n: u32| self.sim_nodes(rows, node, n);
+            let mut ep = vec![Scored {
+                sim: score(entry),
+                id: entry,
+            }];
+            for layer in (level + 1..=top).rev() {
+                ep = self.search_layer(&ep, 1, layer, &score);
+            }
+            for layer in (0..=level.min(top)).rev() {
+                let found = self.search_layer(&ep, self.params.ef_construction, layer, &score);
+                chosen.push((layer, self.select_neighbours(rows, &found, self.max_links(layer))));
+                ep = found;
+            }
+        }
+
+        for (layer, neighbours) in chosen {
+            let max = self.max_links(layer);
+            for &n in &neighbours {
+                let list = &mut self.links[n as usize][layer];
+                list.push(node);
+                if list.len() > max {
+                    let pool = list.clone();
+                    self.links[n as usize][layer] = self.relink(rows, n, layer, pool);
+                }
+            }
+            self.links[id][layer] = neighbours;
+        }
+        if level > top {
+            self.entry = Some(node);
+        }
+    }
+
+    /// Reconcile the graph with `rows` (a fresh read of the encoder's
+    /// embeddings). Rows matching a node by path and vector checksum
+    /// keep their links; other nodes are removed; new rows are
+    /// inserted. On return `rows` is reordered into node order — new
+    /// rows last — and the graph is aligned with it, unless a
+    /// cancellation stopped the inserts early.
+    ///
+    /// Returns None, touching neither the graph nor `rows`, when the
+    /// drift exceeds `max_patch` rows or the dimensions don't match;
+    /// the caller rebuilds or falls back to brute force.
+    pub fn sync(
+        &mut self,
+        rows: &mut EmbeddingMatrix,
+        max_patch: usize,
+        is_cancelled: &dyn Fn() -> bool,
+    ) -> Option<SyncStats> {
+        if !rows.is_empty() && rows.dim() != self.dim {
+            return None;
+        }
+        let row_by_key: HashMap<String, usize> = rows
+            .paths()
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.to_string_lossy().into_owned(), i))
+            .collect();
+        let mut claimed = vec![false; rows.len()];
+        let mut row_of: Vec<Option<usize>> = Vec::with_capacity(self.len());
+        for (key, sum) in self.keys.iter().zip(&self.checksums) {
+            let row = row_by_key
+                .get(key)
+                .copied()
+                .filter(|&r| !claimed[r] && vector_checksum(rows.row(r)) == *sum);
+            if let Some(r) = row {
+                claimed[r] = true;
+            }
+            row_of.push(row);
+        }
+        drop(row_by_key);
+
+        let kept = row_of.iter().flatten().count();
+        let removed = self.len() - kept;
+        let added = rows.len() - kept;
+        if removed + added > max_patch {
+            return None;
+        }
+
+        // Reorder rows into node order so node id == row index.
+        let in_order = row_of.iter().enumerate().all(|(i, r)| *r == Some(i));
+        if !in_order {
+            let order: Vec<usize> = row_of
+                .iter()
+                .flatten()
+                .copied()
+                .chain((0..rows.len()).filter(|&r| !claimed[r]))
+                .collect();
+            rows.permute(&order);
+        }
+        self.synced = true;
+        if removed > 0 {
+            self.remove_nodes(rows, &row_of);
+        }
+        self.extend(rows, is_cancelled);
+
+        Some(SyncStats {
+            kept,
+            removed,
+            inserted: self.len() - kept,
+            rebuilt: false,
+            reordered: !in_order,
+        })
+    }
+
+    /// Drop the nodes whose `row_of` entry is None and compact ids.
+    /// `rows` is already in post-compaction order. Each surviving list
+    /// that lost a neighbour is re-selected from its remaining links
+    /// plus the removed neighbour's links, so paths that ran through
+    /// the removed node stay connected.
+    fn remove_nodes(&mut self, rows: &EmbeddingMatrix, row_of: &[Option<usize>]) {
+        let mut new_id: Vec<Option<u32>> = Vec::with_capacity(row_of.len());
+        let mut next = 0u32;
+        for r in row_of {
+            new_id.push(r.map(|_| {
+                next += 1;
+                next - 1
+            }));
+        }
+
+        let old_links = std::mem::take(&mut self.links);
+        let mut links: Vec<Vec<Vec<u32>>> = Vec::with_capacity(next as usize);
+        for (old, layers) in old_links.iter().enumerate() {
+            let Some(node) = new_id[old] else {
+                continue;
+            };
+            let mut new_layers = Vec::with_capacity(layers.len());
+            for (layer, neighbours) in layers.iter().enumerate() {
+                let mut kept: Vec<u32> = Vec::with_capacity(neighbours.len());
+                let mut lost = false;
+                for &n in neighbours {
+                    match new_id[n as usize] {
+                        Some(id) => kept.push(id),
+                        None => {
+                            lost = true;
+                            if let Some(through) = old_links[n as usize].get(layer) {
+                                kept.extend(through.iter().filter_map(|&x| new_id[x as usize]));
+                            }
+                        }
+                    }
+                }
+                new_layers.push(if lost {
+                    self.relink(rows, node, layer, kept)
+                } else {
+                    kept
+                });
+            }
+            links.push(new_layers);
+        }
+
+        let mut old = 0;
+        self.keys.retain(|_| {
+            old += 1;
+            new_id[old - 1].is_some()
+        });
+        let mut old = 0;
+        self.checksums.retain(|_| {
+            old += 1;
+            new_id[old - 1].is_some()
+        });
+        self.entry = self
+            .entry
+            .and_then(|e| new_id[e as usize])
+            .or_else(|| {
+                // Entry point was removed: promote the highest node.
+                (0..links.len())
+                    .max_by_key(|&i| (links[i].len(), Reverse(i)))
+                    .map(|i| i as u32)
+            });
+        self.links = links;
+    }
+
+    /// Persist to `path`. Failure is logged, not fatal — the next
+    /// pipeline run rebuilds.
+    pub fn save_to_path(&self, path: &Path) {
+        match bincode::serialize(self) {
+            Ok(bytes) => match fs::write(path, bytes) {
+                Ok(_) => info!("hnsw graph saved to {} ({} nodes)", path.display(), self.len()),
+                Err(e) => warn!("hnsw graph write failed: {e}"),
+            },
+            Err(e) => warn!("hnsw graph serialise failed: {e}"),
+        }
+    }
+
+    /// Load a graph saved by `save_to_path`. None if the file is
+    /// missing, corrupt, or from another format version. The result
+    /// must be `sync`ed against a cache before it can be searched.
+    pub fn load_from_path(path: &Path) -> Option<Self> {
+        let bytes = fs::read(path).ok()?;
+        let graph: HnswGraph = match bincode::deserialize(&bytes) {
+            Ok(g) => g,
+            Err(e) => {
+                warn!("hnsw graph deserialise failed: {e}; will rebuild");
+                return None;
+            }
+        };
+        let consistent = graph.version == FORMAT_VERSION
+            && graph.links.len() == graph.keys.len()
+            && graph.checksums.len() == graph.keys.len()
+            && graph.entry.is_none_or(|e| (e as usize) < graph.len())
+            && graph
+                .links
+                .iter()
+                .flatten()
+                .flatten()
+                .all(|&n| (n as usize) < graph.len());
+        consistent.then_some(graph)
+    }
+}
+
+/// Bring the persisted graph for `encoder_id` at `path` up to date
+/// with `rows` (the encoder's current embeddings): load, `sync` (or
+/// rebuild past `MAX_PATCH_FRACTION` drift), save. Encoders with fewer
+/// than `min_images` embeddings have their graph file removed instead.
+///
+/// Called from `cache::refresh_encoder_files` after the encoder phase,
+/// so each run links exactly the rows `upsert_embeddings_batch` landed
+/// since the last one. `rows` may come back reordered into node order
+/// (`SyncStats::reordered`). Returns None when no graph applies.
+pub fn sync_graph_file(
+    rows: &mut EmbeddingMatrix,
+    encoder_id: &str,
+    path: &Path,
+    min_images: usize,
+    is_cancelled: &dyn Fn() -> bool,
+) -> Option<SyncStats> {
+    let start = Instant::now();
+    if rows.len() < min_images {
+        if path.exists() {
+            let _ = fs::remove_file(path);
+        }
+        return None;
+    }
+
+    let params = HnswParams::default();
+    let max_patch = (rows.len() as f32 * MAX_PATCH_FRACTION) as usize;
+    let patched = HnswGraph::load_from_path(path)
+        .filter(|g| g.params == params && g.dim == rows.dim())
+        .and_then(|mut g| g.sync(rows, max_patch, is_cancelled).map(|s| (g, s)));
+    let (graph, stats) = match patched {
+        Some(p) => p,
+        None => {
+            let graph = HnswGraph::build(params, rows, is_cancelled)?;
+            let stats = SyncStats {
+                inserted: graph.len(),
+                rebuilt: true,
+                ..SyncStats::default()
+            };
+            (graph, stats)
+        }
+    };
+    graph.save_to_path(path);
+
+    let elapsed_ms = start.elapsed().as_millis() as u64;
+    info!(
+        "hnsw sync({encoder_id}): {} nodes, +{} -{} (rebuilt: {}) in {elapsed_ms} ms",
+        graph.len(),
+        stats.inserted,
+        stats.removed,
+        stats.rebuilt
+    );
+    let recall = if graph.is_aligned_with(rows) {
+        diagnostics::ann_recall(rows, &graph, 10)
+    } else {
+        serde_json::json!({ "note": "build cancelled before completion — recall skipped" })
+    };
+    crate::perf::record_diagnostic(
+        "ann_graph_synced",
+        serde_json::json!({
+            "encoder_id": encoder_id,
+            "nodes": graph.len(),
+            "stats": stats,
+            "duration_ms": elapsed_ms,
+            "recall": recall,
+        }),
+    );
+    Some(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use std::path::PathBuf;
+    use rand::{Rng, SeedableRng};
+
+    fn random_rows(n: usize, dim: usize, seed: u64) -> EmbeddingMatrix {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut rows = EmbeddingMatrix::new();
+        for i in 0..n {
+            let v: Vec<f32> = (0..dim).map(|_| rng.random_range(-1.0..1.0)).collect();
+            rows.push(PathBuf::from(format!("/lib/{seed}_{i}.jpg")), &v);
+        }
+        rows
+    }
+
+    fn brute_top_k(rows: &EmbeddingMatrix, q: &[f32], k: usize) -> Vec<usize> {
+        let mut scores = Vec::new();
+        rows.scores_into(q, &mut scores);
+        let mut scored: Vec<(usize, f32)> = scores.into_iter().enumerate().collect();
+        scored.sort_by(super::super::math::score_cmp_desc);
+        scored.into_iter().take(k).map(|(i, _)| i).collect()
+    }
+
+    fn recall(graph: &HnswGraph, rows: &EmbeddingMatrix, queries: usize) -> f32 {
+        let k = 10;
+        let mut hits = 0;
+        for qi in 0..queries {
+            let q = rows.row(qi * rows.len() / queries);
+            let truth = brute_top_k(rows, q, k);
+            let got: Vec<usize> = graph
+                .search(rows, q, k, graph.params().ef_search)
+                .into_iter()
+                .map(|(i, _)| i)
+                .collect();
+            hits += truth.iter().filter(|t| got.contains(t)).count();
+        }
+        hits as f32 / (queries * k) as f32
+    }
+
+    #[test]
+    fn search_matches_brute_force_closely() {
+        let rows = random_rows(1_000, 24, 1);
+        let graph = HnswGraph::build(HnswParams::default(), &rows, &|| false).unwrap();
+        assert!(graph.is_aligned_with(&rows));
+        let r = recall(&graph, &rows, 50);
+        assert!(r >= 0.9, "recall@10 too low: {r}");
+    }
+
+    #[test]
+    fn search_scores_are_cosine_similarities() {
+        let rows = random_rows(300, 16, 2);
+        let graph = HnswGraph::build(HnswParams::default(), &rows, &|| false).unwrap();
+        let q = rows.row(7);
+        let hits = graph.search(&rows, q, 5, 64);
+        assert_eq!(hits[0].0, 7, "a stored vector finds itself first");
+        for (i, s) in hits {
+            let exact = super::super::math::cosine_similarity(
+                &rows.row_view(7).to_owned(),
+                &rows.row_view(i).to_owned(),
+            );
+            assert!((s - exact).abs() < 1e-4);
+        }
+        assert!(graph.search(&rows, &[0.0; 3], 5, 64).is_empty(), "dim mismatch");
+    }
+
+    #[test]
+    fn sync_patches_removed_changed_and_new_rows() {
+        let rows = random_rows(800, 16, 3);
+        let mut graph = HnswGraph::build(HnswParams::default(), &rows, &|| false).unwrap();
+
+        // Drop 60 rows, re-encode 10, add 80, and shuffle the order
+        // the DB hands them back in.
+        let mut next = EmbeddingMatrix::new();
+        for (i, (p, v)) in rows.iter().enumerate().skip(60) {
+            let v: Vec<f32> = if i < 70 { v.iter().map(|x| -x).collect() } else { v.to_vec() };
+            next.push(p.clone(), &v);
+        }
+        let added = random_rows(80, 16, 4);
+        for (p, v) in added.iter() {
+            next.push(p.clone(), v);
+        }
+        let reversed: Vec<usize> = (0..next.len()).rev().collect();
+        next.permute(&reversed);
+
+        let stats = graph.sync(&mut next, 500, &|| false).unwrap();
+        assert_eq!(stats.removed, 70);
+        assert_eq!(stats.kept, 730);
+        assert_eq!(stats.inserted, 90);
+        assert!(graph.is_aligned_with(&next));
+        let r = recall(&graph, &next, 50);
+        assert!(r >= 0.9, "recall@10 after patch too low: {r}");
+
+        // Nothing changed: a second sync is a no-op.
+        let again = graph.sync(&mut next, 0, &|| false).unwrap();
+        assert_eq!((again.removed, again.inserted), (0, 0));
+    }
+
+    #[test]
+    fn sync_refuses_drift_above_max_patch() {
+        let rows = random_rows(200, 8, 5);
+        let mut graph = HnswGraph::build(HnswParams::default(), &rows, &|| false).unwrap();
+        let mut other = random_rows(200, 8, 6);
+        let before = other.clone();
+        assert!(graph.sync(&mut other, 50, &|| false).is_none());
+        assert_eq!(other, before, "rows untouched on refusal");
+        assert_eq!(graph.len(), 200);
+    }
+
+    #[test]
+    fn cancelled_build_is_a_valid_prefix_that_sync_completes() {
+        let rows = random_rows(1_000, 16, 7);
+        let polls = std::cell::Cell::new(0);
+        let partial = HnswGraph::build(HnswParams::default(), &rows, &|| {
+            polls.set(polls.get() + 1);
+            polls.get() > 2
+        })
+        .unwrap();
+        assert_eq!(partial.len(), 2 * CANCEL_POLL_EVERY);
+        assert!(!partial.is_aligned_with(&rows));
+
+        let mut graph = partial;
+        let mut rows = rows;
+        let stats = graph.sync(&mut rows, usize::MAX, &|| false).unwrap();
+        assert_eq!(stats.inserted, 1_000 - 2 * CANCEL_POLL_EVERY);
+        assert!(graph.is_aligned_with(&rows));
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ann_test.bin");
+        let mut rows = random_rows(500, 16, 8);
+        let graph = HnswGraph::build(HnswParams::default(), &rows, &|| false).unwrap();
+        graph.save_to_path(&path);
+
+        let mut loaded = HnswGraph::load_from_path(&path).unwrap();
+        assert!(!loaded.is_aligned_with(&rows), "a loaded graph must be synced first");
+        let stats = loaded.sync(&mut rows, 0, &|| false).unwrap();
+        assert_eq!(stats.kept, 500);
+        let q = rows.row(3);
+        assert_eq!(loaded.search(&rows, q, 10, 64), graph.search(&rows, q, 10, 64));
+
+        std::fs::write(&path, b"junk").unwrap();
+        assert!(HnswGraph::load_from_path(&path).is_none());
+    }
+}
@@ -1,13 +1,26 @@
-use super::math::{cosine_similarity, score_cmp_desc};
-use crate::db;
+use super::hnsw::{self, HnswGraph};
+use super::math::{contiguous, cosine_similarity, score_cmp_desc};
+use super::matrix::EmbeddingMatrix;
+use crate::{db, paths};
 use ndarray::Array1;
 use rand::prelude::*;
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::time::Instant;
 use tracing::{debug, info, warn};
 
+/// With an ANN graph attached, `get_similar_images` samples from at
+/// most this many nearest neighbours instead of the top 20% of the
+/// library. At 250k images 20% is 50k — sampling from that is noise,
+/// and it would cost a full scan anyway.
+const ANN_DIVERSITY_POOL: usize = 500;
+
 pub struct CosineIndex {
-    pub cached_images: Vec<(PathBuf, Array1<f32>)>,
+    /// One row per image, contiguous — see `matrix.rs`.
+    pub cached_images: EmbeddingMatrix,
+    /// Reusable per-row score buffer for brute-force queries, filled
+    /// by `EmbeddingMatrix::scores_into`.
+    pub(super) scores: Vec<f32>,
     /// Reusable scratch buffer for per-query similarity calculations.
     /// Holds `(index_into_cached_images, similarity)` tuples — keyed
     /// by index so the inner loop never clones a `PathBuf`. Cleared
@@ -18,6 +31,10 @@ pub struct CosineIndex {
     /// `get_similar_images`) to make warm queries effectively
     /// allocation-free in the inner loop.
     pub(super) scratch: Vec<(usize, f32)>,
+    /// Optional HNSW graph over `cached_images` (see `hnsw.rs`). Only
+    /// consulted while it is aligned with the cache — any `add_image`
+    /// or repopulate without a re-attach drops back to brute force.
+    pub(super) ann: Option<HnswGraph>,
 }
 
 impl Default for CosineIndex {
@@ -29,41 +46,104 @@ impl Default for CosineIndex {
 impl CosineIndex {
     pub fn new() -> Self {
         CosineIndex {
-            cached_images: Vec::new(),
+            cached_images: EmbeddingMatrix::new(),
+            scores: Vec::new(),
             scratch: Vec::new(),
+            ann: None,
         }
     }
 
+    /// Append one image. Refused (with a warning) if its width differs
+    /// from the rows already cached.
     pub fn add_image(&mut self, path: PathBuf, embedding: Array1<f32>) {
-        self.cached_images.push((path, embedding));
+        self.cached_images.push(path, &contiguous(&embedding));
     }
 
-    /// Populate the in-memory index from the per-encoder embeddings
-    /// table, picking only rows for the given encoder_id.
-    ///
-    /// Used by the encoder-picker dispatch: when the user switches
-    /// the chosen image encoder, the cache is wiped and repopulated
-    /// from this method. The on-disk embeddings stay intact (one row
-    /// per (image_id, encoder_id)) so swapping back to a previously-
-    /// used encoder is instant — the embeddings are already there.
-    ///
-    /// Special case: for `clip_vit_b_32` the new embeddings table
-    /// might be empty for users who haven't re-indexed under the new
-    /// schema. Falls back to the legacy `images.embedding` column
-    /// (`get_all_embeddings`) so those users still get results.
-    #[tracing::instrument(name = "cosine.populate_for_encoder", skip(self, db))]
-    pub fn populate_from_db_for_encoder(
-        &mut self,
-        db: &db::ImageDatabase,
-        encoder_id: &str,
-    ) {
-        let start = Instant::now();
-        info!("populate_from_db_for_encoder({encoder_id})");
+    /// Empty the cache and drop any attached ANN graph.
+    pub fn clear(&mut self) {
+        self.cached_images.clear();
+        self.ann = None;
+    }
+
+    /// Attach an HNSW graph, syncing it against `cached_images` first
+    /// (which reorders the cache into node order). Returns false —
+    /// leaving the index on brute force — if more than `max_drift`
+    /// rows would have to be removed or inserted.
+    pub fn attach_ann(&mut self, mut graph: HnswGraph, max_drift: usize) -> bool {
+        self.ann = None;
+        if graph.sync(&mut self.cached_images, max_drift, &|| false).is_none()
+            || !graph.is_aligned_with(&self.cached_images)
+        {
+            return false;
+        }
+        self.ann = Some(graph);
+        true
+    }
+
+    /// True when retrieval is going through the ANN graph.
+    pub fn has_ann(&self) -> bool {
+        self.ann
+            .as_ref()
+            .is_some_and(|g| g.is_aligned_with(&self.cached_images))
+    }
+
+    /// The attached graph, if it can answer a query of this dimension.
+    fn active_ann(&self, embedding: &Array1<f32>) -> Option<&HnswGraph> {
+        self.ann
+            .as_ref()
+            .filter(|g| g.is_aligned_with(&self.cached_images) && g.dim() == embedding.len())
+    }
+
+    /// Load the pipeline-built graph for `encoder_id` and attach it,
+    /// patching up to `ANN_ATTACH_MAX_DRIFT` of drift in memory. Caches
+    /// below `ANN_MIN_IMAGES` never get a graph.
+    fn attach_persisted_ann(&mut self, encoder_id: &str) {
+        if self.cached_images.len() < hnsw::ANN_MIN_IMAGES {
+            return;
+        }
+        let Some(graph) = HnswGraph::load_from_path(&paths::ann_graph_path(encoder_id)) else {
+            debug!("no ANN graph on disk for {encoder_id}; staying on brute force");
+            return;
+        };
+        let nodes = graph.len();
+        let max_drift =
+            (self.cached_images.len() as f32 * hnsw::ANN_ATTACH_MAX_DRIFT) as usize;
+        let attached = self.attach_ann(graph, max_drift);
+        info!("ANN graph for {encoder_id}: {nodes} nodes, attached: {attached}");
+        crate::perf::record_diagnostic(
+            "ann_graph_attached",
+            serde_json::json!({
+                "encoder_id": encoder_id,
+                "nodes": nodes,
+                "cached": self.cached_images.len(),
+                "attached": attached,
+                "recall": self.ann.as_ref().map(|g| {
+                    super::diagnostics::ann_recall(&self.cached_images, g, 10)
+                }),
+            }),
+        );
+    }
+
+    /// Launch-time warm-up: load `encoder_id`'s cache file if it still
+    /// matches the DB, and attach its ANN graph. Returns false without
+    /// touching the DB's embedding BLOBs otherwise — the caller leaves
+    /// the slot for the first query or pipeline run to populate.
+    pub fn warm_from_cache_file(&mut self, db: &db::ImageDatabase, encoder_id: &str) -> bool {
+        if !self.load_cache_for(db, encoder_id) {
+            return false;
+        }
+        self.attach_persisted_ann(encoder_id);
+        true
+    }
+
+    /// Replace the cache with `encoder_id`'s rows read from the DB.
+    /// False (cache untouched) if the read failed.
+    pub(super) fn fill_from_db(&mut self, db: &db::ImageDatabase, encoder_id: &str) -> bool {
         let rows = match db.get_all_embeddings_for(encoder_id) {
             Ok(r) => r,
             Err(e) => {
                 warn!("populate_from_db_for_encoder({encoder_id}) failed: {e}");
-                return;
+                return false;
             }
         };
 
@@ -80,7 +160,7 @@ impl CosineIndex {
                 Ok(r) => r,
                 Err(e) => {
                     warn!("legacy get_all_embeddings fallback failed: {e}");
-                    return;
+                    return false;
                 }
             }
         } else {
@@ -88,15 +168,47 @@ impl CosineIndex {
         };
 
         let total = rows.len();
-        self.cached_images.clear();
+        self.clear();
         self.cached_images.reserve(total);
         for (_id, path, embedding) in rows {
             if embedding.is_empty() {
                 continue;
             }
-            self.cached_images
-                .push((PathBuf::from(path), Array1::from_vec(embedding)));
+            self.cached_images.push(PathBuf::from(path), &embedding);
         }
+        true
+    }
+
+    /// Populate the in-memory index from the per-encoder embeddings
+    /// table, picking only rows for the given encoder_id. A fresh
+    /// on-disk cache file for the encoder is preferred over the DB.
+    ///
+    /// Used by the encoder-picker dispatch: when the user switches
+    /// the chosen image encoder, the cache is wiped and repopulated
+    /// from this method. The on-disk embeddings stay intact (one row
+    /// per (image_id, encoder_id)) so swapping back to a previously-
+    /// used encoder is instant — the embeddings are already there.
+    ///
+    /// Special case: for `clip_vit_b_32` the new embeddings table
+    /// might be empty for users who haven't re-indexed under the new
+    /// schema. Falls back to the legacy `images.embedding` column
+    /// (`get_all_embeddings`) so those users still get results.
+    #[tracing::instrument(name = "cosine.populate_for_encoder", skip(self, db))]
+    pub fn populate_from_db_for_encoder(
+        &mut self,
+        db: &db::ImageDatabase,
+        encoder_id: &str,
+    ) {
+        let start = Instant::now();
+        info!("populate_from_db_for_encoder({encoder_id})");
+        let source = if self.load_cache_for(db, encoder_id) {
+            "cache_file"
+        } else if self.fill_from_db(db, encoder_id) {
+            "db"
+        } else {
+            return;
+        };
+        self.attach_persisted_ann(encoder_id);
         let elapsed_ms = start.elapsed().as_millis() as u64;
         info!(
             "populate_for_encoder({encoder_id}) done: {} embeddings in {} ms",
@@ -113,6 +225,8 @@ impl CosineIndex {
             serde_json::json!({
                 "encoder_id": encoder_id,
                 "count": self.cached_images.len(),
+                "source": source,
+                "ann": self.has_ann(),
                 "duration_ms": elapsed_ms,
             }),
         );
@@ -164,14 +278,13 @@ impl CosineIndex {
             }
         };
         let total = rows.len();
-        self.cached_images.clear();
+        self.clear();
         self.cached_images.reserve(total);
         for (_id, path, embedding) in rows {
             if embedding.is_empty() {
                 continue;
             }
-            self.cached_images
-                .push((PathBuf::from(path), Array1::from_vec(embedding)));
+            self.cached_images.push(PathBuf::from(path), &embedding);
         }
         info!(
             "Population complete: {} embeddings loaded in {:?}",
@@ -189,6 +302,33 @@ impl CosineIndex {
         cosine_similarity(a, b)
     }
 
+    /// Brute-force step shared by the retrieval methods: score every
+    /// cached row against `embedding` in one matrix pass, then fill
+    /// `scratch` with `(row, similarity)` for all rows but
+    /// `exclude_path` and, when given, those outside `allowed`.
+    /// Returns how many rows were excluded.
+    fn score_into_scratch(
+        &mut self,
+        embedding: &Array1<f32>,
+        exclude_path: Option<&PathBuf>,
+        allowed: Option<&HashSet<PathBuf>>,
+    ) -> usize {
+        self.cached_images
+            .scores_into(&contiguous(embedding), &mut self.scores);
+        self.scratch.clear();
+        self.scratch.extend(self.scores.iter().copied().enumerate());
+        if exclude_path.is_none() && allowed.is_none() {
+            return 0;
+        }
+        let before = self.scratch.len();
+        let paths = self.cached_images.paths();
+        self.scratch.retain(|(idx, _)| {
+            let path = &paths[*idx];
+            exclude_path != Some(path) && allowed.is_none_or(|a| a.contains(path))
+        });
+        before - self.scratch.len()
+    }
+
     // write the return images function. This function is going to take an embedding and return the top n most similar images from the cached_images vector
     // the images that ir returns will be a top x percent of the cached images based on cosine similarity to encourage diversity
     // exclude_path: optional path to exclude from results (e.g., the query image itself)
@@ -206,22 +346,40 @@ impl CosineIndex {
             exclude_path
         );
 
+        // Large library with a graph attached: the diversity pool is the
+        // ANN_DIVERSITY_POOL nearest neighbours rather than the top 20%.
+        if let Some(graph) = self.active_ann(embedding) {
+            let base_pool = (self.cached_images.len() as f32 * 0.2).ceil() as usize;
+            let pool = base_pool.min(ANN_DIVERSITY_POOL).max(top_n);
+            let hits = graph.search(
+                &self.cached_images,
+                &contiguous(embedding),
+                pool + usize::from(exclude_path.is_some()),
+                graph.params().ef_search,
+            );
+            let candidates: Vec<(usize, f32)> = hits
+                .into_iter()
+                .filter(|(idx, _)| exclude_path != Some(self.cached_images.path(*idx)))
+                .take(pool)
+                .collect();
+            let mut rng = rand::rng();
+            let selected: Vec<(PathBuf, f32)> = candidates
+                .choose_multiple(&mut rng, top_n.min(candidates.len()))
+                .map(|(idx, sim)| (self.cached_images.path(*idx).clone(), *sim))
+                .collect();
+            debug!(
+                "ANN diversity pool: {} candidates, {} selected",
+                candidates.len(),
+                selected.len()
+            );
+            return selected;
+        }
+
         // Step 1: compute similarities for every cached image (except the
         // optionally-excluded query image) into the reusable scratch
         // buffer. Indices into cached_images, NOT cloned PathBufs — we
         // only clone the paths that actually survive into the final result.
-        self.scratch.clear();
-        let mut excluded_count = 0;
-        for (idx, (path, emb)) in self.cached_images.iter().enumerate() {
-            if let Some(exclude) = exclude_path {
-                if path == exclude {
-                    excluded_count += 1;
-                    continue;
-                }
-            }
-            let sim = Self::cosine_similarity(embedding, emb);
-            self.scratch.push((idx, sim));
-        }
+        let excluded_count = self.score_into_scratch(embedding, exclude_path, None);
 
         debug!(
             "Calculated similarities for {} images (excluded {}), query embedding length: {}",
@@ -269,7 +427,7 @@ impl CosineIndex {
             self.scratch.choose_multiple(&mut rng, take).collect();
         let selected: Vec<(PathBuf, f32)> = sampled
             .iter()
-            .map(|(cache_idx, sim)| (self.cached_images[*cache_idx].0.clone(), *sim))
+            .map(|(cache_idx, sim)| (self.cached_images.path(*cache_idx).clone(), *sim))
             .collect();
 
         debug!("Final selected results: {} images", selected.len());
@@ -289,33 +447,62 @@ impl CosineIndex {
     /// Unlike get_similar_images, this does NOT randomly sample - it returns
     /// results in exact order of similarity. Best for semantic search where
     /// ranking accuracy matters.
-    #[tracing::instrument(name = "cosine.get_similar_sorted", skip(self, embedding, exclude_path), fields(cached = self.cached_images.len(), top_n))]
     pub fn get_similar_images_sorted(
         &mut self,
         embedding: &Array1<f32>,
         top_n: usize,
         exclude_path: Option<&PathBuf>,
+    ) -> Vec<(PathBuf, f32)> {
+        self.get_similar_images_sorted_within(embedding, top_n, exclude_path, None)
+    }
+
+    /// `get_similar_images_sorted` over only the rows whose path is in
+    /// `allowed` (all rows when `None`). The restriction is applied
+    /// before the top-N selection, so a filtered query still returns
+    /// `top_n` results when that many rows pass. A filtered query
+    /// always runs brute force: the ANN graph's neighbourhood may hold
+    /// none of the allowed rows.
+    #[tracing::instrument(name = "cosine.get_similar_sorted", skip(self, embedding, exclude_path, allowed), fields(cached = self.cached_images.len(), top_n, filtered = allowed.is_some()))]
+    pub fn get_similar_images_sorted_within(
+        &mut self,
+        embedding: &Array1<f32>,
+        top_n: usize,
+        exclude_path: Option<&PathBuf>,
+        allowed: Option<&HashSet<PathBuf>>,
     ) -> Vec<(PathBuf, f32)> {
         debug!(
-            "get_similar_images_sorted called - cached_images: {}, top_n: {}, exclude_path: {:?}",
+            "get_similar_images_sorted called - cached_images: {}, top_n: {}, exclude_path: {:?}, allowed: {:?}",
             self.cached_images.len(),
             top_n,
-            exclude_path
+            exclude_path,
+            allowed.map(HashSet::len)
         );
 
-        // Step 1: scratch buffer of (cache_idx, similarity) for every
-        // non-excluded image. No PathBuf clones in the inner loop.
-        self.scratch.clear();
-        for (idx, (path, emb)) in self.cached_images.iter().enumerate() {
-            if let Some(exclude) = exclude_path {
-                if path == exclude {
-                    continue;
-                }
-            }
-            let sim = Self::cosine_similarity(embedding, emb);
-            self.scratch.push((idx, sim));
+        // Large library with a graph attached: approximate top-N. One
+        // extra hit covers the excluded query image.
+        if let Some(graph) = self.active_ann(embedding).filter(|_| allowed.is_none()) {
+            let hits = graph.search(
+                &self.cached_images,
+                &contiguous(embedding),
+                top_n + usize::from(exclude_path.is_some()),
+                graph.params().ef_search,
+            );
+            let result: Vec<(PathBuf, f32)> = hits
+                .into_iter()
+                .map(|(idx, sim)| (self.cached_images.path(idx), sim))
+                .filter(|(path, _)| exclude_path != Some(*path))
+                .take(top_n)
+                .map(|(path, sim)| (path.clone(), sim))
+                .collect();
+            debug!("Returning {} ANN results sorted by similarity", result.len());
+            return result;
         }
 
+        // Step 1: scratch buffer of (cache_idx, similarity) for every
+        // non-excluded, allowed image. No PathBuf clones in the inner
+        // loop.
+        self.score_into_scratch(embedding, exclude_path, allowed);
+
         if self.scratch.is_empty() {
             warn!("No similarities calculated! Returning empty result.");
             return Vec::new();
@@ -345,7 +532,7 @@ impl CosineIndex {
         let result: Vec<(PathBuf, f32)> = self
             .scratch
             .iter()
-            .map(|(cache_idx, sim)| (self.cached_images[*cache_idx].0.clone(), *sim))
+            .map(|(cache_idx, sim)| (self.cached_images.path(*cache_idx).clone(), *sim))
             .collect();
 
         debug!(
@@ -381,6 +568,9 @@ impl CosineIndex {
     /// - 5 random from top 5-10%
     /// - 5 random from top 10-15%
     /// ... and so on until top 50%
+    ///
+    /// Always brute force, even with an ANN graph attached: the tiers
+    /// are percentiles of the whole similarity distribution.
     #[tracing::instrument(name = "cosine.get_tiered_similar", skip(self, embedding, exclude_path), fields(cached = self.cached_images.len()))]
     pub fn get_tiered_similar_images(
         &mut self,
@@ -395,16 +585,7 @@ impl CosineIndex {
 
         // Step 1: similarities into the scratch buffer (index-keyed,
         // no PathBuf clones in the inner loop).
-        self.scratch.clear();
-        for (idx, (path, emb)) in self.cached_images.iter().enumerate() {
-            if let Some(exclude) = exclude_path {
-                if path == exclude {
-                    continue;
-                }
-            }
-            let sim = Self::cosine_similarity(embedding, emb);
-            self.scratch.push((idx, sim));
-        }
+        self.score_into_scratch(embedding, exclude_path, None);
 
         if self.scratch.is_empty() {
             warn!("No similarities calculated! Returning empty result.");
@@ -460,7 +641,7 @@ impl CosineIndex {
             for scratch_idx in sampled {
                 used_indices.insert(scratch_idx);
                 let (cache_idx, sim) = self.scratch[scratch_idx];
-                result.push((self.cached_images[cache_idx].0.clone(), sim));
+                result.push((self.cached_images.path(cache_idx).clone(), sim));
             }
         }
 
@@ -499,8 +680,8 @@ mod tests {
         index.add_image(path.clone(), embedding.clone());
 
         assert_eq!(index.cached_images.len(), 1);
-        assert_eq!(index.cached_images[0].0, path);
-        assert_eq!(index.cached_images[0].1, embedding);
+        assert_eq!(index.cached_images.path(0), &path);
+        assert_eq!(index.cached_images.row_view(0), embedding);
     }
 
     #[test]
@@ -606,6 +787,75 @@ mod tests {
         assert!(results.len() <= 3, "Returned more images than available");
     }
 
+    #[test]
+    fn attached_ann_serves_sorted_queries_and_detaches_on_add() {
+        use super::super::hnsw::{HnswGraph, HnswParams};
+
+        let mut index = CosineIndex::new();
+        for i in 0..600 {
+            // Points on a circle: neighbours by angle are unambiguous.
+            let t = i as f32 * 0.01;
+            index.add_image(
+                PathBuf::from(format!("/images/img_{i}.jpg")),
+                array![t.cos(), t.sin(), 0.1],
+            );
+        }
+        let graph =
+            HnswGraph::build(HnswParams::default(), &index.cached_images, &|| false).unwrap();
+        assert!(index.attach_ann(graph, 0));
+        assert!(index.has_ann());
+
+        let query = index.cached_images.row_view(300).to_owned();
+        let exclude = index.cached_images.path(300).clone();
+        let ann = index.get_similar_images_sorted(&query, 4, Some(&exclude));
+        let names: Vec<String> = ann.iter().map(|(p, _)| p.display().to_string()).collect();
+        let mut expected = ["299", "301", "298", "302"].map(|n| format!("/images/img_{n}.jpg"));
+        let mut got = names.clone();
+        expected.sort();
+        got.sort();
+        assert_eq!(got, expected, "ANN top-4 around img_300: {names:?}");
+        assert!(ann.windows(2).all(|w| w[0].1 >= w[1].1), "sorted descending");
+
+        // A row the graph doesn't know about puts the index back on
+        // brute force rather than serving a misaligned graph.
+        index.add_image(PathBuf::from("/images/new.jpg"), array![1.0, 0.0, 0.1]);
+        assert!(!index.has_ann());
+        assert_eq!(index.get_similar_images_sorted(&query, 4, Some(&exclude)).len(), 4);
+    }
+
+    #[test]
+    fn allowed_rows_are_filtered_before_the_top_n() {
+        use super::super::hnsw::{HnswGraph, HnswParams};
+
+        let mut index = CosineIndex::new();
+        for i in 0..600 {
+            let t = i as f32 * 0.01;
+            index.add_image(
+                PathBuf::from(format!("/images/img_{i}.jpg")),
+                array![t.cos(), t.sin(), 0.1],
+            );
+        }
+        let graph =
+            HnswGraph::build(HnswParams::default(), &index.cached_images, &|| false).unwrap();
+        assert!(index.attach_ann(graph, 0));
+
+        // Every tenth image, none of them near the query: the graph's
+        // neighbourhood holds no allowed row, yet the page is full.
+        let allowed: HashSet<PathBuf> = (3..600)
+            .step_by(10)
+            .filter(|i| !(250..350).contains(i))
+            .map(|i| PathBuf::from(format!("/images/img_{i}.jpg")))
+            .collect();
+        let query = index.cached_images.row_view(300).to_owned();
+        let results = index.get_similar_images_sorted_within(&query, 5, None, Some(&allowed));
+        let names: Vec<String> = results.iter().map(|(p, _)| p.display().to_string()).collect();
+        assert_eq!(
+            names,
+            ["353", "243", "363", "233", "373"].map(|n| format!("/images/img_{n}.jpg"))
+        );
+        assert!(index.has_ann(), "filtering leaves the graph attached");
+    }
+
     #[test]
     fn test_empty_index() {
         // The retrieval methods take &mut self for the scratch buffer.
@@ -1,4 +1,8 @@
 use ndarray::Array1;
+use std::borrow::Cow;
+
+/// Lanes in the `dot` kernel's accumulator.
+const DOT_LANES: usize = 8;
 
 /// Comparator for `(usize, f32)` similarity tuples — sorts by score
 /// descending, NaN-tolerant. Pulled out as a free function so the
@@ -47,6 +51,57 @@ pub(crate) fn cosine_similarity(a: &Array1<f32>, b: &Array1<f32>) -> f32 {
     dot_product / (norm_a * norm_b)
 }
 
+/// Dot product of two equal-length slices — the scoring kernel.
+/// Eight independent accumulators let the compiler keep the loop in
+/// SIMD registers (SSE/AVX on x86, NEON on ARM) without intrinsics or
+/// nightly `std::simd`; a single running sum would serialise on the
+/// add latency. Summation order differs from `ndarray::dot`, so
+/// results agree to rounding, not bit-for-bit.
+pub(crate) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    debug_assert_eq!(a.len(), b.len());
+    let mut acc = [0.0f32; DOT_LANES];
+    let mut ca = a.chunks_exact(DOT_LANES);
+    let mut cb = b.chunks_exact(DOT_LANES);
+    // Unrolled by hand rather than zipped: identical code in release,
+    // several times faster in the unoptimised test profile.
+    for (x, y) in (&mut ca).zip(&mut cb) {
+        acc[0] += x[0] * y[0];
+        acc[1] += x[1] * y[1];
+        acc[2] += x[2] * y[2];
+        acc[3] += x[3] * y[3];
+        acc[4] += x[4] * y[4];
+        acc[5] += x[5] * y[5];
+        acc[6] += x[6] * y[6];
+        acc[7] += x[7] * y[7];
+    }
+    let mut sum = (acc[0] + acc[4]) + (acc[1] + acc[5]) + (acc[2] + acc[6]) + (acc[3] + acc[7]);
+    for (x, y) in ca.remainder().iter().zip(cb.remainder()) {
+        sum += x * y;
+    }
+    sum
+}
+
+/// 1 / ‖v‖, or 0 for a zero or non-finite vector — so a score
+/// computed as `dot * inv_a * inv_b` comes out 0 ("not similar")
+/// exactly where `cosine_similarity` returns 0.
+pub(crate) fn inv_norm(v: &[f32]) -> f32 {
+    let n = dot(v, v).sqrt();
+    if n > 0.0 && n.is_finite() {
+        1.0 / n
+    } else {
+        0.0
+    }
+}
+
+/// `v` as a plain slice, copying only if it isn't contiguous (every
+/// `Array1` built by this crate is).
+pub(crate) fn contiguous(v: &Array1<f32>) -> Cow<'_, [f32]> {
+    match v.as_slice() {
+        Some(s) => Cow::Borrowed(s),
+        None => Cow::Owned(v.to_vec()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::CosineIndex;
@@ -163,6 +218,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn dot_kernel_matches_ndarray_across_tail_lengths() {
+        for len in [0, 1, 7, 8, 9, 31, 512, 515] {
+            let a = Array1::from_iter((0..len).map(|i| (i as f32 * 0.37).sin()));
+            let b = Array1::from_iter((0..len).map(|i| (i as f32 * 0.11).cos()));
+            let got = super::dot(a.as_slice().unwrap(), b.as_slice().unwrap());
+            assert!((got - a.dot(&b)).abs() < 1e-4, "len {len}: {got} vs {}", a.dot(&b));
+        }
+        assert_eq!(super::inv_norm(&[0.0, 0.0]), 0.0);
+        assert_eq!(super::inv_norm(&[3.0, 4.0]), 0.2);
+    }
+
     #[test]
     fn test_cosine_similarity_normalized_vectors() {
         // Test with pre-normalized vectors (unit vectors)
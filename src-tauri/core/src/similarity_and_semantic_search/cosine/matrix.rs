@@ -0,0 +1,305 @@
+//! `EmbeddingMatrix` — the storage behind `CosineIndex`.
+//!
+//! One contiguous, row-major `rows × dim` f32 block plus a parallel
+//! path table and per-row inverse norms, instead of a heap-allocated
+//! `Array1` per image next to its `PathBuf`. A brute-force query is a
+//! single matrix–vector pass over that block (`scores_into`): each
+//! row's dot product runs through the `math::dot` kernel, and
+//! libraries past `PAR_MIN_ROWS` are split into rayon chunks.
+//!
+//! A matrix loaded from an on-disk cache (`from_cache_file`) scores
+//! straight out of the mmap'd file; the first mutation copies the
+//! block into memory.
+
+use super::cache::CacheFile;
+use super::math::{dot, inv_norm};
+use ndarray::{ArrayView1, ArrayView2};
+use rayon::prelude::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Below this many rows a query is scored on the calling thread —
+/// the rayon fork/join costs more than it saves.
+pub(super) const PAR_MIN_ROWS: usize = 8_192;
+
+/// Rows per rayon task. 2048 × 768-d is 6 MB of embeddings: big
+/// enough to amortise scheduling, small enough to balance.
+pub(super) const PAR_CHUNK_ROWS: usize = 2_048;
+
+#[derive(Clone)]
+enum Storage {
+    Owned(Vec<f32>),
+    Mapped(Arc<CacheFile>),
+}
+
+#[derive(Clone, Default)]
+pub struct EmbeddingMatrix {
+    dim: usize,
+    paths: Vec<PathBuf>,
+    storage: Storage,
+    inv_norms: Vec<f32>,
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Storage::Owned(Vec::new())
+    }
+}
+
+impl std::fmt::Debug for EmbeddingMatrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmbeddingMatrix")
+            .field("rows", &self.len())
+            .field("dim", &self.dim)
+            .field("mapped", &self.is_mapped())
+            .finish()
+    }
+}
+
+impl PartialEq for EmbeddingMatrix {
+    fn eq(&self, other: &Self) -> bool {
+        self.dim == other.dim && self.paths == other.paths && self.data() == other.data()
+    }
+}
+
+impl EmbeddingMatrix {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// View a validated cache file's rows in place.
+    pub fn from_cache_file(file: CacheFile) -> Self {
+        let paths = (0..file.len()).map(|i| PathBuf::from(file.path(i))).collect();
+        let dim = file.header().dim;
+        let mut matrix = EmbeddingMatrix {
+            dim,
+            paths,
+            storage: Storage::Mapped(Arc::new(file)),
+            inv_norms: Vec::new(),
+        };
+        matrix.inv_norms = matrix.rows().map(inv_norm).collect();
+        // Windows won't rename over a mapped file, which would wedge
+        // the pipeline's next write of this cache; copy out there.
+        #[cfg(windows)]
+        matrix.owned_mut();
+        matrix
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// Embedding width; 0 while empty.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// True while the rows are still read from an mmap'd cache file.
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.storage, Storage::Mapped(_))
+    }
+
+    /// The whole `rows × dim` block, row-major.
+    pub fn data(&self) -> &[f32] {
+        match &self.storage {
+            Storage::Owned(v) => v,
+            Storage::Mapped(f) => f.matrix_data(),
+        }
+    }
+
+    pub fn view(&self) -> ArrayView2<'_, f32> {
+        ArrayView2::from_shape((self.len(), self.dim), self.data()).expect("rows × dim")
+    }
+
+    pub fn path(&self, i: usize) -> &PathBuf {
+        &self.paths[i]
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    pub fn row(&self, i: usize) -> &[f32] {
+        &self.data()[i * self.dim..(i + 1) * self.dim]
+    }
+
+    pub fn row_view(&self, i: usize) -> ArrayView1<'_, f32> {
+        ArrayView1::from(self.row(i))
+    }
+
+    /// 1 / ‖row i‖, or 0 for a zero row.
+    pub fn inv_norm(&self, i: usize) -> f32 {
+        self.inv_norms[i]
+    }
+
+    pub fn rows(&self) -> std::slice::ChunksExact<'_, f32> {
+        self.data().chunks_exact(self.dim.max(1))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&PathBuf, &[f32])> {
+        self.paths.iter().zip(self.rows())
+    }
+
+    pub fn clear(&mut self) {
+        self.dim = 0;
+        self.paths.clear();
+        self.inv_norms.clear();
+        self.storage = Storage::default();
+    }
+
+    pub fn reserve(&mut self, rows: usize) {
+        self.paths.reserve(rows);
+        self.inv_norms.reserve(rows);
+        let dim = self.dim;
+        self.owned_mut().reserve(rows * dim);
+    }
+
+    /// Append a row. The first row fixes the width; an empty row or
+    /// one of a different width is refused (false) — a cache only
+    /// ever holds one encoder's embeddings.
+    pub fn push(&mut self, path: PathBuf, row: &[f32]) -> bool {
+        if row.is_empty() || (!self.is_empty() && row.len() != self.dim) {
+            warn!(
+                "embedding for {} has dim {}, cache has {}; skipped",
+                path.display(),
+                row.len(),
+                self.dim
+            );
+            return false;
+        }
+        self.dim = row.len();
+        self.owned_mut().extend_from_slice(row);
+        self.inv_norms.push(inv_norm(row));
+        self.paths.push(path);
+        true
+    }
+
+    /// Rearrange rows so new row `j` is old row `order[j]`. `order`
+    /// may omit rows, which are dropped.
+    pub fn permute(&mut self, order: &[usize]) {
+        let mut data = Vec::with_capacity(order.len() * self.dim);
+        for &i in order {
+            data.extend_from_slice(self.row(i));
+        }
+        self.paths = order.iter().map(|&i| self.paths[i].clone()).collect();
+        self.inv_norms = order.iter().map(|&i| self.inv_norms[i]).collect();
+        self.storage = Storage::Owned(data);
+        if self.paths.is_empty() {
+            self.dim = 0;
+        }
+    }
+
+    /// Cosine similarity of `query` against every row, into `out`
+    /// (resized to `len()`). A query of the wrong width scores 0
+    /// everywhere, like `math::cosine_similarity` does per pair.
+    pub fn scores_into(&self, query: &[f32], out: &mut Vec<f32>) {
+        out.clear();
+        out.resize(self.len(), 0.0);
+        if self.is_empty() {
+            return;
+        }
+        if query.len() != self.dim {
+            warn!(
+                "cosine dim mismatch: query={} cache={}; scoring 0.0",
+                query.len(),
+                self.dim
+            );
+            return;
+        }
+        let q_inv = inv_norm(query);
+        if q_inv == 0.0 {
+            return;
+        }
+        let score_chunk = |(out, (rows, invs)): (&mut [f32], (&[f32], &[f32]))| {
+            for ((s, row), inv) in out.iter_mut().zip(rows.chunks_exact(self.dim)).zip(invs) {
+                *s = dot(query, row) * q_inv * inv;
+            }
+        };
+        let data = self.data();
+        if self.len() < PAR_MIN_ROWS {
+            score_chunk((out.as_mut_slice(), (data, &self.inv_norms)));
+        } else {
+            out.par_chunks_mut(PAR_CHUNK_ROWS)
+                .zip(
+                    data.par_chunks(PAR_CHUNK_ROWS * self.dim)
+                        .zip(self.inv_norms.par_chunks(PAR_CHUNK_ROWS)),
+                )
+                .for_each(score_chunk);
+        }
+    }
+
+    /// The in-memory block, copying out of the mapped file first.
+    fn owned_mut(&mut self) -> &mut Vec<f32> {
+        if let Storage::Mapped(f) = &self.storage {
+            self.storage = Storage::Owned(f.matrix_data().to_vec());
+        }
+        match &mut self.storage {
+            Storage::Owned(v) => v,
+            Storage::Mapped(_) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::math::cosine_similarity;
+    use super::*;
+    use ndarray::Array1;
+
+    fn matrix(n: usize, dim: usize) -> EmbeddingMatrix {
+        let mut m = EmbeddingMatrix::new();
+        for i in 0..n {
+            let row: Vec<f32> = (0..dim).map(|j| ((i * 31 + j * 7) as f32).sin()).collect();
+            assert!(m.push(PathBuf::from(format!("/m/{i}.jpg")), &row));
+        }
+        m
+    }
+
+    #[test]
+    fn scores_match_pairwise_cosine_serial_and_parallel() {
+        for n in [5, PAR_MIN_ROWS + 3] {
+            let m = matrix(n, 19);
+            let q: Vec<f32> = (0..19).map(|j| (j as f32).cos()).collect();
+            let mut out = Vec::new();
+            m.scores_into(&q, &mut out);
+            assert_eq!(out.len(), n);
+            for i in [0, 1, n / 2, n - 1] {
+                let want = cosine_similarity(
+                    &Array1::from_vec(q.clone()),
+                    &m.row_view(i).to_owned(),
+                );
+                assert!((out[i] - want).abs() < 1e-5, "row {i}: {} vs {want}", out[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn mismatched_or_zero_query_scores_zero() {
+        let m = matrix(4, 3);
+        let mut out = vec![9.0; 2];
+        m.scores_into(&[1.0, 2.0], &mut out);
+        assert_eq!(out, vec![0.0; 4]);
+        m.scores_into(&[0.0, 0.0, 0.0], &mut out);
+        assert_eq!(out, vec![0.0; 4]);
+    }
+
+    #[test]
+    fn push_refuses_other_widths_and_permute_reorders() {
+        let mut m = matrix(3, 4);
+        assert!(!m.push(PathBuf::from("/wide.jpg"), &[1.0; 5]));
+        assert!(!m.push(PathBuf::from("/empty.jpg"), &[]));
+        assert_eq!(m.len(), 3);
+
+        let row2 = m.row(2).to_vec();
+        m.permute(&[2, 0]);
+        assert_eq!(m.len(), 2);
+        assert_eq!(m.path(0), &PathBuf::from("/m/2.jpg"));
+        assert_eq!(m.row(0), row2.as_slice());
+        assert_eq!(m.view().dim(), (2, 4));
+    }
+}
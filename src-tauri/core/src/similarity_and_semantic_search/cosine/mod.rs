@@ -0,0 +1,43 @@
+//! Cosine-similarity index split into focused submodules:
+//!
+//! - `math`  — pure helpers: the `cosine_similarity` formula, the
+//!   `dot` scoring kernel and the `score_cmp_desc` comparator shared
+//!   by all retrieval methods.
+//! - `matrix` — `EmbeddingMatrix`, the contiguous row-major storage
+//!   behind the cache, and its rayon-chunked scoring pass.
+//! - `index` — the `CosineIndex` struct, embedding ingestion
+//!   (`add_image`, `populate_from_db`), and the three retrieval
+//!   methods (`get_similar_images`, `get_similar_images_sorted`,
+//!   `get_tiered_similar_images`).
+//! - `cache` — disk persistence: one checksummed, mmap-loaded file per
+//!   encoder (`save_cache_file` / `load_cache_file_if_fresh`), and
+//!   `refresh_encoder_files`, which the indexing pipeline calls to keep
+//!   those files and the `hnsw` graphs in step with the DB.
+//! - `quant` — `QuantizedIndex`, the int8 / binary alternative to a
+//!   full-precision fusion cache: candidates from compact codes,
+//!   re-ranked with the f32 embeddings from SQLite.
+//! - `hnsw`  — optional approximate nearest-neighbour graph per
+//!   encoder, attached to large caches so the top-K retrieval methods
+//!   don't scan every embedding.
+//!
+//! The struct lives in `index` and the cache impl block lives in
+//! `cache`; both contribute to the same `CosineIndex` inherent impl,
+//! so the public API stays exactly as it was when everything lived in
+//! a single file.
+//!
+//! `cosine_similarity.rs` is preserved as a re-export shim so existing
+//! `crate::similarity_and_semantic_search::cosine_similarity::CosineIndex`
+//! imports continue to work without any caller changes.
+
+pub mod cache;
+pub mod diagnostics;
+pub mod hnsw;
+pub mod index;
+pub mod matrix;
+pub(crate) mod math;
+pub mod quant;
+pub mod rrf;
+
+pub use index::CosineIndex;
+pub use matrix::EmbeddingMatrix;
+pub use quant::{QuantMode, QuantizedIndex};
@@ -0,0 +1,726 @@
+//! Quantised per-encoder caches for multi-encoder fusion.
+//!
+//! A full-precision fusion slot holds `rows × dim` f32s — at 500k
+//! images that is 1–1.5 GB per encoder, three encoders deep. A
+//! `QuantizedIndex` keeps a compact code per image instead and only
+//! touches full precision for the few hundred best candidates:
+//!
+//! - **`Int8`** — per-dimension scalar quantisation, one byte per
+//!   component (4× smaller). Queries are scored asymmetrically: the
+//!   query stays f32 and is dotted against the dequantised codes, so
+//!   the only error is the codes' rounding.
+//! - **`Binary`** — one bit per component, set when the component is
+//!   above that dimension's median (32× smaller). Candidates are
+//!   ranked by Hamming distance to the query's bits.
+//!
+//! Either way the top `rerank_pool()` candidates are re-scored with
+//! exact cosine on their f32 embeddings fetched from SQLite
+//! (`ImageDatabase::get_embeddings_for_paths`), so the scores fusion
+//! sees are the real ones; quantisation can only cost recall at the
+//! edge of the candidate pool.
+//!
+//! The per-dimension parameters (`QuantParams`) are fitted once from a
+//! random sample of the encoder's embeddings and stored in the DB's
+//! `meta` table, so every rebuild of the codes — after a root toggle
+//! or an indexing pass — quantises against the same grid. They are
+//! refitted when the embedding pipeline version or width changes.
+
+use super::index::CosineIndex;
+use super::math::{dot, inv_norm, score_cmp_desc};
+use super::matrix::{PAR_CHUNK_ROWS, PAR_MIN_ROWS};
+use crate::db::{ImageDatabase, EMBEDDING_PIPELINE_VERSION};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Instant;
+use tracing::{info, warn};
+
+/// Rows sampled to fit `QuantParams`. Quantiles over 10k rows are
+/// stable to well under one int8 step.
+const FIT_SAMPLE_ROWS: usize = 10_000;
+
+/// Int8 range per dimension is the [0.1%, 99.9%] quantile band of the
+/// sample rather than min..max, so one outlier doesn't stretch the
+/// grid for everyone; components outside it clamp to the end codes.
+const INT8_CLIP_QUANTILE: f32 = 0.001;
+
+/// The candidate pool is at least this many times the requested top-K.
+const RERANK_FACTOR: usize = 4;
+
+/// Compact representation used for a fusion cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuantMode {
+    Int8,
+    Binary,
+}
+
+impl QuantMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            QuantMode::Int8 => "int8",
+            QuantMode::Binary => "binary",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "int8" => Some(QuantMode::Int8),
+            "binary" => Some(QuantMode::Binary),
+            _ => None,
+        }
+    }
+
+    /// Candidates re-ranked at full precision per query. Binary codes
+    /// order the library more coarsely, so they get a deeper pool.
+    pub fn rerank_pool(self) -> usize {
+        match self {
+            QuantMode::Int8 => 200,
+            QuantMode::Binary => 500,
+        }
+    }
+}
+
+/// Per-dimension quantisation grid for one encoder, persisted as JSON
+/// in `meta` under `meta_key`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuantParams {
+    pub mode: QuantMode,
+    /// `EMBEDDING_PIPELINE_VERSION` the grid was fitted under.
+    pub pipeline_version: i64,
+    pub dim: usize,
+    /// Int8: value of code 0 per dimension. Binary: the bit threshold
+    /// (per-dimension median).
+    pub offset: Vec<f32>,
+    /// Int8: value of one code step per dimension. Empty for Binary.
+    pub scale: Vec<f32>,
+}
+
+impl QuantParams {
+    pub fn meta_key(encoder_id: &str, mode: QuantMode) -> String {
+        format!("quant_params:{encoder_id}:{}", mode.as_str())
+    }
+
+    /// Fit a grid to `sample` (rows of equal width). None for an empty
+    /// sample.
+    pub fn fit(mode: QuantMode, sample: &[Vec<f32>]) -> Option<Self> {
+        let dim = sample.first()?.len();
+        if dim == 0 {
+            return None;
+        }
+        let mut column = Vec::with_capacity(sample.len());
+        let mut offset = Vec::with_capacity(dim);
+        let mut scale = Vec::new();
+        for d in 0..dim {
+            column.clear();
+            column.extend(sample.iter().filter(|r| r.len() == dim).map(|r| r[d]));
+            match mode {
+                QuantMode::Int8 => {
+                    let lo = quantile(&mut column, INT8_CLIP_QUANTILE);
+                    let hi = quantile(&mut column, 1.0 - INT8_CLIP_QUANTILE);
+                    offset.push(lo);
+                    scale.push((hi - lo).max(0.0) / 255.0);
+                }
+                QuantMode::Binary => offset.push(quantile(&mut column, 0.5)),
+            }
+        }
+        Some(QuantParams {
+            mode,
+            pipeline_version: EMBEDDING_PIPELINE_VERSION,
+            dim,
+            offset,
+            scale,
+        })
+    }
+
+    /// The stored grid for (`encoder_id`, `mode`) if it is still valid,
+    /// otherwise a freshly fitted one (which is stored). `Ok(None)` when
+    /// the encoder has no embeddings to fit from.
+    pub fn load_or_fit(
+        db: &ImageDatabase,
+        encoder_id: &str,
+        mode: QuantMode,
+    ) -> rusqlite::Result<Option<Self>> {
+        let key = Self::meta_key(encoder_id, mode);
+        if let Some(json) = db.get_meta(&key)? {
+            match serde_json::from_str::<QuantParams>(&json) {
+                Ok(p) if p.is_valid_for(mode) => return Ok(Some(p)),
+                Ok(_) => info!("{key} is from an older pipeline; refitting"),
+                Err(e) => warn!("{key} unreadable ({e}); refitting"),
+            }
+        }
+        Self::fit_and_store(db, encoder_id, mode)
+    }
+
+    /// Fit from a fresh random sample and overwrite the stored grid.
+    pub fn fit_and_store(
+        db: &ImageDatabase,
+        encoder_id: &str,
+        mode: QuantMode,
+    ) -> rusqlite::Result<Option<Self>> {
+        let mut sample: Vec<Vec<f32>> = Vec::new();
+        db.for_each_embedding_for(encoder_id, Some(FIT_SAMPLE_ROWS), |_, row| {
+            if sample.first().is_none_or(|f| f.len() == row.len()) {
+                sample.push(row.to_vec());
+            }
+        })?;
+        let Some(params) = Self::fit(mode, &sample) else {
+            return Ok(None);
+        };
+        let json = serde_json::to_string(&params)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        db.set_meta(&Self::meta_key(encoder_id, mode), &json)?;
+        info!(
+            "fitted {} quantisation for {encoder_id} from {} rows (dim {})",
+            mode.as_str(),
+            sample.len(),
+            params.dim
+        );
+        Ok(Some(params))
+    }
+
+    fn is_valid_for(&self, mode: QuantMode) -> bool {
+        self.mode == mode
+            && self.pipeline_version == EMBEDDING_PIPELINE_VERSION
+            && self.dim > 0
+            && self.offset.len() == self.dim
+            && self.scale.len() == if mode == QuantMode::Int8 { self.dim } else { 0 }
+    }
+
+    /// Bytes per encoded row. Binary codes are padded to whole u64
+    /// words so Hamming distance can run a word at a time.
+    pub fn code_len(&self) -> usize {
+        match self.mode {
+            QuantMode::Int8 => self.dim,
+            QuantMode::Binary => self.dim.div_ceil(64) * 8,
+        }
+    }
+
+    /// Append `row`'s code to `out`. `row` must be `dim` wide.
+    fn encode_into(&self, row: &[f32], out: &mut Vec<u8>) {
+        match self.mode {
+            QuantMode::Int8 => out.extend(row.iter().zip(&self.offset).zip(&self.scale).map(
+                |((x, lo), step)| {
+                    if *step > 0.0 {
+                        ((x - lo) / step).round().clamp(0.0, 255.0) as u8
+                    } else {
+                        0
+                    }
+                },
+            )),
+            QuantMode::Binary => {
+                let start = out.len();
+                out.resize(start + self.code_len(), 0);
+                for (d, (x, t)) in row.iter().zip(&self.offset).enumerate() {
+                    if x > t {
+                        out[start + d / 8] |= 1 << (d % 8);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `q`-quantile of `values` (reordered in place).
+fn quantile(values: &mut [f32], q: f32) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let k = ((values.len() - 1) as f32 * q).round() as usize;
+    *values.select_nth_unstable_by(k, |a, b| a.total_cmp(b)).1
+}
+
+/// f32 · u8 dot product — the int8 scoring kernel. Same eight-lane,
+/// hand-unrolled shape as `math::dot`.
+fn dot_u8(q: &[f32], c: &[u8]) -> f32 {
+    debug_assert_eq!(q.len(), c.len());
+    let mut acc = [0.0f32; 8];
+    let mut cq = q.chunks_exact(8);
+    let mut cc = c.chunks_exact(8);
+    for (x, y) in (&mut cq).zip(&mut cc) {
+        acc[0] += x[0] * y[0] as f32;
+        acc[1] += x[1] * y[1] as f32;
+        acc[2] += x[2] * y[2] as f32;
+        acc[3] += x[3] * y[3] as f32;
+        acc[4] += x[4] * y[4] as f32;
+        acc[5] += x[5] * y[5] as f32;
+        acc[6] += x[6] * y[6] as f32;
+        acc[7] += x[7] * y[7] as f32;
+    }
+    let mut sum = (acc[0] + acc[4]) + (acc[1] + acc[5]) + (acc[2] + acc[6]) + (acc[3] + acc[7]);
+    for (x, y) in cq.remainder().iter().zip(cc.remainder()) {
+        sum += x * *y as f32;
+    }
+    sum
+}
+
+fn hamming(a: &[u8], b: &[u8]) -> u32 {
+    a.chunks_exact(8)
+        .zip(b.chunks_exact(8))
+        .map(|(x, y)| {
+            let x = u64::from_le_bytes(x.try_into().unwrap());
+            let y = u64::from_le_bytes(y.try_into().unwrap());
+            (x ^ y).count_ones()
+        })
+        .sum()
+}
+
+/// Query-side precomputation for one scoring pass.
+enum PreparedQuery {
+    /// Query pre-multiplied by the step size, plus the part of the dot
+    /// product contributed by the offsets and the query's inverse norm.
+    Int8 {
+        scaled: Vec<f32>,
+        base: f32,
+        inv: f32,
+    },
+    Binary {
+        code: Vec<u8>,
+        dim: f32,
+    },
+}
+
+impl PreparedQuery {
+    fn score(&self, code: &[u8], row_inv_norm: f32) -> f32 {
+        match self {
+            PreparedQuery::Int8 { scaled, base, inv } => {
+                (base + dot_u8(scaled, code)) * inv * row_inv_norm
+            }
+            // Cosine between the two ±1 sign vectors.
+            PreparedQuery::Binary { code: q, dim } => 1.0 - 2.0 * hamming(q, code) as f32 / dim,
+        }
+    }
+}
+
+/// One encoder's fusion cache in compact form — see the module docs.
+pub struct QuantizedIndex {
+    params: QuantParams,
+    paths: Vec<PathBuf>,
+    codes: Vec<u8>,
+    /// 1 / ‖row‖ of the full-precision row; int8 scores are cosines.
+    inv_norms: Vec<f32>,
+    scores: Vec<f32>,
+    scratch: Vec<(usize, f32)>,
+}
+
+impl QuantizedIndex {
+    pub fn new(params: QuantParams) -> Self {
+        QuantizedIndex {
+            params,
+            paths: Vec::new(),
+            codes: Vec::new(),
+            inv_norms: Vec::new(),
+            scores: Vec::new(),
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn params(&self) -> &QuantParams {
+        &self.params
+    }
+
+    pub fn mode(&self) -> QuantMode {
+        self.params.mode
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn path(&self, i: usize) -> &PathBuf {
+        &self.paths[i]
+    }
+
+    /// Heap bytes held by the codes and per-row norms (paths excluded).
+    pub fn code_bytes(&self) -> usize {
+        self.codes.len() + self.inv_norms.len() * std::mem::size_of::<f32>()
+    }
+
+    /// Encode and append one row. False (row skipped) if its width
+    /// isn't the grid's.
+    pub fn push(&mut self, path: PathBuf, row: &[f32]) -> bool {
+        if row.len() != self.params.dim {
+            return false;
+        }
+        self.params.encode_into(row, &mut self.codes);
+        self.inv_norms.push(inv_norm(row));
+        self.paths.push(path);
+        true
+    }
+
+    /// Build `encoder_id`'s quantised cache: grid from `meta` (fitted
+    /// on first use), rows from the encoder's cache file when it is
+    /// fresh, else streamed from the DB. None if the encoder has no
+    /// embeddings or the DB read failed.
+    #[tracing::instrument(name = "cosine.quant_populate", skip(db))]
+    pub fn populate(db: &ImageDatabase, encoder_id: &str, mode: QuantMode) -> Option<Self> {
+        let start = Instant::now();
+        let params = match QuantParams::load_or_fit(db, encoder_id, mode) {
+            Ok(Some(p)) => p,
+            Ok(None) => return None,
+            Err(e) => {
+                warn!("quantisation params for {encoder_id} failed: {e}");
+                return None;
+            }
+        };
+        let (mut index, mut skipped, mut source) = Self::build(db, encoder_id, params)?;
+        if index.is_empty() && skipped > 0 {
+            // Every row disagreed with the stored grid's width — the
+            // encoder changed under it. Refit once and rebuild.
+            let params = match QuantParams::fit_and_store(db, encoder_id, mode) {
+                Ok(Some(p)) => p,
+                Ok(None) => return None,
+                Err(e) => {
+                    warn!("quantisation refit for {encoder_id} failed: {e}");
+                    return None;
+                }
+            };
+            (index, skipped, source) = Self::build(db, encoder_id, params)?;
+        }
+        if skipped > 0 {
+            warn!("{skipped} {encoder_id} embeddings had the wrong width; not quantised");
+        }
+        let elapsed_ms = start.elapsed().as_millis() as u64;
+        info!(
+            "quantised {encoder_id} cache ({}): {} rows, {} code bytes in {elapsed_ms} ms",
+            mode.as_str(),
+            index.len(),
+            index.code_bytes()
+        );
+        crate::perf::record_diagnostic(
+            "quantized_cache_populated",
+            serde_json::json!({
+                "encoder_id": encoder_id,
+                "mode": mode.as_str(),
+                "count": index.len(),
+                "code_bytes": index.code_bytes(),
+                "source": source,
+                "duration_ms": elapsed_ms,
+            }),
+        );
+        Some(index)
+    }
+
+    /// Encode every visible row under `params`. Returns the index, the
+    /// number of rows skipped for width, and where the rows came from.
+    fn build(
+        db: &ImageDatabase,
+        encoder_id: &str,
+        params: QuantParams,
+    ) -> Option<(Self, usize, &'static str)> {
+        let mut index = QuantizedIndex::new(params);
+        let mut skipped = 0usize;
+        // The cache file is mmap'd, so walking it doesn't pull the
+        // full-precision set onto the heap; dropped after the pass.
+        let mut full = CosineIndex::new();
+        if full.load_cache_for(db, encoder_id) {
+            index.paths.reserve(full.cached_images.len());
+            for (path, row) in full.cached_images.iter() {
+                skipped += usize::from(!index.push(path.clone(), row));
+            }
+            return Some((index, skipped, "cache_file"));
+        }
+        let read = db.for_each_embedding_for(encoder_id, None, |path, row| {
+            skipped += usize::from(!index.push(PathBuf::from(path), row));
+        });
+        match read {
+            Ok(()) => Some((index, skipped, "db")),
+            Err(e) => {
+                warn!("streaming {encoder_id} embeddings failed: {e}");
+                None
+            }
+        }
+    }
+
+    fn prepare(&self, query: &[f32]) -> PreparedQuery {
+        match self.params.mode {
+            QuantMode::Int8 => PreparedQuery::Int8 {
+                scaled: query
+                    .iter()
+                    .zip(&self.params.scale)
+                    .map(|(q, s)| q * s)
+                    .collect(),
+                base: dot(query, &self.params.offset),
+                inv: inv_norm(query),
+            },
+            QuantMode::Binary => {
+                let mut code = Vec::with_capacity(self.params.code_len());
+                self.params.encode_into(query, &mut code);
+                PreparedQuery::Binary {
+                    code,
+                    dim: self.params.dim as f32,
+                }
+            }
+        }
+    }
+
+    /// Approximate top-`n` rows for `query` from the codes alone, as
+    /// (row, approximate score) best first, drawn only from rows in
+    /// `allowed` when given. Empty for a query of the wrong width.
+    pub fn candidates(
+        &mut self,
+        query: &[f32],
+        n: usize,
+        exclude_path: Option<&PathBuf>,
+        allowed: Option<&HashSet<PathBuf>>,
+    ) -> Vec<(usize, f32)> {
+        if query.len() != self.params.dim || self.is_empty() || n == 0 {
+            if query.len() != self.params.dim {
+                warn!(
+                    "quantised query dim mismatch: query={} cache={}",
+                    query.len(),
+                    self.params.dim
+                );
+            }
+            return Vec::new();
+        }
+        let prepared = self.prepare(query);
+        let code_len = self.params.code_len();
+        self.scores.clear();
+        self.scores.resize(self.len(), 0.0);
+        let score_chunk = |(out, (codes, invs)): (&mut [f32], (&[u8], &[f32]))| {
+            for ((s, code), inv) in out.iter_mut().zip(codes.chunks_exact(code_len)).zip(invs) {
+                *s = prepared.score(code, *inv);
+            }
+        };
+        if self.len() < PAR_MIN_ROWS {
+            score_chunk((self.scores.as_mut_slice(), (&self.codes, &self.inv_norms)));
+        } else {
+            self.scores
+                .par_chunks_mut(PAR_CHUNK_ROWS)
+                .zip(
+                    self.codes
+                        .par_chunks(PAR_CHUNK_ROWS * code_len)
+                        .zip(self.inv_norms.par_chunks(PAR_CHUNK_ROWS)),
+                )
+                .for_each(score_chunk);
+        }
+
+        self.scratch.clear();
+        self.scratch.extend(
+            self.scores
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| {
+                    let path = &self.paths[*i];
+                    exclude_path != Some(path) && allowed.is_none_or(|a| a.contains(path))
+                })
+                .map(|(i, s)| (i, *s)),
+        );
+        let want = n.min(self.scratch.len());
+        if want == 0 {
+            return Vec::new();
+        }
+        if want < self.scratch.len() {
+            self.scratch
+                .select_nth_unstable_by(want - 1, score_cmp_desc);
+            self.scratch.truncate(want);
+        }
+        self.scratch.sort_unstable_by(score_cmp_desc);
+        self.scratch.clone()
+    }
+
+    /// Top-`top_k` (path, exact cosine) for `query`: candidates from the
+    /// codes, re-scored with `encoder_id`'s f32 embeddings from `db`.
+    /// Candidates whose row has since left the DB are dropped; `allowed`
+    /// restricts the candidates as in `candidates`.
+    pub fn search_reranked(
+        &mut self,
+        db: &ImageDatabase,
+        encoder_id: &str,
+        query: &[f32],
+        top_k: usize,
+        exclude_path: Option<&PathBuf>,
+        allowed: Option<&HashSet<PathBuf>>,
+    ) -> rusqlite::Result<Vec<(PathBuf, f32)>> {
+        let pool = self
+            .mode()
+            .rerank_pool()
+            .max(top_k.saturating_mul(RERANK_FACTOR));
+        let candidates = self.candidates(query, pool, exclude_path, allowed);
+        let paths: Vec<&str> = candidates
+            .iter()
+            .filter_map(|(i, _)| self.paths[*i].to_str())
+            .collect();
+        let full = db.get_embeddings_for_paths(encoder_id, &paths)?;
+        let q_inv = inv_norm(query);
+        let mut ranked: Vec<(PathBuf, f32)> = full
+            .into_iter()
+            .filter(|(_, row)| row.len() == query.len())
+            .map(|(path, row)| {
+                let sim = dot(query, &row) * q_inv * inv_norm(&row);
+                (PathBuf::from(path), sim)
+            })
+            .collect();
+        ranked.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+        ranked.truncate(top_k);
+        Ok(ranked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIM: usize = 48;
+
+    /// Clustered synthetic rows so nearest neighbours are meaningful.
+    fn rows(n: usize) -> Vec<Vec<f32>> {
+        (0..n)
+            .map(|i| {
+                let centre = (i % 10) as f32;
+                (0..DIM)
+                    .map(|d| {
+                        (centre * 1.7 + d as f32 * 0.31).sin()
+                            + ((i * 13 + d * 7) as f32).cos() * 0.15
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn db_with(rows: &[Vec<f32>]) -> ImageDatabase {
+        let db = ImageDatabase::new(":memory:").unwrap();
+        db.initialize().unwrap();
+        for (i, row) in rows.iter().enumerate() {
+            let path = format!("/q/{i}.jpg");
+            db.add_image(path.clone(), None).unwrap();
+            let id = db.get_image_id_by_path(&path).unwrap();
+            db.upsert_embedding(id, "clip_vit_b_32", row).unwrap();
+        }
+        db
+    }
+
+    fn exact_top(rows: &[Vec<f32>], query: &[f32], k: usize, exclude: usize) -> Vec<usize> {
+        let mut scored: Vec<(usize, f32)> = rows
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != exclude)
+            .map(|(i, r)| (i, dot(query, r) * inv_norm(query) * inv_norm(r)))
+            .collect();
+        scored.sort_unstable_by(score_cmp_desc);
+        scored.into_iter().take(k).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn int8_scores_track_exact_cosine() {
+        let data = rows(500);
+        let params = QuantParams::fit(QuantMode::Int8, &data).unwrap();
+        let mut index = QuantizedIndex::new(params);
+        for (i, r) in data.iter().enumerate() {
+            assert!(index.push(PathBuf::from(format!("/q/{i}.jpg")), r));
+        }
+        assert_eq!(index.code_bytes(), 500 * DIM + 500 * 4);
+        let query = &data[3];
+        for (i, approx) in index.candidates(query, 500, None, None) {
+            let exact = dot(query, &data[i]) * inv_norm(query) * inv_norm(&data[i]);
+            assert!(
+                (approx - exact).abs() < 0.02,
+                "row {i}: {approx} vs {exact}"
+            );
+        }
+    }
+
+    #[test]
+    fn binary_codes_pack_bits_and_rank_self_first() {
+        let data = rows(200);
+        let params = QuantParams::fit(QuantMode::Binary, &data).unwrap();
+        assert_eq!(params.code_len(), 8, "48 bits round up to one word");
+        let mut index = QuantizedIndex::new(params);
+        for (i, r) in data.iter().enumerate() {
+            index.push(PathBuf::from(format!("/q/{i}.jpg")), r);
+        }
+        assert_eq!(index.code_bytes(), 200 * 8 + 200 * 4);
+        // Rows of one cluster can share a code, so 17 may tie for first.
+        let top = index.candidates(&data[17], 5, None, None);
+        assert_eq!(top[0].1, 1.0);
+        assert!(top.iter().any(|&(i, s)| i == 17 && s == 1.0));
+        assert!(index.candidates(&[1.0; 3], 5, None, None).is_empty());
+    }
+
+    #[test]
+    fn reranked_results_match_exact_search_and_use_full_scores() {
+        // Larger than either re-rank pool, so the codes do the cut.
+        let data = rows(1_500);
+        let db = db_with(&data);
+        for mode in [QuantMode::Int8, QuantMode::Binary] {
+            let mut index = QuantizedIndex::populate(&db, "clip_vit_b_32", mode).unwrap();
+            assert_eq!(index.len(), 1_500);
+            let query = &data[42];
+            let exclude = PathBuf::from("/q/42.jpg");
+            let got = index
+                .search_reranked(&db, "clip_vit_b_32", query, 10, Some(&exclude), None)
+                .unwrap();
+            let want = exact_top(&data, query, 10, 42);
+            let got_rows: Vec<usize> = got
+                .iter()
+                .map(|(p, _)| p.file_stem().unwrap().to_str().unwrap().parse().unwrap())
+                .collect();
+            assert_eq!(got_rows, want, "{mode:?}");
+            for (path, sim) in &got {
+                let i: usize = path.file_stem().unwrap().to_str().unwrap().parse().unwrap();
+                let exact = dot(query, &data[i]) * inv_norm(query) * inv_norm(&data[i]);
+                assert!(
+                    (sim - exact).abs() < 1e-5,
+                    "{mode:?} re-ranked score is exact"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn allowed_rows_fill_the_reranked_page() {
+        let data = rows(1_500);
+        let db = db_with(&data);
+        // One cluster in ten, and not the query's.
+        let allowed: HashSet<PathBuf> = (0..1_500)
+            .filter(|i| i % 10 == 5)
+            .map(|i| PathBuf::from(format!("/q/{i}.jpg")))
+            .collect();
+        for mode in [QuantMode::Int8, QuantMode::Binary] {
+            let mut index = QuantizedIndex::populate(&db, "clip_vit_b_32", mode).unwrap();
+            let got = index
+                .search_reranked(&db, "clip_vit_b_32", &data[42], 10, None, Some(&allowed))
+                .unwrap();
+            assert_eq!(got.len(), 10, "{mode:?}");
+            assert!(got.iter().all(|(p, _)| allowed.contains(p)), "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn params_are_stored_in_meta_and_reused() {
+        let data = rows(50);
+        let db = db_with(&data);
+        let first = QuantParams::load_or_fit(&db, "clip_vit_b_32", QuantMode::Int8)
+            .unwrap()
+            .unwrap();
+        let key = QuantParams::meta_key("clip_vit_b_32", QuantMode::Int8);
+        assert_eq!(key, "quant_params:clip_vit_b_32:int8");
+        let stored: QuantParams =
+            serde_json::from_str(&db.get_meta(&key).unwrap().unwrap()).unwrap();
+        assert_eq!(stored, first);
+
+        // A grid from another pipeline version is refitted and replaced.
+        let stale = QuantParams {
+            pipeline_version: EMBEDDING_PIPELINE_VERSION - 1,
+            ..first.clone()
+        };
+        db.set_meta(&key, &serde_json::to_string(&stale).unwrap())
+            .unwrap();
+        let refit = QuantParams::load_or_fit(&db, "clip_vit_b_32", QuantMode::Int8)
+            .unwrap()
+            .unwrap();
+        assert_eq!(refit.pipeline_version, EMBEDDING_PIPELINE_VERSION);
+
+        assert!(
+            QuantParams::load_or_fit(&db, "dinov2_base", QuantMode::Binary)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,313 @@
+//! kNN tag propagation: suggest tags for an image from its visually
+//! similar tagged neighbours, and spread a tag to the images that look
+//! most like the ones already carrying it.
+//!
+//! Both directions run on the same neighbourhood as View Similar — one
+//! ranked list per enabled encoder from `FusionIndexState`, fused with
+//! `reciprocal_rank_fusion` (`search::similarity::ranked_lists_for_image`).
+//!
+//! - **Suggest** (`suggest_tags`): take the image's nearest *tagged*
+//!   neighbours and let each vote for its tags, weighted by fused
+//!   score. A tag's score is the share of the total vote weight it
+//!   received, so 1.0 means every voting neighbour carries it.
+//! - **Propagate** (`propagate_tag`): fuse the neighbourhoods of the
+//!   images already tagged X, sum each candidate's fused scores across
+//!   them, and tag the top N that don't have X yet.
+//!
+//! Unlike auto-tagging (`autotag.rs`) this needs no text encoder and no
+//! label vocabulary — only tags the user has already applied by hand.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use serde::Serialize;
+use tracing::info;
+
+use crate::db::{ImageDatabase, JournalOp, ID};
+use crate::error::ApiError;
+use crate::search::similarity::ranked_lists_for_image;
+use crate::search::{resolve_image_id_for_cosine_path, ImageSearchResult};
+use crate::similarity_and_semantic_search::cosine::rrf::{reciprocal_rank_fusion, DEFAULT_K_RRF};
+use crate::tag_struct::Tag;
+use crate::FusionIndexState;
+
+/// Tagged neighbours that vote on an image's suggestions.
+pub const DEFAULT_VOTERS: usize = 15;
+
+/// Depth of each encoder's ranked list. Deeper than View Similar's
+/// default because untagged neighbours are skipped when voting — a
+/// sparsely tagged library still needs to reach `DEFAULT_VOTERS`.
+const NEIGHBOURHOOD_DEPTH: usize = 200;
+
+/// Most tagged images used as seeds by `propagate_tag`. Each seed is a
+/// full fused query, so a tag on thousands of images is sampled
+/// evenly rather than queried exhaustively.
+const MAX_SEEDS: usize = 64;
+
+/// One suggested tag for an image.
+#[derive(Debug, Clone, Serialize)]
+pub struct TagVote {
+    pub tag: Tag,
+    /// Share of the voting neighbours' fused weight that carries the
+    /// tag, in (0, 1].
+    pub score: f32,
+    /// How many of the voting neighbours carry the tag.
+    pub votes: usize,
+}
+
+/// Suggest tags for `image_id` from its `voters` nearest tagged
+/// neighbours, best first. Tags the image already has are left out.
+pub fn suggest_tags(
+    db: &ImageDatabase,
+    fusion_state: &FusionIndexState,
+    image_id: ID,
+    voters: usize,
+) -> Result<Vec<TagVote>, ApiError> {
+    let tag_map = db.get_image_tag_map()?;
+    let own = tag_map.get(&image_id).cloned().unwrap_or_default();
+    let neighbours = fused_neighbours(db, fusion_state, image_id, NEIGHBOURHOOD_DEPTH)?;
+    let ranked = vote_tags(&neighbours, &tag_map, &own, voters);
+
+    let tags: HashMap<ID, Tag> = db.get_tags()?.into_iter().map(|t| (t.id, t)).collect();
+    Ok(ranked
+        .into_iter()
+        .filter_map(|(tag_id, score, votes)| {
+            tags.get(&tag_id).map(|tag| TagVote {
+                tag: tag.clone(),
+                score,
+                votes,
+            })
+        })
+        .collect())
+}
+
+/// Tag the `top_n` images that look most like those already tagged
+/// `tag_id`, as one undoable entry. Returns the newly tagged images,
+/// best first, with the summed fused score as `score`.
+pub fn propagate_tag(
+    db: &ImageDatabase,
+    fusion_state: &FusionIndexState,
+    tag_id: ID,
+    top_n: usize,
+) -> Result<Vec<ImageSearchResult>, ApiError> {
+    let tagged = db.get_image_ids_with_tag(tag_id)?;
+    let seeds = sample_evenly(&tagged, MAX_SEEDS);
+    // Enough depth per seed that the top N survive dropping the
+    // already-tagged neighbours.
+    let depth = top_n
+        .saturating_add(tagged.len())
+        .clamp(NEIGHBOURHOOD_DEPTH, 4 * NEIGHBOURHOOD_DEPTH);
+
+    let mut per_seed = Vec::with_capacity(seeds.len());
+    for seed in &seeds {
+        per_seed.push(fused_neighbours(db, fusion_state, *seed, depth)?);
+    }
+    let already: HashSet<ID> = tagged.iter().copied().collect();
+    let picks = rank_lookalikes(&per_seed, &already, top_n);
+
+    let ids: Vec<ID> = picks.iter().map(|(id, _)| *id).collect();
+    let tag = db.get_tag(tag_id)?;
+    db.journaled(
+        &format!("Propagate tag \"{}\"", tag.name),
+        vec![JournalOp::TagImages {
+            tag_id,
+            image_ids: ids.clone(),
+        }],
+    )?;
+    info!(
+        "propagate_tag {tag_id}: {} seeds of {} tagged, {} images tagged",
+        seeds.len(),
+        tagged.len(),
+        ids.len()
+    );
+
+    let mut results = Vec::with_capacity(picks.len());
+    for (id, score) in picks {
+        let path = db.get_image_path(id)?;
+        let (thumbnail_path, width, height) = db
+            .get_image_thumbnail_info(id)?
+            .map(|(tp, w, h)| (Some(tp), Some(w), Some(h)))
+            .unwrap_or((None, None, None));
+        results.push(ImageSearchResult {
+            id,
+            path,
+            score,
+            thumbnail_path,
+            width,
+            height,
+        });
+    }
+    Ok(results)
+}
+
+/// `image_id`'s fused neighbours as `(id, fused_score)`, best first.
+/// Empty when the image has no embeddings yet.
+fn fused_neighbours(
+    db: &ImageDatabase,
+    fusion_state: &FusionIndexState,
+    image_id: ID,
+    depth: usize,
+) -> Result<Vec<(ID, f32)>, ApiError> {
+    let exclude_path = PathBuf::from(db.get_image_path(image_id)?);
+    let enabled = crate::settings::Settings::load().resolved_enabled_encoders();
+    let encoders: Vec<&str> = enabled.iter().map(|s| s.as_str()).collect();
+
+    let mut diag = Vec::new();
+    let lists = ranked_lists_for_image(
+        db,
+        fusion_state,
+        image_id,
+        &encoders,
+        depth,
+        Some(&exclude_path),
+        None,
+        &mut diag,
+    )?;
+    if lists.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(reciprocal_rank_fusion(&lists, DEFAULT_K_RRF, depth)
+        .into_iter()
+        .filter_map(|f| {
+            resolve_image_id_for_cosine_path(db, &f.path, None).map(|(id, _)| (id, f.fused_score))
+        })
+        .collect())
+}
+
+/// Weighted kNN vote. Walks `neighbours` (best first), counts the first
+/// `voters` that carry any tag, and scores each tag by the fused weight
+/// of the voters carrying it over the voters' total weight. Tags in
+/// `exclude` are skipped. Returns `(tag_id, score, votes)` best first;
+/// ties break on vote count, then tag id.
+pub fn vote_tags(
+    neighbours: &[(ID, f32)],
+    tags_of: &HashMap<ID, Vec<ID>>,
+    exclude: &[ID],
+    voters: usize,
+) -> Vec<(ID, f32, usize)> {
+    let mut total = 0.0f32;
+    let mut tally: HashMap<ID, (f32, usize)> = HashMap::new();
+    for (image_id, weight) in neighbours
+        .iter()
+        .filter(|(id, _)| tags_of.get(id).is_some_and(|t| !t.is_empty()))
+        .take(voters)
+    {
+        total += weight;
+        for tag_id in &tags_of[image_id] {
+            if exclude.contains(tag_id) {
+                continue;
+            }
+            let entry = tally.entry(*tag_id).or_default();
+            entry.0 += weight;
+            entry.1 += 1;
+        }
+    }
+    if total <= 0.0 {
+        return Vec::new();
+    }
+
+    let mut ranked: Vec<(ID, f32, usize)> = tally
+        .into_iter()
+        .map(|(id, (w, n))| (id, w / total, n))
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.2.cmp(&a.2)).then(a.0.cmp(&b.0)));
+    ranked
+}
+
+/// Sum each candidate's fused score over every seed's neighbour list
+/// and return the `top_n` best that aren't in `already`. Ties break on
+/// image id so reruns are stable.
+pub fn rank_lookalikes(
+    per_seed: &[Vec<(ID, f32)>],
+    already: &HashSet<ID>,
+    top_n: usize,
+) -> Vec<(ID, f32)> {
+    let mut sums: HashMap<ID, f32> = HashMap::new();
+    for (id, score) in per_seed.iter().flatten() {
+        if !already.contains(id) {
+            *sums.entry(*id).or_default() += score;
+        }
+    }
+    let mut ranked: Vec<(ID, f32)> = sums.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked.truncate(top_n);
+    ranked
+}
+
+/// At most `max` of `ids`, spread evenly across the slice — the same
+/// picks every run for the same input.
+pub fn sample_evenly(ids: &[ID], max: usize) -> Vec<ID> {
+    if ids.len() <= max {
+        return ids.to_vec();
+    }
+    (0..max).map(|i| ids[i * ids.len() / max]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn votes_are_weighted_by_fused_score() {
+        // Neighbour 2 is untagged and doesn't count towards `voters`.
+        let neighbours = [(1, 0.04), (2, 0.035), (3, 0.03), (4, 0.01), (5, 0.009)];
+        let tags_of: HashMap<ID, Vec<ID>> = [
+            (1, vec![10]),
+            (3, vec![10, 20]),
+            (4, vec![20]),
+            (5, vec![30]),
+        ]
+        .into_iter()
+        .collect();
+
+        let ranked = vote_tags(&neighbours, &tags_of, &[], 3);
+        assert_eq!(ranked.iter().map(|r| r.0).collect::<Vec<_>>(), vec![10, 20]);
+        assert!((ranked[0].1 - 0.07 / 0.08).abs() < 1e-6);
+        assert_eq!(ranked[0].2, 2);
+        assert_eq!(ranked[1].2, 2);
+    }
+
+    #[test]
+    fn existing_tags_are_not_suggested() {
+        let tags_of: HashMap<ID, Vec<ID>> = [(1, vec![10, 20])].into_iter().collect();
+        let ranked = vote_tags(&[(1, 0.5)], &tags_of, &[10], 5);
+        assert_eq!(ranked, vec![(20, 1.0, 1)]);
+        assert!(vote_tags(&[(2, 0.5)], &tags_of, &[], 5).is_empty());
+    }
+
+    #[test]
+    fn a_tagged_image_of_a_removed_root_is_not_a_seed() {
+        let db = ImageDatabase::new(":memory:").unwrap();
+        db.initialize().unwrap();
+        let kept = db.add_root("/kept".into()).unwrap();
+        let gone = db.add_root("/gone".into()).unwrap();
+        db.add_image("/kept/a.jpg".into(), Some(kept.id)).unwrap();
+        db.add_image("/gone/b.jpg".into(), Some(gone.id)).unwrap();
+        let tag = db.create_tag("cat".into(), "#fff".into()).unwrap();
+        db.add_tag_to_images(tag.id, &[1, 2]).unwrap();
+        db.remove_root(gone.id).unwrap();
+
+        assert_eq!(db.get_image_ids_with_tag(tag.id).unwrap(), vec![1]);
+        let tagged = propagate_tag(&db, &FusionIndexState::default(), tag.id, 5).unwrap();
+        assert!(tagged.is_empty());
+    }
+
+    #[test]
+    fn lookalikes_near_many_seeds_rank_first() {
+        let per_seed = vec![
+            vec![(7, 0.03), (8, 0.02), (1, 0.02)],
+            vec![(8, 0.03), (9, 0.025)],
+        ];
+        let already: HashSet<ID> = [1].into_iter().collect();
+        let ranked = rank_lookalikes(&per_seed, &already, 2);
+        assert_eq!(ranked.iter().map(|r| r.0).collect::<Vec<_>>(), vec![8, 7]);
+        assert!((ranked[0].1 - 0.05).abs() < 1e-6);
+    }
+
+    #[test]
+    fn seeds_are_sampled_evenly() {
+        let ids: Vec<ID> = (0..10).collect();
+        assert_eq!(sample_evenly(&ids, 4), vec![0, 2, 5, 7]);
+        assert_eq!(sample_evenly(&ids[..3], 4), vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db::ID;
+
+/// Colour for tags created without one (CLI, accepted auto-tag
+/// suggestions). Matches the frontend's `createTag` default.
+pub const DEFAULT_TAG_COLOR: &str = "#3B82F6";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: ID,
+    pub name: String,
+    pub color: String,
+    /// The tag this one sits under; `None` at the top level.
+    #[serde(default)]
+    pub parent_id: Option<ID>,
+}
+
+impl Tag {
+    /// A top-level tag.
+    pub fn new(id: ID, name: String, color: String) -> Self {
+        Self {
+            id,
+            name,
+            color,
+            parent_id: None,
+        }
+    }
+}
@@ -1,3 +1,4 @@
 pub mod generator;
+pub mod phash;
 
 pub use generator::ThumbnailGenerator;
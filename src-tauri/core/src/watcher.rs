@@ -0,0 +1,443 @@
+//! Filesystem watcher for live integrity.
+//!
+//! Watches every enabled root recursively. Filesystem events are
+//! debounced (5s of quiet) and the changed paths are handed to the
+//! indexing pipeline as an incremental run: new image files appear in
+//! the DB, rewritten files are re-encoded, files that have disappeared
+//! get marked `orphaned = 1` — touching only those paths and the root
+//! they belong to (`incremental.rs`). No root walk, no library-wide
+//! orphan pass.
+//!
+//! Lifecycle:
+//! - `start` runs once during the Tauri setup callback.
+//! - The returned handle owns the notify watcher. Dropping it stops
+//!   the watch threads, which disconnects the channel and ends the
+//!   debounce thread (it's stored in a Tauri-managed state).
+//! - A probe thread checks every enabled root's folder every
+//!   `PROBE_INTERVAL` (`root_availability.rs`). A folder that vanished
+//!   without an event (network share dropped) is handed to an
+//!   incremental run, which flags the root offline; a root that is
+//!   back is watched again and rescanned by a full run. Roots added or
+//!   enabled after startup are picked up by the same check. Removed
+//!   roots stay watched until restart; their events match no root and
+//!   are ignored.
+//!
+//! Implementation notes:
+//! - Raw notify events can fire dozens of times per "save" on macOS
+//!   (every metadata change, every fsync). The debounce thread folds
+//!   them into one set of distinct paths; a batch closes after 5s
+//!   without events, or after 30s of continuous activity so a long
+//!   copy still shows progress.
+//! - We debounce ourselves rather than with notify-debouncer-mini:
+//!   the mini debouncer drops notify's "rescan" flag (inotify queue
+//!   overflow, FSEvents dropped events), and that flag is exactly when
+//!   per-path updates can't be trusted.
+//! - Overflow → full rescan via `try_spawn_pipeline`. So is a watcher
+//!   error, and a batch over `MAX_INCREMENTAL_PATHS` paths.
+//! - Access events (open, read, close-without-write) are dropped. The
+//!   pipeline itself opens every file it hashes, thumbnails or
+//!   encodes; counting those would feed the watcher its own reads.
+//! - Batches that arrive while a run is in flight are queued onto it
+//!   (`indexing::try_spawn_incremental`) and merged into one
+//!   follow-up. The watcher never cancels: a burst of file events
+//!   shouldn't throw away thumbnail or encoder progress.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use notify::event::{AccessKind, AccessMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tracing::{debug, info, warn};
+
+use crate::db::ImageDatabase;
+use crate::incremental::MAX_INCREMENTAL_PATHS;
+use crate::indexing::{self, ProgressSink};
+use crate::root_availability::is_available;
+use crate::similarity_and_semantic_search::cosine_similarity::CosineIndex;
+
+/// A batch closes once no event has arrived for this long.
+const QUIET_PERIOD: Duration = Duration::from_secs(5);
+/// ...or once it has been open this long, whichever comes first.
+const MAX_BATCH_AGE: Duration = Duration::from_secs(30);
+/// How often the probe thread checks that each root's folder is there.
+const PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Owns the notify watcher; dropping it stops watching. Opaque so the
+/// backend's type stays out of public signatures.
+pub struct WatcherHandle {
+    _watcher: Arc<Mutex<RecommendedWatcher>>,
+}
+
+/// Changed paths collected over one debounce window.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct WatchBatch {
+    paths: HashSet<PathBuf>,
+    /// Events were lost (or the batch is too big to apply path by
+    /// path): only a full rescan is trustworthy.
+    overflow: bool,
+}
+
+impl WatchBatch {
+    fn absorb(&mut self, event: notify::Result<Event>) {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("watcher error, falling back to a full rescan: {e}");
+                self.overflow = true;
+                return;
+            }
+        };
+        if event.need_rescan() {
+            warn!("watcher overflow, falling back to a full rescan");
+            self.overflow = true;
+            return;
+        }
+        if matches!(event.kind, EventKind::Access(kind) if kind != AccessKind::Close(AccessMode::Write))
+        {
+            return;
+        }
+        if self.overflow {
+            return;
+        }
+        self.paths.extend(event.paths);
+        if self.paths.len() > MAX_INCREMENTAL_PATHS {
+            info!(
+                "watcher: more than {MAX_INCREMENTAL_PATHS} changed paths, falling back to a full rescan"
+            );
+            self.overflow = true;
+            self.paths.clear();
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.overflow && self.paths.is_empty()
+    }
+}
+
+/// Block until an event arrives, then keep absorbing until the batch
+/// is quiet for `quiet` or older than `max_age`. `None` once the
+/// watcher is gone and nothing is left to flush.
+fn collect_batch(
+    rx: &Receiver<notify::Result<Event>>,
+    quiet: Duration,
+    max_age: Duration,
+) -> Option<WatchBatch> {
+    let mut batch = WatchBatch::default();
+    batch.absorb(rx.recv().ok()?);
+    let opened = Instant::now();
+    loop {
+        let remaining = max_age.saturating_sub(opened.elapsed());
+        if remaining.is_zero() {
+            return Some(batch);
+        }
+        match rx.recv_timeout(quiet.min(remaining)) {
+            Ok(event) => batch.absorb(event),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                return Some(batch);
+            }
+        }
+    }
+}
+
+/// What one probe found.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ProbeOutcome {
+    /// Folders of roots not yet flagged offline that are gone.
+    vanished: Vec<PathBuf>,
+    /// An offline root's folder is back.
+    reappeared: bool,
+}
+
+/// Check every enabled root's folder against its `offline` flag and
+/// keep `watched` in step: a vanished folder's watch is dropped, a
+/// folder that isn't watched yet is watched. `set_watch(path, true)`
+/// watches and reports success; `set_watch(path, false)` unwatches.
+/// The flags themselves are left to the runs the caller spawns.
+fn probe_roots(
+    database: &ImageDatabase,
+    watched: &mut HashSet<PathBuf>,
+    mut set_watch: impl FnMut(&Path, bool) -> bool,
+) -> rusqlite::Result<ProbeOutcome> {
+    let mut outcome = ProbeOutcome::default();
+    for root in database.list_roots()?.into_iter().filter(|r| r.enabled) {
+        let path = PathBuf::from(&root.path);
+        if !is_available(&root) {
+            if watched.remove(&path) {
+                set_watch(&path, false);
+            }
+            if !root.offline {
+                outcome.vanished.push(path);
+            }
+            continue;
+        }
+        if !watched.contains(&path) && set_watch(&path, true) {
+            watched.insert(path);
+        }
+        outcome.reappeared |= root.offline;
+    }
+    Ok(outcome)
+}
+
+fn set_watch(watcher: &mut RecommendedWatcher, path: &Path, watch: bool) -> bool {
+    if !watch {
+        // Usually already gone with the folder; nothing to do if so.
+        let _ = watcher.unwatch(path);
+        return false;
+    }
+    match watcher.watch(path, RecursiveMode::Recursive) {
+        Ok(()) => {
+            info!("watching {} (recursive)", path.display());
+            true
+        }
+        Err(e) => {
+            warn!("could not watch {}: {e}", path.display());
+            false
+        }
+    }
+}
+
+/// Spin up watchers for every enabled root path, reporting the runs
+/// they trigger to `sink`. Paths that don't exist yet (offline roots),
+/// and roots added or enabled later, are watched once the probe sees
+/// them — so the watcher starts even with no root to watch yet. The
+/// returned handle must outlive the app — typically held in a
+/// Tauri-managed state struct. Returns None if the underlying notify
+/// backend can't be initialised on the current platform.
+#[tracing::instrument(name = "watcher.start", skip(sink, indexing_state, cosine_index, cosine_current_encoder))]
+pub fn start<S: ProgressSink + Clone + 'static>(
+    sink: S,
+    paths_to_watch: Vec<PathBuf>,
+    db_path: String,
+    indexing_state: Arc<indexing::IndexingState>,
+    cosine_index: Arc<std::sync::Mutex<CosineIndex>>,
+    cosine_current_encoder: Arc<std::sync::Mutex<String>>,
+) -> Option<WatcherHandle> {
+    if paths_to_watch.is_empty() {
+        info!("watcher: no enabled roots yet; the probe will watch them once added");
+    }
+
+    let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
+    let mut watcher = match notify::recommended_watcher(tx) {
+        Ok(w) => w,
+        Err(e) => {
+            warn!("could not initialise filesystem watcher: {e}");
+            return None;
+        }
+    };
+
+    let watched: HashSet<PathBuf> = paths_to_watch
+        .into_iter()
+        .filter(|path| path.is_dir() && set_watch(&mut watcher, path, true))
+        .collect();
+    let watcher = Arc::new(Mutex::new(watcher));
+
+    spawn_probe(
+        sink.clone(),
+        Arc::downgrade(&watcher),
+        watched,
+        db_path.clone(),
+        indexing_state.clone(),
+        cosine_index.clone(),
+        cosine_current_encoder.clone(),
+    );
+
+    thread::spawn(move || {
+        while let Some(batch) = collect_batch(&rx, QUIET_PERIOD, MAX_BATCH_AGE) {
+            if batch.is_empty() {
+                continue;
+            }
+            // Manual span per debounce batch. Closure-based callbacks
+            // can't carry #[tracing::instrument] directly.
+            let _span = tracing::info_span!("watcher.event").entered();
+            if batch.overflow {
+                indexing::try_spawn_pipeline(
+                    sink.clone(),
+                    indexing_state.clone(),
+                    db_path.clone(),
+                    cosine_index.clone(),
+                    cosine_current_encoder.clone(),
+                );
+            } else {
+                debug!(
+                    "watcher: {} changed paths, triggering incremental update",
+                    batch.paths.len()
+                );
+                indexing::try_spawn_incremental(
+                    sink.clone(),
+                    indexing_state.clone(),
+                    db_path.clone(),
+                    cosine_index.clone(),
+                    cosine_current_encoder.clone(),
+                    batch.paths.into_iter().collect(),
+                );
+            }
+        }
+        debug!("watcher: event channel closed, debounce thread exiting");
+    });
+
+    Some(WatcherHandle { _watcher: watcher })
+}
+
+/// Run `probe_roots` every `PROBE_INTERVAL` until the handle is
+/// dropped. A vanished folder goes to an incremental run, which flags
+/// its root offline and refreshes the grid; a reappeared one triggers
+/// a full run, which flags it online and rescans it.
+fn spawn_probe<S: ProgressSink + Clone + 'static>(
+    sink: S,
+    watcher: Weak<Mutex<RecommendedWatcher>>,
+    mut watched: HashSet<PathBuf>,
+    db_path: String,
+    indexing_state: Arc<indexing::IndexingState>,
+    cosine_index: Arc<std::sync::Mutex<CosineIndex>>,
+    cosine_current_encoder: Arc<std::sync::Mutex<String>>,
+) {
+    thread::spawn(move || {
+        let database = match ImageDatabase::new(&db_path) {
+            Ok(database) => database,
+            Err(e) => {
+                warn!("watcher: could not open the DB for root probing: {e}");
+                return;
+            }
+        };
+        loop {
+            thread::sleep(PROBE_INTERVAL);
+            let Some(watcher) = watcher.upgrade() else {
+                break;
+            };
+            let outcome = {
+                let mut watcher = watcher.lock().unwrap();
+                probe_roots(&database, &mut watched, |path, watch| {
+                    set_watch(&mut watcher, path, watch)
+                })
+            };
+            let outcome = match outcome {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    warn!("watcher: root probe failed: {e}");
+                    continue;
+                }
+            };
+            if outcome.reappeared {
+                info!("watcher: an offline root is back, triggering a full rescan");
+                indexing::try_spawn_pipeline(
+                    sink.clone(),
+                    indexing_state.clone(),
+                    db_path.clone(),
+                    cosine_index.clone(),
+                    cosine_current_encoder.clone(),
+                );
+            } else if !outcome.vanished.is_empty() {
+                indexing::try_spawn_incremental(
+                    sink.clone(),
+                    indexing_state.clone(),
+                    db_path.clone(),
+                    cosine_index.clone(),
+                    cosine_current_encoder.clone(),
+                    outcome.vanished,
+                );
+            }
+        }
+        debug!("watcher: handle dropped, root probe exiting");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, Flag, ModifyKind};
+
+    fn event(kind: EventKind, path: &str) -> notify::Result<Event> {
+        Ok(Event::new(kind).add_path(PathBuf::from(path)))
+    }
+
+    #[test]
+    fn batch_collects_distinct_paths_and_skips_reads() {
+        let mut batch = WatchBatch::default();
+        batch.absorb(event(EventKind::Create(CreateKind::File), "/r/a.jpg"));
+        batch.absorb(event(EventKind::Modify(ModifyKind::Any), "/r/a.jpg"));
+        batch.absorb(event(EventKind::Access(AccessKind::Open(AccessMode::Read)), "/r/b.jpg"));
+        batch.absorb(event(EventKind::Access(AccessKind::Close(AccessMode::Write)), "/r/c.jpg"));
+
+        let mut paths: Vec<_> = batch.paths.iter().cloned().collect();
+        paths.sort();
+        assert_eq!(paths, vec![PathBuf::from("/r/a.jpg"), PathBuf::from("/r/c.jpg")]);
+        assert!(!batch.overflow);
+    }
+
+    #[test]
+    fn rescan_flag_and_errors_force_a_full_rescan() {
+        let mut batch = WatchBatch::default();
+        batch.absorb(Ok(Event::new(EventKind::Other).set_flag(Flag::Rescan)));
+        assert!(batch.overflow && !batch.is_empty());
+
+        let mut batch = WatchBatch::default();
+        batch.absorb(Err(notify::Error::generic("queue overflow")));
+        assert!(batch.overflow);
+    }
+
+    #[test]
+    fn oversized_batch_becomes_overflow() {
+        let mut batch = WatchBatch::default();
+        for i in 0..=MAX_INCREMENTAL_PATHS {
+            batch.absorb(event(EventKind::Create(CreateKind::File), &format!("/r/{i}.jpg")));
+        }
+        assert!(batch.overflow);
+        assert!(batch.paths.is_empty(), "paths are dropped once overflowed");
+    }
+
+    #[test]
+    fn collect_batch_closes_on_quiet_and_on_disconnect() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(event(EventKind::Create(CreateKind::File), "/r/a.jpg")).unwrap();
+        tx.send(event(EventKind::Create(CreateKind::File), "/r/b.jpg")).unwrap();
+        let batch = collect_batch(&rx, Duration::from_millis(20), Duration::from_secs(5)).unwrap();
+        assert_eq!(batch.paths.len(), 2);
+
+        tx.send(event(EventKind::Create(CreateKind::File), "/r/c.jpg")).unwrap();
+        drop(tx);
+        let batch = collect_batch(&rx, Duration::from_secs(5), Duration::from_secs(5)).unwrap();
+        assert_eq!(batch.paths.len(), 1);
+        assert!(collect_batch(&rx, Duration::from_secs(5), Duration::from_secs(5)).is_none());
+    }
+
+    #[test]
+    fn probe_rewatches_a_root_that_comes_back() {
+        let tmp = tempfile::tempdir().unwrap();
+        let usb = tmp.path().join("usb");
+        let db = ImageDatabase::new(":memory:").unwrap();
+        db.initialize().unwrap();
+        let root = db.add_root(usb.to_string_lossy().into_owned()).unwrap();
+        let mut watched = HashSet::new();
+        let mut calls = Vec::new();
+        let mut probe = |db: &ImageDatabase, watched: &mut HashSet<PathBuf>| {
+            probe_roots(db, watched, |path, watch| {
+                calls.push((path.to_path_buf(), watch));
+                watch
+            })
+            .unwrap()
+        };
+
+        // Unplugged and not flagged yet: reported once, never watched.
+        let outcome = probe(&db, &mut watched);
+        assert_eq!(outcome.vanished, vec![usb.clone()]);
+        assert!(!outcome.reappeared && watched.is_empty());
+        db.set_root_offline(root.id, true).unwrap();
+        assert_eq!(probe(&db, &mut watched), ProbeOutcome::default());
+
+        // Plugged back in: watched again and a rescan asked for.
+        std::fs::create_dir(&usb).unwrap();
+        assert!(probe(&db, &mut watched).reappeared);
+        assert!(watched.contains(&usb));
+
+        // Unplugged again: the dead watch is dropped.
+        db.set_root_offline(root.id, false).unwrap();
+        std::fs::remove_dir(&usb).unwrap();
+        assert_eq!(probe(&db, &mut watched).vanished, vec![usb.clone()]);
+        assert!(watched.is_empty());
+        assert_eq!(calls, vec![(usb.clone(), true), (usb, false)]);
+    }
+}
@@ -23,7 +23,7 @@
 //! recommends changing; running it as part of CI would lock in the
 //! current shape.
 
-use image_browser_lib::settings::Settings;
+use image_browser_core::settings::Settings;
 
 const TEXT_CAPABLE: &[&str] = &["clip_vit_b_32", "siglip2_base"];
 
@@ -38,6 +38,8 @@ fn dinov2_only_settings_resolves_with_zero_text_capable_encoders() {
         scan_root: None,
         priority_image_encoder: None,
         enabled_encoders: Some(vec!["dinov2_base".to_string()]),
+        embedding_quantization: None,
+        http_api_port: None,
     };
     let resolved = s.resolved_enabled_encoders();
     assert_eq!(resolved, vec!["dinov2_base".to_string()]);
@@ -69,6 +71,8 @@ fn empty_enabled_list_falls_back_to_default_with_text_capable_encoders() {
         scan_root: None,
         priority_image_encoder: None,
         enabled_encoders: Some(vec![]),
+        embedding_quantization: None,
+        http_api_port: None,
     };
     let resolved = s.resolved_enabled_encoders();
     let intersection: Vec<&str> = TEXT_CAPABLE
@@ -0,0 +1,80 @@
+//! Boards end to end on a real DB: a full clustering run, then the
+//! incremental assignment the indexing pipeline does for new images.
+//! Embeddings are synthetic and there is no text encoder, so the
+//! boards come back unnamed.
+
+use image_browser_core::clusters::{self, DEFAULT_ENCODER};
+use image_browser_core::db::ImageDatabase;
+use image_browser_core::TextEncoderState;
+
+/// `count` unit vectors around `axis` of a 4-d space.
+fn group(axis: usize, count: usize) -> Vec<[f32; 4]> {
+    (0..count)
+        .map(|i| {
+            let mut v = [0.05 * i as f32, 0.0, 0.02, 0.0];
+            v[axis] = 1.0;
+            v
+        })
+        .collect()
+}
+
+#[test]
+fn clusters_then_assigns_new_images_to_their_board() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = ImageDatabase::new(tmp.path().join("t.db").to_str().unwrap()).unwrap();
+    db.initialize().unwrap();
+
+    let mut id = 0;
+    for (axis, count) in [(1, 6), (2, 4), (3, 2)] {
+        for e in group(axis, count) {
+            id += 1;
+            db.add_image(format!("/lib/{id}.jpg"), None).unwrap();
+            db.upsert_embedding(id, DEFAULT_ENCODER, &e).unwrap();
+        }
+    }
+
+    let boards =
+        clusters::run(&db, &TextEncoderState::default(), DEFAULT_ENCODER, Some(3)).unwrap();
+    assert_eq!(
+        boards.iter().map(|b| b.size).collect::<Vec<_>>(),
+        vec![6, 4, 2]
+    );
+    assert!(boards.iter().all(|b| b.encoder_id == DEFAULT_ENCODER));
+    assert!(boards.iter().all(|b| b.label.is_none()));
+    let members: Vec<i64> = db
+        .get_cluster_members(boards[1].id, 10, 0)
+        .unwrap()
+        .iter()
+        .map(|m| m.image_id)
+        .collect();
+    assert_eq!(members.len(), 4);
+    assert!(members.iter().all(|id| (7..=10).contains(id)));
+
+    // Nothing new yet.
+    assert_eq!(clusters::assign_new_images(&db).unwrap(), 0);
+
+    // A new image near the smallest board joins it.
+    db.add_image("/lib/new.jpg".into(), None).unwrap();
+    db.upsert_embedding(id + 1, DEFAULT_ENCODER, &[0.0, 0.0, 0.0, 1.0])
+        .unwrap();
+    assert_eq!(clusters::assign_new_images(&db).unwrap(), 1);
+    let smallest = db.get_cluster(boards[2].id).unwrap();
+    assert_eq!(smallest.size, 3);
+    let centroid = db
+        .get_cluster_centroids()
+        .unwrap()
+        .into_iter()
+        .find(|c| c.id == smallest.id)
+        .unwrap();
+    assert_eq!(centroid.member_count, 3);
+
+    // Asking for more boards than images is capped, and an encoder
+    // with no embeddings is refused.
+    assert_eq!(
+        clusters::run(&db, &TextEncoderState::default(), DEFAULT_ENCODER, Some(50))
+            .unwrap()
+            .len(),
+        13
+    );
+    assert!(clusters::run(&db, &TextEncoderState::default(), "siglip2_base", None).is_err());
+}
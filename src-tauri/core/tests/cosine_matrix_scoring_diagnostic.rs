@@ -0,0 +1,96 @@
+//! Diagnostic test for the contiguous embedding matrix.
+//!
+//! `CosineIndex` used to hold one heap-allocated `Array1<f32>` per
+//! image and score a query with one `cosine_similarity` call per row —
+//! three dot products and two square roots each, chasing a pointer per
+//! image. It now stores a single row-major block with precomputed
+//! inverse norms (`EmbeddingMatrix`) and scores it in one pass.
+//!
+//! What the test asserts:
+//!
+//! 1. **Score equivalence.** Every row's matrix score matches the
+//!    per-pair `CosineIndex::cosine_similarity` to rounding.
+//!
+//! 2. **Ranking equivalence.** `get_similar_images_sorted` returns the
+//!    same top-N as a full sort of the per-pair scores — the baseline
+//!    pinned by `cosine_topk_partial_sort_diagnostic.rs`.
+//!
+//! No timing assertion: wall-clock comparisons flake on loaded CI
+//! runners, and rayon only helps with cores to spare. Benchmark the
+//! matrix pass by hand when changing it.
+
+use image_browser_core::similarity_and_semantic_search::cosine::{CosineIndex, EmbeddingMatrix};
+use ndarray::Array1;
+use std::path::PathBuf;
+
+const DIM: usize = 512;
+
+/// Deterministic, well-spread synthetic embeddings.
+fn synth_rows(n: usize) -> Vec<(PathBuf, Array1<f32>)> {
+    (0..n)
+        .map(|i| {
+            let v = Array1::from_iter(
+                (0..DIM).map(|j| ((i * 7919 + j * 104_729) % 1000) as f32 / 500.0 - 1.0),
+            );
+            (PathBuf::from(format!("/synth/{i}.jpg")), v)
+        })
+        .collect()
+}
+
+fn matrix_of(rows: &[(PathBuf, Array1<f32>)]) -> EmbeddingMatrix {
+    let mut m = EmbeddingMatrix::new();
+    for (p, v) in rows {
+        assert!(m.push(p.clone(), v.as_slice().unwrap()));
+    }
+    m
+}
+
+#[test]
+fn matrix_scores_match_per_row_cosine() {
+    let rows = synth_rows(2_000);
+    let matrix = matrix_of(&rows);
+    let query = rows[42].1.clone();
+
+    let mut scores = Vec::new();
+    matrix.scores_into(query.as_slice().unwrap(), &mut scores);
+    assert_eq!(scores.len(), rows.len());
+    for (i, (_, v)) in rows.iter().enumerate() {
+        let want = CosineIndex::cosine_similarity(&query, v);
+        assert!(
+            (scores[i] - want).abs() < 1e-5,
+            "row {i}: matrix {} vs per-row {want}",
+            scores[i]
+        );
+    }
+}
+
+#[test]
+fn sorted_top_n_matches_full_sort_of_per_row_scores() {
+    let rows = synth_rows(3_000);
+    let query = rows[1234].1.clone();
+
+    let mut expected: Vec<(PathBuf, f32)> = rows
+        .iter()
+        .map(|(p, v)| (p.clone(), CosineIndex::cosine_similarity(&query, v)))
+        .collect();
+    expected.sort_by(|a, b| b.1.total_cmp(&a.1));
+    expected.truncate(50);
+
+    let mut index = CosineIndex::new();
+    for (p, v) in &rows {
+        index.add_image(p.clone(), v.clone());
+    }
+    let got = index.get_similar_images_sorted(&query, 50, None);
+
+    assert_eq!(got.len(), 50);
+    for (g, e) in got.iter().zip(&expected) {
+        assert!((g.1 - e.1).abs() < 1e-5, "score drift: {g:?} vs {e:?}");
+    }
+    // Paths can only differ where two scores tie to rounding.
+    let mismatched = got
+        .iter()
+        .zip(&expected)
+        .filter(|(g, e)| g.0 != e.0 && (g.1 - e.1).abs() > 1e-6)
+        .count();
+    assert_eq!(mismatched, 0);
+}
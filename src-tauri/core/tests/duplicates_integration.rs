@@ -0,0 +1,134 @@
+//! Duplicate finder end to end: real files, real thumbnails, a real
+//! DB — only the DINOv2 embeddings are synthetic, since the model
+//! isn't shipped with the repo.
+
+use image_browser_core::db::ImageDatabase;
+use image_browser_core::duplicates::{
+    find_duplicates, trash_duplicates, DuplicateKind, TrashSelection, DEFAULT_MIN_SIMILARITY,
+};
+use image_browser_core::filesystem::FileFingerprint;
+use image_browser_core::thumbnail::ThumbnailGenerator;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A JPEG of a 12×9 grid of grey blocks whose shades come from
+/// `seed`: equal seeds give the same picture at any size, different
+/// seeds give unrelated gradients.
+fn write_jpeg(path: &Path, w: u32, h: u32, seed: u32) {
+    let mut state = seed.wrapping_mul(2_654_435_761).wrapping_add(1);
+    let shades: Vec<u8> = (0..12 * 9)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect();
+    let img = image::RgbImage::from_fn(w, h, |x, y| {
+        let v = shades[((y * 9 / h) * 12 + x * 12 / w) as usize];
+        image::Rgb([v, v, v])
+    });
+    image::DynamicImage::ImageRgb8(img)
+        .save_with_format(path, image::ImageFormat::Jpeg)
+        .expect("write jpeg");
+}
+
+/// Six photos in `dir`: an exact pair (1, 2; keeper 1), a near pair
+/// (3, 6; keeper 3), and two singles (4, 5).
+fn library_with_duplicates(dir: &Path) -> ImageDatabase {
+    let photos = dir.join("photos");
+    let thumbs = dir.join("thumbnails");
+    fs::create_dir(&photos).unwrap();
+
+    // 1 and 2: byte-identical. 3: a picture; 6: the same picture at
+    // half size. 4: a different picture whose embedding is still
+    // close to 3's (same subject). 5: unrelated.
+    let files: Vec<PathBuf> = (1..=6).map(|i| photos.join(format!("{i}.jpg"))).collect();
+    write_jpeg(&files[0], 640, 480, 1);
+    fs::copy(&files[0], &files[1]).unwrap();
+    write_jpeg(&files[2], 1280, 960, 7);
+    write_jpeg(&files[3], 1280, 960, 20);
+    write_jpeg(&files[4], 640, 480, 40);
+    write_jpeg(&files[5], 640, 480, 7);
+
+    let db = ImageDatabase::new(dir.join("t.db").to_str().unwrap()).unwrap();
+    db.initialize().unwrap();
+    let mut fingerprints = Vec::new();
+    for (i, f) in files.iter().enumerate() {
+        db.add_image(f.to_string_lossy().into_owned(), None).unwrap();
+        fingerprints.push((i as i64 + 1, FileFingerprint::compute(f).unwrap()));
+    }
+    db.set_content_fingerprints(&fingerprints).unwrap();
+    ThumbnailGenerator::new(&thumbs, 400, 400)
+        .unwrap()
+        .generate_all_missing_thumbnails(&db)
+        .unwrap();
+
+    let embeddings: [(i64, [f32; 3]); 6] = [
+        (1, [1.0, 0.0, 0.0]),
+        (2, [1.0, 0.0, 0.0]),
+        (3, [0.0, 1.0, 0.0]),
+        (4, [0.0, 0.934, 0.358]), // cosine ≈ 0.93 to 3, but a different picture
+        (5, [0.0, 0.0, 1.0]),
+        (6, [0.0, 1.0, 0.05]),
+    ];
+    for (id, e) in embeddings {
+        db.upsert_embedding(id, "dinov2_base", &e).unwrap();
+    }
+    db
+}
+
+#[test]
+fn finds_exact_and_near_duplicates_with_a_keeper() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = library_with_duplicates(tmp.path());
+
+    let clusters = find_duplicates(&db, DEFAULT_MIN_SIMILARITY).unwrap();
+    assert_eq!(clusters.len(), 2, "{clusters:#?}");
+
+    let exact = clusters.iter().find(|c| c.kind == DuplicateKind::Exact).unwrap();
+    let ids: Vec<i64> = exact.images.iter().map(|i| i.id).collect();
+    assert_eq!(ids, vec![1, 2]);
+    assert_eq!(exact.keeper_id, 1, "equal copies keep the oldest row");
+
+    let near = clusters.iter().find(|c| c.kind == DuplicateKind::Near).unwrap();
+    assert_eq!(near.keeper_id, 3, "the full-size original is kept");
+    let ids: Vec<i64> = near.images.iter().map(|i| i.id).collect();
+    assert_eq!(ids, vec![3, 6], "4 is similar but its thumbnail hash differs");
+
+    // The dHashes computed on the way were stored.
+    assert!(db
+        .get_duplicate_candidates()
+        .unwrap()
+        .iter()
+        .all(|c| c.perceptual_hash.is_some()));
+}
+
+#[test]
+fn trashing_refuses_keepers_and_strangers() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = library_with_duplicates(tmp.path());
+    let select = |keeper_id, image_ids: &[i64]| TrashSelection {
+        keeper_id,
+        image_ids: image_ids.to_vec(),
+    };
+    let refused = [
+        // Its own keeper: the exact pair would lose its last live copy.
+        vec![select(1, &[1, 2])],
+        // A stale pick that kept 2 and trashed 1, next to one keeping 1.
+        vec![select(1, &[2]), select(2, &[1])],
+        // 4 looks like 3 but isn't in its cluster; 5 is in none.
+        vec![select(3, &[4])],
+        vec![select(5, &[1])],
+    ];
+    for selections in refused {
+        let err = trash_duplicates(&db, &selections, DEFAULT_MIN_SIMILARITY).unwrap_err();
+        assert!(err.to_string().starts_with("bad input"), "{err}");
+    }
+    // Nothing moved or left the library.
+    assert!((1..=6).all(|i| tmp.path().join(format!("photos/{i}.jpg")).exists()));
+    assert_eq!(db.get_duplicate_candidates().unwrap().len(), 6);
+
+    // A keeper other than the suggested one is fine.
+    let report = trash_duplicates(&db, &[select(2, &[1])], DEFAULT_MIN_SIMILARITY).unwrap();
+    assert_eq!(report.trashed.len() + report.failed.len(), 1, "{report:?}");
+    assert!(tmp.path().join("photos/2.jpg").exists());
+}
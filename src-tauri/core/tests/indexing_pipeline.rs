@@ -8,9 +8,9 @@
 //! orphan-detection pass — all the moving parts that broke in
 //! recent sessions.
 
-use image_browser_lib::db::ImageDatabase;
-use image_browser_lib::filesystem::ImageScanner;
-use image_browser_lib::thumbnail::ThumbnailGenerator;
+use image_browser_core::db::ImageDatabase;
+use image_browser_core::filesystem::{FileFingerprint, ImageScanner};
+use image_browser_core::thumbnail::ThumbnailGenerator;
 use std::fs;
 use std::path::PathBuf;
 
@@ -163,12 +163,65 @@ fn orphan_detection_marks_disappeared_files() {
     assert!(!visible_paths.iter().any(|p| p.ends_with("a.jpg")));
 }
 
+/// Mirror of the pipeline's fingerprint step: hash every row that
+/// doesn't have a fingerprint yet.
+fn fingerprint_all(db: &ImageDatabase) {
+    let rows: Vec<_> = db
+        .get_content_fingerprints()
+        .unwrap()
+        .into_iter()
+        .filter(|(_, fp)| fp.hash.is_none())
+        .map(|(path, fp)| (fp.id, FileFingerprint::compute(std::path::Path::new(&path)).unwrap()))
+        .collect();
+    db.set_content_fingerprints(&rows).unwrap();
+}
+
+#[test]
+fn moved_file_is_reconciled_instead_of_orphaned() {
+    let (_tmp, db, root_path, _thumb_dir) = setup_workspace();
+    let scanner = ImageScanner::new();
+    let root = db.add_root(root_path.to_string_lossy().into_owned()).unwrap();
+    for p in scanner.scan_directory(&root_path).unwrap() {
+        db.add_image(p, Some(root.id)).unwrap();
+    }
+    fingerprint_all(&db);
+
+    let a_path = root_path.join("a.jpg").to_string_lossy().into_owned();
+    let a_id = db.get_image_id_by_path(&a_path).unwrap();
+    let tag = db.create_tag("moved".into(), "#123456".into()).unwrap();
+    db.add_tag_to_image(a_id, tag.id).unwrap();
+
+    // Move a.jpg into a new subfolder, then run the scan half again.
+    let dest = root_path.join("archive");
+    fs::create_dir(&dest).unwrap();
+    fs::rename(root_path.join("a.jpg"), dest.join("a.jpg")).unwrap();
+    let alive = scanner.scan_directory(&root_path).unwrap();
+    for p in &alive {
+        db.add_image(p.clone(), Some(root.id)).unwrap();
+    }
+    fingerprint_all(&db);
+    db.mark_orphaned(root.id, &alive).unwrap();
+    assert_eq!(db.reconcile_moved_images().unwrap().len(), 1);
+
+    let visible = db
+        .get_images_with_thumbnails(vec![], "".into(), false)
+        .unwrap();
+    assert_eq!(visible.len(), 4, "no duplicate row, no orphan");
+    let moved = db
+        .get_images_with_thumbnails(vec![tag.id], "".into(), false)
+        .unwrap();
+    assert_eq!(moved.len(), 1);
+    assert_eq!(moved[0].id, a_id);
+    assert!(moved[0].path.contains("archive"));
+}
+
 #[test]
-fn remove_root_cascade_takes_thumbnails_with_it_logically() {
+fn removed_root_drops_out_of_thumbnail_work() {
     // We can't directly test the disk-side rm -rf inside this
-    // integration test (it lives in the Tauri command, not the lib),
-    // but we can verify the DB cascade: removing a root drops its
-    // images, which means subsequent thumbnail-needs returns nothing.
+    // integration test (it lives in the startup purge, not the DB
+    // layer), but we can verify the DB side: a removed root's images
+    // are hidden until purged, which means subsequent thumbnail-needs
+    // returns nothing either way.
     let (_tmp, db, root_path, _thumb_dir) = setup_workspace();
     let scanner = ImageScanner::new();
     let paths = scanner.scan_directory(&root_path).unwrap();
@@ -181,6 +234,9 @@ fn remove_root_cascade_takes_thumbnails_with_it_logically() {
     db.remove_root(root.id).unwrap();
     assert!(db.get_all_images().unwrap().is_empty());
     assert!(db.get_images_without_thumbnails().unwrap().is_empty());
+
+    db.purge_removed_roots(i64::MAX).unwrap();
+    assert!(db.get_images_without_thumbnails().unwrap().is_empty());
 }
 
 #[test]
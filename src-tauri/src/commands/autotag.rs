@@ -0,0 +1,93 @@
+use std::sync::Arc;
+use tauri::State;
+
+use image_browser_core::autotag::AutoTagReport;
+use image_browser_core::db::{TagLabel, TagSuggestion};
+use image_browser_core::Library;
+
+use crate::commands::ApiError;
+
+/// Auto-tagging vocabulary, each label with its pending-suggestion count.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.list_tag_labels", skip(library))]
+pub fn list_tag_labels(library: State<'_, Arc<Library>>) -> Result<Vec<TagLabel>, ApiError> {
+    library.tag_labels()
+}
+
+/// Add a candidate label. `templates` are prompts with `{}` where the
+/// name goes ("a photo of a {}"); empty uses the defaults.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.add_tag_label", skip(library))]
+pub fn add_tag_label(
+    library: State<'_, Arc<Library>>,
+    name: String,
+    templates: Vec<String>,
+) -> Result<TagLabel, ApiError> {
+    library.add_tag_label(&name, templates)
+}
+
+/// Replace a label's templates and threshold; `threshold: null` hands
+/// the threshold back to per-run calibration.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.update_tag_label", skip(library))]
+pub fn update_tag_label(
+    library: State<'_, Arc<Library>>,
+    label_id: i64,
+    templates: Vec<String>,
+    threshold: Option<f32>,
+) -> Result<(), ApiError> {
+    library.update_tag_label(label_id, templates, threshold)
+}
+
+#[tauri::command]
+pub fn delete_tag_label(library: State<'_, Arc<Library>>, label_id: i64) -> Result<(), ApiError> {
+    library.delete_tag_label(label_id)
+}
+
+/// Score the whole library against every label in the chosen text
+/// encoder's space (the frontend's `textEncoder` preference) and
+/// refresh the pending suggestions.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.run_auto_tagging", skip(library))]
+pub fn run_auto_tagging(
+    library: State<'_, Arc<Library>>,
+    text_encoder_id: Option<String>,
+) -> Result<AutoTagReport, ApiError> {
+    library.run_auto_tagging(text_encoder_id.as_deref())
+}
+
+/// Pending suggestions, best first — for one label, or all when
+/// `label_id` is null.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.get_auto_tag_suggestions", skip(library))]
+pub fn get_auto_tag_suggestions(
+    library: State<'_, Arc<Library>>,
+    label_id: Option<i64>,
+    limit: usize,
+) -> Result<Vec<TagSuggestion>, ApiError> {
+    library.auto_tag_suggestions(label_id, limit)
+}
+
+/// Accept a label's pending suggestions into `images_tags` — all of
+/// them when `image_ids` is null. Returns the number accepted.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.accept_tag_suggestions", skip(library, image_ids))]
+pub fn accept_tag_suggestions(
+    library: State<'_, Arc<Library>>,
+    label_id: i64,
+    image_ids: Option<Vec<i64>>,
+) -> Result<usize, ApiError> {
+    library.accept_tag_suggestions(label_id, image_ids.as_deref())
+}
+
+/// Reject a label's pending suggestions — all of them when `image_ids`
+/// is null. Rejected pairs are never re-suggested.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.reject_tag_suggestions", skip(library, image_ids))]
+pub fn reject_tag_suggestions(
+    library: State<'_, Arc<Library>>,
+    label_id: i64,
+    image_ids: Option<Vec<i64>>,
+) -> Result<usize, ApiError> {
+    library.reject_tag_suggestions(label_id, image_ids.as_deref())
+}
@@ -0,0 +1,52 @@
+use std::sync::Arc;
+use tauri::State;
+
+use image_browser_core::db::Cluster;
+use image_browser_core::tag_struct::Tag;
+use image_browser_core::Library;
+
+use crate::commands::{ApiError, ImageSearchResult};
+
+/// Re-cluster the library into visual boards and return them, largest
+/// first. `encoder_id: null` clusters DINOv2; `k: null` picks the
+/// board count from the library size.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.run_clustering", skip(library))]
+pub fn run_clustering(
+    library: State<'_, Arc<Library>>,
+    encoder_id: Option<String>,
+    k: Option<usize>,
+) -> Result<Vec<Cluster>, ApiError> {
+    library.run_clustering(encoder_id.as_deref(), k)
+}
+
+/// The boards of the last clustering run, largest first.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.get_clusters", skip(library))]
+pub fn get_clusters(library: State<'_, Arc<Library>>) -> Result<Vec<Cluster>, ApiError> {
+    library.clusters()
+}
+
+/// A page of one board's images, most representative first.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.get_cluster_images", skip(library))]
+pub fn get_cluster_images(
+    library: State<'_, Arc<Library>>,
+    cluster_id: i64,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<ImageSearchResult>, ApiError> {
+    library.cluster_images(cluster_id, limit, offset)
+}
+
+/// Tag every image of a board; `name: null` uses the board's proposed
+/// label.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.cluster_to_tag", skip(library))]
+pub fn cluster_to_tag(
+    library: State<'_, Arc<Library>>,
+    cluster_id: i64,
+    name: Option<String>,
+) -> Result<Tag, ApiError> {
+    library.cluster_to_tag(cluster_id, name.as_deref())
+}
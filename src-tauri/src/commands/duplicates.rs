@@ -0,0 +1,34 @@
+use std::sync::Arc;
+use tauri::State;
+
+use image_browser_core::duplicates::{DuplicateCluster, TrashReport, TrashSelection};
+use image_browser_core::Library;
+
+use crate::commands::ApiError;
+
+/// Exact (same content hash) and near-duplicate (DINOv2 + thumbnail
+/// dHash) clusters across every enabled root, each with a suggested
+/// keeper listed first. `min_similarity: null` uses the default floor.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.find_duplicates", skip(library))]
+pub fn find_duplicates(
+    library: State<'_, Arc<Library>>,
+    min_similarity: Option<f32>,
+) -> Result<Vec<DuplicateCluster>, ApiError> {
+    library.find_duplicates(min_similarity)
+}
+
+/// Send each selection's duplicates to the OS trash, keeping its
+/// keeper, and remove them from the library. `min_similarity` is the
+/// floor the clusters were found at. A selection that doesn't match
+/// the current clusters refuses the whole request; per-file failures
+/// are reported, not fatal.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.trash_duplicates", skip(library, selections))]
+pub fn trash_duplicates(
+    library: State<'_, Arc<Library>>,
+    selections: Vec<TrashSelection>,
+    min_similarity: Option<f32>,
+) -> Result<TrashReport, ApiError> {
+    library.trash_duplicates(&selections, min_similarity)
+}
@@ -12,8 +12,17 @@
 //!
 //! Static list — encoders are compiled in. Adding an encoder means
 //! editing this file + the matching Rust impl.
+//!
+//! Also home to the per-encoder settings IPCs: the enabled set
+//! (`get/set_enabled_encoders`) and the fusion-cache representation
+//! (`get/set_embedding_quantization`).
 
 use serde::Serialize;
+use std::sync::Arc;
+use tauri::State;
+
+use image_browser_core::similarity_and_semantic_search::cosine::QuantMode;
+use image_browser_core::{settings, Library};
 
 #[derive(Debug, Serialize, Clone)]
 pub struct EncoderInfo {
@@ -81,10 +90,10 @@ fn is_known_encoder(id: &str) -> bool {
 /// frontend ordering — `["clip", "dino"]` and `["dino", "clip"]`
 /// hash-equal under this function so the dedup doesn't fight the
 /// user.
-// `pub` (not `pub(crate)`) so audit-generated diagnostic tests under
-// `src-tauri/tests/` can reference the validator directly. The function
-// is otherwise an implementation detail of `set_enabled_encoders` —
-// callers should still go through the IPC.
+// `pub` (not `pub(crate)`) so diagnostic tests can reference the
+// validator directly. The function is otherwise an implementation
+// detail of `set_enabled_encoders` — callers should still go through
+// the IPC.
 pub fn decide_enabled_write(
     current: Option<&[String]>,
     requested: &[String],
@@ -127,7 +136,7 @@ pub fn decide_enabled_write(
 #[tauri::command]
 #[tracing::instrument(name = "ipc.get_enabled_encoders")]
 pub fn get_enabled_encoders() -> Vec<String> {
-    crate::settings::Settings::load().resolved_enabled_encoders()
+    settings::Settings::load().resolved_enabled_encoders()
 }
 
 /// Persist the per-encoder enable/disable list. Frontend calls this
@@ -141,7 +150,7 @@ pub fn get_enabled_encoders() -> Vec<String> {
 #[tauri::command]
 #[tracing::instrument(name = "ipc.set_enabled_encoders", skip())]
 pub fn set_enabled_encoders(ids: Vec<String>) -> Result<(), super::ApiError> {
-    let mut s = crate::settings::Settings::load();
+    let mut s = settings::Settings::load();
     let current = s.enabled_encoders.as_deref();
     match decide_enabled_write(current, &ids)? {
         None => Ok(()),
@@ -155,6 +164,52 @@ pub fn set_enabled_encoders(ids: Vec<String>) -> Result<(), super::ApiError> {
     }
 }
 
+/// Validate a requested fusion-cache representation: `"off"` (full
+/// precision), `"int8"` or `"binary"`. Pure so it's testable without
+/// the settings file.
+pub fn parse_quantization(requested: &str) -> Result<Option<QuantMode>, super::ApiError> {
+    match requested {
+        "off" => Ok(None),
+        other => QuantMode::parse(other).map(Some).ok_or_else(|| {
+            super::ApiError::BadInput(format!(
+                "Unknown quantization '{other}' — expected off, int8 or binary"
+            ))
+        }),
+    }
+}
+
+/// Read the persisted fusion-cache representation as the string the
+/// Settings drawer's segmented control uses (`"off"` when unset).
+#[tauri::command]
+#[tracing::instrument(name = "ipc.get_embedding_quantization")]
+pub fn get_embedding_quantization() -> &'static str {
+    settings::Settings::load()
+        .resolved_quantization()
+        .map_or("off", QuantMode::as_str)
+}
+
+/// Persist the fusion-cache representation and apply it: the fusion
+/// slots are dropped and rebuilt in the new form on the next fusion
+/// call. The embeddings table itself is untouched — re-ranking always
+/// reads the full-precision rows.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.set_embedding_quantization", skip(library))]
+pub fn set_embedding_quantization(
+    mode: String,
+    library: State<'_, Arc<Library>>,
+) -> Result<(), super::ApiError> {
+    let next = parse_quantization(&mode)?;
+    let mut s = settings::Settings::load();
+    if s.resolved_quantization() == next {
+        return Ok(());
+    }
+    s.embedding_quantization = next.map(|m| m.as_str().to_string());
+    s.save()
+        .map_err(|e| super::ApiError::Internal(format!("settings save failed: {e}")))?;
+    library.fusion().set_quantization(next);
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -217,4 +272,15 @@ mod tests {
                 .unwrap();
         assert_eq!(result, Some(vec!["clip_vit_b_32".to_string()]));
     }
+
+    #[test]
+    fn parse_quantization_accepts_off_and_both_modes() {
+        assert_eq!(parse_quantization("off").unwrap(), None);
+        assert_eq!(parse_quantization("int8").unwrap(), Some(QuantMode::Int8));
+        assert_eq!(parse_quantization("binary").unwrap(), Some(QuantMode::Binary));
+        match parse_quantization("fp16").unwrap_err() {
+            super::super::ApiError::BadInput(msg) => assert!(msg.contains("fp16")),
+            other => panic!("expected BadInput, got {other:?}"),
+        }
+    }
 }
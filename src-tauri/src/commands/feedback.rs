@@ -0,0 +1,66 @@
+use std::sync::Arc;
+use tauri::State;
+
+use image_browser_core::db::SearchFilter;
+use image_browser_core::search::FeedbackResults;
+use image_browser_core::Library;
+
+use crate::commands::ApiError;
+
+/// Open a relevance-feedback session on a text query and return its
+/// first page — see `image_browser_core::search::feedback`. `filter`
+/// holds for every refinement of the session.
+#[tauri::command]
+#[tracing::instrument(
+    name = "ipc.start_search_session",
+    skip(library, filter),
+    fields(query_len = query.len(), top_n)
+)]
+pub fn start_search_session(
+    library: State<'_, Arc<Library>>,
+    query: String,
+    top_n: usize,
+    filter: Option<SearchFilter>,
+) -> Result<FeedbackResults, ApiError> {
+    library.start_search_session(&query, top_n, filter.as_ref())
+}
+
+/// Open a relevance-feedback session on a query image.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.start_similar_session", skip(library, filter))]
+pub fn start_similar_session(
+    library: State<'_, Arc<Library>>,
+    image_id: i64,
+    top_n: usize,
+    filter: Option<SearchFilter>,
+) -> Result<FeedbackResults, ApiError> {
+    library.start_similar_session(image_id, top_n, filter.as_ref())
+}
+
+/// Thumbs-up / thumbs-down some results and re-rank. `neutral`
+/// withdraws earlier marks.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.refine_search", skip(library))]
+pub fn refine_search(
+    library: State<'_, Arc<Library>>,
+    session_id: u64,
+    positive: Vec<i64>,
+    negative: Vec<i64>,
+    neutral: Option<Vec<i64>>,
+    top_n: usize,
+) -> Result<FeedbackResults, ApiError> {
+    library.refine_search(
+        session_id,
+        &positive,
+        &negative,
+        neutral.as_deref().unwrap_or_default(),
+        top_n,
+    )
+}
+
+/// Drop a feedback session once the user leaves the results.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.end_search_session", skip(library))]
+pub fn end_search_session(library: State<'_, Arc<Library>>, session_id: u64) -> bool {
+    library.end_search_session(session_id)
+}
@@ -1,13 +1,16 @@
+use std::sync::Arc;
 use tauri::State;
 
+use image_browser_core::db::{images_query::PipelineStats, ID};
+use image_browser_core::image_struct::ImageData;
+use image_browser_core::Library;
+
 use crate::commands::ApiError;
-use crate::db::{images_query::PipelineStats, ImageDatabase, ID};
-use crate::image_struct::ImageData;
 
 #[tauri::command]
-#[tracing::instrument(name = "ipc.get_images", skip(db), fields(tag_count = filter_tag_ids.len()))]
+#[tracing::instrument(name = "ipc.get_images", skip(library), fields(tag_count = filter_tag_ids.len()))]
 pub fn get_images(
-    db: State<'_, ImageDatabase>,
+    library: State<'_, Arc<Library>>,
     filter_tag_ids: Vec<ID>,
     filter_string: String,
     match_all_tags: Option<bool>,
@@ -15,7 +18,7 @@ pub fn get_images(
     // match_all_tags is Option so older frontend builds (or tests)
     // can call without specifying — defaults to false (OR semantic).
     let match_all = match_all_tags.unwrap_or(false);
-    Ok(db.get_images_with_thumbnails(filter_tag_ids, filter_string, match_all)?)
+    Ok(library.db().get_images_with_thumbnails(filter_tag_ids, filter_string, match_all)?)
 }
 
 /// Snapshot of pipeline progress — counts of images at each stage
@@ -26,7 +29,7 @@ pub fn get_images(
 ///
 /// Single SELECT — one DB Mutex acquire regardless of library size.
 #[tauri::command]
-#[tracing::instrument(name = "ipc.get_pipeline_stats", skip(db))]
-pub fn get_pipeline_stats(db: State<'_, ImageDatabase>) -> Result<PipelineStats, ApiError> {
-    Ok(db.get_pipeline_stats()?)
+#[tracing::instrument(name = "ipc.get_pipeline_stats", skip(library))]
+pub fn get_pipeline_stats(library: State<'_, Arc<Library>>) -> Result<PipelineStats, ApiError> {
+    Ok(library.db().get_pipeline_stats()?)
 }
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+use tauri::{AppHandle, State};
+
+use image_browser_core::db::JournalEntry;
+use image_browser_core::Library;
+
+use crate::commands::ApiError;
+use crate::AppProgress;
+
+/// Reverse the newest undoable change (tag create/delete/assign, notes,
+/// folder removal). Returns it, or `null` when there is nothing to
+/// undo. Undoing a folder removal re-indexes the folder.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.undo", skip(app, library))]
+pub fn undo(
+    app: AppHandle,
+    library: State<'_, Arc<Library>>,
+) -> Result<Option<JournalEntry>, ApiError> {
+    library.undo(AppProgress(app))
+}
+
+/// Re-apply the change `undo` last reversed; `null` when there is
+/// nothing to redo.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.redo", skip(app, library))]
+pub fn redo(
+    app: AppHandle,
+    library: State<'_, Arc<Library>>,
+) -> Result<Option<JournalEntry>, ApiError> {
+    library.redo(AppProgress(app))
+}
+
+/// The undo history, newest first. `undone` entries are the redo
+/// stack.
+#[tauri::command]
+pub fn get_journal(library: State<'_, Arc<Library>>) -> Result<Vec<JournalEntry>, ApiError> {
+    library.journal()
+}
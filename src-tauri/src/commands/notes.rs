@@ -1,26 +1,28 @@
+use std::sync::Arc;
 use tauri::State;
 
+use image_browser_core::Library;
+
 use crate::commands::ApiError;
-use crate::db::ImageDatabase;
 
 /// Read the free-text annotation for an image. Returns "" if there
 /// is no annotation set (the column is either NULL or "" — we treat
 /// both as "no annotation" at the user-facing level).
 #[tauri::command]
 pub fn get_image_notes(
-    db: State<'_, ImageDatabase>,
+    library: State<'_, Arc<Library>>,
     image_id: i64,
 ) -> Result<String, ApiError> {
-    Ok(db.get_image_notes(image_id)?.unwrap_or_default())
+    Ok(library.db().get_image_notes(image_id)?.unwrap_or_default())
 }
 
 /// Write an annotation for an image. Empty / whitespace-only string
-/// clears the field.
+/// clears the field. Undoable.
 #[tauri::command]
 pub fn set_image_notes(
-    db: State<'_, ImageDatabase>,
+    library: State<'_, Arc<Library>>,
     image_id: i64,
     notes: String,
 ) -> Result<(), ApiError> {
-    Ok(db.set_image_notes(image_id, &notes)?)
+    library.set_image_notes(image_id, &notes)
 }
@@ -0,0 +1,46 @@
+use std::sync::Arc;
+use tauri::State;
+
+use image_browser_core::db::{GridQuery, SearchFilter};
+use image_browser_core::library::ImagePage;
+use image_browser_core::search::{SearchPage, SearchRequest};
+use image_browser_core::Library;
+
+use crate::commands::ApiError;
+
+/// One page of the grid in a stable order. `cursor` is the previous
+/// page's `next_cursor`; omit it for the first page.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.get_images_page", skip(library, query, cursor), fields(sort = ?query.sort, limit))]
+pub fn get_images_page(
+    library: State<'_, Arc<Library>>,
+    query: GridQuery,
+    cursor: Option<String>,
+    limit: usize,
+) -> Result<ImagePage, ApiError> {
+    library.images_page(&query, cursor.as_deref(), limit)
+}
+
+/// Run a search once and return its first page plus a cursor into the
+/// ranked list kept server-side — see `image_browser_core::search::pages`.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.start_paged_search", skip(library, request, filter))]
+pub fn start_paged_search(
+    library: State<'_, Arc<Library>>,
+    request: SearchRequest,
+    page_size: usize,
+    filter: Option<SearchFilter>,
+) -> Result<SearchPage, ApiError> {
+    library.start_paged_search(&request, page_size, filter.as_ref())
+}
+
+/// The next page of a paged search, without searching again.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.get_search_page", skip(library, cursor))]
+pub fn get_search_page(
+    library: State<'_, Arc<Library>>,
+    cursor: String,
+    page_size: usize,
+) -> Result<SearchPage, ApiError> {
+    library.search_page(&cursor, page_size)
+}
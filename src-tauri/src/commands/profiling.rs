@@ -1,5 +1,4 @@
-use crate::paths;
-use crate::perf;
+use image_browser_core::{paths, perf};
 
 /// True if the binary was launched with `--profile`. The frontend
 /// reads this once at startup to decide whether to mount the perf
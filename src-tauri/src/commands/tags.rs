@@ -1,44 +1,159 @@
+use std::sync::Arc;
 use tauri::State;
 
+use image_browser_core::db::TagAlias;
+use image_browser_core::search::ImageSearchResult;
+use image_browser_core::tag_propagation::TagVote;
+use image_browser_core::tag_struct::Tag;
+use image_browser_core::Library;
+
 use crate::commands::ApiError;
-use crate::db::ImageDatabase;
-use crate::tag_struct::Tag;
 
 #[tauri::command]
-#[tracing::instrument(name = "ipc.get_tags", skip(db))]
-pub fn get_tags(db: State<'_, ImageDatabase>) -> Result<Vec<Tag>, ApiError> {
-    Ok(db.get_tags()?)
+#[tracing::instrument(name = "ipc.get_tags", skip(library))]
+pub fn get_tags(library: State<'_, Arc<Library>>) -> Result<Vec<Tag>, ApiError> {
+    Ok(library.db().get_tags()?)
 }
 
+/// Create a tag; `parent_id` places it under another tag.
 #[tauri::command]
-#[tracing::instrument(name = "ipc.create_tag", skip(db))]
+#[tracing::instrument(name = "ipc.create_tag", skip(library))]
 pub fn create_tag(
-    db: State<'_, ImageDatabase>,
+    library: State<'_, Arc<Library>>,
     name: String,
     color: String,
+    parent_id: Option<i64>,
 ) -> Result<Tag, ApiError> {
-    Ok(db.create_tag(name, color)?)
+    library.create_tag(&name, &color, parent_id)
+}
+
+/// Delete a tag. Its children move up to its parent. Undoable.
+#[tauri::command]
+pub fn delete_tag(library: State<'_, Arc<Library>>, tag_id: i64) -> Result<(), ApiError> {
+    library.delete_tag(tag_id)
+}
+
+/// Move a tag and its subtree under `parent_id`; `null` moves it to
+/// the top level.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.move_tag", skip(library))]
+pub fn move_tag(
+    library: State<'_, Arc<Library>>,
+    tag_id: i64,
+    parent_id: Option<i64>,
+) -> Result<(), ApiError> {
+    library.move_tag(tag_id, parent_id)
+}
+
+/// Fold `source_id` into `target_id` — images, children and all — and
+/// delete it. Returns how many images newly gained the target.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.merge_tags", skip(library))]
+pub fn merge_tags(
+    library: State<'_, Arc<Library>>,
+    source_id: i64,
+    target_id: i64,
+) -> Result<usize, ApiError> {
+    library.merge_tags(source_id, target_id)
+}
+
+/// Rename a tag. A name another tag (or another tag's alias) already
+/// has is `bad_input`.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.rename_tag", skip(library))]
+pub fn rename_tag(
+    library: State<'_, Arc<Library>>,
+    tag_id: i64,
+    name: String,
+) -> Result<Tag, ApiError> {
+    library.rename_tag(tag_id, &name)
+}
+
+#[tauri::command]
+pub fn get_tag_aliases(library: State<'_, Arc<Library>>) -> Result<Vec<TagAlias>, ApiError> {
+    library.tag_aliases()
 }
 
+/// Make `name` another name for `tag_id`.
 #[tauri::command]
-pub fn delete_tag(db: State<'_, ImageDatabase>, tag_id: i64) -> Result<(), ApiError> {
-    Ok(db.delete_tag(tag_id)?)
+#[tracing::instrument(name = "ipc.add_tag_alias", skip(library))]
+pub fn add_tag_alias(
+    library: State<'_, Arc<Library>>,
+    tag_id: i64,
+    name: String,
+) -> Result<TagAlias, ApiError> {
+    library.add_tag_alias(tag_id, &name)
+}
+
+#[tauri::command]
+#[tracing::instrument(name = "ipc.remove_tag_alias", skip(library))]
+pub fn remove_tag_alias(library: State<'_, Arc<Library>>, name: String) -> Result<(), ApiError> {
+    library.remove_tag_alias(&name)
+}
+
+/// Tag every image in `image_ids` in one transaction. Returns how many
+/// gained the tag.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.add_tag_to_images", skip(library, image_ids))]
+pub fn add_tag_to_images(
+    library: State<'_, Arc<Library>>,
+    tag_id: i64,
+    image_ids: Vec<i64>,
+) -> Result<usize, ApiError> {
+    library.add_tag_to_images(tag_id, &image_ids)
+}
+
+/// Untag every image in `image_ids` in one transaction. Returns how
+/// many lost the tag.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.remove_tag_from_images", skip(library, image_ids))]
+pub fn remove_tag_from_images(
+    library: State<'_, Arc<Library>>,
+    tag_id: i64,
+    image_ids: Vec<i64>,
+) -> Result<usize, ApiError> {
+    library.remove_tag_from_images(tag_id, &image_ids)
 }
 
 #[tauri::command]
 pub fn add_tag_to_image(
-    db: State<'_, ImageDatabase>,
+    library: State<'_, Arc<Library>>,
     image_id: i64,
     tag_id: i64,
 ) -> Result<(), ApiError> {
-    Ok(db.add_tag_to_image(image_id, tag_id)?)
+    library.add_tag_to_image(image_id, tag_id)
 }
 
 #[tauri::command]
 pub fn remove_tag_from_image(
-    db: State<'_, ImageDatabase>,
+    library: State<'_, Arc<Library>>,
     image_id: i64,
     tag_id: i64,
 ) -> Result<(), ApiError> {
-    Ok(db.remove_tag_from_image(image_id, tag_id)?)
+    library.remove_tag_from_image(image_id, tag_id)
+}
+
+/// Tags suggested for `image_id` by its nearest tagged look-alikes
+/// (fused across the enabled encoders), best first. `voters: null`
+/// uses the default neighbour count.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.get_tag_suggestions", skip(library))]
+pub fn get_tag_suggestions(
+    library: State<'_, Arc<Library>>,
+    image_id: i64,
+    voters: Option<usize>,
+) -> Result<Vec<TagVote>, ApiError> {
+    library.suggest_tags_for_image(image_id, voters)
+}
+
+/// Tag the `top_n` images that look most like those already tagged
+/// `tag_id`. Returns the newly tagged images.
+#[tauri::command]
+#[tracing::instrument(name = "ipc.propagate_tag", skip(library))]
+pub fn propagate_tag(
+    library: State<'_, Arc<Library>>,
+    tag_id: i64,
+    top_n: usize,
+) -> Result<Vec<ImageSearchResult>, ApiError> {
+    library.propagate_tag(tag_id, top_n)
 }
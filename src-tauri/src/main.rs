@@ -1,7 +1,7 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use image_browser_lib::{db, paths, perf};
+use image_browser_core::{paths, perf, Library};
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 fn main() {
@@ -46,7 +46,7 @@ fn main() {
     // what the human-readable terminal output shows.
     let _ = profiling; // currently unused; kept above for future use
     let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-        EnvFilter::new("warn,image_browser_lib=info,image_browser=info")
+        EnvFilter::new("warn,image_browser_core=info,image_browser_lib=info,image_browser=info")
     });
 
     let registry = tracing_subscriber::registry()
@@ -85,17 +85,15 @@ fn main() {
         let _ = registry.try_init();
     }
 
-    // Pre-Tauri startup work is now minimal: open the SQLite handle and
-    // ensure the schema is current. Everything that takes time (model
+    // Pre-Tauri startup work is now minimal: open the library (SQLite
+    // handle + schema migration; every cache starts empty). Everything that takes time (model
     // download, scan, thumbnails, embeddings) moved into the indexing
     // pipeline that the Tauri setup() callback spawns on a background
-    // thread — see lib.rs::run and indexing.rs.
+    // thread — see lib.rs::run and the core crate's indexing.rs.
     //
     // The window opens immediately and the user sees progress over the
     // `indexing-progress` event (Pass 5b renders this as a status pill).
-    let db_path = db::ImageDatabase::default_database_path();
-    let database = db::ImageDatabase::new(&db_path).expect("failed to init db");
-    database.initialize().expect("failed to create tables");
+    let library = Library::open_default().expect("failed to open the image library");
 
-    image_browser_lib::run(database, db_path);
+    image_browser_lib::run(library);
 }
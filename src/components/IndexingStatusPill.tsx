@@ -1,10 +1,11 @@
 import { motion, AnimatePresence } from "framer-motion";
-import { Loader2, AlertCircle, CheckCircle2 } from "lucide-react";
+import { Loader2, AlertCircle, CheckCircle2, CircleSlash } from "lucide-react";
 import { useEffect, useState } from "react";
 import {
   useIndexingProgress,
   type IndexingPhase,
 } from "../hooks/useIndexingProgress";
+import { cancelIndexing } from "../services/roots";
 
 const PHASE_LABELS: Record<IndexingPhase, string> = {
   scan: "Scanning",
@@ -13,6 +14,7 @@ const PHASE_LABELS: Record<IndexingPhase, string> = {
   encode: "Encoding embeddings",
   ready: "Ready",
   error: "Error",
+  cancelled: "Cancelled",
 };
 
 /**
@@ -22,8 +24,12 @@ const PHASE_LABELS: Record<IndexingPhase, string> = {
  * - Hidden until the first event arrives (no flash on cold launch).
  * - Shown for any active phase.
  * - "ready" lingers 4s as a "X images indexed" confirmation, fades.
+ * - "cancelled" lingers 4s the same way.
  * - "error" sticks until the user dismisses (×) or a new run starts.
  *
+ * While a run is active the × stops it (cancel_indexing) instead of
+ * hiding the pill.
+ *
  * Visual: minimal — icon + label + counter + thin progress bar. We
  * deliberately dropped the redundant "message" detail line that the
  * previous design had; the phase label tells you what the counter
@@ -36,7 +42,7 @@ export function IndexingStatusPill() {
 
   useEffect(() => {
     if (!progress) return;
-    if (progress.phase === "ready") {
+    if (progress.phase === "ready" || progress.phase === "cancelled") {
       setShowFinal(true);
       setDismissed(false);
       const t = setTimeout(() => setShowFinal(false), 4000);
@@ -53,15 +59,21 @@ export function IndexingStatusPill() {
 
   if (!progress) return null;
   if (dismissed) return null;
-  if (progress.phase === "ready" && !showFinal) return null;
+  if (
+    (progress.phase === "ready" || progress.phase === "cancelled") &&
+    !showFinal
+  )
+    return null;
 
   const isError = progress.phase === "error";
   const isReady = progress.phase === "ready";
+  const isCancelled = progress.phase === "cancelled";
+  const isFinal = isError || isReady || isCancelled;
   const label = PHASE_LABELS[progress.phase];
 
   const fill =
     progress.total > 0 ? Math.min(1, progress.processed / progress.total) : 0;
-  const showBar = progress.total > 0 && !isError && !isReady;
+  const showBar = progress.total > 0 && !isFinal;
 
   return (
     <AnimatePresence>
@@ -85,6 +97,8 @@ export function IndexingStatusPill() {
             <AlertCircle className="h-4 w-4 text-destructive" />
           ) : isReady ? (
             <CheckCircle2 className="h-4 w-4 text-primary" />
+          ) : isCancelled ? (
+            <CircleSlash className="h-4 w-4 text-muted-foreground" />
           ) : (
             <Loader2 className="h-4 w-4 animate-spin text-primary" />
           )}
@@ -100,7 +114,7 @@ export function IndexingStatusPill() {
             >
               {label}
             </span>
-            {progress.total > 0 && !isError && !isReady && (
+            {progress.total > 0 && !isFinal && (
               <span className="text-[10px] tabular-nums text-muted-foreground">
                 {humanize(progress.processed, progress.total, progress.phase)}
               </span>
@@ -125,7 +139,7 @@ export function IndexingStatusPill() {
           )}
         </div>
 
-        {(isError || isReady) && (
+        {isFinal ? (
           <button
             type="button"
             onClick={() => setDismissed(true)}
@@ -134,6 +148,18 @@ export function IndexingStatusPill() {
           >
             ×
           </button>
+        ) : (
+          <button
+            type="button"
+            onClick={() => {
+              cancelIndexing().catch((e) => console.error(e));
+            }}
+            aria-label="Cancel indexing"
+            title="Cancel indexing"
+            className="shrink-0 rounded p-1 text-muted-foreground hover:bg-accent hover:text-foreground transition"
+          >
+            ×
+          </button>
         )}
       </motion.div>
     </AnimatePresence>
@@ -1,6 +1,7 @@
 import { memo, useState, useCallback, useRef } from "react";
 import { ImageItem } from "../types";
 import { motion } from "framer-motion";
+import { CloudOff } from "lucide-react";
 
 interface MasonryItemProps {
   item: ImageItem;
@@ -22,7 +23,8 @@ interface MasonryItemProps {
  * via the animationLevel prop so the user can tone it down or off.
  *
  * Multi-select highlight uses the warm amber accent token to indicate
- * inclusion in a bulk-tag operation.
+ * inclusion in a bulk-tag operation. Images of an offline folder get a
+ * badge in the top-right corner.
  */
 export const MasonryItem = memo(function MasonryItem(props: MasonryItemProps) {
   // Use full resolution for selected image (it's bigger, needs clarity).
@@ -151,6 +153,15 @@ export const MasonryItem = memo(function MasonryItem(props: MasonryItemProps) {
         {props.isMultiSelected && (
           <div className="absolute top-2 left-2 h-5 w-5 rounded-full bg-primary border-2 border-background shadow-md" />
         )}
+
+        {props.item.offline && (
+          <div
+            className="absolute top-2 right-2 rounded-full bg-background/80 p-1 text-muted-foreground shadow-md"
+            title="Folder offline — showing the cached thumbnail"
+          >
+            <CloudOff className="h-3.5 w-3.5" />
+          </div>
+        )}
       </motion.div>
     </motion.div>
   );
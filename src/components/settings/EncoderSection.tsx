@@ -1,7 +1,7 @@
 import { useEffect, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { recordAction } from "../../services/perf";
-import { Section } from "./controls";
+import { Field, Section, SegmentedButtons } from "./controls";
 
 /**
  * Phase 11c — per-encoder enable/disable toggles (replaces both
@@ -25,6 +25,9 @@ import { Section } from "./controls";
  * Disabling an encoder does NOT delete its embeddings. They stay in
  * the per-encoder embeddings table; re-enabling brings them back
  * instantly with no re-encoding.
+ *
+ * Below the toggles, `QuantizationPicker` chooses how the fusion
+ * caches are held in memory (`set_embedding_quantization`).
  */
 
 interface EncoderInfo {
@@ -138,10 +141,74 @@ export function EncoderSection() {
           />
         ))}
       </div>
+
+      <QuantizationPicker />
     </Section>
   );
 }
 
+type Quantization = "off" | "int8" | "binary";
+
+const QUANTIZATION_HINTS: Record<Quantization, string> = {
+  off: "f32",
+  int8: "4× smaller",
+  binary: "32× smaller",
+};
+
+/**
+ * Fusion-cache representation. "Full" keeps every enabled encoder's
+ * embeddings in memory as f32; Int8 / Binary keep compact codes
+ * (4× / 32× smaller) and re-rank each query's top candidates against
+ * the full-precision embeddings on disk. Applies from the next search.
+ */
+function QuantizationPicker() {
+  const [mode, setMode] = useState<Quantization | null>(null);
+
+  useEffect(() => {
+    let cancelled = false;
+    invoke<Quantization>("get_embedding_quantization")
+      .then((m) => {
+        if (!cancelled) setMode(m);
+      })
+      .catch((e) => console.warn("get_embedding_quantization failed:", e));
+    return () => {
+      cancelled = true;
+    };
+  }, []);
+
+  if (!mode) return null;
+
+  async function choose(next: Quantization) {
+    const prev = mode;
+    setMode(next);
+    recordAction("embedding_quantization", { mode: next });
+    try {
+      await invoke("set_embedding_quantization", { mode: next });
+    } catch (e) {
+      console.warn("set_embedding_quantization failed:", e);
+      setMode(prev);
+    }
+  }
+
+  return (
+    <Field label="Search memory" hint={QUANTIZATION_HINTS[mode]}>
+      <SegmentedButtons<Quantization>
+        value={mode}
+        onChange={choose}
+        options={[
+          { value: "off", label: "Full" },
+          { value: "int8", label: "Int8" },
+          { value: "binary", label: "Binary" },
+        ]}
+      />
+      <p className="text-[11px] text-muted-foreground">
+        Compact modes hold far less in RAM on large libraries. Results
+        are still re-scored with the full-precision embeddings.
+      </p>
+    </Field>
+  );
+}
+
 function EncoderToggle({
   info,
   enabled,
@@ -50,12 +50,17 @@ export function FoldersSection() {
               >
                 {root.path}
               </p>
+              {root.offline && (
+                <p className="text-[10px] text-muted-foreground">
+                  Offline — images kept until the folder is back
+                </p>
+              )}
             </div>
             <button
               onClick={() => {
                 if (
                   window.confirm(
-                    `Remove ${root.path}?\n\nThe images from this folder will be removed from the index. The actual files on disk are not touched.`,
+                    `Remove ${root.path}?\n\nThe images from this folder will be hidden from the library. Adding the folder again within 30 days brings them back with their tags and notes. The actual files on disk are not touched.`,
                   )
                 ) {
                   recordAction("folder_remove", {
@@ -12,7 +12,8 @@ export type IndexingPhase =
   | "thumbnail"
   | "encode"
   | "ready"
-  | "error";
+  | "error"
+  | "cancelled";
 
 /**
  * Event payload emitted by the backend on `indexing-progress`. Mirrors
@@ -35,8 +36,8 @@ export interface IndexingState {
   /** The latest progress event, or null before any event has arrived. */
   progress: IndexingProgress | null;
   /**
-   * True while the pipeline is actively running. Goes false on `ready`
-   * or `error`. Pill should hide when this is false (with a brief grace
+   * True while the pipeline is actively running. Goes false on `ready`,
+   * `error` or `cancelled`. Pill should hide when this is false (with a brief grace
    * period to display the final state).
    */
   isIndexing: boolean;
@@ -117,6 +118,12 @@ export function useIndexingProgress(): IndexingState {
               readyInvalidatedFor.current = runKey;
               queryClient.invalidateQueries({ queryKey: ["images"] });
             }
+          } else if (payload.phase === "cancelled") {
+            // Work committed before the checkpoint (rows, thumbnails)
+            // is kept — show it.
+            lastInvalidatedAt.current = 0;
+            readyInvalidatedFor.current = null;
+            queryClient.invalidateQueries({ queryKey: ["images"] });
           } else if (payload.phase === "scan") {
             // New run starting — re-arm the ready de-dupe.
             readyInvalidatedFor.current = null;
@@ -141,7 +148,8 @@ export function useIndexingProgress(): IndexingState {
   const isIndexing =
     progress !== null &&
     progress.phase !== "ready" &&
-    progress.phase !== "error";
+    progress.phase !== "error" &&
+    progress.phase !== "cancelled";
 
   return { progress, isIndexing };
 }
@@ -21,6 +21,7 @@ export type ApiError =
   | { kind: "cosine"; details: string }
   | { kind: "not_found"; details: string }
   | { kind: "bad_input"; details: string }
+  | { kind: "unauthorized"; details: string }
   | { kind: "io"; details: string }
   | { kind: "internal"; details: string };
 
@@ -75,6 +76,8 @@ function formatStructured(e: ApiError): string {
       return `Not found: ${e.details}`;
     case "bad_input":
       return `Invalid input: ${e.details}`;
+    case "unauthorized":
+      return `Unauthorized: ${e.details}`;
     case "io":
       return `I/O error: ${e.details}`;
     case "internal":
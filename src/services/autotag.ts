@@ -0,0 +1,116 @@
+/**
+ * Zero-shot auto-tagging — IPC wrappers for the Tauri commands defined
+ * in src-tauri/src/commands/autotag.rs.
+ *
+ * The user keeps a vocabulary of labels; a run scores every image
+ * against each label in the chosen text encoder's space and stores the
+ * hits as pending suggestions. Accepting tags the images with a tag
+ * named after the label (created on first accept); rejecting stops the
+ * pair from being suggested again.
+ */
+import { invoke } from "@tauri-apps/api/core";
+import { AutoTagReport, TagLabel, TagSuggestion } from "../types";
+
+export async function listTagLabels(): Promise<TagLabel[]> {
+  try {
+    return await invoke<TagLabel[]>("list_tag_labels");
+  } catch (error) {
+    throw new Error(`Failed to list labels: ${error}`);
+  }
+}
+
+export async function addTagLabel(
+  name: string,
+  templates: string[] = [],
+): Promise<TagLabel> {
+  try {
+    return await invoke<TagLabel>("add_tag_label", { name, templates });
+  } catch (error) {
+    throw new Error(`Failed to add label: ${error}`);
+  }
+}
+
+/**
+ * Replace a label's prompt templates and threshold. Pass a null
+ * threshold to let each run calibrate it.
+ */
+export async function updateTagLabel(
+  labelId: number,
+  templates: string[],
+  threshold: number | null,
+): Promise<void> {
+  try {
+    await invoke("update_tag_label", { labelId, templates, threshold });
+  } catch (error) {
+    throw new Error(`Failed to update label: ${error}`);
+  }
+}
+
+export async function deleteTagLabel(labelId: number): Promise<void> {
+  try {
+    await invoke("delete_tag_label", { labelId });
+  } catch (error) {
+    throw new Error(`Failed to delete label: ${error}`);
+  }
+}
+
+/** Score the library against every label; replaces pending suggestions. */
+export async function runAutoTagging(
+  textEncoderId?: string,
+): Promise<AutoTagReport> {
+  try {
+    return await invoke<AutoTagReport>("run_auto_tagging", {
+      textEncoderId: textEncoderId ?? null,
+    });
+  } catch (error) {
+    throw new Error(`Failed to run auto-tagging: ${error}`);
+  }
+}
+
+/** Pending suggestions, best first; all labels when `labelId` is omitted. */
+export async function fetchAutoTagSuggestions(
+  labelId?: number,
+  limit = 200,
+): Promise<TagSuggestion[]> {
+  try {
+    return await invoke<TagSuggestion[]>("get_auto_tag_suggestions", {
+      labelId: labelId ?? null,
+      limit,
+    });
+  } catch (error) {
+    throw new Error(`Failed to fetch suggestions: ${error}`);
+  }
+}
+
+/**
+ * Accept a label's pending suggestions — only `imageIds` when given,
+ * otherwise all of them. Resolves to the number accepted.
+ */
+export async function acceptTagSuggestions(
+  labelId: number,
+  imageIds?: number[],
+): Promise<number> {
+  try {
+    return await invoke<number>("accept_tag_suggestions", {
+      labelId,
+      imageIds: imageIds ?? null,
+    });
+  } catch (error) {
+    throw new Error(`Failed to accept suggestions: ${error}`);
+  }
+}
+
+/** Reject a label's pending suggestions — `imageIds` or all of them. */
+export async function rejectTagSuggestions(
+  labelId: number,
+  imageIds?: number[],
+): Promise<number> {
+  try {
+    return await invoke<number>("reject_tag_suggestions", {
+      labelId,
+      imageIds: imageIds ?? null,
+    });
+  } catch (error) {
+    throw new Error(`Failed to reject suggestions: ${error}`);
+  }
+}
@@ -0,0 +1,94 @@
+/**
+ * Visual boards — IPC wrappers for the Tauri commands defined in
+ * src-tauri/src/commands/boards.rs.
+ *
+ * Boards are unsupervised clusters of the library (DINOv2 by default),
+ * each with a proposed name and a cover image. The indexing pipeline
+ * adds newly encoded images to their nearest board; re-running the
+ * clustering rebuilds them from scratch.
+ */
+import { convertFileSrc, invoke } from "@tauri-apps/api/core";
+import { Board, SimilarImageItem, Tag } from "../types";
+import { mapImageSearchResult } from "./images";
+
+/** The board row as the backend sends it. */
+type ClusterRow = {
+  id: number;
+  encoder_id: string;
+  label: string | null;
+  label_score: number | null;
+  size: number;
+  cover_image_id: number;
+  cover_path: string;
+  cover_thumbnail_path: string | null;
+};
+
+function mapCluster(row: ClusterRow): Board {
+  return {
+    id: row.id,
+    encoderId: row.encoder_id,
+    label: row.label,
+    labelScore: row.label_score,
+    size: row.size,
+    coverImageId: row.cover_image_id,
+    coverUrl: convertFileSrc(row.cover_thumbnail_path ?? row.cover_path),
+  };
+}
+
+/** The boards of the last clustering run, largest first. */
+export async function fetchBoards(): Promise<Board[]> {
+  try {
+    const rows = await invoke<ClusterRow[]>("get_clusters");
+    return rows.map(mapCluster);
+  } catch (error) {
+    throw new Error(`Failed to fetch boards: ${error}`);
+  }
+}
+
+/**
+ * Re-cluster the library. Omit `encoderId` for DINOv2 and `k` to let
+ * the backend pick the board count from the library size.
+ */
+export async function runClustering(
+  encoderId?: string,
+  k?: number,
+): Promise<Board[]> {
+  try {
+    const rows = await invoke<ClusterRow[]>("run_clustering", {
+      encoderId: encoderId ?? null,
+      k: k ?? null,
+    });
+    return rows.map(mapCluster);
+  } catch (error) {
+    throw new Error(`Failed to cluster the library: ${error}`);
+  }
+}
+
+/** A page of a board's images, most representative first. */
+export async function fetchBoardImages(
+  clusterId: number,
+  limit = 100,
+  offset = 0,
+): Promise<SimilarImageItem[]> {
+  try {
+    const results: Parameters<typeof mapImageSearchResult>[0][] = await invoke(
+      "get_cluster_images",
+      { clusterId, limit, offset },
+    );
+    return results.map(mapImageSearchResult);
+  } catch (error) {
+    throw new Error(`Failed to fetch board images: ${error}`);
+  }
+}
+
+/** Tag every image of a board; `name` defaults to the board's label. */
+export async function boardToTag(clusterId: number, name?: string): Promise<Tag> {
+  try {
+    return await invoke<Tag>("cluster_to_tag", {
+      clusterId,
+      name: name ?? null,
+    });
+  } catch (error) {
+    throw new Error(`Failed to tag board: ${error}`);
+  }
+}
@@ -0,0 +1,59 @@
+/**
+ * Duplicate finder — IPC wrappers for the Tauri commands defined in
+ * src-tauri/src/commands/duplicates.rs.
+ *
+ * Clusters come back keeper-first: the backend suggests the copy with
+ * the most pixels (then the largest file) to keep. Trashing moves files
+ * to the OS trash, so a wrong pick can be restored from there; the next
+ * scan brings a restored file back with its tags and notes.
+ */
+import { invoke } from "@tauri-apps/api/core";
+import { DuplicateCluster, TrashReport, TrashSelection } from "../types";
+
+/**
+ * Exact and near-duplicate clusters, largest first. `minSimilarity` is
+ * the DINOv2 cosine floor for near duplicates; omit for the default.
+ */
+export async function findDuplicates(
+  minSimilarity?: number,
+): Promise<DuplicateCluster[]> {
+  try {
+    return await invoke<DuplicateCluster[]>("find_duplicates", {
+      minSimilarity: minSimilarity ?? null,
+    });
+  } catch (error) {
+    throw new Error(`Failed to find duplicates: ${error}`);
+  }
+}
+
+/** Every non-keeper image, per cluster — the default trash selection. */
+export function duplicatesToTrash(
+  clusters: DuplicateCluster[],
+): TrashSelection[] {
+  return clusters.map((c) => ({
+    keeper_id: c.keeper_id,
+    image_ids: c.images
+      .filter((img) => img.id !== c.keeper_id)
+      .map((img) => img.id),
+  }));
+}
+
+/**
+ * Move the selected duplicates' files to the OS trash and drop them
+ * from the library. Pass the `minSimilarity` the clusters were found
+ * at: the backend re-derives them and refuses a selection that would
+ * trash a keeper or an image outside its keeper's cluster.
+ */
+export async function trashDuplicates(
+  selections: TrashSelection[],
+  minSimilarity?: number,
+): Promise<TrashReport> {
+  try {
+    return await invoke<TrashReport>("trash_duplicates", {
+      selections,
+      minSimilarity: minSimilarity ?? null,
+    });
+  } catch (error) {
+    throw new Error(`Failed to move images to the trash: ${error}`);
+  }
+}
@@ -0,0 +1,105 @@
+/**
+ * Relevance feedback — IPC wrappers for the Tauri commands defined in
+ * src-tauri/src/commands/feedback.rs.
+ *
+ * A session starts from a text query or a query image (the same fused
+ * search as `fetchFusedSemanticSearch` / `fetchFusedSimilarImages`)
+ * and is refined with thumbs-up / thumbs-down on its results. The
+ * judgements live on the backend under the session id; every call
+ * returns the re-ranked page together with the current marks.
+ */
+import { FeedbackPage, SearchFilter } from "../types";
+import { formatApiError } from "./apiError";
+import { mapImageSearchResult, toSearchFilterArg } from "./images";
+import { perfInvoke } from "./perf";
+
+/** A feedback page as the backend sends it. */
+type FeedbackRow = {
+  session_id: number;
+  positive: number[];
+  negative: number[];
+  results: Parameters<typeof mapImageSearchResult>[0][];
+};
+
+function mapFeedback(row: FeedbackRow): FeedbackPage {
+  return {
+    sessionId: row.session_id,
+    positive: row.positive,
+    negative: row.negative,
+    results: row.results.map(mapImageSearchResult),
+  };
+}
+
+/**
+ * Open a feedback session on a text query. `filter` holds for every
+ * refinement of the session.
+ */
+export async function startSearchSession(
+  query: string,
+  topN: number = 50,
+  filter?: SearchFilter,
+): Promise<FeedbackPage> {
+  try {
+    const row = await perfInvoke<FeedbackRow>("start_search_session", {
+      query,
+      topN,
+      filter: toSearchFilterArg(filter),
+    });
+    return mapFeedback(row);
+  } catch (error) {
+    console.error("[Frontend] Error in startSearchSession:", error);
+    throw new Error(formatApiError(error));
+  }
+}
+
+/** Open a feedback session on a query image. */
+export async function startSimilarSession(
+  imageId: number,
+  topN: number = 30,
+  filter?: SearchFilter,
+): Promise<FeedbackPage> {
+  try {
+    const row = await perfInvoke<FeedbackRow>("start_similar_session", {
+      imageId,
+      topN,
+      filter: toSearchFilterArg(filter),
+    });
+    return mapFeedback(row);
+  } catch (error) {
+    console.error("[Frontend] Error in startSimilarSession:", error);
+    throw new Error(formatApiError(error));
+  }
+}
+
+/**
+ * Mark results and re-rank. A mark replaces any earlier one on the same
+ * image; list an image in `neutral` to clear its mark.
+ */
+export async function refineSearch(
+  sessionId: number,
+  marks: { positive?: number[]; negative?: number[]; neutral?: number[] },
+  topN: number = 50,
+): Promise<FeedbackPage> {
+  try {
+    const row = await perfInvoke<FeedbackRow>("refine_search", {
+      sessionId,
+      positive: marks.positive ?? [],
+      negative: marks.negative ?? [],
+      neutral: marks.neutral ?? null,
+      topN,
+    });
+    return mapFeedback(row);
+  } catch (error) {
+    console.error("[Frontend] Error in refineSearch:", error);
+    throw new Error(formatApiError(error));
+  }
+}
+
+/** Drop a session once its results are closed. */
+export async function endSearchSession(sessionId: number): Promise<void> {
+  try {
+    await perfInvoke<boolean>("end_search_session", { sessionId });
+  } catch (error) {
+    console.error("[Frontend] Error in endSearchSession:", error);
+  }
+}
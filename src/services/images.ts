@@ -1,6 +1,16 @@
 import { convertFileSrc, invoke } from "@tauri-apps/api/core";
 import { open } from "@tauri-apps/plugin-dialog";
-import { ImageData, ImageItem, SimilarImageItem } from "../types";
+import {
+  GridQuery,
+  ImageData,
+  ImageItem,
+  ImagePage,
+  QueryTerm,
+  SearchFilter,
+  SearchPage,
+  SearchRequest,
+  SimilarImageItem,
+} from "../types";
 import { perfInvoke } from "./perf";
 import { formatApiError } from "./apiError";
 
@@ -51,22 +61,7 @@ export async function fetchImages(
     // Square placeholder is the symmetric least-bad: minimal reflow
     // when actual aspect arrives, looks visually intentional as a
     // loading state. The status pill still shows real progress.
-    const images: ImageItem[] = imagesDB.map((img) => {
-      const url = convertFileSrc(img.path);
-      const thumbnailUrl = img.thumbnail_path
-        ? convertFileSrc(img.thumbnail_path)
-        : url;
-
-      return {
-        id: img.id,
-        name: img.name,
-        url,
-        thumbnailUrl,
-        width: img.width ?? PLACEHOLDER_WIDTH,
-        height: img.height ?? PLACEHOLDER_HEIGHT,
-        tags: img.tags,
-      };
-    });
+    const images = imagesDB.map(toImageItem);
 
     // Apply sort mode frontend-side. Backend returned stable order
     // by id; we re-order here as the user prefers.
@@ -76,6 +71,59 @@ export async function fetchImages(
   }
 }
 
+function toImageItem(img: ImageData): ImageItem {
+  const thumbnailUrl = img.thumbnail_path
+    ? convertFileSrc(img.thumbnail_path)
+    : undefined;
+  // An offline root's files are out of reach; the cached thumbnail is
+  // all there is to show, full size included.
+  const url =
+    img.offline && thumbnailUrl ? thumbnailUrl : convertFileSrc(img.path);
+  return {
+    id: img.id,
+    name: img.name,
+    url,
+    thumbnailUrl: thumbnailUrl ?? url,
+    width: img.width ?? PLACEHOLDER_WIDTH,
+    height: img.height ?? PLACEHOLDER_HEIGHT,
+    tags: img.tags,
+    offline: img.offline ?? false,
+  };
+}
+
+/**
+ * One page of the grid, sorted by the backend in a stable order
+ * (ties broken by id). Pass the previous page's `nextCursor` for the
+ * next one; a cursor only continues the sort it was issued for.
+ */
+export async function fetchImagesPage(
+  query: GridQuery = {},
+  cursor?: string,
+  limit: number = 200,
+): Promise<ImagePage> {
+  try {
+    const page: { images: ImageData[]; next_cursor: string | null } =
+      await perfInvoke("get_images_page", {
+        query: {
+          filter_tag_ids: query.filterTagIds ?? [],
+          filter_string: query.filterString ?? "",
+          match_all_tags: query.matchAllTags ?? false,
+          sort: query.sort ?? "id",
+          descending: query.descending ?? false,
+          seed: query.seed ?? 0,
+        },
+        cursor,
+        limit,
+      });
+    return {
+      images: page.images.map(toImageItem),
+      nextCursor: page.next_cursor,
+    };
+  } catch (error) {
+    throw new Error(formatApiError(error));
+  }
+}
+
 /**
  * Apply the user's preferred sort to a list of images.
  *
@@ -215,13 +263,13 @@ export async function setScanRoot(path: string): Promise<void> {
  * Map a backend ImageSearchResult into the frontend's SimilarImageItem
  * shape. All three search commands (semantic, similar, tiered) now
  * return the same struct (audit consolidation), so this single helper
- * covers every call site.
+ * covers every call site (and `propagateTag` in `tags.ts`).
  *
  * Backend supplies thumbnail_path/width/height when the image was
  * thumbnailed at indexing time. Legacy DB rows may lack them; fall
  * back to the canonical thumb_{id}.jpg path + default dimensions.
  */
-function mapImageSearchResult(res: {
+export function mapImageSearchResult(res: {
   id: number;
   path: string;
   score: number;
@@ -243,6 +291,30 @@ function mapImageSearchResult(res: {
   };
 }
 
+/**
+ * A `SearchFilter` in the backend's snake_case shape, or undefined when
+ * there is nothing to filter on.
+ */
+export function toSearchFilterArg(filter?: SearchFilter) {
+  if (!filter) return undefined;
+  return {
+    include_tag_ids: filter.includeTagIds ?? [],
+    match_all_tags: filter.matchAllTags ?? false,
+    exclude_tag_ids: filter.excludeTagIds ?? [],
+    root_ids: filter.rootIds ?? [],
+    min_width: filter.minWidth ?? null,
+    max_width: filter.maxWidth ?? null,
+    min_height: filter.minHeight ?? null,
+    max_height: filter.maxHeight ?? null,
+    min_aspect_ratio: filter.minAspectRatio ?? null,
+    max_aspect_ratio: filter.maxAspectRatio ?? null,
+    file_types: filter.fileTypes ?? [],
+    modified_after: filter.modifiedAfter ?? null,
+    modified_before: filter.modifiedBefore ?? null,
+    has_notes: filter.hasNotes ?? null,
+  };
+}
+
 export async function fetchSimilarImages(
   imageId: number,
   topN: number = 8,
@@ -295,17 +367,19 @@ export async function fetchTieredSimilarImages(imageId: number, encoderId?: stri
  *
  * `topN` is how many fused results to return. The backend defaults
  * `perEncoderTopK` to ~5×topN so each encoder contributes enough
- * candidates to the fusion pool.
+ * candidates to the fusion pool. `filter` narrows each encoder's
+ * candidates before its top-K, so the page stays full.
  */
 export async function fetchFusedSimilarImages(
   imageId: number,
   topN: number = 30,
-  perEncoderTopK?: number
+  perEncoderTopK?: number,
+  filter?: SearchFilter
 ) {
   try {
     const results: Parameters<typeof mapImageSearchResult>[0][] = await perfInvoke(
       "get_fused_similar_images",
-      { imageId, topN, perEncoderTopK }
+      { imageId, topN, perEncoderTopK, filter: toSearchFilterArg(filter) }
     );
     return results.map(mapImageSearchResult);
   } catch (error) {
@@ -328,16 +402,18 @@ export async function fetchFusedSimilarImages(
  * Settings panel.
  *
  * `topN` defaults to 50 to match the previous semantic_search default.
+ * `filter` works as in `fetchFusedSimilarImages`.
  */
 export async function fetchFusedSemanticSearch(
   query: string,
   topN: number = 50,
-  perEncoderTopK?: number
+  perEncoderTopK?: number,
+  filter?: SearchFilter
 ): Promise<SimilarImageItem[]> {
   try {
     const results: Parameters<typeof mapImageSearchResult>[0][] = await perfInvoke(
       "get_fused_semantic_search",
-      { query, topN, perEncoderTopK }
+      { query, topN, perEncoderTopK, filter: toSearchFilterArg(filter) }
     );
     return results.map(mapImageSearchResult);
   } catch (error) {
@@ -346,6 +422,101 @@ export async function fetchFusedSemanticSearch(
   }
 }
 
+/**
+ * Composite query — a weighted blend of text phrases and example
+ * images, fused across every enabled encoder like
+ * `fetchFusedSemanticSearch`. A negative weight steers away from a
+ * term ("no people" is `{ kind: "text", text: "people", weight: -1 }`);
+ * weights default to 1. Example images are left out of the results.
+ */
+export async function fetchCompositeSearch(
+  terms: QueryTerm[],
+  topN: number = 50,
+  filter?: SearchFilter
+): Promise<SimilarImageItem[]> {
+  try {
+    const results: Parameters<typeof mapImageSearchResult>[0][] = await perfInvoke(
+      "get_composite_search",
+      {
+        terms: terms.map(toQueryTermArg),
+        topN,
+        filter: toSearchFilterArg(filter),
+      }
+    );
+    return results.map(mapImageSearchResult);
+  } catch (error) {
+    console.error("[Frontend] Error in fetchCompositeSearch:", error);
+    throw new Error(formatApiError(error));
+  }
+}
+
+function toQueryTermArg(t: QueryTerm) {
+  return t.kind === "text"
+    ? { kind: "text", text: t.text, weight: t.weight ?? 1 }
+    : { kind: "image", image_id: t.imageId, weight: t.weight ?? 1 };
+}
+
+type RawSearchPage = {
+  results: Parameters<typeof mapImageSearchResult>[0][];
+  next_cursor: string | null;
+  total: number;
+};
+
+function toSearchPage(page: RawSearchPage): SearchPage {
+  return {
+    results: page.results.map(mapImageSearchResult),
+    nextCursor: page.next_cursor,
+    total: page.total,
+  };
+}
+
+/**
+ * Run a fused text, similar-image or composite search once and return
+ * its first page. The backend keeps the ranked list, so
+ * `fetchSearchPage(nextCursor)` reads further pages without searching
+ * again. A cursor whose list has expired fails with `not_found`; run
+ * the search again.
+ */
+export async function startPagedSearch(
+  request: SearchRequest,
+  pageSize: number = 50,
+  filter?: SearchFilter
+): Promise<SearchPage> {
+  try {
+    const page: RawSearchPage = await perfInvoke("start_paged_search", {
+      request:
+        request.kind === "text"
+          ? { kind: "text", query: request.query }
+          : request.kind === "similar"
+            ? { kind: "similar", image_id: request.imageId }
+            : { kind: "composite", terms: request.terms.map(toQueryTermArg) },
+      pageSize,
+      filter: toSearchFilterArg(filter),
+    });
+    return toSearchPage(page);
+  } catch (error) {
+    console.error("[Frontend] Error in startPagedSearch:", error);
+    throw new Error(formatApiError(error));
+  }
+}
+
+/** The next page of a paged search. */
+export async function fetchSearchPage(
+  cursor: string,
+  pageSize: number = 50
+): Promise<SearchPage> {
+  try {
+    const page: RawSearchPage = await perfInvoke("get_search_page", {
+      cursor,
+      pageSize,
+    });
+    return toSearchPage(page);
+  } catch (error) {
+    console.error("[Frontend] Error in fetchSearchPage:", error);
+    throw new Error(formatApiError(error));
+  }
+}
+
 /**
  * LEGACY (Phase 4 single-encoder dispatch). Preserved as an internal
  * fallback so anything that imports `semanticSearch` directly keeps
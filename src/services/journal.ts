@@ -0,0 +1,58 @@
+/**
+ * Undo / redo — IPC wrappers for the Tauri commands defined in
+ * src-tauri/src/commands/journal.rs.
+ *
+ * Tag create / delete / assign (single and bulk), notes edits and
+ * folder removal are journaled by the backend; `undo` reverses the
+ * newest, `redo` re-applies the last one undone. Callers refetch what
+ * the entry touched — tags, images, roots — afterwards.
+ */
+import { invoke } from "@tauri-apps/api/core";
+import { JournalEntry } from "../types";
+
+/** The entry as the backend sends it. */
+type JournalRow = {
+  id: number;
+  label: string;
+  created_at: number;
+  undone: boolean;
+};
+
+function mapEntry(row: JournalRow): JournalEntry {
+  return {
+    id: row.id,
+    label: row.label,
+    createdAt: row.created_at,
+    undone: row.undone,
+  };
+}
+
+/** Reverse the newest change; `null` when there is nothing to undo. */
+export async function undo(): Promise<JournalEntry | null> {
+  try {
+    const row = await invoke<JournalRow | null>("undo");
+    return row ? mapEntry(row) : null;
+  } catch (error) {
+    throw new Error(`Failed to undo: ${error}`);
+  }
+}
+
+/** Re-apply the last undone change; `null` when there is nothing to redo. */
+export async function redo(): Promise<JournalEntry | null> {
+  try {
+    const row = await invoke<JournalRow | null>("redo");
+    return row ? mapEntry(row) : null;
+  } catch (error) {
+    throw new Error(`Failed to redo: ${error}`);
+  }
+}
+
+/** The undo history, newest first. */
+export async function fetchJournal(): Promise<JournalEntry[]> {
+  try {
+    const rows = await invoke<JournalRow[]>("get_journal");
+    return rows.map(mapEntry);
+  } catch (error) {
+    throw new Error(`Failed to fetch undo history: ${error}`);
+  }
+}
@@ -1,7 +1,7 @@
 /**
  * Multi-folder root management — IPC wrappers for the Tauri commands
- * defined in src-tauri/src/lib.rs (list_roots, add_root, remove_root,
- * set_root_enabled).
+ * defined in src-tauri/src/commands/roots.rs (list_roots, add_root,
+ * remove_root, set_root_enabled, cancel_indexing).
  *
  * The settings drawer (Phase 9) renders the list of configured roots
  * with toggle / remove controls and an "add folder" button.
@@ -31,8 +31,9 @@ export async function addRoot(path: string): Promise<Root> {
 }
 
 /**
- * Remove a root and CASCADE-delete its image rows from the DB. Tags
- * survive — they're catalogue-level user knowledge.
+ * Remove a root. A soft delete: its images are hidden, and adding the
+ * same path again within the retention window restores them with their
+ * tags, notes and embeddings.
  */
 export async function removeRoot(id: number): Promise<void> {
   try {
@@ -45,7 +46,7 @@ export async function removeRoot(id: number): Promise<void> {
 /**
  * Toggle a root's enabled flag. Disabled roots keep their image rows
  * on disk (re-enabling is instant) but are filtered out of the grid.
- * No re-index is triggered — the change is purely a query filter.
+ * Enabling also restarts indexing so the root's files are picked up.
  */
 export async function setRootEnabled(
   id: number,
@@ -57,3 +58,16 @@ export async function setRootEnabled(
     throw new Error(`Failed to toggle root: ${error}`);
   }
 }
+
+/**
+ * Stop the running indexing pass at its next checkpoint. Resolves to
+ * whether a pass was running; the pill flips to "cancelled" once the
+ * backend has actually stopped.
+ */
+export async function cancelIndexing(): Promise<boolean> {
+  try {
+    return await invoke<boolean>("cancel_indexing");
+  } catch (error) {
+    throw new Error(`Failed to cancel indexing: ${error}`);
+  }
+}
@@ -107,6 +107,34 @@ describe("services/notes", () => {
   });
 });
 
+describe("services/journal", () => {
+  it("undo and redo map the replayed entry, or pass null through", async () => {
+    const { undo, redo } = await import("./journal");
+    mockInvoke.mockResolvedValueOnce({
+      id: 3,
+      label: 'Delete tag "cat"',
+      created_at: 1700000000,
+      undone: true,
+    });
+    expect(await undo()).toEqual({
+      id: 3,
+      label: 'Delete tag "cat"',
+      createdAt: 1700000000,
+      undone: true,
+    });
+    expect(mockInvoke).toHaveBeenLastCalledWith("undo");
+    mockInvoke.mockResolvedValueOnce(null);
+    expect(await redo()).toBeNull();
+    expect(mockInvoke).toHaveBeenLastCalledWith("redo");
+  });
+
+  it("undo wraps errors", async () => {
+    const { undo } = await import("./journal");
+    mockInvoke.mockRejectedValueOnce("bad_input");
+    await expect(undo()).rejects.toThrow(/Failed to undo/);
+  });
+});
+
 describe("services/images", () => {
   it("fetchImages threads matchAllTags through to invoke", async () => {
     const { fetchImages } = await import("./images");
@@ -165,6 +193,25 @@ describe("services/images", () => {
     expect(items[0].thumbnailUrl).toContain("photo.jpg");
   });
 
+  it("fetchImages serves an offline image from its thumbnail", async () => {
+    const { fetchImages } = await import("./images");
+    mockInvoke.mockResolvedValueOnce([
+      {
+        id: 1,
+        path: "/usb/photo.jpg",
+        name: "photo.jpg",
+        thumbnail_path: "/tmp/thumb.jpg",
+        width: 800,
+        height: 600,
+        tags: [],
+        offline: true,
+      },
+    ]);
+    const items = await fetchImages();
+    expect(items[0].offline).toBe(true);
+    expect(items[0].url).toContain("thumb.jpg");
+  });
+
   it("setScanRoot sends only the path argument", async () => {
     const { setScanRoot } = await import("./images");
     mockInvoke.mockResolvedValueOnce(undefined);
@@ -255,6 +302,107 @@ describe("services/fusedSemantic", () => {
       perEncoderTopK: 200,
     });
   });
+
+  it("fetchFusedSemanticSearch sends the filter in snake_case", async () => {
+    const { fetchFusedSemanticSearch } = await import("./images");
+    mockInvoke.mockResolvedValueOnce([]);
+    await fetchFusedSemanticSearch("street", 50, undefined, {
+      includeTagIds: [3],
+      minWidth: 1920,
+      fileTypes: ["jpg"],
+    });
+    expect(mockInvoke).toHaveBeenCalledWith("get_fused_semantic_search", {
+      query: "street",
+      topN: 50,
+      perEncoderTopK: undefined,
+      filter: {
+        include_tag_ids: [3],
+        match_all_tags: false,
+        exclude_tag_ids: [],
+        root_ids: [],
+        min_width: 1920,
+        max_width: null,
+        min_height: null,
+        max_height: null,
+        min_aspect_ratio: null,
+        max_aspect_ratio: null,
+        file_types: ["jpg"],
+        modified_after: null,
+        modified_before: null,
+        has_notes: null,
+      },
+    });
+  });
+
+  it("fetchCompositeSearch sends snake_case terms with default weights", async () => {
+    const { fetchCompositeSearch } = await import("./images");
+    mockInvoke.mockResolvedValueOnce([]);
+    await fetchCompositeSearch(
+      [
+        { kind: "image", imageId: 4 },
+        { kind: "text", text: "at night", weight: 0.5 },
+        { kind: "text", text: "people", weight: -1 },
+      ],
+      20
+    );
+    expect(mockInvoke).toHaveBeenCalledWith("get_composite_search", {
+      terms: [
+        { kind: "image", image_id: 4, weight: 1 },
+        { kind: "text", text: "at night", weight: 0.5 },
+        { kind: "text", text: "people", weight: -1 },
+      ],
+      topN: 20,
+    });
+  });
+});
+
+describe("services/pages", () => {
+  it("fetchImagesPage sends a snake_case query and maps the page", async () => {
+    const { fetchImagesPage } = await import("./images");
+    mockInvoke.mockResolvedValueOnce({
+      images: [{ id: 7, name: "a.jpg", path: "/r/a.jpg", tags: [] }],
+      next_cursor: "7b7d",
+    });
+    const page = await fetchImagesPage({ sort: "mtime", descending: true }, "abcd", 100);
+    expect(mockInvoke).toHaveBeenCalledWith("get_images_page", {
+      query: {
+        filter_tag_ids: [],
+        filter_string: "",
+        match_all_tags: false,
+        sort: "mtime",
+        descending: true,
+        seed: 0,
+      },
+      cursor: "abcd",
+      limit: 100,
+    });
+    expect(page.nextCursor).toBe("7b7d");
+    expect(page.images[0]).toMatchObject({ id: 7, width: 400, height: 400 });
+  });
+
+  it("startPagedSearch tags the request and fetchSearchPage follows the cursor", async () => {
+    const { startPagedSearch, fetchSearchPage } = await import("./images");
+    mockInvoke.mockResolvedValueOnce({
+      results: [{ id: 4, path: "/r/b.jpg", score: 0.9 }],
+      next_cursor: "ab",
+      total: 120,
+    });
+    const first = await startPagedSearch({ kind: "similar", imageId: 3 }, 1);
+    expect(mockInvoke).toHaveBeenCalledWith("start_paged_search", {
+      request: { kind: "similar", image_id: 3 },
+      pageSize: 1,
+    });
+    expect(first).toMatchObject({ nextCursor: "ab", total: 120 });
+    expect(first.results[0].name).toBe("b.jpg");
+
+    mockInvoke.mockResolvedValueOnce({ results: [], next_cursor: null, total: 120 });
+    const next = await fetchSearchPage("ab");
+    expect(mockInvoke).toHaveBeenLastCalledWith("get_search_page", {
+      cursor: "ab",
+      pageSize: 50,
+    });
+    expect(next.nextCursor).toBeNull();
+  });
 });
 
 describe("services/fusedSimilar", () => {
@@ -295,6 +443,153 @@ describe("services/fusedSimilar", () => {
   });
 });
 
+describe("services/autotag", () => {
+  it("acceptTagSuggestions sends null image ids to accept everything", async () => {
+    const { acceptTagSuggestions } = await import("./autotag");
+    mockInvoke.mockResolvedValueOnce(12);
+    expect(await acceptTagSuggestions(3)).toBe(12);
+    expect(mockInvoke).toHaveBeenCalledWith("accept_tag_suggestions", {
+      labelId: 3,
+      imageIds: null,
+    });
+  });
+
+  it("rejectTagSuggestions passes the selected image ids", async () => {
+    const { rejectTagSuggestions } = await import("./autotag");
+    mockInvoke.mockResolvedValueOnce(2);
+    await rejectTagSuggestions(3, [10, 11]);
+    expect(mockInvoke).toHaveBeenCalledWith("reject_tag_suggestions", {
+      labelId: 3,
+      imageIds: [10, 11],
+    });
+  });
+
+  it("runAutoTagging wraps backend errors", async () => {
+    const { runAutoTagging } = await import("./autotag");
+    mockInvoke.mockRejectedValueOnce("text model missing");
+    await expect(runAutoTagging("siglip2_base")).rejects.toThrow(
+      "Failed to run auto-tagging: text model missing",
+    );
+    expect(mockInvoke).toHaveBeenCalledWith("run_auto_tagging", {
+      textEncoderId: "siglip2_base",
+    });
+  });
+});
+
+describe("services/duplicates", () => {
+  it("findDuplicates sends a null floor by default", async () => {
+    const { findDuplicates } = await import("./duplicates");
+    mockInvoke.mockResolvedValueOnce([]);
+    await findDuplicates();
+    expect(mockInvoke).toHaveBeenCalledWith("find_duplicates", {
+      minSimilarity: null,
+    });
+  });
+
+  it("duplicatesToTrash keeps every cluster's keeper", async () => {
+    const { duplicatesToTrash } = await import("./duplicates");
+    const image = (id: number) => ({
+      id,
+      path: `/lib/${id}.jpg`,
+      thumbnail_path: null,
+      width: null,
+      height: null,
+      file_size: null,
+      similarity: 1,
+    });
+    const selections = duplicatesToTrash([
+      { kind: "exact", keeper_id: 1, images: [image(1), image(2)] },
+      { kind: "near", keeper_id: 5, images: [image(5), image(3), image(4)] },
+    ]);
+    expect(selections).toEqual([
+      { keeper_id: 1, image_ids: [2] },
+      { keeper_id: 5, image_ids: [3, 4] },
+    ]);
+  });
+
+  it("trashDuplicates wraps backend errors", async () => {
+    const { trashDuplicates } = await import("./duplicates");
+    mockInvoke.mockRejectedValueOnce("bad input: image 1 is a keeper");
+    const selections = [{ keeper_id: 2, image_ids: [1] }];
+    await expect(trashDuplicates(selections)).rejects.toThrow(
+      "Failed to move images to the trash: bad input: image 1 is a keeper",
+    );
+    expect(mockInvoke).toHaveBeenCalledWith("trash_duplicates", {
+      selections,
+      minSimilarity: null,
+    });
+  });
+});
+
+describe("services/boards", () => {
+  it("runClustering sends nulls for the defaults and maps the rows", async () => {
+    const { runClustering } = await import("./boards");
+    mockInvoke.mockResolvedValueOnce([
+      {
+        id: 4,
+        encoder_id: "dinov2_base",
+        label: "beach",
+        label_score: 0.27,
+        size: 12,
+        cover_image_id: 9,
+        cover_path: "/lib/9.jpg",
+        cover_thumbnail_path: "/thumbs/9.jpg",
+      },
+    ]);
+    const boards = await runClustering();
+    expect(mockInvoke).toHaveBeenCalledWith("run_clustering", {
+      encoderId: null,
+      k: null,
+    });
+    expect(boards[0]).toMatchObject({
+      id: 4,
+      label: "beach",
+      size: 12,
+      coverImageId: 9,
+      coverUrl: "tauri://localhost//thumbs/9.jpg",
+    });
+  });
+
+  it("boardToTag falls back to the proposed label", async () => {
+    const { boardToTag } = await import("./boards");
+    mockInvoke.mockResolvedValueOnce({ id: 2, name: "beach", color: "#3B82F6" });
+    await boardToTag(4);
+    expect(mockInvoke).toHaveBeenCalledWith("cluster_to_tag", {
+      clusterId: 4,
+      name: null,
+    });
+  });
+});
+
+describe("services/feedback", () => {
+  it("refineSearch sends every mark list and maps the page", async () => {
+    const { refineSearch } = await import("./feedback");
+    mockInvoke.mockResolvedValueOnce({
+      session_id: 3,
+      positive: [7],
+      negative: [8],
+      results: [{ id: 9, path: "/lib/9.jpg", score: 0.03, thumbnail_path: "/thumbs/9.jpg" }],
+    });
+    const page = await refineSearch(3, { positive: [7], negative: [8] }, 20);
+    expect(mockInvoke).toHaveBeenCalledWith("refine_search", {
+      sessionId: 3,
+      positive: [7],
+      negative: [8],
+      neutral: null,
+      topN: 20,
+    });
+    expect(page.sessionId).toBe(3);
+    expect(page.negative).toEqual([8]);
+    expect(page.results[0]).toMatchObject({ id: 9, name: "9.jpg" });
+  });
+
+  it("startSearchSession surfaces backend errors", async () => {
+    const { startSearchSession } = await import("./feedback");
+    mockInvoke.mockRejectedValueOnce({ kind: "bad_input", details: "search query is empty" });
+    await expect(startSearchSession(" ")).rejects.toThrow(/search query is empty/);
+  });
+});
+
 describe("services/tags", () => {
   it("createTag uses default colour when none provided", async () => {
     const { createTag } = await import("./tags");
@@ -320,6 +615,56 @@ describe("services/tags", () => {
     });
   });
 
+  it("moveTag and mergeTags pass camelCase ids", async () => {
+    const { moveTag, mergeTags } = await import("./tags");
+    mockInvoke.mockResolvedValueOnce(undefined);
+    await moveTag(4, null);
+    expect(mockInvoke).toHaveBeenCalledWith("move_tag", { tagId: 4, parentId: null });
+    mockInvoke.mockResolvedValueOnce(2);
+    expect(await mergeTags(4, 9)).toBe(2);
+    expect(mockInvoke).toHaveBeenLastCalledWith("merge_tags", { sourceId: 4, targetId: 9 });
+  });
+
+  it("renameTag, alias and bulk calls pass camelCase args", async () => {
+    const { renameTag, addTagAlias, removeTagAlias, addTagToImages, removeTagFromImages } =
+      await import("./tags");
+    mockInvoke.mockResolvedValueOnce({ id: 4, name: "kitten", color: "#fff" });
+    expect((await renameTag(4, "kitten")).name).toBe("kitten");
+    expect(mockInvoke).toHaveBeenLastCalledWith("rename_tag", { tagId: 4, name: "kitten" });
+    mockInvoke.mockResolvedValueOnce({ name: "kitty", tag_id: 4 });
+    await addTagAlias(4, "kitty");
+    expect(mockInvoke).toHaveBeenLastCalledWith("add_tag_alias", { tagId: 4, name: "kitty" });
+    mockInvoke.mockResolvedValueOnce(undefined);
+    await removeTagAlias("kitty");
+    expect(mockInvoke).toHaveBeenLastCalledWith("remove_tag_alias", { name: "kitty" });
+    mockInvoke.mockResolvedValueOnce(2);
+    expect(await addTagToImages(4, [1, 2, 3])).toBe(2);
+    expect(mockInvoke).toHaveBeenLastCalledWith("add_tag_to_images", {
+      tagId: 4,
+      imageIds: [1, 2, 3],
+    });
+    mockInvoke.mockResolvedValueOnce(1);
+    expect(await removeTagFromImages(4, [1])).toBe(1);
+    expect(mockInvoke).toHaveBeenLastCalledWith("remove_tag_from_images", {
+      tagId: 4,
+      imageIds: [1],
+    });
+  });
+
+  it("buildTagTree nests children and sorts siblings by name", async () => {
+    const { buildTagTree } = await import("./tags");
+    const tree = buildTagTree([
+      { id: 3, name: "brutalist", color: "#fff", parent_id: 2 },
+      { id: 1, name: "reference", color: "#fff", parent_id: null },
+      { id: 2, name: "architecture", color: "#fff", parent_id: 1 },
+      { id: 5, name: "bauhaus", color: "#fff", parent_id: 2 },
+      { id: 4, name: "cat", color: "#fff" },
+    ]);
+    expect(tree.map((n) => n.name)).toEqual(["cat", "reference"]);
+    const architecture = tree[1].children[0];
+    expect(architecture.children.map((n) => n.name)).toEqual(["bauhaus", "brutalist"]);
+  });
+
   it("deleteTag passes only the id", async () => {
     const { deleteTag } = await import("./tags");
     mockInvoke.mockResolvedValueOnce(undefined);
@@ -327,6 +672,26 @@ describe("services/tags", () => {
     expect(mockInvoke).toHaveBeenCalledWith("delete_tag", { tagId: 99 });
   });
 
+  it("fetchTagSuggestions asks for one image's suggestions", async () => {
+    const { fetchTagSuggestions } = await import("./tags");
+    mockInvoke.mockResolvedValueOnce([]);
+    await fetchTagSuggestions(5);
+    expect(mockInvoke).toHaveBeenCalledWith("get_tag_suggestions", {
+      imageId: 5,
+      voters: null,
+    });
+  });
+
+  it("propagateTag maps the newly tagged images", async () => {
+    const { propagateTag } = await import("./tags");
+    mockInvoke.mockResolvedValueOnce([
+      { id: 8, path: "/lib/cat.jpg", score: 0.05, thumbnail_path: null },
+    ]);
+    const items = await propagateTag(2, 10);
+    expect(mockInvoke).toHaveBeenCalledWith("propagate_tag", { tagId: 2, topN: 10 });
+    expect(items[0]).toMatchObject({ id: 8, name: "cat.jpg", score: 0.05 });
+  });
+
   it("fetchTags returns the backend list", async () => {
     const { fetchTags } = await import("./tags");
     mockInvoke.mockResolvedValueOnce([
@@ -1,5 +1,6 @@
-import { Tag } from "@/types";
+import { SimilarImageItem, Tag, TagAlias, TagNode, TagVote } from "@/types";
 import { invoke } from "@tauri-apps/api/core";
+import { mapImageSearchResult } from "./images";
 
 export async function fetchTags(): Promise<Tag[]> {
   try {
@@ -12,15 +13,136 @@ export async function fetchTags(): Promise<Tag[]> {
 
 export async function createTag(
   name: string,
-  color: string = "#3B82F6"
+  color: string = "#3B82F6",
+  parentId?: number
 ): Promise<Tag> {
   try {
-    return await invoke("create_tag", { name, color });
+    return await invoke("create_tag", { name, color, parentId });
   } catch (error) {
     throw new Error(`Failed to create tag: ${error}`);
   }
 }
 
+/**
+ * Move a tag, with everything below it, under `parentId` — or to the
+ * top level with `null`. Moving a tag under its own descendant fails.
+ */
+export async function moveTag(
+  tagId: number,
+  parentId: number | null
+): Promise<void> {
+  try {
+    await invoke("move_tag", { tagId, parentId });
+  } catch (error) {
+    throw new Error(`Failed to move tag: ${error}`);
+  }
+}
+
+/**
+ * Fold `sourceId` into `targetId`: its images gain the target, its
+ * children move under the target, its name and aliases become aliases
+ * of the target, and it is deleted. Resolves to how many images newly
+ * gained the target.
+ */
+export async function mergeTags(
+  sourceId: number,
+  targetId: number
+): Promise<number> {
+  try {
+    return await invoke<number>("merge_tags", { sourceId, targetId });
+  } catch (error) {
+    throw new Error(`Failed to merge tags: ${error}`);
+  }
+}
+
+/**
+ * Rename a tag. Fails if another tag, or another tag's alias, already
+ * has the name.
+ */
+export async function renameTag(tagId: number, name: string): Promise<Tag> {
+  try {
+    return await invoke<Tag>("rename_tag", { tagId, name });
+  } catch (error) {
+    throw new Error(`Failed to rename tag: ${error}`);
+  }
+}
+
+export async function fetchTagAliases(): Promise<TagAlias[]> {
+  try {
+    return await invoke<TagAlias[]>("get_tag_aliases");
+  } catch (error) {
+    throw new Error(`Failed to fetch tag aliases: ${error}`);
+  }
+}
+
+/** Make `name` resolve to `tagId`. */
+export async function addTagAlias(
+  tagId: number,
+  name: string
+): Promise<TagAlias> {
+  try {
+    return await invoke<TagAlias>("add_tag_alias", { tagId, name });
+  } catch (error) {
+    throw new Error(`Failed to add tag alias: ${error}`);
+  }
+}
+
+export async function removeTagAlias(name: string): Promise<void> {
+  try {
+    await invoke("remove_tag_alias", { name });
+  } catch (error) {
+    throw new Error(`Failed to remove tag alias: ${error}`);
+  }
+}
+
+/**
+ * Tag every image in `imageIds` in one transaction. Resolves to how
+ * many gained the tag; already-tagged and unknown images are skipped.
+ */
+export async function addTagToImages(
+  tagId: number,
+  imageIds: number[]
+): Promise<number> {
+  try {
+    return await invoke<number>("add_tag_to_images", { tagId, imageIds });
+  } catch (error) {
+    throw new Error(`Failed to tag images: ${error}`);
+  }
+}
+
+/** Untag every image in `imageIds`; resolves to how many lost the tag. */
+export async function removeTagFromImages(
+  tagId: number,
+  imageIds: number[]
+): Promise<number> {
+  try {
+    return await invoke<number>("remove_tag_from_images", { tagId, imageIds });
+  } catch (error) {
+    throw new Error(`Failed to untag images: ${error}`);
+  }
+}
+
+/**
+ * Arrange the flat `get_tags` list into a forest, siblings by name.
+ * A tag whose parent isn't in the list becomes a root.
+ */
+export function buildTagTree(tags: Tag[]): TagNode[] {
+  const nodes = new Map<number, TagNode>(
+    tags.map((t) => [t.id, { ...t, children: [] }])
+  );
+  const roots: TagNode[] = [];
+  for (const node of nodes.values()) {
+    const parent = node.parent_id != null ? nodes.get(node.parent_id) : undefined;
+    (parent ? parent.children : roots).push(node);
+  }
+  const sort = (list: TagNode[]) => {
+    list.sort((a, b) => a.name.localeCompare(b.name));
+    list.forEach((n) => sort(n.children));
+  };
+  sort(roots);
+  return roots;
+}
+
 export async function deleteTag(tagId: number): Promise<void> {
   try {
     await invoke("delete_tag", { tagId });
@@ -28,3 +150,40 @@ export async function deleteTag(tagId: number): Promise<void> {
     throw new Error(`Failed to delete tag: ${error}`);
   }
 }
+
+/**
+ * Tags suggested for an image by its nearest tagged look-alikes, best
+ * first. Tags the image already has are left out.
+ */
+export async function fetchTagSuggestions(
+  imageId: number,
+  voters?: number,
+): Promise<TagVote[]> {
+  try {
+    return await invoke<TagVote[]>("get_tag_suggestions", {
+      imageId,
+      voters: voters ?? null,
+    });
+  } catch (error) {
+    throw new Error(`Failed to fetch tag suggestions: ${error}`);
+  }
+}
+
+/**
+ * Tag the `topN` images that look most like those already carrying
+ * `tagId`. Resolves to the newly tagged images, best first.
+ */
+export async function propagateTag(
+  tagId: number,
+  topN = 20,
+): Promise<SimilarImageItem[]> {
+  try {
+    const results: Parameters<typeof mapImageSearchResult>[0][] = await invoke(
+      "propagate_tag",
+      { tagId, topN },
+    );
+    return results.map(mapImageSearchResult);
+  } catch (error) {
+    throw new Error(`Failed to propagate tag: ${error}`);
+  }
+}
@@ -11,6 +11,8 @@ export type ImageData = {
   height?: number;
   /** Free-text annotation (Phase 11) */
   notes?: string | null;
+  /** The image's folder is offline; only the thumbnail is reachable */
+  offline?: boolean;
 };
 
 export type ImageItem = {
@@ -25,12 +27,25 @@ export type ImageItem = {
   tags: Tag[];
   /** Free-text annotation (Phase 11) */
   notes?: string | null;
+  /** Folder offline: `url` is the thumbnail, shown with a badge */
+  offline?: boolean;
 };
 
 export type Tag = {
   id: number;
   name: string;
   color: string;
+  /** The tag this one sits under; null at the top level */
+  parent_id?: number | null;
+};
+
+/** A tag with the tags below it, as built by `buildTagTree` */
+export type TagNode = Tag & { children: TagNode[] };
+
+/** Another name that resolves to tag `tag_id` */
+export type TagAlias = {
+  name: string;
+  tag_id: number;
 };
 
 export type SimilarImageItem = {
@@ -46,6 +61,15 @@ export type SimilarImageItem = {
   name?: string;
 };
 
+/** A tag suggested for an image by its nearest tagged look-alikes. */
+export type TagVote = {
+  tag: Tag;
+  /** Share of the voting neighbours' fused weight carrying the tag, 0–1 */
+  score: number;
+  /** Voting neighbours that carry the tag */
+  votes: number;
+};
+
 /** A configured scan root (multi-folder support, Phase 6). */
 export type Root = {
   id: number;
@@ -53,4 +77,172 @@ export type Root = {
   enabled: boolean;
   /** Unix epoch seconds */
   added_at: number;
+  /** The folder is missing (unmounted drive or share) */
+  offline: boolean;
+};
+
+/** An auto-tagging vocabulary entry (zero-shot tag suggestions). */
+export type TagLabel = {
+  id: number;
+  name: string;
+  /** Prompt templates with `{}` for the name; empty = backend defaults */
+  templates: string[];
+  /** User-set score threshold; null = calibrated on each run */
+  threshold: number | null;
+  /** Suggestions awaiting accept / reject */
+  pending: number;
+};
+
+/** A pending (image, label) suggestion from the auto-tagger. */
+export type TagSuggestion = {
+  image_id: number;
+  path: string;
+  thumbnail_path: string | null;
+  label_id: number;
+  label: string;
+  score: number;
+};
+
+/** Outcome of one auto-tagging run, per label. */
+export type AutoTagReport = {
+  encoder_id: string;
+  images_scored: number;
+  labels: {
+    label_id: number;
+    name: string;
+    threshold: number;
+    calibrated: boolean;
+    suggested: number;
+  }[];
+};
+
+/** One image in a duplicate cluster. */
+export type DuplicateImage = {
+  id: number;
+  path: string;
+  thumbnail_path: string | null;
+  width: number | null;
+  height: number | null;
+  file_size: number | null;
+  /** DINOv2 cosine to the keeper; 1 for the keeper and identical copies */
+  similarity: number;
+};
+
+/** A group of duplicates from the duplicate finder, keeper first. */
+export type DuplicateCluster = {
+  /** `exact`: same file content; `near`: resized / re-encoded / cropped */
+  kind: "exact" | "near";
+  keeper_id: number;
+  images: DuplicateImage[];
+};
+
+/** One term of a composite search; a negative weight steers away */
+export type QueryTerm =
+  | { kind: "text"; text: string; weight?: number }
+  | { kind: "image"; imageId: number; weight?: number };
+
+/** One page of a relevance-feedback session */
+export type FeedbackPage = {
+  sessionId: number;
+  /** Images marked thumbs-up so far */
+  positive: number[];
+  /** Images marked thumbs-down so far; never in `results` */
+  negative: number[];
+  results: SimilarImageItem[];
+};
+
+/**
+ * Constraints on which images a vector search may return, applied
+ * before each encoder's top-K so a filtered page is still full. Every
+ * field is optional.
+ */
+export type SearchFilter = {
+  includeTagIds?: number[];
+  /** Require every include tag rather than any */
+  matchAllTags?: boolean;
+  excludeTagIds?: number[];
+  rootIds?: number[];
+  minWidth?: number;
+  maxWidth?: number;
+  minHeight?: number;
+  maxHeight?: number;
+  /** width / height; 1 is square */
+  minAspectRatio?: number;
+  maxAspectRatio?: number;
+  /** Extensions, e.g. ["jpg", "png"] */
+  fileTypes?: string[];
+  /** File modification time bounds, unix seconds */
+  modifiedAfter?: number;
+  modifiedBefore?: number;
+  hasNotes?: boolean;
+};
+
+/** What the paginated grid shows and in which order */
+export type GridQuery = {
+  filterTagIds?: number[];
+  filterString?: string;
+  matchAllTags?: boolean;
+  /** `random` is a seeded shuffle, stable across pages for one seed */
+  sort?: "id" | "mtime" | "name" | "size" | "random";
+  descending?: boolean;
+  seed?: number;
+};
+
+/** One page of the grid */
+export type ImagePage = {
+  images: ImageItem[];
+  /** Pass back for the next page; null on the last one */
+  nextCursor: string | null;
+};
+
+/** The search behind a paged result listing */
+export type SearchRequest =
+  | { kind: "text"; query: string }
+  | { kind: "similar"; imageId: number }
+  | { kind: "composite"; terms: QueryTerm[] };
+
+/** One page of a paged search; later pages don't search again */
+export type SearchPage = {
+  results: SimilarImageItem[];
+  /** Pass back for the next page; null on the last one */
+  nextCursor: string | null;
+  /** Results in the whole ranked list */
+  total: number;
+};
+
+/** A visual board: one cluster of the library, with a proposed name. */
+export type Board = {
+  id: number;
+  /** Image encoder the library was clustered in */
+  encoderId: string;
+  /** Proposed name; null when no text encoder was available */
+  label: string | null;
+  labelScore: number | null;
+  size: number;
+  /** The board's most representative image */
+  coverImageId: number;
+  coverUrl: string;
+};
+
+/** One undoable change, as listed by `fetchJournal`. */
+export type JournalEntry = {
+  id: number;
+  /** What was done, e.g. `Delete tag "cat"` */
+  label: string;
+  /** Unix epoch seconds */
+  createdAt: number;
+  /** Undone and waiting to be redone */
+  undone: boolean;
+};
+
+/** One cluster's cleanup: the copy to keep and the copies to trash. */
+export type TrashSelection = {
+  keeper_id: number;
+  image_ids: number[];
+};
+
+/** Outcome of moving images to the OS trash. */
+export type TrashReport = {
+  trashed: number[];
+  failed: { id: number; path: string; error: string }[];
 };